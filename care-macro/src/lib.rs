@@ -1,10 +1,12 @@
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashSet};
 
 use proc_macro2::{Ident, Span, TokenStream};
 use quote::quote;
-use syn::{spanned::Spanned, Block, Expr, ItemFn, ItemStatic, Stmt};
-
-const STATE_VAR_SEPARATOR: &str = "\n\n\n";
+use syn::{
+    parse::{Parse, ParseStream},
+    spanned::Spanned,
+    Block, Expr, ItemFn, ItemStatic, Stmt,
+};
 
 #[rustfmt::skip]
 fn dereference_state_vars(expr: &mut Expr, vars: &HashSet<String>) {
@@ -114,52 +116,250 @@ fn dereference_state_vars_stmt(stmt: &mut Stmt, vars: &HashSet<String>) {
     }
 }
 
-fn care_macro_shared(func: proc_macro::TokenStream, name: &str) -> proc_macro::TokenStream {
-    let func = TokenStream::from(func);
-    let input: ItemFn = match syn::parse2(func.clone()) {
-        Ok(i) => i,
-        Err(e) => return token_stream_with_error(func, e),
+/// The attributes that mark the items inside `care::main!`
+const GAME_ATTRIBUTES: &[&str] = &[
+    "state",
+    "conf",
+    "init",
+    "update",
+    "fixed_update",
+    "event",
+    "resize",
+    "focus",
+    "draw",
+    "quit",
+    "async_main",
+];
+
+/// Find and remove the care attribute on an item inside `care::main!`, if it has one
+fn take_game_attribute(attrs: &mut Vec<syn::Attribute>) -> syn::Result<Option<Ident>> {
+    let Some(pos) = attrs.iter().position(|attr| {
+        let segments = &attr.path().segments;
+        let name = match segments.len() {
+            1 => &segments[0].ident,
+            2 if segments[0].ident == "care" => &segments[1].ident,
+            _ => return false,
+        };
+        GAME_ATTRIBUTES.iter().any(|attribute| name == attribute)
+    }) else {
+        return Ok(None);
     };
-    let state_params = std::env::var("_CARE_INTERNAL_STATE_PARAMS")
-        .ok()
-        .unwrap_or_default();
-    let func_name = format!("care_{}", input.sig.ident);
-    let var_name = format!("_CARE_INTERNAL_{name}");
-    if std::env::var(&var_name).is_ok() {
-        return func.into();
+    let attr = attrs.remove(pos);
+    attr.meta.require_path_only()?;
+    Ok(attr
+        .path()
+        .segments
+        .last()
+        .map(|segment| segment.ident.clone()))
+}
+
+/// The items given to `care::main!`
+#[derive(Default)]
+struct Game {
+    /// The `#[care::state]` statics
+    states: Vec<ItemStatic>,
+    /// The functions marked with a care attribute, by the attribute's name
+    handlers: BTreeMap<String, ItemFn>,
+    /// Every other item, which is kept as it is
+    items: Vec<syn::Item>,
+}
+
+impl Parse for Game {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let mut game = Game::default();
+        let mut errors = Vec::new();
+        while !input.is_empty() {
+            let mut item: syn::Item = input.parse()?;
+            let attribute = match &mut item {
+                syn::Item::Static(syn::ItemStatic { attrs, .. })
+                | syn::Item::Fn(syn::ItemFn { attrs, .. }) => take_game_attribute(attrs),
+                _ => Ok(None),
+            };
+            let name = match attribute {
+                Ok(Some(name)) => name,
+                Ok(None) => {
+                    game.items.push(item);
+                    continue;
+                }
+                Err(e) => {
+                    errors.push(e);
+                    continue;
+                }
+            };
+            match (item, name.to_string().as_str()) {
+                (syn::Item::Static(item), "state") => game.states.push(item),
+                (syn::Item::Fn(item), handler) if handler != "state" => {
+                    if game.handlers.contains_key(handler) {
+                        errors.push(syn::Error::new(
+                            item.sig.ident.span(),
+                            format!("There can only be one #[care::{handler}] function"),
+                        ));
+                    } else {
+                        game.handlers.insert(handler.to_string(), item);
+                    }
+                }
+                (_, "state") => errors.push(syn::Error::new(
+                    name.span(),
+                    "#[care::state] can only be used on a static",
+                )),
+                (_, handler) => errors.push(syn::Error::new(
+                    name.span(),
+                    format!("#[care::{handler}] can only be used on a function"),
+                )),
+            }
+        }
+
+        match errors.into_iter().reduce(|mut all, e| {
+            all.combine(e);
+            all
+        }) {
+            Some(errors) => Err(errors),
+            None => Ok(game),
+        }
     }
-    std::env::set_var(&var_name, func_name.clone());
+}
 
-    let state_vars: HashSet<_> = state_params
-        .split(STATE_VAR_SEPARATOR)
-        .filter(|s| !s.is_empty())
-        .map(|p| p.split_once(':').unwrap().0.trim().to_string())
-        .collect();
+/// A function marked with a care attribute
+struct Handler {
+    /// The function's name, after it's renamed so it doesn't clash with anything
+    ident: Ident,
+    /// Where each of its parameters comes from
+    params: Vec<Param>,
+}
 
-    let state_params = if input.sig.inputs.is_empty() {
-        state_params.trim_start_matches(STATE_VAR_SEPARATOR)
-    } else {
-        &state_params
-    };
-    let new_params: TokenStream = state_params
-        .replace(STATE_VAR_SEPARATOR, ",")
-        .parse()
-        .unwrap();
-    let asyncness = input.sig.asyncness;
-    let ident = Ident::new(&func_name, input.sig.ident.span());
-    let generics = input.sig.generics;
-    let inputs = input.sig.inputs;
-    let output = input.sig.output;
-    let mut block = input.block;
-    for stmt in &mut block.stmts {
-        dereference_state_vars_stmt(stmt, &state_vars);
+/// Where a game function's parameter comes from
+enum Param {
+    /// The argument care gives that kind of function with this index, e.g. the update function's
+    /// delta time
+    Own(usize),
+    /// The `#[care::state]` with this index
+    State(usize),
+    /// The resource with this type, from `care::resource`
+    Resource(Box<syn::Type>),
+}
+
+/// How many arguments care gives each kind of function, before any state or resources
+fn own_arg_count(handler: &str) -> usize {
+    match handler {
+        "init" | "update" | "fixed_update" | "event" | "resize" | "focus" => 1,
+        _ => 0,
     }
-    let result = quote! {
-        #asyncness fn #ident #generics (#inputs #new_params) #output
-        #block
-    };
+}
 
-    result.into()
+/// Get whether a type is `care::event::Event`, as well as a macro can tell
+fn is_event(ty: &syn::Type) -> bool {
+    matches!(ty, syn::Type::Path(p) if p.path.segments.last().is_some_and(|s| s.ident == "Event"))
+}
+
+/// Get whether a type is one of the resources care makes when they're used, `care::Conf` or
+/// `care::debug::DebugStats`, as well as a macro can tell
+///
+/// Changes to them would be thrown away, so they can only be taken by shared reference.
+fn is_read_only_resource(ty: &syn::Type) -> bool {
+    matches!(ty, syn::Type::Path(p) if p.path.segments.last().is_some_and(|s| {
+        s.ident == "Conf" || s.ident == "DebugStats"
+    }))
+}
+
+/// Work out where each of a game function's parameters comes from
+///
+/// References are matched by type, to the `#[care::state]` with that type or otherwise to the
+/// resource with that type, and everything else is the function's own arguments. The state that
+/// isn't taken by type is added to the end of the parameters, so it can be used by name.
+fn match_params(
+    handler: &str,
+    func: &mut ItemFn,
+    states: &[ItemStatic],
+    state_params: &[syn::FnArg],
+) -> syn::Result<Vec<Param>> {
+    let type_string = |ty: &syn::Type| quote! { #ty }.to_string();
+    let mut params = Vec::new();
+    let mut taken_types = HashSet::new();
+    for input in &func.sig.inputs {
+        let syn::FnArg::Typed(arg) = input else {
+            return Err(syn::Error::new(
+                input.span(),
+                format!("The {handler} function can't take self"),
+            ));
+        };
+        let (ty, mutable) = match &*arg.ty {
+            // The event function is given the event by reference
+            syn::Type::Reference(r) if handler == "event" && is_event(&r.elem) => (None, false),
+            syn::Type::Reference(r) => (Some(&*r.elem), r.mutability.is_some()),
+            _ => (None, false),
+        };
+        let Some(ty) = ty else {
+            let own_args = params.iter().filter(|p| matches!(p, Param::Own(_))).count();
+            if own_args == own_arg_count(handler) {
+                return Err(syn::Error::new(
+                    arg.ty.span(),
+                    format!(
+                        "The {handler} function only gets {} argument(s) besides references to \
+                         state and resources",
+                        own_arg_count(handler),
+                    ),
+                ));
+            }
+            params.push(Param::Own(own_args));
+            continue;
+        };
+        if !taken_types.insert(type_string(ty)) {
+            return Err(syn::Error::new(
+                arg.ty.span(),
+                format!("The {handler} function already takes this type"),
+            ));
+        }
+        let mut matching = states
+            .iter()
+            .enumerate()
+            .filter(|(_, st)| type_string(&st.ty) == type_string(ty))
+            .map(|(i, _)| i);
+        match (matching.next(), matching.next()) {
+            (None, _) if mutable && is_read_only_resource(ty) => {
+                return Err(syn::Error::new(
+                    arg.ty.span(),
+                    "Changes to this resource would be lost, so it has to be taken by `&` instead \
+                     of `&mut`",
+                ))
+            }
+            (None, _) => params.push(Param::Resource(Box::new(ty.clone()))),
+            (Some(i), None) => params.push(Param::State(i)),
+            (Some(_), Some(_)) => {
+                return Err(syn::Error::new(
+                    arg.ty.span(),
+                    "More than one #[care::state] has this type, so they have to be used by name",
+                ))
+            }
+        }
+    }
+
+    let mut by_name = HashSet::new();
+    for (i, (st, param)) in states.iter().zip(state_params).enumerate() {
+        if !params
+            .iter()
+            .any(|p| matches!(p, Param::State(j) if *j == i))
+        {
+            func.sig.inputs.push(param.clone());
+            params.push(Param::State(i));
+            by_name.insert(st.ident.to_string());
+        }
+    }
+    for stmt in &mut func.block.stmts {
+        dereference_state_vars_stmt(stmt, &by_name);
+    }
+    Ok(params)
+}
+
+/// The care attributes only mark items for `care::main!`, which removes them, so reaching one
+/// means it's somewhere `care::main!` can't see it
+fn outside_main(item: proc_macro::TokenStream, name: &str, on: &str) -> proc_macro::TokenStream {
+    token_stream_with_error(
+        TokenStream::from(item),
+        syn::Error::new(
+            Span::call_site(),
+            format!("#[care::{name}] only works on {on} directly inside `care::main! {{ .. }}`"),
+        ),
+    )
 }
 
 #[proc_macro_attribute]
@@ -167,39 +367,15 @@ pub fn care_state(
     _attr: proc_macro::TokenStream,
     def: proc_macro::TokenStream,
 ) -> proc_macro::TokenStream {
-    let def = TokenStream::from(def);
-    let item: ItemStatic = match syn::parse2::<ItemStatic>(def.clone()) {
-        Ok(i) => i,
-        Err(e) => return token_stream_with_error(def, e),
-    };
-    let ident = item.ident.clone();
-    let ident_state = Ident::new(&(item.ident.to_string() + "_state"), item.ident.span());
-    let ty = item.ty;
-    let expr = item.expr;
-    std::env::set_var(
-        "_CARE_INTERNAL_STATE_DEFS",
-        std::env::var("_CARE_INTERNAL_STATE_DEFS")
-            .ok()
-            .unwrap_or_default()
-            + &quote! { let mut #ident_state: #ty = #expr; }.to_string(),
-    );
-    std::env::set_var(
-        "_CARE_INTERNAL_STATE_PARAMS",
-        std::env::var("_CARE_INTERNAL_STATE_PARAMS")
-            .ok()
-            .unwrap_or_default()
-            + STATE_VAR_SEPARATOR
-            + &quote! { #ident: &mut #ty }.to_string(),
-    );
-    std::env::set_var(
-        "_CARE_INTERNAL_STATE_ITEMS",
-        std::env::var("_CARE_INTERNAL_STATE_ITEMS")
-            .ok()
-            .unwrap_or_default()
-            + STATE_VAR_SEPARATOR
-            + &quote! { #ident_state }.to_string(),
-    );
-    proc_macro::TokenStream::new()
+    outside_main(def, "state", "statics")
+}
+
+#[proc_macro_attribute]
+pub fn care_conf(
+    _attr: proc_macro::TokenStream,
+    func: proc_macro::TokenStream,
+) -> proc_macro::TokenStream {
+    outside_main(func, "conf", "functions")
 }
 
 #[proc_macro_attribute]
@@ -207,7 +383,7 @@ pub fn care_init(
     _attr: proc_macro::TokenStream,
     func: proc_macro::TokenStream,
 ) -> proc_macro::TokenStream {
-    care_macro_shared(func, "INIT")
+    outside_main(func, "init", "functions")
 }
 
 #[proc_macro_attribute]
@@ -215,7 +391,39 @@ pub fn care_update(
     _attr: proc_macro::TokenStream,
     func: proc_macro::TokenStream,
 ) -> proc_macro::TokenStream {
-    care_macro_shared(func, "UPDATE")
+    outside_main(func, "update", "functions")
+}
+
+#[proc_macro_attribute]
+pub fn care_fixed_update(
+    _attr: proc_macro::TokenStream,
+    func: proc_macro::TokenStream,
+) -> proc_macro::TokenStream {
+    outside_main(func, "fixed_update", "functions")
+}
+
+#[proc_macro_attribute]
+pub fn care_event(
+    _attr: proc_macro::TokenStream,
+    func: proc_macro::TokenStream,
+) -> proc_macro::TokenStream {
+    outside_main(func, "event", "functions")
+}
+
+#[proc_macro_attribute]
+pub fn care_resize(
+    _attr: proc_macro::TokenStream,
+    func: proc_macro::TokenStream,
+) -> proc_macro::TokenStream {
+    outside_main(func, "resize", "functions")
+}
+
+#[proc_macro_attribute]
+pub fn care_focus(
+    _attr: proc_macro::TokenStream,
+    func: proc_macro::TokenStream,
+) -> proc_macro::TokenStream {
+    outside_main(func, "focus", "functions")
 }
 
 #[proc_macro_attribute]
@@ -223,98 +431,502 @@ pub fn care_draw(
     _attr: proc_macro::TokenStream,
     func: proc_macro::TokenStream,
 ) -> proc_macro::TokenStream {
-    care_macro_shared(func, "DRAW")
+    outside_main(func, "draw", "functions")
 }
 
 #[proc_macro_attribute]
-pub fn care_async_main(
+pub fn care_quit(
     _attr: proc_macro::TokenStream,
     func: proc_macro::TokenStream,
 ) -> proc_macro::TokenStream {
-    care_macro_shared(func, "ASYNC_MAIN")
+    outside_main(func, "quit", "functions")
 }
 
-#[proc_macro]
-pub fn care_main(attr: proc_macro::TokenStream) -> proc_macro::TokenStream {
-    // TODO: Config
-    let attr = TokenStream::from(attr);
+#[proc_macro_attribute]
+pub fn care_async_main(
+    _attr: proc_macro::TokenStream,
+    func: proc_macro::TokenStream,
+) -> proc_macro::TokenStream {
+    outside_main(func, "async_main", "functions")
+}
 
-    let conf: Expr = match syn::parse2(attr.clone()) {
+#[proc_macro_attribute]
+pub fn care_rpc(
+    attr: proc_macro::TokenStream,
+    func: proc_macro::TokenStream,
+) -> proc_macro::TokenStream {
+    let func = TokenStream::from(func);
+    let item: ItemFn = match syn::parse2::<ItemFn>(func.clone()) {
         Ok(i) => i,
-        Err(e) => return token_stream_with_error(attr, e),
+        Err(e) => return token_stream_with_error(func, e),
+    };
+    let reliability = match attr.to_string().as_str() {
+        "" => quote! { ::care_multiplayer::Reliability::Reliable },
+        "unreliable" => quote! { ::care_multiplayer::Reliability::Unreliable },
+        _ => {
+            return token_stream_with_error(
+                func,
+                syn::Error::new(
+                    TokenStream::from(attr).span(),
+                    "Expected nothing or `unreliable`",
+                ),
+            )
+        }
     };
+    if let Some(asyncness) = item.sig.asyncness {
+        return token_stream_with_error(
+            func,
+            syn::Error::new(asyncness.span(), "RPCs cannot be async"),
+        );
+    }
+
+    // The first parameter can be an `&RpcContext`, every other one is sent over the network
+    let mut inputs = item.sig.inputs.iter().peekable();
+    let takes_context = match inputs.peek() {
+        Some(syn::FnArg::Typed(arg)) => matches!(
+            &*arg.ty,
+            syn::Type::Reference(r) if matches!(
+                &*r.elem,
+                syn::Type::Path(p) if p.path.segments.last().is_some_and(|s| s.ident == "RpcContext")
+            )
+        ),
+        Some(syn::FnArg::Receiver(receiver)) => {
+            return token_stream_with_error(
+                func,
+                syn::Error::new(receiver.span(), "RPCs cannot take self"),
+            )
+        }
+        None => false,
+    };
+    if takes_context {
+        inputs.next();
+    }
+    let arg_types: Vec<_> = inputs
+        .map(|arg| match arg {
+            syn::FnArg::Typed(arg) => arg.ty.clone(),
+            syn::FnArg::Receiver(_) => unreachable!(),
+        })
+        .collect();
+    let arg_names: Vec<_> = (0..arg_types.len())
+        .map(|i| Ident::new(&format!("arg{i}"), Span::call_site()))
+        .collect();
+    let context_arg = takes_context.then(|| quote! { ctx, });
+    let output = match &item.sig.output {
+        syn::ReturnType::Default => quote! { () },
+        syn::ReturnType::Type(_, ty) => quote! { #ty },
+    };
+
+    let vis = &item.vis;
+    let name = &item.sig.ident;
+    quote! {
+        #item
+
+        #[allow(non_camel_case_types)]
+        #vis struct #name {}
+
+        impl ::care_multiplayer::rpc::Rpc for #name {
+            type Args = (#(#arg_types,)*);
+            type Output = #output;
+            const NAME: &'static str = stringify!(#name);
+            const RELIABILITY: ::care_multiplayer::Reliability = #reliability;
 
-    let init_fn = std::env::var("_CARE_INTERNAL_INIT").ok();
-    let update_fn = std::env::var("_CARE_INTERNAL_UPDATE").ok();
-    let draw_fn = std::env::var("_CARE_INTERNAL_DRAW").ok();
-    let async_main_fn = std::env::var("_CARE_INTERNAL_ASYNC_MAIN").ok();
+            #[allow(unused_variables)]
+            fn call(ctx: &::care_multiplayer::rpc::RpcContext, (#(#arg_names,)*): Self::Args) -> Self::Output {
+                #name(#context_arg #(#arg_names),*)
+            }
+        }
+    }
+    .into()
+}
+
+#[proc_macro]
+pub fn care_main(items: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let items = TokenStream::from(items);
+    // Errors still come with a main function, so it isn't reported missing too
+    let empty_main = quote! { fn main() {} };
+    let game: Game = match syn::parse2(items.clone()) {
+        Ok(game) => game,
+        Err(_) if syn::parse2::<Expr>(items).is_ok() => {
+            return token_stream_with_error(
+                empty_main,
+                syn::Error::new(
+                    Span::call_site(),
+                    "care::main! takes the game's items, \
+                     the config is set with a #[care::conf] function",
+                ),
+            );
+        }
+        Err(e) => return token_stream_with_error(empty_main, e),
+    };
 
-    let state_lets: TokenStream = std::env::var("_CARE_INTERNAL_STATE_DEFS")
-        .ok()
-        .map(|st| st.parse().unwrap())
-        .unwrap_or_default();
+    // Android loads the game as a library and calls this instead of main
+    let android_main = quote! {
+        #[cfg(target_os = "android")]
+        #[no_mangle]
+        fn android_main(app: ::care::mobile::AndroidApp) {
+            ::care::mobile::set_android_app(app);
+            main();
+        }
+    };
 
-    let additional_params: TokenStream = std::env::var("_CARE_INTERNAL_STATE_ITEMS")
-        .ok()
+    let state_vars: HashSet<_> = game.states.iter().map(|st| st.ident.to_string()).collect();
+    let state_params: Vec<syn::FnArg> = game
+        .states
+        .iter()
         .map(|st| {
-            st.trim_start_matches(STATE_VAR_SEPARATOR)
-                .replace(STATE_VAR_SEPARATOR, ",")
-                .parse()
-                .unwrap()
+            let (ident, ty) = (&st.ident, &st.ty);
+            // Not every function uses every bit of state
+            syn::parse_quote! { #[allow(unused_variables)] #ident: &mut #ty }
         })
-        .unwrap_or_default();
+        .collect();
+    let state_items: Vec<_> = game
+        .states
+        .iter()
+        .map(|st| Ident::new(&format!("{}_state", st.ident), st.ident.span()))
+        .collect();
+    let state_lets = game.states.iter().zip(&state_items).map(|(st, item)| {
+        let (ty, expr) = (&st.ty, &st.expr);
+        quote! { let mut #item: #ty = #expr; }
+    });
+    let state_lets = quote! { #(#state_lets)* };
+    let state_names = game.states.iter().map(|st| &st.ident);
+    let inspected_state = quote! { #(#state_names: #state_items),* };
+    let state_fields = quote! { #(#state_items),* };
 
-    if (init_fn.is_some() || update_fn.is_some() || draw_fn.is_some()) && async_main_fn.is_some() {
-        panic!("You cannot define a #[care::async] function along with any other #[care::init], #[care::update] or #[care::draw] function.");
+    let mut functions = Vec::new();
+    let mut handlers = BTreeMap::new();
+    let mut errors = Vec::new();
+    for (handler, mut func) in game.handlers {
+        let params = match handler.as_str() {
+            // The config is read before the state exists
+            "conf" => Vec::new(),
+            // The async main function owns the whole game, so it just gets all of the state
+            "async_main" => {
+                func.sig.inputs.extend(state_params.iter().cloned());
+                for stmt in &mut func.block.stmts {
+                    dereference_state_vars_stmt(stmt, &state_vars);
+                }
+                Vec::new()
+            }
+            _ => match match_params(&handler, &mut func, &game.states, &state_params) {
+                Ok(params) => params,
+                Err(e) => {
+                    errors.push(e);
+                    Vec::new()
+                }
+            },
+        };
+        // State is passed as `&mut` its own type, even when that's a String or a Vec
+        func.attrs
+            .push(syn::parse_quote! { #[allow(clippy::ptr_arg)] });
+        func.sig.ident = Ident::new(&format!("care_{}", func.sig.ident), func.sig.ident.span());
+        handlers.insert(
+            handler,
+            Handler {
+                ident: func.sig.ident.clone(),
+                params,
+            },
+        );
+        functions.push(func);
     }
-    if let Some(async_main_fn) = async_main_fn {
-        let fn_ident = Ident::new(&async_main_fn, Span::call_site());
+    if let Some(errors) = errors.into_iter().reduce(|mut all, e| {
+        all.combine(e);
+        all
+    }) {
+        return token_stream_with_error(empty_main, errors);
+    }
+    let items = game.items;
+    let items = quote! {
+        #(#items)*
+        #(#functions)*
+    };
+
+    let conf = match handlers.get("conf") {
+        Some(Handler { ident: conf_fn, .. }) => quote! { #conf_fn() },
+        None => quote! { ::care::Conf::default() },
+    };
+    if let Some(Handler {
+        ident: async_main_fn,
+        ..
+    }) = handlers.get("async_main")
+    {
+        if [
+            "init",
+            "update",
+            "fixed_update",
+            "event",
+            "resize",
+            "focus",
+            "draw",
+            "quit",
+        ]
+        .iter()
+        .any(|h| handlers.contains_key(*h))
+        {
+            return token_stream_with_error(
+                empty_main,
+                syn::Error::new(
+                    async_main_fn.span(),
+                    "A #[care::async_main] function can only be used on its own, \
+                     without any other care functions",
+                ),
+            );
+        }
         return quote! {
+            #items
+
             fn main() {
-                let config = { #conf };
-                ::care::window::open(env!("CARGO_CRATE_NAME"));
-                #state_lets
-                ::care::event::main_async(#fn_ident(#additional_params));
+                ::care::config::set(#conf);
+                ::care::window::open_with_settings(::care::window::WindowSettings::from_conf(
+                    ::care::config::get(),
+                    env!("CARGO_CRATE_NAME"),
+                ));
+                ::care::event::main_async(async move {
+                    #state_lets
+                    #async_main_fn(#(&mut #state_items),*).await
+                });
             }
+
+            #android_main
         }
         .into();
     }
 
-    let init_call = maybe_call_function(init_fn, quote! {app_args, #additional_params});
-    let update_call = maybe_call_function(update_fn, quote! {delta_time, #additional_params});
-    let draw_call = maybe_call_function(draw_fn, quote! {#additional_params});
+    // The state is still owned by init, the other functions get it borrowed from the game
+    let owned_state: Vec<_> = state_items
+        .iter()
+        .map(|item| quote! { &mut #item })
+        .collect();
+    let borrowed_state: Vec<_> = state_items
+        .iter()
+        .map(|item| quote! { &mut *#item })
+        .collect();
+    let init_call = maybe_call_function(
+        &handlers,
+        "init",
+        &[quote! { ::std::env::args().collect() }],
+        &owned_state,
+    );
+    let update_call = handlers.get("update").map(|update_fn| {
+        let call = call_function("update", update_fn, &[quote! { delta }], &borrowed_state);
+        quote! {
+            #[allow(unused_variables)]
+            fn update(&mut self, delta: ::care::math::Fl) {
+                let Self { #state_fields } = self;
+                #call
+            }
+        }
+    });
+    let fixed_update_call = handlers.get("fixed_update").map(|fixed_update_fn| {
+        let call = call_function(
+            "fixed_update",
+            fixed_update_fn,
+            &[quote! { step }],
+            &borrowed_state,
+        );
+        quote! {
+            #[allow(unused_variables)]
+            fn fixed_update(&mut self, step: ::care::math::Fl) {
+                let Self { #state_fields } = self;
+                #call
+            }
+        }
+    });
+    let event_call = maybe_call_function(&handlers, "event", &[quote! { event }], &borrowed_state);
+    let resize_call = handlers.get("resize").map(|resize_fn| {
+        let call = call_function("resize", resize_fn, &[quote! { *size }], &borrowed_state);
+        quote! {
+            if let ::care::event::EventData::WindowResized { size } = &event.data {
+                #call
+            }
+        }
+    });
+    let focus_call = handlers.get("focus").map(|focus_fn| {
+        let call = call_function("focus", focus_fn, &[quote! { *focused }], &borrowed_state);
+        quote! {
+            if let ::care::event::EventData::FocusChange { focused } = &event.data {
+                #call
+            }
+        }
+    });
+    let event_fn = ["event", "resize", "focus"]
+        .iter()
+        .any(|h| handlers.contains_key(*h))
+        .then(|| {
+            quote! {
+                #[allow(unused_variables)]
+                fn event(&mut self, event: &::care::event::Event) {
+                    let Self { #state_fields } = self;
+                    #event_call
+                    #resize_call
+                    #focus_call
+                }
+            }
+        });
+    let draw_call = maybe_call_function(&handlers, "draw", &[], &borrowed_state);
+    let quit_fn = handlers.get("quit").map(|quit_fn| {
+        let call = call_function("quit", quit_fn, &[], &borrowed_state);
+        quote! {
+            #[allow(unused_variables)]
+            fn quit(&mut self) {
+                let Self { #state_fields } = self;
+                #call
+            }
+        }
+    });
+    let state_types = game.states.iter().map(|st| &st.ty);
 
     let result = quote! {
-        fn main() {
-            let config = { #conf };
-            ::care::window::open(env!("CARGO_CRATE_NAME"));
-            ::care::event::main_loop(move || {
+        #items
+
+        /// The game, made from the functions and state inside `care::main!`
+        struct __CareGame {
+            #(#state_items: #state_types,)*
+        }
+
+        impl ::care::App for __CareGame {
+            fn init() -> Self {
                 #state_lets
-                let app_args: Vec<_> = ::std::env::args().collect();
                 #init_call
-                (::std::time::Instant::now(), (#additional_params))
-            }, move |(last_time, (#additional_params))| {
-                let next_time = ::std::time::Instant::now();
-                let delta_time = next_time.duration_since(*last_time).as_secs_f64() as ::care::math::Fl;
-                *last_time = next_time;
-                #update_call
+                Self { #state_fields }
+            }
+
+            #update_call
+            #fixed_update_call
+            #event_fn
+
+            #[allow(unused_variables)]
+            fn draw(&mut self) {
+                let Self { #state_fields } = self;
                 #draw_call
-            });
+                ::care::__inspect_state!(#inspected_state);
+            }
+
+            #quit_fn
         }
+
+        fn main() {
+            let mut conf = #conf;
+            if conf.title.is_none() {
+                conf.title = Some(env!("CARGO_CRATE_NAME").to_string());
+            }
+            ::care::run::<__CareGame>(conf);
+        }
+
+        #android_main
     };
 
     result.into()
 }
 
-fn maybe_call_function(fn_name: Option<String>, params: TokenStream) -> TokenStream {
-    if let Some(fn_name) = fn_name {
-        let fn_ident = Ident::new(&fn_name, Span::call_site());
-        quote! {
-            #fn_ident(#params);
+#[proc_macro_derive(Inspect, attributes(inspect))]
+pub fn derive_inspect(item: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let item = TokenStream::from(item);
+    let mut input: syn::DeriveInput = match syn::parse2(item.clone()) {
+        Ok(i) => i,
+        Err(e) => return token_stream_with_error(item, e),
+    };
+    let syn::Data::Struct(data) = &input.data else {
+        return syn::Error::new(
+            input.ident.span(),
+            "Inspect can only be derived for structs",
+        )
+        .into_compile_error()
+        .into();
+    };
+
+    let mut fields = Vec::new();
+    for (i, field) in data.fields.iter().enumerate() {
+        let mut skip = false;
+        for attr in field.attrs.iter().filter(|a| a.path().is_ident("inspect")) {
+            let result = attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("skip") {
+                    skip = true;
+                    Ok(())
+                } else {
+                    Err(meta.error("Expected `skip`"))
+                }
+            });
+            if let Err(e) = result {
+                return e.into_compile_error().into();
+            }
         }
-    } else {
-        quote! {}
+        if skip {
+            continue;
+        }
+        let (member, label) = match &field.ident {
+            Some(ident) => (quote! { #ident }, ident.to_string()),
+            None => {
+                let index = syn::Index::from(i);
+                (quote! { #index }, i.to_string())
+            }
+        };
+        fields.push(quote! {
+            ::care::gui::Inspect::inspect(&mut self.#member, ui, #label);
+        });
     }
+
+    for param in input.generics.type_params_mut() {
+        param.bounds.push(syn::parse_quote!(::care::gui::Inspect));
+    }
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    quote! {
+        impl #impl_generics ::care::gui::Inspect for #name #ty_generics #where_clause {
+            #[allow(unused_variables)]
+            fn inspect(&mut self, ui: &mut ::care::gui::Ui, label: &str) {
+                ::care::gui::CollapsingHeader::new(label).show(ui, |ui| {
+                    #(#fields)*
+                });
+            }
+        }
+    }
+    .into()
+}
+
+/// Call a game function if it exists, reporting the error if it returns one
+fn maybe_call_function(
+    handlers: &BTreeMap<String, Handler>,
+    handler: &str,
+    own_args: &[TokenStream],
+    state: &[TokenStream],
+) -> TokenStream {
+    handlers
+        .get(handler)
+        .map(|func| call_function(handler, func, own_args, state))
+        .unwrap_or_default()
+}
+
+/// Call a game function, taking out the resources it uses for the call and reporting the error if
+/// it returns one
+fn call_function(
+    handler: &str,
+    func: &Handler,
+    own_args: &[TokenStream],
+    state: &[TokenStream],
+) -> TokenStream {
+    let mut resources = Vec::new();
+    let args: Vec<_> = func
+        .params
+        .iter()
+        .map(|param| match param {
+            Param::Own(i) => own_args[*i].clone(),
+            Param::State(i) => state[*i].clone(),
+            Param::Resource(ty) => {
+                let resource =
+                    Ident::new(&format!("resource_{}", resources.len()), Span::call_site());
+                resources.push((resource.clone(), ty));
+                quote! { &mut *#resource }
+            }
+        })
+        .collect();
+    let (resources, types): (Vec<_>, Vec<_>) = resources.into_iter().unzip();
+    let ident = &func.ident;
+    quote! {{
+        #(let mut #resources = ::care::resource::__take::<#types>(#handler);)*
+        ::care::error::__handle(#handler, #ident(#(#args),*));
+        #(::care::resource::__put_back(#resources);)*
+    }}
 }
 
 // From tokio (https://github.com/tokio-rs/tokio/blob/tokio-1.35.1/tokio-macros/src/entry.rs#L416)
@@ -0,0 +1,339 @@
+//! Running commands on a server, from its own terminal or from an admin client
+//!
+//! Commands are lines of text split on whitespace, the first word picks the
+//! command and the rest are its arguments. The built in commands are:
+//!
+//! - `help`, to list every command
+//! - `list`, to list the connected clients
+//! - `kick <client>`, to disconnect a client
+//! - `ban <client>` and `unban <ip>`, to stop a client's address from connecting
+//! - `stats [client]`, to show connection statistics
+//! - `save <name>`, to save the world with [crate::server::Server::save_world]
+//!   to a file in [crate::server::ServerConfig::save_dir]
+//!
+//! Games can add their own with [Server::register_command], which also
+//! replaces a built in command with the same name.
+//!
+//! Clients can run commands after logging in with the password from
+//! [crate::server::ServerConfig::admin_password], using
+//! [crate::client::Client::admin_login] and
+//! [crate::client::Client::admin_command]. The output arrives as
+//! [crate::client::ClientEvent::AdminOutput]. The password is sent as is, so
+//! only use remote commands over an encrypted transport, or on a network you
+//! trust.
+//!
+//! A dedicated server can read commands from its terminal with a [Console]:
+//!
+//! ```ignore
+//! let console = Console::stdin();
+//! loop {
+//!     server.update(|server, tick| { /* ... */ })?;
+//!     console.poll(&mut server);
+//!     std::thread::sleep(server.tick_interval() / 2);
+//! }
+//! ```
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt::Write,
+    io::BufRead,
+    net::IpAddr,
+    path::{Component, Path},
+    sync::mpsc::{self, Receiver},
+    time::{Duration, Instant},
+};
+
+use subtle::ConstantTimeEq;
+
+use crate::{
+    packet::PacketWriter,
+    server::{ClientId, Server},
+};
+
+pub(crate) const ADMIN_LOGIN: u8 = 0;
+pub(crate) const ADMIN_COMMAND: u8 = 1;
+pub(crate) const ADMIN_OUTPUT: u8 = 2;
+
+/// Clients are disconnected after this many wrong passwords from their address
+const MAX_LOGIN_ATTEMPTS: u32 = 3;
+/// How long an address can't log in for after too many wrong passwords, which
+/// is also how long wrong passwords are remembered for
+const LOGIN_LOCKOUT: Duration = Duration::from_secs(5 * 60);
+
+/// Runs a command with its arguments, returning the output
+pub(crate) type CommandHandler = Box<dyn FnMut(&mut Server, &[&str]) -> String + Send>;
+
+pub(crate) fn encode(kind: u8, text: &str) -> Vec<u8> {
+    let mut message = PacketWriter::new(kind);
+    message.bytes(text.as_bytes());
+    message.finish()
+}
+
+/// Check a password in a time that doesn't depend on how much of it is right, so it can't be
+/// guessed a character at a time by timing the replies
+pub(crate) fn password_matches(password: &str, guess: &str) -> bool {
+    password.as_bytes().ct_eq(guess.as_bytes()).into()
+}
+
+/// Wrong passwords sent from an address, which are remembered across reconnects
+#[derive(Debug)]
+struct FailedLogins {
+    count: u32,
+    last: Instant,
+}
+
+struct Command {
+    help: String,
+    /// Taken out while the command is running, since it needs the server
+    handler: Option<CommandHandler>,
+}
+
+/// The server's commands, and who is allowed to run them
+pub(crate) struct AdminState {
+    commands: BTreeMap<String, Command>,
+    pub logged_in: HashSet<ClientId>,
+    failed_logins: HashMap<IpAddr, FailedLogins>,
+    pub banned: HashSet<IpAddr>,
+}
+
+impl std::fmt::Debug for AdminState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AdminState")
+            .field("commands", &self.commands.keys())
+            .field("logged_in", &self.logged_in)
+            .field("banned", &self.banned)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Default for AdminState {
+    fn default() -> Self {
+        let mut admin = Self {
+            commands: BTreeMap::new(),
+            logged_in: HashSet::new(),
+            failed_logins: HashMap::new(),
+            banned: HashSet::new(),
+        };
+        admin.register("help", "List every command", help);
+        admin.register("list", "List the connected clients", list);
+        admin.register("kick", "kick <client>: Disconnect a client", kick);
+        admin.register(
+            "ban",
+            "ban <client>: Disconnect a client, and stop its address from connecting again",
+            ban,
+        );
+        admin.register(
+            "unban",
+            "unban <ip>: Let a banned address connect again",
+            unban,
+        );
+        admin.register("stats", "stats [client]: Show connection statistics", stats);
+        admin.register("save", "save <name>: Save the world to a file", save);
+        admin
+    }
+}
+
+impl AdminState {
+    pub fn register(
+        &mut self,
+        name: &str,
+        help: &str,
+        handler: impl FnMut(&mut Server, &[&str]) -> String + Send + 'static,
+    ) {
+        self.commands.insert(
+            name.to_string(),
+            Command {
+                help: help.to_string(),
+                handler: Some(Box::new(handler)),
+            },
+        );
+    }
+
+    pub fn commands(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.commands
+            .iter()
+            .map(|(name, command)| (name.as_str(), command.help.as_str()))
+    }
+
+    pub fn take_handler(&mut self, name: &str) -> Option<CommandHandler> {
+        self.commands.get_mut(name)?.handler.take()
+    }
+
+    /// Give back a handler after running it, unless the command was
+    /// replaced or removed in the meantime
+    pub fn return_handler(&mut self, name: &str, handler: CommandHandler) {
+        if let Some(command) = self.commands.get_mut(name) {
+            command.handler.get_or_insert(handler);
+        }
+    }
+
+    pub fn remove_client(&mut self, client: ClientId) {
+        self.logged_in.remove(&client);
+    }
+
+    /// Whether an address sent too many wrong passwords recently to log in
+    pub fn is_locked_out(&mut self, ip: IpAddr) -> bool {
+        self.forget_failed_logins();
+        self.failed_logins
+            .get(&ip)
+            .is_some_and(|failed| failed.count >= MAX_LOGIN_ATTEMPTS)
+    }
+
+    /// Count a wrong password from an address, returning true if it's now locked out
+    pub fn login_failed(&mut self, ip: IpAddr) -> bool {
+        self.forget_failed_logins();
+        let failed = self.failed_logins.entry(ip).or_insert(FailedLogins {
+            count: 0,
+            last: Instant::now(),
+        });
+        failed.count += 1;
+        failed.last = Instant::now();
+        failed.count >= MAX_LOGIN_ATTEMPTS
+    }
+
+    pub fn login_succeeded(&mut self, ip: IpAddr) {
+        self.failed_logins.remove(&ip);
+    }
+
+    fn forget_failed_logins(&mut self) {
+        self.failed_logins
+            .retain(|_, failed| failed.last.elapsed() < LOGIN_LOCKOUT);
+    }
+}
+
+fn parse_client(args: &[&str]) -> Result<ClientId, String> {
+    args.first()
+        .and_then(|arg| arg.parse().ok())
+        .ok_or_else(|| "Expected a client id".to_string())
+}
+
+fn help(server: &mut Server, _: &[&str]) -> String {
+    let mut output = String::new();
+    for (name, help) in server.commands() {
+        let _ = writeln!(output, "{name}: {help}");
+    }
+    output
+}
+
+fn list(server: &mut Server, _: &[&str]) -> String {
+    let clients: Vec<_> = server.clients().collect();
+    let mut output = format!("{} clients connected\n", clients.len());
+    for client in clients {
+        let addr = server.client_addr(client).unwrap();
+        let ping = server.client_stats(client).unwrap().rtt;
+        let _ = writeln!(output, "{client}: {addr}, ping {}ms", ping.as_millis());
+    }
+    output
+}
+
+fn kick(server: &mut Server, args: &[&str]) -> String {
+    let result = parse_client(args).and_then(|client| {
+        if server.client_addr(client).is_none() {
+            return Err(format!("No client {client}"));
+        }
+        server.disconnect(client).map_err(|e| e.to_string())?;
+        Ok(format!("Kicked client {client}"))
+    });
+    result.unwrap_or_else(|e| e)
+}
+
+fn ban(server: &mut Server, args: &[&str]) -> String {
+    let result = parse_client(args).and_then(|client| {
+        let addr = server
+            .client_addr(client)
+            .ok_or_else(|| format!("No client {client}"))?;
+        server.ban(client).map_err(|e| e.to_string())?;
+        Ok(format!("Banned client {client} ({})", addr.ip()))
+    });
+    result.unwrap_or_else(|e| e)
+}
+
+fn unban(server: &mut Server, args: &[&str]) -> String {
+    match args.first().map(|arg| arg.parse::<IpAddr>()) {
+        Some(Ok(ip)) if server.unban(ip) => format!("Unbanned {ip}"),
+        Some(Ok(ip)) => format!("{ip} isn't banned"),
+        _ => "Expected an IP address".to_string(),
+    }
+}
+
+fn stats(server: &mut Server, args: &[&str]) -> String {
+    let clients: Vec<_> = match args.first() {
+        Some(_) => match parse_client(args) {
+            Ok(client) if server.client_addr(client).is_some() => vec![client],
+            Ok(client) => return format!("No client {client}"),
+            Err(e) => return e,
+        },
+        None => server.clients().collect(),
+    };
+    let mut output = format!("Tick {}, {:.0}s up\n", server.tick(), server.time());
+    for client in clients {
+        let stats = server.client_stats(client).unwrap();
+        let _ = writeln!(
+            output,
+            "{client}: ping {}ms, jitter {}ms, loss {:.1}%, up {}B/s, down {}B/s, dropped {}",
+            stats.rtt.as_millis(),
+            stats.jitter.as_millis(),
+            stats.packet_loss * 100.0,
+            stats.bytes_sent.per_second,
+            stats.bytes_received.per_second,
+            stats.messages_dropped.total,
+        );
+    }
+    output
+}
+
+fn save(server: &mut Server, args: &[&str]) -> String {
+    let Some(dir) = server.save_dir() else {
+        return "Saving is turned off, set ServerConfig::save_dir to turn it on".to_string();
+    };
+    let Some(name) = args.first() else {
+        return "Expected a file name".to_string();
+    };
+    // Only a plain file name, so admins can't write anywhere else
+    let mut components = Path::new(name).components();
+    if !matches!(
+        (components.next(), components.next()),
+        (Some(Component::Normal(_)), None)
+    ) {
+        return "Expected a file name, not a path".to_string();
+    }
+    let path = dir.join(name);
+    match server.save_world(server.version()).write(&path) {
+        Ok(()) => format!("Saved the world to {}", path.display()),
+        Err(e) => format!("Couldn't save the world: {e}"),
+    }
+}
+
+#[derive(Debug)]
+/// Reads commands from the server's terminal, see the [module level documentation](self)
+pub struct Console {
+    lines: Receiver<String>,
+}
+
+impl Console {
+    /// Start reading lines from standard input on a background thread
+    pub fn stdin() -> Self {
+        let (sender, lines) = mpsc::channel();
+        std::thread::spawn(move || {
+            for line in std::io::stdin().lock().lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        Self { lines }
+    }
+
+    /// Run every command that has been typed since the last poll, printing the output
+    pub fn poll(&self, server: &mut Server) {
+        while let Ok(line) = self.lines.try_recv() {
+            let output = server.run_command(&line);
+            if !output.is_empty() {
+                println!("{output}");
+            }
+        }
+    }
+}
@@ -0,0 +1,310 @@
+//! Text chat, split into rooms
+//!
+//! Every client joins the [GLOBAL_ROOM] when it connects, and can join or
+//! leave other rooms with [crate::client::Client::join_room] and
+//! [crate::client::Client::leave_room], unless [ChatConfig::open_rooms] is
+//! turned off, in which case only the server can move clients between rooms
+//! (e.g. for team chat).
+//!
+//! Messages are sent reliably on an internal channel, and arrive as
+//! [crate::client::ClientEvent::Chat] and [crate::server::ServerEvent::Chat].
+//! The server can check and change every message before it's delivered, see
+//! [crate::server::Server::set_chat_filter].
+//!
+//! ```ignore
+//! server.set_chat_filter(|message| {
+//!     (!message.text.contains("spam")).then(|| message.text.replace("heck", "****"))
+//! });
+//!
+//! client.send_chat(GLOBAL_ROOM, "hello everyone")?;
+//! while let Some(event) = client.next_event() {
+//!     if let ClientEvent::Chat(message) = event {
+//!         println!("[{}] {:?}: {}", message.room, message.sender, message.text);
+//!     }
+//! }
+//! ```
+
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::{
+    packet::{PacketReader, PacketWriter},
+    server::ClientId,
+};
+
+/// The room every client is in when it connects
+pub const GLOBAL_ROOM: &str = "global";
+
+pub(crate) const CHAT_JOIN: u8 = 0;
+pub(crate) const CHAT_LEAVE: u8 = 1;
+pub(crate) const CHAT_SEND: u8 = 2;
+pub(crate) const CHAT_MESSAGE: u8 = 3;
+
+#[derive(Debug, Clone, PartialEq)]
+/// A chat message
+pub struct ChatMessage {
+    /// The room the message was sent to
+    pub room: String,
+    /// The client that sent the message, or `None` if it came from the server
+    pub sender: Option<ClientId>,
+    /// The text of the message
+    pub text: String,
+    /// The server time the message was sent at, see [crate::server::Server::time]
+    pub time: f64,
+}
+
+#[derive(Debug, Clone)]
+/// Configuration for chat on a server
+pub struct ChatConfig {
+    /// How many messages to keep for each room, new members are sent these
+    /// when they join
+    pub history: usize,
+    /// Longer messages are cut off at this many characters
+    pub max_length: usize,
+    /// Whether clients can join and leave rooms themselves
+    pub open_rooms: bool,
+    /// Clients can't open rooms with longer names than this many characters
+    pub max_room_name_length: usize,
+    /// Clients can't open new rooms while this many have members
+    pub max_rooms: usize,
+}
+
+impl Default for ChatConfig {
+    fn default() -> Self {
+        Self {
+            history: 50,
+            max_length: 500,
+            open_rooms: true,
+            max_room_name_length: 32,
+            max_rooms: 100,
+        }
+    }
+}
+
+/// Decides whether a message is delivered, and what it says
+pub(crate) type ChatFilter = Box<dyn FnMut(&ChatMessage) -> Option<String> + Send>;
+
+/// A request from a client, or a room change from the server
+pub(crate) fn encode_room(kind: u8, room: &str) -> Vec<u8> {
+    let mut message = PacketWriter::new(kind);
+    message.bytes(room.as_bytes());
+    message.finish()
+}
+
+pub(crate) fn encode_send(room: &str, text: &str) -> Vec<u8> {
+    let mut message = PacketWriter::new(CHAT_SEND);
+    message.bytes(room.as_bytes()).bytes(text.as_bytes());
+    message.finish()
+}
+
+pub(crate) fn encode_message(message: &ChatMessage) -> Vec<u8> {
+    let mut data = PacketWriter::new(CHAT_MESSAGE);
+    data.bytes(message.room.as_bytes())
+        // Client ids start at 1, so 0 is used for the server
+        .u64(message.sender.unwrap_or(0))
+        .f64(message.time)
+        .bytes(message.text.as_bytes());
+    data.finish()
+}
+
+/// Read a string written with [PacketWriter::bytes]
+pub(crate) fn read_string(reader: &mut PacketReader) -> Option<String> {
+    reader
+        .bytes()
+        .map(|bytes| String::from_utf8_lossy(bytes).into_owned())
+}
+
+pub(crate) fn decode_message(mut reader: PacketReader) -> Option<ChatMessage> {
+    let room = read_string(&mut reader)?;
+    let sender = reader.u64()?;
+    let time = reader.f64()?;
+    let text = read_string(&mut reader)?;
+    Some(ChatMessage {
+        room,
+        sender: (sender != 0).then_some(sender),
+        text,
+        time,
+    })
+}
+
+#[derive(Debug, Default)]
+/// The recent messages in each room
+pub(crate) struct ChatHistory {
+    limit: usize,
+    rooms: HashMap<String, VecDeque<ChatMessage>>,
+}
+
+impl ChatHistory {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            rooms: HashMap::new(),
+        }
+    }
+
+    pub fn push(&mut self, message: ChatMessage) {
+        let history = self.rooms.entry(message.room.clone()).or_default();
+        history.push_back(message);
+        while history.len() > self.limit {
+            history.pop_front();
+        }
+    }
+
+    pub fn get(&self, room: &str) -> impl Iterator<Item = &ChatMessage> {
+        self.rooms.get(room).into_iter().flatten()
+    }
+
+    pub fn remove(&mut self, room: &str) {
+        self.rooms.remove(room);
+    }
+
+    pub fn clear(&mut self) {
+        self.rooms.clear();
+    }
+}
+
+/// The server's record of who is in each room
+pub(crate) struct ChatRooms {
+    max_length: usize,
+    max_room_name_length: usize,
+    max_rooms: usize,
+    pub history: ChatHistory,
+    pub filter: Option<ChatFilter>,
+    members: HashMap<String, HashSet<ClientId>>,
+}
+
+impl std::fmt::Debug for ChatRooms {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ChatRooms")
+            .field("members", &self.members)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ChatRooms {
+    pub fn new(config: &ChatConfig) -> Self {
+        Self {
+            max_length: config.max_length,
+            max_room_name_length: config.max_room_name_length,
+            max_rooms: config.max_rooms,
+            history: ChatHistory::new(config.history),
+            filter: None,
+            members: HashMap::new(),
+        }
+    }
+
+    /// Add a client to a room, returning false if it was already in it
+    pub fn join(&mut self, client: ClientId, room: &str) -> bool {
+        self.members
+            .entry(room.to_string())
+            .or_default()
+            .insert(client)
+    }
+
+    /// Remove a client from a room, returning false if it wasn't in it
+    pub fn leave(&mut self, client: ClientId, room: &str) -> bool {
+        let Some(members) = self.members.get_mut(room) else {
+            return false;
+        };
+        let removed = members.remove(&client);
+        if members.is_empty() {
+            self.members.remove(room);
+            self.history.remove(room);
+        }
+        removed
+    }
+
+    /// Remove a client from every room, when it disconnects
+    pub fn remove_client(&mut self, client: ClientId) {
+        let history = &mut self.history;
+        self.members.retain(|room, members| {
+            members.remove(&client);
+            if members.is_empty() {
+                history.remove(room);
+            }
+            !members.is_empty()
+        });
+    }
+
+    /// Whether a client is allowed to join a room, which it can't open if the name is too long,
+    /// or there are too many rooms already
+    pub fn can_join(&self, room: &str) -> bool {
+        self.members.contains_key(room)
+            || (room.chars().count() <= self.max_room_name_length
+                && self.members.len() < self.max_rooms)
+    }
+
+    /// Keep a delivered message in its room's history, unless the room is empty
+    pub fn record(&mut self, message: ChatMessage) {
+        if self.members.contains_key(&message.room) {
+            self.history.push(message);
+        }
+    }
+
+    pub fn members(&self, room: &str) -> impl Iterator<Item = ClientId> + '_ {
+        self.members.get(room).into_iter().flatten().copied()
+    }
+
+    pub fn is_member(&self, client: ClientId, room: &str) -> bool {
+        self.members
+            .get(room)
+            .is_some_and(|members| members.contains(&client))
+    }
+
+    /// Cut a message down to the maximum length
+    pub fn truncate(&self, mut text: String) -> String {
+        if let Some((index, _)) = text.char_indices().nth(self.max_length) {
+            text.truncate(index);
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(room: &str) -> ChatMessage {
+        ChatMessage {
+            room: room.to_string(),
+            sender: None,
+            text: "hi".to_string(),
+            time: 0.0,
+        }
+    }
+
+    #[test]
+    fn clients_cant_open_too_many_rooms() {
+        let mut rooms = ChatRooms::new(&ChatConfig {
+            max_rooms: 2,
+            max_room_name_length: 4,
+            ..Default::default()
+        });
+        assert!(!rooms.can_join("kitchen"));
+        assert!(rooms.can_join("hall"));
+        rooms.join(1, "a");
+        rooms.join(1, "b");
+        assert!(!rooms.can_join("c"));
+        // Rooms that are already open can always be joined
+        assert!(rooms.can_join("b"));
+        rooms.leave(1, "b");
+        assert!(rooms.can_join("c"));
+    }
+
+    #[test]
+    fn empty_rooms_forget_their_history() {
+        let mut rooms = ChatRooms::new(&ChatConfig::default());
+        rooms.record(message("nobody"));
+        assert_eq!(rooms.history.get("nobody").count(), 0);
+
+        rooms.join(1, "a");
+        rooms.join(2, "a");
+        rooms.join(2, "b");
+        rooms.record(message("a"));
+        rooms.record(message("b"));
+        rooms.leave(1, "a");
+        assert_eq!(rooms.history.get("a").count(), 1);
+        rooms.remove_client(2);
+        assert_eq!(rooms.history.get("a").count(), 0);
+        assert_eq!(rooms.history.get("b").count(), 0);
+    }
+}
@@ -1 +1,652 @@
+//! Clients, which connect to a [crate::server::Server]
 
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    io,
+    net::{SocketAddr, ToSocketAddrs},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    admin::{self, ADMIN_COMMAND, ADMIN_LOGIN, ADMIN_OUTPUT},
+    chat::{
+        decode_message, encode_room, encode_send, read_string, ChatHistory, ChatMessage,
+        CHAT_JOIN, CHAT_LEAVE, CHAT_MESSAGE,
+    },
+    clock::ClockSync,
+    compression::{Compression, CompressionConfig},
+    connection::{
+        Connection, Reliability, CHANNEL_ADMIN, CHANNEL_CHAT, CHANNEL_OWNERSHIP, CHANNEL_RPC,
+        CHANNEL_SYNC, CHANNEL_SYNC_DELTA, CHANNEL_SYNC_REMOVE, CHANNEL_VOICE, MAX_USER_CHANNEL,
+        PACKET_ACCEPT, PACKET_CONNECT, PACKET_DISCONNECT, PACKET_PING, PACKET_PONG, PACKET_REJECT,
+        PROTOCOL_VERSION,
+    },
+    packet::{PacketReader, PacketWriter},
+    rpc::{Rpc, RpcContext, RpcEndpoint, RpcReply},
+    server::ClientId,
+    stats::NetStats,
+    sync::{
+        decode_sync, encode_sync, SyncManager, SyncQueue, OWNERSHIP_CHANGED, OWNERSHIP_RELEASE,
+        OWNERSHIP_REQUEST,
+    },
+    throttle::BandwidthConfig,
+    transport::{Address, Transport, UdpTransport},
+};
+
+const CONNECT_RETRY_INTERVAL: Duration = Duration::from_millis(250);
+
+#[derive(Debug, Clone)]
+/// Configuration for a [Client]
+pub struct ClientConfig {
+    /// The connection is dropped after not hearing from the server for this long
+    pub timeout: Duration,
+    /// How messages are compressed, the server picks the algorithm so only
+    /// [CompressionConfig::threshold] is used, unless the algorithm is
+    /// [Compression::None] which turns compression off entirely
+    pub compression: CompressionConfig,
+    /// The version of your game's network messages, which must match
+    /// [crate::server::ServerConfig::version]
+    pub version: u32,
+    /// How many chat messages to keep for each room, see [Client::chat_history]
+    pub chat_history: usize,
+    /// Send limits for the connection to the server, see [crate::throttle]
+    pub bandwidth: BandwidthConfig,
+}
+
+impl Default for ClientConfig {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(10),
+            compression: CompressionConfig::default(),
+            version: 0,
+            chat_history: 50,
+            bandwidth: BandwidthConfig::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Why a client is no longer connected
+pub enum DisconnectReason {
+    /// Nothing was heard from the server for too long
+    TimedOut,
+    /// The server refused the connection
+    Rejected(String),
+    /// The server is running a different version of the game, see
+    /// [ClientConfig::version]
+    VersionMismatch {
+        /// The version of this client
+        client: u32,
+        /// The version of the server
+        server: u32,
+    },
+    /// The server closed the connection, or [Client::disconnect] was called
+    Disconnected,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// Something that happened on the client
+pub enum ClientEvent {
+    /// The server accepted the connection
+    Connected(ClientId),
+    /// The connection was closed
+    Disconnected(DisconnectReason),
+    /// The server sent a message
+    Message {
+        /// The channel the message was sent on
+        channel: u8,
+        /// The contents of the message
+        data: Vec<u8>,
+    },
+    /// The server sent a new value for a synced value
+    Sync {
+        /// The id of the synced value
+        id: usize,
+        /// The server tick the value was sent on, see [crate::server::Server::update]
+        tick: u64,
+        /// The new value, or changes to a synced collection, see
+        /// [crate::sync::SyncedValue::receive] and [crate::sync::SyncedMap::receive]
+        data: Vec<u8>,
+    },
+    /// A synced value is no longer being sent to this client, e.g. it went
+    /// out of this client's area of interest
+    SyncRemoved(usize),
+    /// The owner of a synced value changed, see [crate::server::OwnershipPolicy]
+    OwnershipChanged {
+        /// The id of the synced value
+        id: usize,
+        /// The new owner, or `None` if it's now owned by the server
+        owner: Option<ClientId>,
+    },
+    /// A chat message arrived in one of the rooms this client is in, see [crate::chat]
+    Chat(ChatMessage),
+    /// This client joined a chat room
+    RoomJoined(String),
+    /// This client left a chat room
+    RoomLeft(String),
+    /// Another player sent a voice packet, see [crate::voice]
+    Voice {
+        /// The client that is speaking
+        speaker: ClientId,
+        /// The voice packet
+        data: Vec<u8>,
+    },
+    /// The server replied to [Client::admin_login] or [Client::admin_command]
+    AdminOutput(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ClientState {
+    Connecting { last_attempt: Instant },
+    Connected(ClientId),
+    Disconnected,
+}
+
+/// A connection to a game server
+pub struct Client {
+    transport: Box<dyn Transport>,
+    config: ClientConfig,
+    connection: Connection,
+    state: ClientState,
+    events: VecDeque<ClientEvent>,
+    sync_queue: Arc<SyncQueue>,
+    owners: HashMap<usize, ClientId>,
+    server_tick: u64,
+    clock: ClockSync,
+    chat_rooms: HashSet<String>,
+    chat: ChatHistory,
+    rpc: RpcEndpoint,
+}
+
+impl std::fmt::Debug for Client {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Client")
+            .field("config", &self.config)
+            .field("connection", &self.connection)
+            .field("state", &self.state)
+            .field("events", &self.events)
+            .field("rpc", &self.rpc)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Client {
+    /// Connect to a server over UDP
+    ///
+    /// This doesn't wait for the connection to complete, see [ClientEvent::Connected]
+    pub fn connect(addr: impl ToSocketAddrs) -> io::Result<Self> {
+        let server = addr
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "No address given"))?;
+        let local: SocketAddr = if server.is_ipv4() {
+            "0.0.0.0:0".parse().unwrap()
+        } else {
+            "[::]:0".parse().unwrap()
+        };
+        Self::new(UdpTransport::bind(local)?, server, ClientConfig::default())
+    }
+
+    /// Connect to a server on any transport
+    pub fn new(
+        transport: impl Transport + 'static,
+        server: Address,
+        config: ClientConfig,
+    ) -> io::Result<Self> {
+        let mut connection = Connection::new(server);
+        connection.set_bandwidth(config.bandwidth.clone());
+        let mut client = Self {
+            transport: Box::new(transport),
+            chat: ChatHistory::new(config.chat_history),
+            config,
+            connection,
+            state: ClientState::Connecting {
+                last_attempt: Instant::now(),
+            },
+            events: VecDeque::new(),
+            sync_queue: Arc::default(),
+            owners: HashMap::new(),
+            server_tick: 0,
+            clock: ClockSync::default(),
+            chat_rooms: HashSet::new(),
+            rpc: RpcEndpoint::default(),
+        };
+        client.send_connect()?;
+        Ok(client)
+    }
+
+    /// The id the server assigned to this client, once connected
+    pub fn id(&self) -> Option<ClientId> {
+        match self.state {
+            ClientState::Connected(id) => Some(id),
+            _ => None,
+        }
+    }
+
+    /// Whether the client is currently connected to the server
+    pub fn is_connected(&self) -> bool {
+        matches!(self.state, ClientState::Connected(_))
+    }
+
+    /// Whether the connection is closed, and won't be retried
+    pub(crate) fn is_disconnected(&self) -> bool {
+        self.state == ClientState::Disconnected
+    }
+
+    /// Measurements of the connection to the server
+    pub fn stats(&self) -> &NetStats {
+        self.connection.stats()
+    }
+
+    /// Change the send limits for the connection to the server
+    pub fn set_bandwidth(&mut self, config: BandwidthConfig) {
+        self.connection.set_bandwidth(config);
+    }
+
+    /// A sync manager to create [crate::sync::SyncedValue]s that this client sends to the server
+    pub fn sync_manager<C>(&self) -> Arc<dyn SyncManager<C>> {
+        self.sync_queue.clone()
+    }
+
+    /// The round trip time to the server and back
+    pub fn ping(&self) -> Duration {
+        self.connection.stats().rtt
+    }
+
+    /// The estimated current time on the server's clock, in seconds since the server started
+    ///
+    /// This is only accurate once [Client::is_clock_synced], and can be used
+    /// to agree on when things happened, e.g. for hit registration.
+    pub fn server_time(&self) -> f64 {
+        self.clock.server_time()
+    }
+
+    /// Whether the server's clock has been measured yet
+    pub fn is_clock_synced(&self) -> bool {
+        self.clock.is_synced()
+    }
+
+    /// The estimated time a server tick ran at, on the same clock as [Client::server_time]
+    ///
+    /// Snapshots can be given to [crate::interpolation::Interpolator::push_at]
+    /// at this time, and rendered with [crate::interpolation::Interpolator::sample_at]
+    /// at [Client::server_time] minus the delay.
+    pub fn tick_time(&self, tick: u64) -> Option<f64> {
+        self.clock.tick_time(tick)
+    }
+
+    /// The newest server tick a synced value has been received from
+    ///
+    /// This is also sent along with synced values from this client.
+    pub fn server_tick(&self) -> u64 {
+        self.server_tick
+    }
+
+    /// The client that owns a synced value, or `None` if the server owns it
+    pub fn owner(&self, id: usize) -> Option<ClientId> {
+        self.owners.get(&id).copied()
+    }
+
+    /// Receive and send everything that's waiting, call this at least once per frame
+    pub fn poll(&mut self) -> io::Result<()> {
+        if self.state == ClientState::Disconnected {
+            return Ok(());
+        }
+        while let Some((addr, data)) = self.transport.recv_from()? {
+            if addr == self.connection.addr {
+                self.receive_packet(&data)?;
+            }
+        }
+        if self.state == ClientState::Disconnected {
+            return Ok(());
+        }
+        if self.connection.timed_out(self.config.timeout) {
+            self.close(DisconnectReason::TimedOut);
+            return Ok(());
+        }
+        self.rpc.expire();
+
+        match self.state {
+            ClientState::Connecting { last_attempt }
+                if last_attempt.elapsed() >= CONNECT_RETRY_INTERVAL =>
+            {
+                self.send_connect()?;
+            }
+            ClientState::Connected(_) => {
+                for (id, request) in self.sync_queue.take_ownership_requests() {
+                    let kind = if request {
+                        OWNERSHIP_REQUEST
+                    } else {
+                        OWNERSHIP_RELEASE
+                    };
+                    let mut message = PacketWriter::new(kind);
+                    message.u64(id as u64);
+                    self.connection.send(
+                        &*self.transport,
+                        CHANNEL_OWNERSHIP,
+                        message.finish(),
+                        Reliability::Reliable,
+                    )?;
+                }
+                for (id, data, delta) in self.sync_queue.take() {
+                    self.connection.send(
+                        &*self.transport,
+                        if delta { CHANNEL_SYNC_DELTA } else { CHANNEL_SYNC },
+                        encode_sync(id, self.server_tick, &data),
+                        Reliability::Reliable,
+                    )?;
+                }
+                if let Some(time) = self.clock.ping_due() {
+                    let mut ping = PacketWriter::new(PACKET_PING);
+                    ping.f64(time);
+                    self.connection
+                        .send_raw(&*self.transport, &ping.finish())?;
+                }
+                self.connection.update(&*self.transport)?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Get the next event that happened, if any
+    pub fn next_event(&mut self) -> Option<ClientEvent> {
+        self.events.pop_front()
+    }
+
+    /// Take every event that happened since this was last called, e.g. once per frame
+    pub fn take_events(&mut self) -> Vec<ClientEvent> {
+        self.events.drain(..).collect()
+    }
+
+    /// Send a message to the server, on a user channel (up to [MAX_USER_CHANNEL])
+    pub fn send(&mut self, channel: u8, data: Vec<u8>, reliability: Reliability) -> io::Result<()> {
+        assert!(
+            channel <= MAX_USER_CHANNEL,
+            "Channels above {MAX_USER_CHANNEL} are reserved"
+        );
+        self.connection
+            .send(&*self.transport, channel, data, reliability)
+    }
+
+    /// Send a voice packet to every other player, see [crate::voice]
+    pub fn send_voice(&mut self, packet: Vec<u8>) -> io::Result<()> {
+        self.connection.send(
+            &*self.transport,
+            CHANNEL_VOICE,
+            packet,
+            Reliability::Unreliable,
+        )
+    }
+
+    /// Allow the server to call a remote procedure on this client
+    pub fn register_rpc<R: Rpc>(&mut self) {
+        self.rpc.register::<R>();
+    }
+
+    /// Call a remote procedure on the server, ignoring the return value
+    pub fn call<R: Rpc>(&mut self, args: R::Args) -> io::Result<()> {
+        let message = self.rpc.call::<R>(&args);
+        self.connection
+            .send(&*self.transport, CHANNEL_RPC, message, R::RELIABILITY)
+    }
+
+    /// Call a remote procedure on the server, the return value can be awaited
+    pub fn request<R: Rpc>(&mut self, args: R::Args) -> io::Result<RpcReply<R::Output>> {
+        let (message, reply) = self.rpc.request::<R>(None, &args);
+        self.connection
+            .send(&*self.transport, CHANNEL_RPC, message, R::RELIABILITY)?;
+        Ok(reply)
+    }
+
+    /// Send a chat message to a room this client is in
+    pub fn send_chat(&mut self, room: &str, text: &str) -> io::Result<()> {
+        self.connection.send(
+            &*self.transport,
+            CHANNEL_CHAT,
+            encode_send(room, text),
+            Reliability::Reliable,
+        )
+    }
+
+    /// Ask to join a chat room, see [crate::chat::ChatConfig::open_rooms]
+    pub fn join_room(&mut self, room: &str) -> io::Result<()> {
+        self.connection.send(
+            &*self.transport,
+            CHANNEL_CHAT,
+            encode_room(CHAT_JOIN, room),
+            Reliability::Reliable,
+        )
+    }
+
+    /// Ask to leave a chat room
+    pub fn leave_room(&mut self, room: &str) -> io::Result<()> {
+        self.connection.send(
+            &*self.transport,
+            CHANNEL_CHAT,
+            encode_room(CHAT_LEAVE, room),
+            Reliability::Reliable,
+        )
+    }
+
+    /// Log in to run commands on the server, see [crate::admin]
+    ///
+    /// Sending the wrong password too many times gets this client disconnected, and stops its
+    /// address from logging in for a few minutes.
+    pub fn admin_login(&mut self, password: &str) -> io::Result<()> {
+        self.connection.send(
+            &*self.transport,
+            CHANNEL_ADMIN,
+            admin::encode(ADMIN_LOGIN, password),
+            Reliability::Reliable,
+        )
+    }
+
+    /// Run a command on the server, after logging in with [Client::admin_login]
+    pub fn admin_command(&mut self, command: &str) -> io::Result<()> {
+        self.connection.send(
+            &*self.transport,
+            CHANNEL_ADMIN,
+            admin::encode(ADMIN_COMMAND, command),
+            Reliability::Reliable,
+        )
+    }
+
+    /// Iterate over the chat rooms this client is in
+    pub fn rooms(&self) -> impl Iterator<Item = &str> {
+        self.chat_rooms.iter().map(String::as_str)
+    }
+
+    /// The most recent messages in a chat room, oldest first
+    pub fn chat_history(&self, room: &str) -> impl Iterator<Item = &ChatMessage> {
+        self.chat.get(room)
+    }
+
+    /// Close the connection to the server
+    pub fn disconnect(&mut self) -> io::Result<()> {
+        if self.state != ClientState::Disconnected {
+            self.connection
+                .send_raw(&*self.transport, &PacketWriter::new(PACKET_DISCONNECT).finish())?;
+            self.close(DisconnectReason::Disconnected);
+        }
+        Ok(())
+    }
+
+    fn send_connect(&mut self) -> io::Result<()> {
+        self.state = ClientState::Connecting {
+            last_attempt: Instant::now(),
+        };
+        let supported = match self.config.compression.algorithm {
+            Compression::None => 0,
+            _ => Compression::available_mask(),
+        };
+        let mut packet = PacketWriter::new(PACKET_CONNECT);
+        packet
+            .u16(PROTOCOL_VERSION)
+            .u32(self.config.version)
+            .u8(supported);
+        self.connection.send_raw(&*self.transport, &packet.finish())
+    }
+
+    fn close(&mut self, reason: DisconnectReason) {
+        self.state = ClientState::Disconnected;
+        self.owners.clear();
+        self.sync_queue.clear_owned();
+        self.rpc.close(None);
+        self.chat_rooms.clear();
+        self.chat.clear();
+        self.events.push_back(ClientEvent::Disconnected(reason));
+    }
+
+    fn receive_packet(&mut self, data: &[u8]) -> io::Result<()> {
+        let Some((kind, mut reader)) = PacketReader::new(data) else {
+            return Ok(());
+        };
+        self.connection.record_received(data.len());
+        match kind {
+            PACKET_ACCEPT => {
+                self.connection.mark_received();
+                if let (ClientState::Connecting { .. }, Some(id)) = (self.state, reader.u64()) {
+                    let algorithm = reader.u8().and_then(Compression::from_id);
+                    self.connection.set_compression(CompressionConfig {
+                        algorithm: algorithm.unwrap_or(Compression::None),
+                        threshold: self.config.compression.threshold,
+                    });
+                    self.state = ClientState::Connected(id);
+                    self.events.push_back(ClientEvent::Connected(id));
+                }
+            }
+            PACKET_REJECT => {
+                let reason = reader
+                    .bytes()
+                    .map(|r| String::from_utf8_lossy(r).into_owned())
+                    .unwrap_or_default();
+                // A version mismatch also includes the server's version
+                let reason = match reader.u32() {
+                    Some(server) => DisconnectReason::VersionMismatch {
+                        client: self.config.version,
+                        server,
+                    },
+                    None => DisconnectReason::Rejected(reason),
+                };
+                self.close(reason);
+            }
+            PACKET_DISCONNECT => self.close(DisconnectReason::Disconnected),
+            PACKET_PONG => {
+                self.connection.mark_received();
+                let (Some(sent), Some(server_time), Some(tick), Some(tick_time), Some(interval)) = (
+                    reader.f64(),
+                    reader.f64(),
+                    reader.u64(),
+                    reader.f64(),
+                    reader.f64(),
+                ) else {
+                    return Ok(());
+                };
+                if let Some(rtt) = self
+                    .clock
+                    .pong(sent, server_time, tick, tick_time, interval)
+                {
+                    self.connection.record_rtt(rtt);
+                }
+            }
+            _ => {
+                for (channel, data) in self.connection.receive(kind, reader) {
+                    self.handle_message(channel, data)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn handle_message(&mut self, channel: u8, data: Vec<u8>) -> io::Result<()> {
+        match channel {
+            0..=MAX_USER_CHANNEL => self
+                .events
+                .push_back(ClientEvent::Message { channel, data }),
+            CHANNEL_SYNC | CHANNEL_SYNC_DELTA => {
+                if let Some((id, tick, data)) = decode_sync(&data) {
+                    self.server_tick = self.server_tick.max(tick);
+                    self.events.push_back(ClientEvent::Sync {
+                        id,
+                        tick,
+                        data: data.to_vec(),
+                    });
+                }
+            }
+            CHANNEL_SYNC_REMOVE => {
+                if let Some(id) = data.first_chunk::<8>() {
+                    self.events
+                        .push_back(ClientEvent::SyncRemoved(u64::from_le_bytes(*id) as usize));
+                }
+            }
+            CHANNEL_OWNERSHIP => {
+                let Some((OWNERSHIP_CHANGED, mut reader)) = PacketReader::new(&data) else {
+                    return Ok(());
+                };
+                let (Some(id), Some(owner)) = (reader.u64(), reader.u64()) else {
+                    return Ok(());
+                };
+                let (id, owner) = (id as usize, (owner != 0).then_some(owner));
+                match owner {
+                    Some(owner) => self.owners.insert(id, owner),
+                    None => self.owners.remove(&id),
+                };
+                self.sync_queue
+                    .set_owned(id, owner.is_some() && owner == self.id());
+                self.events
+                    .push_back(ClientEvent::OwnershipChanged { id, owner });
+            }
+            CHANNEL_VOICE => {
+                if let Some((speaker, data)) = data.split_first_chunk::<8>() {
+                    self.events.push_back(ClientEvent::Voice {
+                        speaker: u64::from_le_bytes(*speaker),
+                        data: data.to_vec(),
+                    });
+                }
+            }
+            CHANNEL_CHAT => match PacketReader::new(&data) {
+                Some((CHAT_MESSAGE, reader)) => {
+                    if let Some(message) = decode_message(reader) {
+                        self.chat.push(message.clone());
+                        self.events.push_back(ClientEvent::Chat(message));
+                    }
+                }
+                Some((CHAT_JOIN, mut reader)) => {
+                    if let Some(room) = read_string(&mut reader) {
+                        self.chat_rooms.insert(room.clone());
+                        self.events.push_back(ClientEvent::RoomJoined(room));
+                    }
+                }
+                Some((CHAT_LEAVE, mut reader)) => {
+                    if let Some(room) = read_string(&mut reader) {
+                        self.chat_rooms.remove(&room);
+                        self.chat.remove(&room);
+                        self.events.push_back(ClientEvent::RoomLeft(room));
+                    }
+                }
+                _ => {}
+            },
+            CHANNEL_ADMIN => {
+                if let Some((ADMIN_OUTPUT, mut reader)) = PacketReader::new(&data) {
+                    if let Some(output) = read_string(&mut reader) {
+                        self.events.push_back(ClientEvent::AdminOutput(output));
+                    }
+                }
+            }
+            CHANNEL_RPC => {
+                if let Some(reply) = self.rpc.handle(&RpcContext { caller: None }, &data) {
+                    self.connection.send(
+                        &*self.transport,
+                        CHANNEL_RPC,
+                        reply,
+                        Reliability::Reliable,
+                    )?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,127 @@
+//! Round trip time measurement and server clock estimation, for clients
+
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+/// How often pings are sent once the clock has settled
+const PING_INTERVAL: Duration = Duration::from_secs(1);
+/// How often pings are sent while there are only a few samples
+const FAST_PING_INTERVAL: Duration = Duration::from_millis(100);
+/// The number of recent samples the offset is chosen from
+const MAX_SAMPLES: usize = 8;
+
+#[derive(Debug, Clone, Copy)]
+struct Sample {
+    rtt: Duration,
+    /// Server time minus local time, in seconds
+    offset: f64,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct TickInfo {
+    tick: u64,
+    /// When the tick ran, in server time
+    time: f64,
+    interval: f64,
+}
+
+#[derive(Debug)]
+pub(crate) struct ClockSync {
+    epoch: Instant,
+    samples: VecDeque<Sample>,
+    offset: f64,
+    last_ping: Option<Instant>,
+    tick: Option<TickInfo>,
+}
+
+impl Default for ClockSync {
+    fn default() -> Self {
+        Self {
+            epoch: Instant::now(),
+            samples: VecDeque::new(),
+            offset: 0.0,
+            last_ping: None,
+            tick: None,
+        }
+    }
+}
+
+impl ClockSync {
+    /// Seconds since this clock was created
+    pub fn local_time(&self) -> f64 {
+        self.epoch.elapsed().as_secs_f64()
+    }
+
+    /// The estimated current time on the server
+    pub fn server_time(&self) -> f64 {
+        self.local_time() + self.offset
+    }
+
+    /// Whether at least one sample has arrived
+    pub fn is_synced(&self) -> bool {
+        !self.samples.is_empty()
+    }
+
+    /// The estimated server time that a tick ran at
+    pub fn tick_time(&self, tick: u64) -> Option<f64> {
+        let info = self.tick?;
+        Some(info.time + (tick as f64 - info.tick as f64) * info.interval)
+    }
+
+    /// If it's time to send a ping, returns the local time to put in it
+    pub fn ping_due(&mut self) -> Option<f64> {
+        let interval = if self.samples.len() < MAX_SAMPLES / 2 {
+            FAST_PING_INTERVAL
+        } else {
+            PING_INTERVAL
+        };
+        if self.last_ping.is_some_and(|last| last.elapsed() < interval) {
+            return None;
+        }
+        self.last_ping = Some(Instant::now());
+        Some(self.local_time())
+    }
+
+    /// Handle a reply to a ping, returning the round trip time
+    ///
+    /// Replies with times that can't be right are ignored, returning `None`.
+    pub fn pong(
+        &mut self,
+        sent: f64,
+        server_time: f64,
+        tick: u64,
+        tick_time: f64,
+        tick_interval: f64,
+    ) -> Option<Duration> {
+        if ![server_time, tick_time, tick_interval]
+            .iter()
+            .all(|time| time.is_finite())
+        {
+            return None;
+        }
+        let now = self.local_time();
+        // Fails for pings from the future, or that aren't numbers
+        let rtt = Duration::try_from_secs_f64(now - sent).ok()?;
+        // Assume the reply took half of the round trip to get here
+        let offset = server_time + rtt.as_secs_f64() / 2.0 - now;
+        self.samples.push_back(Sample { rtt, offset });
+        if self.samples.len() > MAX_SAMPLES {
+            self.samples.pop_front();
+        }
+        // The sample with the lowest round trip has the least room for error
+        self.offset = self
+            .samples
+            .iter()
+            .min_by_key(|sample| sample.rtt)
+            .unwrap()
+            .offset;
+        self.tick = Some(TickInfo {
+            tick,
+            time: tick_time,
+            interval: tick_interval,
+        });
+        Some(rtt)
+    }
+}
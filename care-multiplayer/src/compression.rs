@@ -0,0 +1,125 @@
+//! Optional compression of messages, negotiated when connecting
+//!
+//! Enable the `lz4` or `zstd` feature to make that algorithm available. When
+//! a client connects it tells the server which algorithms it supports, and
+//! the server picks the one from its [CompressionConfig] if the client
+//! supports it, otherwise messages are sent uncompressed.
+//!
+//! Messages smaller than [CompressionConfig::threshold] are never compressed,
+//! and neither are messages that don't get any smaller.
+
+/// Decompressed messages are never allowed to be bigger than this, so a
+/// malicious peer can't make us allocate huge amounts of memory
+#[cfg(any(feature = "lz4", feature = "zstd"))]
+const MAX_DECOMPRESSED_SIZE: usize = 1 << 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// A compression algorithm
+pub enum Compression {
+    /// Don't compress anything
+    None,
+    /// [LZ4](https://lz4.org/), very fast with decent compression, needs the `lz4` feature
+    Lz4,
+    /// [Zstandard](https://facebook.github.io/zstd/), slower than LZ4 but
+    /// compresses better, needs the `zstd` feature
+    Zstd,
+}
+
+impl Default for Compression {
+    /// The fastest available algorithm
+    fn default() -> Self {
+        [Self::Lz4, Self::Zstd]
+            .into_iter()
+            .find(|c| c.is_available())
+            .unwrap_or(Self::None)
+    }
+}
+
+impl Compression {
+    /// Whether the feature for this algorithm is enabled
+    pub fn is_available(self) -> bool {
+        match self {
+            Self::None => true,
+            Self::Lz4 => cfg!(feature = "lz4"),
+            Self::Zstd => cfg!(feature = "zstd"),
+        }
+    }
+
+    pub(crate) fn id(self) -> u8 {
+        match self {
+            Self::None => 0,
+            Self::Lz4 => 1,
+            Self::Zstd => 2,
+        }
+    }
+
+    pub(crate) fn from_id(id: u8) -> Option<Self> {
+        [Self::None, Self::Lz4, Self::Zstd]
+            .into_iter()
+            .find(|c| c.id() == id && c.is_available())
+    }
+
+    /// A bitmask of every available algorithm, sent when connecting
+    pub(crate) fn available_mask() -> u8 {
+        [Self::Lz4, Self::Zstd]
+            .into_iter()
+            .filter(|c| c.is_available())
+            .fold(0, |mask, c| mask | 1 << c.id())
+    }
+
+    pub(crate) fn in_mask(self, mask: u8) -> bool {
+        self == Self::None || mask & 1 << self.id() != 0
+    }
+
+    #[cfg_attr(not(any(feature = "lz4", feature = "zstd")), allow(unused_variables))]
+    pub(crate) fn compress(self, data: &[u8]) -> Option<Vec<u8>> {
+        match self {
+            Self::None => None,
+            #[cfg(feature = "lz4")]
+            Self::Lz4 => Some(lz4_flex::compress_prepend_size(data)),
+            #[cfg(feature = "zstd")]
+            Self::Zstd => zstd::bulk::compress(data, 0).ok(),
+            #[allow(unreachable_patterns)]
+            _ => None,
+        }
+    }
+
+    #[cfg_attr(not(any(feature = "lz4", feature = "zstd")), allow(unused_variables))]
+    pub(crate) fn decompress(self, data: &[u8]) -> Option<Vec<u8>> {
+        match self {
+            Self::None => None,
+            #[cfg(feature = "lz4")]
+            Self::Lz4 => {
+                let size = u32::from_le_bytes(*data.first_chunk::<4>()?) as usize;
+                if size > MAX_DECOMPRESSED_SIZE {
+                    return None;
+                }
+                lz4_flex::decompress_size_prepended(data).ok()
+            }
+            #[cfg(feature = "zstd")]
+            Self::Zstd => zstd::bulk::decompress(data, MAX_DECOMPRESSED_SIZE).ok(),
+            #[allow(unreachable_patterns)]
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// How messages are compressed
+pub struct CompressionConfig {
+    /// The algorithm to use, on a server this is used for every client that
+    /// supports it, on a client it can be set to [Compression::None] to
+    /// refuse compression
+    pub algorithm: Compression,
+    /// Messages smaller than this many bytes are sent uncompressed
+    pub threshold: usize,
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        Self {
+            algorithm: Compression::default(),
+            threshold: 128,
+        }
+    }
+}
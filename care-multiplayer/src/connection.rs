@@ -0,0 +1,530 @@
+//! Per-peer connection state, adding reliable, ordered messages on top of an
+//! unreliable [Transport]
+
+use std::{
+    collections::BTreeMap,
+    io,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    compression::{Compression, CompressionConfig},
+    packet::{PacketReader, PacketWriter},
+    stats::{NetStats, StatsTracker},
+    throttle::{BandwidthConfig, Throttle},
+    transport::{Address, Transport, MAX_PACKET_SIZE},
+};
+
+/// The version of the packet format, bumped whenever it changes incompatibly
+pub(crate) const PROTOCOL_VERSION: u16 = 1;
+
+pub(crate) const PACKET_CONNECT: u8 = 1;
+pub(crate) const PACKET_ACCEPT: u8 = 2;
+pub(crate) const PACKET_REJECT: u8 = 3;
+pub(crate) const PACKET_DISCONNECT: u8 = 4;
+pub(crate) const PACKET_MESSAGE: u8 = 5;
+pub(crate) const PACKET_ACK: u8 = 6;
+pub(crate) const PACKET_HEARTBEAT: u8 = 7;
+pub(crate) const PACKET_PING: u8 = 8;
+pub(crate) const PACKET_PONG: u8 = 9;
+
+/// Channels from this value up are reserved for internal use
+pub const MAX_USER_CHANNEL: u8 = 127;
+pub(crate) const CHANNEL_SYNC: u8 = 255;
+pub(crate) const CHANNEL_SYNC_REMOVE: u8 = 254;
+pub(crate) const CHANNEL_RPC: u8 = 253;
+pub(crate) const CHANNEL_VOICE: u8 = 252;
+pub(crate) const CHANNEL_OWNERSHIP: u8 = 251;
+pub(crate) const CHANNEL_SYNC_DELTA: u8 = 250;
+pub(crate) const CHANNEL_CHAT: u8 = 249;
+pub(crate) const CHANNEL_ADMIN: u8 = 248;
+
+/// Message flags
+const FLAG_RELIABLE: u8 = 1;
+const FLAG_COMPRESSED: u8 = 2;
+/// Set on every fragment of a reliable message except the last one
+const FLAG_MORE_FRAGMENTS: u8 = 4;
+
+/// The packet kind, flags, id, channel and length of a reliable message
+const RELIABLE_HEADER_SIZE: usize = 11;
+/// The packet kind, flags, channel and length of an unreliable message
+const UNRELIABLE_HEADER_SIZE: usize = 7;
+/// Reliable messages bigger than this are split into fragments that each fit in a packet
+const MAX_FRAGMENT_SIZE: usize = MAX_PACKET_SIZE - RELIABLE_HEADER_SIZE;
+/// The biggest message that can be sent reliably, small enough that all of its
+/// fragments fit in the receiver's window
+///
+/// Unreliable messages can't be split, so they have to fit in a single packet
+/// of [MAX_PACKET_SIZE] (after compression).
+pub const MAX_MESSAGE_SIZE: usize = 1024 * 1024;
+
+const RESEND_INTERVAL: Duration = Duration::from_millis(100);
+/// Reliable messages this far ahead of the next one expected are dropped
+/// without being acked, so they're resent later, which also caps how many
+/// messages wait for an earlier one to arrive
+const RECEIVE_WINDOW: u32 = 1024;
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// How a message should be delivered
+pub enum Reliability {
+    /// The message is resent until it arrives, and messages arrive in the order they were sent
+    Reliable,
+    /// The message is sent once, and may be lost or arrive out of order
+    Unreliable,
+}
+
+#[derive(Debug)]
+struct Unacked {
+    flags: u8,
+    channel: u8,
+    data: Vec<u8>,
+    first_sent: Instant,
+    last_sent: Instant,
+    resent: bool,
+}
+
+#[derive(Debug)]
+pub(crate) struct Connection {
+    pub addr: Address,
+    next_reliable_id: u32,
+    unacked: BTreeMap<u32, Unacked>,
+    next_expected_id: u32,
+    out_of_order: BTreeMap<u32, (u8, u8, Vec<u8>)>,
+    /// The fragments received so far of a message that was split up
+    fragments: Vec<u8>,
+    /// Whether the message being put back together is too big, so its
+    /// fragments are thrown away
+    fragments_too_big: bool,
+    pending_acks: Vec<u32>,
+    last_received: Instant,
+    last_sent: Instant,
+    stats: StatsTracker,
+    compression: CompressionConfig,
+    throttle: Throttle,
+}
+
+impl Connection {
+    pub fn new(addr: Address) -> Self {
+        Self {
+            addr,
+            next_reliable_id: 0,
+            unacked: BTreeMap::new(),
+            next_expected_id: 0,
+            out_of_order: BTreeMap::new(),
+            fragments: Vec::new(),
+            fragments_too_big: false,
+            pending_acks: Vec::new(),
+            last_received: Instant::now(),
+            last_sent: Instant::now(),
+            stats: StatsTracker::default(),
+            compression: CompressionConfig {
+                algorithm: Compression::None,
+                threshold: 0,
+            },
+            throttle: Throttle::new(BandwidthConfig::default()),
+        }
+    }
+
+    /// Start compressing messages, once both peers have agreed on an algorithm
+    pub fn set_compression(&mut self, compression: CompressionConfig) {
+        self.compression = compression;
+    }
+
+    pub fn set_bandwidth(&mut self, config: BandwidthConfig) {
+        self.throttle.set_config(config);
+    }
+
+    pub fn compression(&self) -> Compression {
+        self.compression.algorithm
+    }
+
+    pub fn stats(&self) -> &NetStats {
+        self.stats.stats()
+    }
+
+    /// Send a control packet (not a message) directly to the peer
+    pub fn send_raw(&mut self, transport: &dyn Transport, packet: &[u8]) -> io::Result<()> {
+        self.throttle.spend(packet.len());
+        self.transmit(transport, packet)
+    }
+
+    /// Send a packet that has already been taken out of the budget
+    fn transmit(&mut self, transport: &dyn Transport, packet: &[u8]) -> io::Result<()> {
+        self.last_sent = Instant::now();
+        self.stats.packet_sent(packet.len());
+        transport.send_to(self.addr, packet)
+    }
+
+    /// Send a message, splitting it into fragments if it's reliable and too
+    /// big for a single packet
+    ///
+    /// Messages that are too big to be sent at all (see [MAX_MESSAGE_SIZE]) are
+    /// rejected with an error, and never queued.
+    pub fn send(
+        &mut self,
+        transport: &dyn Transport,
+        channel: u8,
+        mut data: Vec<u8>,
+        reliability: Reliability,
+    ) -> io::Result<()> {
+        if data.len() > MAX_MESSAGE_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Messages can be at most {MAX_MESSAGE_SIZE} bytes"),
+            ));
+        }
+        self.stats
+            .message_sent(channel, data.len(), reliability == Reliability::Reliable);
+        let mut flags = 0;
+        if data.len() >= self.compression.threshold {
+            if let Some(compressed) = self.compression.algorithm.compress(&data) {
+                if compressed.len() < data.len() {
+                    data = compressed;
+                    flags |= FLAG_COMPRESSED;
+                }
+            }
+        }
+        if reliability == Reliability::Reliable {
+            let fragments = data.len().div_ceil(MAX_FRAGMENT_SIZE).max(1);
+            for i in 0..fragments {
+                let start = i * MAX_FRAGMENT_SIZE;
+                let fragment = &data[start..(start + MAX_FRAGMENT_SIZE).min(data.len())];
+                let more = if i + 1 < fragments {
+                    FLAG_MORE_FRAGMENTS
+                } else {
+                    0
+                };
+                self.send_reliable(transport, flags | more, channel, fragment.to_vec())?;
+            }
+            return Ok(());
+        }
+        if UNRELIABLE_HEADER_SIZE + data.len() > MAX_PACKET_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Unreliable messages have to fit in a single packet",
+            ));
+        }
+        let mut packet = PacketWriter::new(PACKET_MESSAGE);
+        packet.u8(flags).u8(channel).bytes(&data);
+        if self.throttle.is_throttled(channel) {
+            if self.throttle.queue(channel, packet.finish()) {
+                self.stats.messages_dropped(1);
+            }
+            return Ok(());
+        }
+        self.send_raw(transport, &packet.finish())
+    }
+
+    /// Send a reliable message, or a fragment of one, and keep it to resend until it's acked
+    fn send_reliable(
+        &mut self,
+        transport: &dyn Transport,
+        flags: u8,
+        channel: u8,
+        data: Vec<u8>,
+    ) -> io::Result<()> {
+        let id = self.next_reliable_id;
+        self.next_reliable_id = self.next_reliable_id.wrapping_add(1);
+        let flags = flags | FLAG_RELIABLE;
+        let mut packet = PacketWriter::new(PACKET_MESSAGE);
+        packet.u8(flags).u32(id).u8(channel).bytes(&data);
+        self.unacked.insert(
+            id,
+            Unacked {
+                flags,
+                channel,
+                data,
+                first_sent: Instant::now(),
+                last_sent: Instant::now(),
+                resent: false,
+            },
+        );
+        self.send_raw(transport, &packet.finish())
+    }
+
+    /// Note that a packet was received from the peer, so it's still alive
+    pub fn mark_received(&mut self) {
+        self.last_received = Instant::now();
+    }
+
+    /// Count a packet received from the peer in the stats
+    pub fn record_received(&mut self, len: usize) {
+        self.stats.packet_received(len);
+    }
+
+    /// Add a round trip time measured outside of the connection, e.g. from a ping
+    pub fn record_rtt(&mut self, rtt: Duration) {
+        self.stats.rtt_sample(rtt);
+    }
+
+    /// Handle a message or ack packet, returning any messages that are ready to be processed
+    pub fn receive(&mut self, kind: u8, mut reader: PacketReader) -> Vec<(u8, Vec<u8>)> {
+        self.mark_received();
+        let mut messages = Vec::new();
+        match kind {
+            PACKET_MESSAGE => {
+                let Some(flags) = reader.u8() else {
+                    return messages;
+                };
+                if flags & FLAG_RELIABLE == 0 {
+                    if let (Some(channel), Some(data)) = (reader.u8(), reader.bytes()) {
+                        if let Some(data) = self.decode(flags, data) {
+                            messages.push((channel, data));
+                        }
+                    }
+                    return messages;
+                }
+                let (Some(id), Some(channel), Some(data)) = (reader.u32(), reader.u8(), reader.bytes())
+                else {
+                    return messages;
+                };
+                let ahead = id.wrapping_sub(self.next_expected_id);
+                if (RECEIVE_WINDOW..u32::MAX / 2).contains(&ahead) {
+                    // Too far ahead, see RECEIVE_WINDOW
+                    return messages;
+                }
+                // Always ack, even for duplicates, since the last ack may have been lost
+                self.pending_acks.push(id);
+                if ahead >= u32::MAX / 2 {
+                    // Already delivered
+                    return messages;
+                }
+                self.out_of_order.insert(id, (flags, channel, data.to_vec()));
+                while let Some((flags, channel, data)) =
+                    self.out_of_order.remove(&self.next_expected_id)
+                {
+                    self.next_expected_id = self.next_expected_id.wrapping_add(1);
+                    messages.extend(self.reassemble(flags, channel, data));
+                }
+            }
+            PACKET_ACK => {
+                let count = reader.u16().unwrap_or(0);
+                for _ in 0..count {
+                    let Some(id) = reader.u32() else {
+                        break;
+                    };
+                    if let Some(unacked) = self.unacked.remove(&id) {
+                        // Resent messages are ambiguous, the ack could be for either copy
+                        if !unacked.resent {
+                            self.stats.rtt_sample(unacked.first_sent.elapsed());
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+        for (channel, data) in &messages {
+            self.stats.message_received(*channel, data.len());
+        }
+        messages
+    }
+
+    /// Send acks, resend lost reliable messages, send unreliable messages that
+    /// were held back, and keep the connection alive
+    pub fn update(&mut self, transport: &dyn Transport) -> io::Result<()> {
+        let pending_acks = std::mem::take(&mut self.pending_acks);
+        for acks in pending_acks.chunks(256) {
+            let mut packet = PacketWriter::new(PACKET_ACK);
+            packet.u16(acks.len() as u16);
+            for &id in acks {
+                packet.u32(id);
+            }
+            self.send_raw(transport, &packet.finish())?;
+        }
+
+        let now = Instant::now();
+        for (&id, unacked) in &mut self.unacked {
+            if now.duration_since(unacked.last_sent) >= RESEND_INTERVAL {
+                unacked.last_sent = now;
+                unacked.resent = true;
+                self.stats.message_resent();
+                let mut packet = PacketWriter::new(PACKET_MESSAGE);
+                packet
+                    .u8(unacked.flags)
+                    .u32(id)
+                    .u8(unacked.channel)
+                    .bytes(&unacked.data);
+                let packet = packet.finish();
+                self.throttle.spend(packet.len());
+                self.last_sent = now;
+                self.stats.packet_sent(packet.len());
+                transport.send_to(self.addr, &packet)?;
+            }
+        }
+
+        let (packets, dropped) = self.throttle.take();
+        self.stats.messages_dropped(dropped);
+        for packet in packets {
+            self.transmit(transport, &packet)?;
+        }
+
+        if self.last_sent.elapsed() >= HEARTBEAT_INTERVAL {
+            self.send_raw(transport, &PacketWriter::new(PACKET_HEARTBEAT).finish())?;
+        }
+        self.stats.update();
+        Ok(())
+    }
+
+    /// Add a reliable message's fragment to the ones before it, returning the
+    /// whole message once its last fragment arrives
+    fn reassemble(&mut self, flags: u8, channel: u8, data: Vec<u8>) -> Option<(u8, Vec<u8>)> {
+        if self.fragments.len() + data.len() > MAX_MESSAGE_SIZE {
+            self.fragments_too_big = true;
+            self.fragments = Vec::new();
+        }
+        if flags & FLAG_MORE_FRAGMENTS != 0 {
+            if !self.fragments_too_big {
+                self.fragments.extend_from_slice(&data);
+            }
+            return None;
+        }
+        let data = if std::mem::take(&mut self.fragments_too_big) {
+            Vec::new()
+        } else if self.fragments.is_empty() {
+            data
+        } else {
+            let mut whole = std::mem::take(&mut self.fragments);
+            whole.extend_from_slice(&data);
+            whole
+        };
+        // A message that's too big or fails to decompress is still delivered
+        // (empty), so every reliable message sent is received exactly once
+        Some((channel, self.decode(flags, &data).unwrap_or_default()))
+    }
+
+    /// Decompress a message's data if needed
+    fn decode(&self, flags: u8, data: &[u8]) -> Option<Vec<u8>> {
+        if flags & FLAG_COMPRESSED != 0 {
+            self.compression.algorithm.decompress(data)
+        } else {
+            Some(data.to_vec())
+        }
+    }
+
+    /// Whether nothing has been heard from the peer for at least `timeout`
+    pub fn timed_out(&self, timeout: Duration) -> bool {
+        self.last_received.elapsed() >= timeout
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use parking_lot::Mutex;
+
+    use super::*;
+
+    /// Keeps every packet sent, and fails like UDP does for ones that are far too big
+    #[derive(Default)]
+    struct TestTransport {
+        sent: Mutex<Vec<Vec<u8>>>,
+    }
+
+    impl TestTransport {
+        fn take(&self) -> Vec<Vec<u8>> {
+            std::mem::take(&mut self.sent.lock())
+        }
+    }
+
+    impl Transport for TestTransport {
+        fn send_to(&self, _addr: Address, data: &[u8]) -> io::Result<()> {
+            if data.len() > 65507 {
+                return Err(io::Error::other("Message too long"));
+            }
+            self.sent.lock().push(data.to_vec());
+            Ok(())
+        }
+
+        fn recv_from(&self) -> io::Result<Option<(Address, Vec<u8>)>> {
+            Ok(None)
+        }
+
+        fn local_addr(&self) -> io::Result<Address> {
+            Ok(addr())
+        }
+    }
+
+    fn addr() -> Address {
+        "127.0.0.1:4000".parse().unwrap()
+    }
+
+    /// Pass packets to a connection, returning the messages that are ready
+    fn receive(connection: &mut Connection, packets: &[Vec<u8>]) -> Vec<(u8, Vec<u8>)> {
+        let mut messages = Vec::new();
+        for packet in packets {
+            let (kind, reader) = PacketReader::new(packet).unwrap();
+            messages.extend(connection.receive(kind, reader));
+        }
+        messages
+    }
+
+    #[test]
+    fn big_reliable_messages_are_fragmented() {
+        let transport = TestTransport::default();
+        let mut sender = Connection::new(addr());
+        let mut receiver = Connection::new(addr());
+        let big: Vec<u8> = (0..100_000).map(|i| i as u8).collect();
+        sender
+            .send(&transport, 1, big.clone(), Reliability::Reliable)
+            .unwrap();
+        sender
+            .send(&transport, 2, vec![1, 2, 3], Reliability::Reliable)
+            .unwrap();
+
+        let mut packets = transport.take();
+        assert!(packets.len() > 2);
+        assert!(packets.iter().all(|packet| packet.len() <= MAX_PACKET_SIZE));
+        // Fragments that arrive out of order still make up the same message
+        packets.reverse();
+        assert_eq!(
+            receive(&mut receiver, &packets),
+            vec![(1, big), (2, vec![1, 2, 3])]
+        );
+    }
+
+    #[test]
+    fn lost_fragments_are_resent() {
+        let transport = TestTransport::default();
+        let mut sender = Connection::new(addr());
+        let mut receiver = Connection::new(addr());
+        let big = vec![7; 5000];
+        sender
+            .send(&transport, 1, big.clone(), Reliability::Reliable)
+            .unwrap();
+        let mut packets = transport.take();
+        let lost = packets.remove(1);
+        assert!(receive(&mut receiver, &packets).is_empty());
+
+        // Ack what arrived, so only the lost fragment is resent
+        receiver.update(&transport).unwrap();
+        receive(&mut sender, &transport.take());
+        std::thread::sleep(RESEND_INTERVAL);
+        sender.update(&transport).unwrap();
+        let resent = transport.take();
+        assert_eq!(resent, vec![lost]);
+        assert_eq!(receive(&mut receiver, &resent), vec![(1, big)]);
+    }
+
+    #[test]
+    fn messages_that_are_too_big_are_rejected() {
+        let transport = TestTransport::default();
+        let mut connection = Connection::new(addr());
+        let too_big = vec![0; MAX_MESSAGE_SIZE + 1];
+        assert!(connection
+            .send(&transport, 1, too_big, Reliability::Reliable)
+            .is_err());
+        let too_big_to_fit = vec![0; MAX_PACKET_SIZE];
+        assert!(connection
+            .send(&transport, 1, too_big_to_fit, Reliability::Unreliable)
+            .is_err());
+        assert!(transport.take().is_empty());
+
+        // Nothing was queued to be resent either
+        std::thread::sleep(RESEND_INTERVAL);
+        connection.update(&transport).unwrap();
+        assert!(transport
+            .take()
+            .iter()
+            .all(|packet| packet[0] != PACKET_MESSAGE));
+    }
+}
@@ -0,0 +1,135 @@
+//! Running networking alongside a game loop
+//!
+//! The simplest way is to poll once at the start of every frame, and then
+//! handle everything that arrived before updating the game:
+//!
+//! ```ignore
+//! loop {
+//!     client.poll()?;
+//!     for event in client.take_events() {
+//!         // ...
+//!     }
+//!     update();
+//!     draw();
+//!     care::event::next_frame().await;
+//! }
+//! ```
+//!
+//! With an executor that can spawn tasks, networking can instead be driven
+//! in the background. The client (or server) is wrapped in a [Shared]
+//! handle, [Shared::drive] polls it once per frame, and the game locks it to
+//! take the events that arrived since the last frame or to send messages:
+//!
+//! ```ignore
+//! let client = Shared::new(Client::connect("127.0.0.1:4000")?);
+//! let driver = client.drive(care::event::next_frame);
+//! care::event::spawn(async move { driver.await.unwrap() });
+//! loop {
+//!     let events = client.lock().take_events();
+//!     for event in events {
+//!         // ...
+//!     }
+//!     care::event::next_frame().await;
+//! }
+//! ```
+//!
+//! Don't keep the lock held across an `.await`, or the driver won't be able
+//! to poll. The lock isn't reentrant either, so take the events out before
+//! looping over them, as above.
+
+use std::{future::Future, io, sync::Arc};
+
+use parking_lot::{Mutex, MutexGuard};
+
+use crate::{
+    client::Client,
+    lockstep::LockstepSession,
+    server::Server,
+    sync::Transferable,
+};
+
+/// Something that has to be polled regularly to keep its connections running
+pub trait Drive: Send + 'static {
+    /// Receive and send everything that's waiting
+    fn poll(&mut self) -> io::Result<()>;
+    /// Whether there's nothing left to poll, e.g. the client disconnected
+    fn is_finished(&self) -> bool {
+        false
+    }
+}
+
+impl Drive for Client {
+    fn poll(&mut self) -> io::Result<()> {
+        Client::poll(self)
+    }
+
+    fn is_finished(&self) -> bool {
+        self.is_disconnected()
+    }
+}
+
+impl Drive for Server {
+    fn poll(&mut self) -> io::Result<()> {
+        Server::poll(self)
+    }
+}
+
+impl<I> Drive for LockstepSession<I>
+where
+    I: Transferable + Clone + Default + Send + 'static,
+{
+    fn poll(&mut self) -> io::Result<()> {
+        LockstepSession::poll(self)
+    }
+}
+
+#[derive(Debug, Default)]
+/// A client, server or session that can be polled in the background by
+/// [Shared::drive] while the game uses it
+pub struct Shared<T>(Arc<Mutex<T>>);
+
+impl<T> Clone for Shared<T> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<T: Drive> Shared<T> {
+    /// Wrap something to share it with a driver
+    pub fn new(inner: T) -> Self {
+        Self(Arc::new(Mutex::new(inner)))
+    }
+
+    /// Lock it for use, blocking until the driver is done polling
+    pub fn lock(&self) -> MutexGuard<'_, T> {
+        self.0.lock()
+    }
+
+    /// Poll once per frame until finished, or until every other handle has
+    /// been dropped
+    ///
+    /// `next_frame` should wait until the next frame, normally it's
+    /// `care::event::next_frame`.
+    pub fn drive<F>(
+        &self,
+        mut next_frame: impl FnMut() -> F + Send + 'static,
+    ) -> impl Future<Output = io::Result<()>> + Send + 'static
+    where
+        F: Future<Output = ()> + Send,
+    {
+        let shared = self.0.clone();
+        async move {
+            while Arc::strong_count(&shared) > 1 {
+                {
+                    let mut inner = shared.lock();
+                    inner.poll()?;
+                    if inner.is_finished() {
+                        break;
+                    }
+                }
+                next_frame().await;
+            }
+            Ok(())
+        }
+    }
+}
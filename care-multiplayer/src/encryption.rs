@@ -0,0 +1,620 @@
+//! Encrypted transports, using the [Noise protocol](https://noiseprotocol.org/)
+//!
+//! Without encryption anyone between a client and server can read chat
+//! messages, or change packets as they're being sent. Wrapping a transport in
+//! an [EncryptedTransport] prevents both of these.
+//!
+//! The server has a long-term [Keypair], the public half of which is given to
+//! players along with the server's address, e.g. as a hex string. Clients
+//! only connect if the server proves it has the matching private key, so
+//! nobody can pretend to be the server without stealing its key.
+//!
+//! ```ignore
+//! // On the server, generate this once and keep it somewhere safe
+//! let key = Keypair::generate();
+//! println!("Server key: {}", key.public());
+//! let transport = EncryptedTransport::server(UdpTransport::bind("0.0.0.0:7777")?, &key);
+//! let server = Server::new(transport, ServerConfig::default())?;
+//!
+//! // On the client
+//! let server_key: PublicKey = "3f5a...".parse()?;
+//! let transport = EncryptedTransport::client(UdpTransport::bind("0.0.0.0:0")?, server_key);
+//! let client = Client::new(transport, server_addr, ClientConfig::default())?;
+//! ```
+
+use std::{
+    collections::HashMap,
+    fmt, io,
+    net::IpAddr,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use parking_lot::Mutex;
+use snow::{
+    params::DHChoice,
+    resolvers::{CryptoResolver, DefaultResolver},
+    Builder, HandshakeState, StatelessTransportState,
+};
+
+use crate::transport::{Address, Transport};
+
+const NOISE_PARAMS: &str = "Noise_NK_25519_ChaChaPoly_BLAKE2s";
+
+const KIND_HANDSHAKE_INIT: u8 = 1;
+const KIND_HANDSHAKE_REPLY: u8 = 2;
+const KIND_DATA: u8 = 3;
+
+const HANDSHAKE_RESEND_INTERVAL: Duration = Duration::from_millis(250);
+/// Peers that haven't sent anything for this long are forgotten
+const PEER_TIMEOUT: Duration = Duration::from_secs(60);
+/// Handshakes answered by the server that haven't been followed by any data
+/// are forgotten after this long, since they may have come from a spoofed address
+const UNCONFIRMED_TIMEOUT: Duration = Duration::from_secs(10);
+/// The server stops answering new handshakes while this many haven't been
+/// followed by any data, or this many addresses have recently started one
+const MAX_UNCONFIRMED: usize = 256;
+/// Each IP address can start this many handshakes with the server every
+/// [HANDSHAKE_RATE_WINDOW]
+const MAX_HANDSHAKES_PER_IP: u32 = 8;
+const HANDSHAKE_RATE_WINDOW: Duration = Duration::from_secs(10);
+/// Packets sent before the handshake completes are queued, up to this many
+const MAX_QUEUED: usize = 64;
+/// How far behind the newest packet an older one can arrive and still be accepted
+const REPLAY_WINDOW: u64 = 64;
+
+/// The number of bytes encryption adds to each packet
+pub const OVERHEAD: usize = 1 + 8 + 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// The public half of a server's [Keypair], which clients use to check they're
+/// talking to the right server
+///
+/// This is written and parsed as a 64 character hex string.
+pub struct PublicKey(pub [u8; 32]);
+
+impl fmt::Display for PublicKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for b in self.0 {
+            write!(f, "{b:02x}")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// The error returned when parsing an invalid [PublicKey]
+pub struct InvalidKey;
+
+impl fmt::Display for InvalidKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Expected a key as 64 hex characters")
+    }
+}
+
+impl std::error::Error for InvalidKey {}
+
+impl FromStr for PublicKey {
+    type Err = InvalidKey;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.len() != 64 || !s.is_ascii() {
+            return Err(InvalidKey);
+        }
+        let mut key = [0; 32];
+        for (i, b) in key.iter_mut().enumerate() {
+            *b = u8::from_str_radix(&s[i * 2..i * 2 + 2], 16).map_err(|_| InvalidKey)?;
+        }
+        Ok(Self(key))
+    }
+}
+
+#[derive(Clone)]
+/// A server's long-term identity
+pub struct Keypair {
+    private: [u8; 32],
+    public: PublicKey,
+}
+
+impl fmt::Debug for Keypair {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Keypair")
+            .field("public", &self.public)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Keypair {
+    /// Generate a new random keypair
+    pub fn generate() -> Self {
+        let keypair = Builder::new(NOISE_PARAMS.parse().unwrap())
+            .generate_keypair()
+            .expect("Failed to generate a keypair");
+        Self::from_private(keypair.private.try_into().unwrap())
+    }
+
+    /// Load a keypair from a private key previously returned by [Keypair::private]
+    pub fn from_private(private: [u8; 32]) -> Self {
+        let mut dh = DefaultResolver
+            .resolve_dh(&DHChoice::Curve25519)
+            .unwrap();
+        dh.set(&private);
+        Self {
+            private,
+            public: PublicKey(dh.pubkey().try_into().unwrap()),
+        }
+    }
+
+    /// The public key, which should be given to clients
+    pub fn public(&self) -> PublicKey {
+        self.public
+    }
+
+    /// The private key, which must be kept secret
+    pub fn private(&self) -> [u8; 32] {
+        self.private
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Role {
+    Server { private: [u8; 32] },
+    Client { server_key: PublicKey },
+}
+
+/// Keeps track of recently received nonces, so replayed packets are dropped
+#[derive(Debug, Default)]
+struct ReplayWindow {
+    /// One more than the highest nonce received so far
+    next: u64,
+    /// Bit `i` is set if nonce `next - 1 - i` has been received
+    seen: u64,
+}
+
+impl ReplayWindow {
+    /// Mark a nonce as received, returning false if it already was (or is too old to tell)
+    fn accept(&mut self, nonce: u64) -> bool {
+        if nonce >= self.next {
+            let shift = nonce - self.next + 1;
+            self.seen = if shift >= REPLAY_WINDOW {
+                0
+            } else {
+                self.seen << shift
+            } | 1;
+            self.next = nonce + 1;
+            return true;
+        }
+        let age = self.next - 1 - nonce;
+        if age >= REPLAY_WINDOW || self.seen & (1 << age) != 0 {
+            return false;
+        }
+        self.seen |= 1 << age;
+        true
+    }
+}
+
+#[derive(Debug)]
+struct Session {
+    state: StatelessTransportState,
+    next_nonce: u64,
+    replay: ReplayWindow,
+    /// The handshake messages that created this session, so a resent
+    /// handshake can be answered again without starting over
+    handshake: (Vec<u8>, Vec<u8>),
+    /// Whether the peer has proven it's really at its address, by sending data
+    /// only it could have encrypted after getting our handshake
+    confirmed: bool,
+}
+
+#[derive(Debug)]
+enum PeerState {
+    Handshaking {
+        state: Box<HandshakeState>,
+        init: Vec<u8>,
+        last_sent: Instant,
+        queued: Vec<Vec<u8>>,
+    },
+    Established(Box<Session>),
+}
+
+#[derive(Debug)]
+struct Peer {
+    state: PeerState,
+    last_received: Instant,
+}
+
+/// A transport that encrypts and authenticates every packet sent over another transport
+///
+/// This is meant for client/server games, where one side is created with
+/// [EncryptedTransport::server] and the other with [EncryptedTransport::client].
+pub struct EncryptedTransport<T> {
+    inner: T,
+    role: Role,
+    peers: Mutex<HashMap<Address, Peer>>,
+    /// When each IP address started its first handshake in the current
+    /// window, and how many it has started since, for servers
+    handshakes: Mutex<HashMap<IpAddr, (Instant, u32)>>,
+}
+
+impl<T: fmt::Debug> fmt::Debug for EncryptedTransport<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EncryptedTransport")
+            .field("inner", &self.inner)
+            .field("peers", &self.peers.lock().keys())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T: Transport> EncryptedTransport<T> {
+    /// Accept encrypted connections, proving to clients that this is the server with `key`
+    pub fn server(inner: T, key: &Keypair) -> Self {
+        Self {
+            inner,
+            role: Role::Server {
+                private: key.private,
+            },
+            peers: Mutex::new(HashMap::new()),
+            handshakes: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Connect to a server, only accepting it if it has the private key for `server_key`
+    pub fn client(inner: T, server_key: PublicKey) -> Self {
+        Self {
+            inner,
+            role: Role::Client { server_key },
+            peers: Mutex::new(HashMap::new()),
+            handshakes: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Whether the handshake with a peer has completed, so packets can be sent to it
+    pub fn is_established(&self, addr: Address) -> bool {
+        self.peers
+            .lock()
+            .get(&addr)
+            .is_some_and(|peer| matches!(peer.state, PeerState::Established(_)))
+    }
+
+    fn send_encrypted(&self, addr: Address, session: &mut Session, data: &[u8]) -> io::Result<()> {
+        let nonce = session.next_nonce;
+        session.next_nonce += 1;
+        let mut packet = vec![0; OVERHEAD + data.len()];
+        packet[0] = KIND_DATA;
+        packet[1..9].copy_from_slice(&nonce.to_le_bytes());
+        let len = session
+            .state
+            .write_message(nonce, data, &mut packet[9..])
+            .map_err(io::Error::other)?;
+        packet.truncate(9 + len);
+        self.inner.send_to(addr, &packet)
+    }
+
+    fn send_handshake(&self, addr: Address, kind: u8, message: &[u8]) -> io::Result<()> {
+        let mut packet = Vec::with_capacity(1 + message.len());
+        packet.push(kind);
+        packet.extend_from_slice(message);
+        self.inner.send_to(addr, &packet)
+    }
+
+    fn start_handshake(&self, server_key: &PublicKey) -> io::Result<(HandshakeState, Vec<u8>)> {
+        let mut state = Builder::new(NOISE_PARAMS.parse().unwrap())
+            .remote_public_key(&server_key.0)
+            .build_initiator()
+            .map_err(io::Error::other)?;
+        let mut init = vec![0; 1024];
+        let len = state
+            .write_message(&[], &mut init)
+            .map_err(io::Error::other)?;
+        init.truncate(len);
+        Ok((state, init))
+    }
+
+    /// Answer a client's handshake, returning the new peer if it was valid
+    fn accept_handshake(&self, private: &[u8; 32], init: &[u8]) -> Option<Session> {
+        let mut state = Builder::new(NOISE_PARAMS.parse().unwrap())
+            .local_private_key(private)
+            .build_responder()
+            .ok()?;
+        state.read_message(init, &mut []).ok()?;
+        let mut reply = vec![0; 1024];
+        let len = state.write_message(&[], &mut reply).ok()?;
+        reply.truncate(len);
+        Some(Session {
+            state: state.into_stateless_transport_mode().ok()?,
+            next_nonce: 0,
+            replay: ReplayWindow::default(),
+            handshake: (init.to_vec(), reply),
+            confirmed: false,
+        })
+    }
+
+    /// Whether the server can afford to answer a new handshake from `addr`
+    ///
+    /// Answering takes a Diffie-Hellman operation and keeps a session until it
+    /// times out, and anyone can send handshakes from spoofed addresses, so
+    /// both the handshakes that haven't been confirmed and the handshakes each
+    /// IP address starts are limited.
+    fn allow_handshake(&self, peers: &HashMap<Address, Peer>, addr: Address) -> bool {
+        let unconfirmed = peers
+            .values()
+            .filter(|peer| matches!(&peer.state, PeerState::Established(s) if !s.confirmed))
+            .count();
+        if unconfirmed >= MAX_UNCONFIRMED {
+            return false;
+        }
+        let mut handshakes = self.handshakes.lock();
+        let now = Instant::now();
+        handshakes.retain(|_, (start, _)| now.duration_since(*start) < HANDSHAKE_RATE_WINDOW);
+        if handshakes.len() >= MAX_UNCONFIRMED && !handshakes.contains_key(&addr.ip()) {
+            return false;
+        }
+        let (_, count) = handshakes.entry(addr.ip()).or_insert((now, 0));
+        *count += 1;
+        *count <= MAX_HANDSHAKES_PER_IP
+    }
+
+    /// Handle a single incoming packet, returning the decrypted contents if it was data
+    fn receive(
+        &self,
+        peers: &mut HashMap<Address, Peer>,
+        addr: Address,
+        data: &[u8],
+    ) -> io::Result<Option<Vec<u8>>> {
+        let Some((&kind, body)) = data.split_first() else {
+            return Ok(None);
+        };
+        match (kind, self.role) {
+            (KIND_HANDSHAKE_INIT, Role::Server { private }) => {
+                if let Some(Peer {
+                    state: PeerState::Established(session),
+                    ..
+                }) = peers.get(&addr)
+                {
+                    if session.handshake.0 != body {
+                        // Anyone can send a handshake from a spoofed address, so it mustn't
+                        // replace the session. A client that restarted has to wait for the old
+                        // one to time out.
+                        return Ok(None);
+                    }
+                    // The reply was lost, send it again. This doesn't keep the session alive,
+                    // since anyone could resend the handshake.
+                    return self
+                        .send_handshake(addr, KIND_HANDSHAKE_REPLY, &session.handshake.1)
+                        .map(|_| None);
+                }
+                if !self.allow_handshake(peers, addr) {
+                    return Ok(None);
+                }
+                if let Some(session) = self.accept_handshake(&private, body) {
+                    self.send_handshake(addr, KIND_HANDSHAKE_REPLY, &session.handshake.1)?;
+                    peers.insert(
+                        addr,
+                        Peer {
+                            state: PeerState::Established(Box::new(session)),
+                            last_received: Instant::now(),
+                        },
+                    );
+                }
+            }
+            (KIND_HANDSHAKE_REPLY, Role::Client { .. }) => {
+                let Some(peer) = peers.remove(&addr) else {
+                    return Ok(None);
+                };
+                let PeerState::Handshaking {
+                    mut state,
+                    init,
+                    queued,
+                    ..
+                } = peer.state
+                else {
+                    peers.insert(addr, peer);
+                    return Ok(None);
+                };
+                if state.read_message(body, &mut []).is_err() {
+                    // Not from the server we expected, keep waiting for the real one
+                    peers.insert(
+                        addr,
+                        Peer {
+                            state: PeerState::Handshaking {
+                                state,
+                                init,
+                                last_sent: Instant::now(),
+                                queued,
+                            },
+                            last_received: peer.last_received,
+                        },
+                    );
+                    return Ok(None);
+                }
+                let mut session = Session {
+                    state: state
+                        .into_stateless_transport_mode()
+                        .map_err(io::Error::other)?,
+                    next_nonce: 0,
+                    replay: ReplayWindow::default(),
+                    handshake: (init, body.to_vec()),
+                    // Only the server could have answered our handshake
+                    confirmed: true,
+                };
+                for data in queued {
+                    self.send_encrypted(addr, &mut session, &data)?;
+                }
+                peers.insert(
+                    addr,
+                    Peer {
+                        state: PeerState::Established(Box::new(session)),
+                        last_received: Instant::now(),
+                    },
+                );
+            }
+            (KIND_DATA, _) => {
+                let Some(Peer {
+                    state: PeerState::Established(session),
+                    last_received,
+                }) = peers.get_mut(&addr)
+                else {
+                    return Ok(None);
+                };
+                let Some((nonce, ciphertext)) = body.split_first_chunk::<8>() else {
+                    return Ok(None);
+                };
+                let nonce = u64::from_le_bytes(*nonce);
+                let mut plaintext = vec![0; ciphertext.len()];
+                let Ok(len) = session.state.read_message(nonce, ciphertext, &mut plaintext) else {
+                    return Ok(None);
+                };
+                if !session.replay.accept(nonce) {
+                    return Ok(None);
+                }
+                session.confirmed = true;
+                *last_received = Instant::now();
+                plaintext.truncate(len);
+                return Ok(Some(plaintext));
+            }
+            _ => {}
+        }
+        Ok(None)
+    }
+}
+
+impl<T: Transport> Transport for EncryptedTransport<T> {
+    fn send_to(&self, addr: Address, data: &[u8]) -> io::Result<()> {
+        let mut peers = self.peers.lock();
+        match (peers.get_mut(&addr), self.role) {
+            (
+                Some(Peer {
+                    state: PeerState::Established(session),
+                    ..
+                }),
+                _,
+            ) => self.send_encrypted(addr, session, data),
+            (
+                Some(Peer {
+                    state: PeerState::Handshaking { queued, .. },
+                    ..
+                }),
+                _,
+            ) => {
+                if queued.len() < MAX_QUEUED {
+                    queued.push(data.to_vec());
+                }
+                Ok(())
+            }
+            (None, Role::Client { server_key }) => {
+                let (state, init) = self.start_handshake(&server_key)?;
+                self.send_handshake(addr, KIND_HANDSHAKE_INIT, &init)?;
+                peers.insert(
+                    addr,
+                    Peer {
+                        state: PeerState::Handshaking {
+                            state: Box::new(state),
+                            init,
+                            last_sent: Instant::now(),
+                            queued: vec![data.to_vec()],
+                        },
+                        last_received: Instant::now(),
+                    },
+                );
+                Ok(())
+            }
+            // Servers can't start a handshake, so there's no way to reach this peer
+            (None, Role::Server { .. }) => Ok(()),
+        }
+    }
+
+    fn recv_from(&self) -> io::Result<Option<(Address, Vec<u8>)>> {
+        let mut peers = self.peers.lock();
+        let now = Instant::now();
+        peers.retain(|_, peer| {
+            let timeout = match &peer.state {
+                PeerState::Established(session) if !session.confirmed => UNCONFIRMED_TIMEOUT,
+                _ => PEER_TIMEOUT,
+            };
+            now.duration_since(peer.last_received) < timeout
+        });
+        for (&addr, peer) in peers.iter_mut() {
+            if let PeerState::Handshaking {
+                init, last_sent, ..
+            } = &mut peer.state
+            {
+                if now.duration_since(*last_sent) >= HANDSHAKE_RESEND_INTERVAL {
+                    *last_sent = now;
+                    self.send_handshake(addr, KIND_HANDSHAKE_INIT, init)?;
+                }
+            }
+        }
+
+        while let Some((addr, data)) = self.inner.recv_from()? {
+            if let Some(data) = self.receive(&mut peers, addr, &data)? {
+                return Ok(Some((addr, data)));
+            }
+        }
+        Ok(None)
+    }
+
+    fn local_addr(&self) -> io::Result<Address> {
+        self.inner.local_addr()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use super::*;
+
+    /// Delivers queued packets, and drops everything sent
+    #[derive(Default)]
+    struct QueueTransport {
+        incoming: Mutex<VecDeque<(Address, Vec<u8>)>>,
+    }
+
+    impl Transport for QueueTransport {
+        fn send_to(&self, _addr: Address, _data: &[u8]) -> io::Result<()> {
+            Ok(())
+        }
+
+        fn recv_from(&self) -> io::Result<Option<(Address, Vec<u8>)>> {
+            Ok(self.incoming.lock().pop_front())
+        }
+
+        fn local_addr(&self) -> io::Result<Address> {
+            Ok("127.0.0.1:7777".parse().unwrap())
+        }
+    }
+
+    fn handshake(server: &EncryptedTransport<QueueTransport>, key: &Keypair) -> Vec<u8> {
+        let (_, init) = server.start_handshake(&key.public()).unwrap();
+        let mut packet = vec![KIND_HANDSHAKE_INIT];
+        packet.extend(init);
+        packet
+    }
+
+    #[test]
+    fn handshakes_are_limited_per_ip() {
+        let key = Keypair::generate();
+        let server = EncryptedTransport::server(QueueTransport::default(), &key);
+        for port in 0..20 {
+            let addr = Address::from(([10, 0, 0, 1], 1000 + port));
+            let packet = handshake(&server, &key);
+            server.inner.incoming.lock().push_back((addr, packet));
+        }
+        server.recv_from().unwrap();
+        assert_eq!(server.peers.lock().len(), MAX_HANDSHAKES_PER_IP as usize);
+    }
+
+    #[test]
+    fn unconfirmed_handshakes_are_limited() {
+        let key = Keypair::generate();
+        let server = EncryptedTransport::server(QueueTransport::default(), &key);
+        for i in 0..MAX_UNCONFIRMED as u32 + 20 {
+            let addr = Address::from((i.to_be_bytes(), 1000));
+            let packet = handshake(&server, &key);
+            server.inner.incoming.lock().push_back((addr, packet));
+        }
+        server.recv_from().unwrap();
+        assert_eq!(server.peers.lock().len(), MAX_UNCONFIRMED);
+    }
+}
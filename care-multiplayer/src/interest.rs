@@ -0,0 +1,118 @@
+//! Interest management, scoping which synced values each client receives
+//!
+//! Broadcasting every value to every client does not scale beyond a handful
+//! of players, so the server can limit what each client receives, either
+//! spatially (using [Region]s) or with explicit subscription groups.
+//!
+//! A synced value is sent to a client when all of these are true:
+//! - The value has no position, or its position is inside the client's region
+//! - The value has no group, or the client is subscribed to its group
+
+use std::collections::{HashMap, HashSet};
+
+use crate::server::ClientId;
+
+/// An explicit subscription group that synced values can belong to
+pub type Group = u32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+/// An area of the world a client is interested in
+pub enum Region {
+    #[default]
+    /// The entire world
+    Everywhere,
+    /// Everything within `radius` of `center`
+    Circle {
+        /// Center of the circle
+        center: [f32; 2],
+        /// Radius of the circle
+        radius: f32,
+    },
+    /// Everything inside an axis aligned rectangle
+    Rect {
+        /// The corner with the lowest coordinates
+        min: [f32; 2],
+        /// The corner with the highest coordinates
+        max: [f32; 2],
+    },
+}
+
+impl Region {
+    /// Check whether a point is inside of the region
+    pub fn contains(&self, point: [f32; 2]) -> bool {
+        match *self {
+            Region::Everywhere => true,
+            Region::Circle { center, radius } => {
+                let (dx, dy) = (point[0] - center[0], point[1] - center[1]);
+                dx * dx + dy * dy <= radius * radius
+            }
+            Region::Rect { min, max } => {
+                point[0] >= min[0] && point[0] <= max[0] && point[1] >= min[1] && point[1] <= max[1]
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct InterestManager {
+    regions: HashMap<ClientId, Region>,
+    subscriptions: HashMap<ClientId, HashSet<Group>>,
+    positions: HashMap<usize, [f32; 2]>,
+    groups: HashMap<usize, Group>,
+    /// Whether anything has changed since the last time visibility was recalculated
+    pub dirty: bool,
+}
+
+impl InterestManager {
+    pub fn set_region(&mut self, client: ClientId, region: Region) {
+        self.regions.insert(client, region);
+        self.dirty = true;
+    }
+
+    pub fn subscribe(&mut self, client: ClientId, group: Group) {
+        self.dirty |= self.subscriptions.entry(client).or_default().insert(group);
+    }
+
+    pub fn unsubscribe(&mut self, client: ClientId, group: Group) {
+        if let Some(groups) = self.subscriptions.get_mut(&client) {
+            self.dirty |= groups.remove(&group);
+        }
+    }
+
+    pub fn set_position(&mut self, id: usize, position: Option<[f32; 2]>) {
+        let old = match position {
+            Some(position) => self.positions.insert(id, position),
+            None => self.positions.remove(&id),
+        };
+        self.dirty |= old != position;
+    }
+
+    pub fn set_group(&mut self, id: usize, group: Option<Group>) {
+        let old = match group {
+            Some(group) => self.groups.insert(id, group),
+            None => self.groups.remove(&id),
+        };
+        self.dirty |= old != group;
+    }
+
+    pub fn remove_client(&mut self, client: ClientId) {
+        self.regions.remove(&client);
+        self.subscriptions.remove(&client);
+    }
+
+    pub fn is_interested(&self, client: ClientId, id: usize) -> bool {
+        let in_region = self.positions.get(&id).is_none_or(|&pos| {
+            self.regions
+                .get(&client)
+                .copied()
+                .unwrap_or_default()
+                .contains(pos)
+        });
+        let in_group = self.groups.get(&id).is_none_or(|group| {
+            self.subscriptions
+                .get(&client)
+                .is_some_and(|groups| groups.contains(group))
+        });
+        in_region && in_group
+    }
+}
@@ -0,0 +1,302 @@
+//! Snapshot interpolation for smoothly rendering remote state
+//!
+//! Updates from the network arrive at irregular intervals, and usually a lot
+//! less often than frames are rendered, so simply applying the latest value
+//! makes remote objects visibly jitter. Instead, snapshots are stored with a
+//! timestamp, and rendering happens slightly in the past (~100ms by default)
+//! by interpolating between the two snapshots surrounding the render time.
+//!
+//! Snapshots can either be timestamped when they arrive, or with the time
+//! the server sent them using [crate::client::Client::tick_time], which
+//! removes network jitter from the timestamps entirely.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    hash::Hash,
+    time::{Duration, Instant},
+};
+
+use crate::sync::{SyncedValue, Transferable};
+
+/// The default amount of time to render remote state in the past
+pub const DEFAULT_DELAY: Duration = Duration::from_millis(100);
+
+/// The default number of snapshots kept for each value
+pub const DEFAULT_CAPACITY: usize = 32;
+
+/// Values that can be blended between two snapshots
+pub trait Interpolate: Clone {
+    /// Blend between `self` (at `t = 0`) and `other` (at `t = 1`)
+    fn interpolate(&self, other: &Self, t: f64) -> Self;
+}
+
+impl Interpolate for f32 {
+    fn interpolate(&self, other: &Self, t: f64) -> Self {
+        self + (other - self) * t as f32
+    }
+}
+
+impl Interpolate for f64 {
+    fn interpolate(&self, other: &Self, t: f64) -> Self {
+        self + (other - self) * t
+    }
+}
+
+impl<T: Interpolate, const N: usize> Interpolate for [T; N] {
+    fn interpolate(&self, other: &Self, t: f64) -> Self {
+        std::array::from_fn(|i| self[i].interpolate(&other[i], t))
+    }
+}
+
+impl<A: Interpolate, B: Interpolate> Interpolate for (A, B) {
+    fn interpolate(&self, other: &Self, t: f64) -> Self {
+        (self.0.interpolate(&other.0, t), self.1.interpolate(&other.1, t))
+    }
+}
+
+impl<A: Interpolate, B: Interpolate, C: Interpolate> Interpolate for (A, B, C) {
+    fn interpolate(&self, other: &Self, t: f64) -> Self {
+        (
+            self.0.interpolate(&other.0, t),
+            self.1.interpolate(&other.1, t),
+            self.2.interpolate(&other.2, t),
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+/// The position, rotation and scale of an entity, in 2d
+pub struct Transform2d {
+    /// Position of the entity
+    pub position: [f32; 2],
+    /// Rotation in radians
+    pub rotation: f32,
+    /// Scale of the entity
+    pub scale: [f32; 2],
+}
+
+impl Interpolate for Transform2d {
+    fn interpolate(&self, other: &Self, t: f64) -> Self {
+        // Rotate the short way around, so going from 359 to 1 degrees doesn't spin backwards
+        let tau = std::f32::consts::TAU;
+        let delta = (other.rotation - self.rotation + tau / 2.0).rem_euclid(tau) - tau / 2.0;
+        Self {
+            position: self.position.interpolate(&other.position, t),
+            rotation: self.rotation + delta * t as f32,
+            scale: self.scale.interpolate(&other.scale, t),
+        }
+    }
+}
+
+impl<C> Transferable<C> for Transform2d {
+    fn send(&self, _context: &C) -> Vec<u8> {
+        [
+            self.position[0],
+            self.position[1],
+            self.rotation,
+            self.scale[0],
+            self.scale[1],
+        ]
+        .iter()
+        .flat_map(|n| n.to_le_bytes())
+        .collect()
+    }
+    fn receive(data: &[u8], context: &C) -> Self {
+        let field = |i: usize| f32::receive(data.get(i * 4..).unwrap_or_default(), context);
+        Self {
+            position: [field(0), field(1)],
+            rotation: field(2),
+            scale: [field(3), field(4)],
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+/// A time-ordered list of snapshots of a single value
+///
+/// Timestamps are in seconds, on whatever timeline the snapshots were taken
+/// on (usually the server's clock).
+pub struct SnapshotBuffer<T> {
+    snapshots: VecDeque<(f64, T)>,
+    capacity: usize,
+}
+
+impl<T: Interpolate> SnapshotBuffer<T> {
+    /// Create an empty snapshot buffer, that keeps at most `capacity` snapshots
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            snapshots: VecDeque::with_capacity(capacity),
+            capacity: capacity.max(2),
+        }
+    }
+
+    /// Add a snapshot taken at `time`
+    ///
+    /// Snapshots may arrive out of order, they are kept sorted by time.
+    /// Snapshots older than everything in a full buffer are dropped.
+    pub fn push(&mut self, time: f64, value: T) {
+        if self.snapshots.len() >= self.capacity {
+            if self.snapshots.front().is_some_and(|(t, _)| time <= *t) {
+                return;
+            }
+            self.snapshots.pop_front();
+        }
+        let idx = self.snapshots.partition_point(|(t, _)| *t <= time);
+        if idx > 0 && self.snapshots[idx - 1].0 == time {
+            self.snapshots[idx - 1].1 = value;
+        } else {
+            self.snapshots.insert(idx, (time, value));
+        }
+    }
+
+    /// Get the value at `time`, interpolated between the surrounding snapshots
+    ///
+    /// Times before the first or after the last snapshot are clamped to that
+    /// snapshot, values are never extrapolated.
+    pub fn sample(&self, time: f64) -> Option<T> {
+        let idx = self.snapshots.partition_point(|(t, _)| *t <= time);
+        if idx == 0 {
+            return self.snapshots.front().map(|(_, v)| v.clone());
+        }
+        let (t0, v0) = &self.snapshots[idx - 1];
+        let Some((t1, v1)) = self.snapshots.get(idx) else {
+            return Some(v0.clone());
+        };
+        Some(v0.interpolate(v1, (time - t0) / (t1 - t0)))
+    }
+
+    /// The most recent snapshot
+    pub fn latest(&self) -> Option<&(f64, T)> {
+        self.snapshots.back()
+    }
+
+    /// Remove all snapshots older than the two surrounding `time`
+    pub fn discard_before(&mut self, time: f64) {
+        while self.snapshots.len() > 2 && self.snapshots[1].0 <= time {
+            self.snapshots.pop_front();
+        }
+    }
+
+    /// Number of snapshots currently stored
+    pub fn len(&self) -> usize {
+        self.snapshots.len()
+    }
+
+    /// Whether there are no snapshots stored
+    pub fn is_empty(&self) -> bool {
+        self.snapshots.is_empty()
+    }
+
+    /// Remove all snapshots
+    pub fn clear(&mut self) {
+        self.snapshots.clear();
+    }
+}
+
+#[derive(Debug)]
+/// Client-side interpolation buffer for a set of remote values, e.g. the
+/// transforms of all remote entities, keyed by id
+///
+/// Values are rendered [Interpolator::delay] in the past, so there is
+/// (usually) a snapshot on either side of the render time.
+pub struct Interpolator<K, T> {
+    delay: Duration,
+    capacity: usize,
+    epoch: Instant,
+    buffers: HashMap<K, SnapshotBuffer<T>>,
+}
+
+impl<K: Eq + Hash, T: Interpolate> Default for Interpolator<K, T> {
+    fn default() -> Self {
+        Self::new(DEFAULT_DELAY)
+    }
+}
+
+impl<K: Eq + Hash, T: Interpolate> Interpolator<K, T> {
+    /// Create a new interpolator, rendering values `delay` in the past
+    pub fn new(delay: Duration) -> Self {
+        Self {
+            delay,
+            capacity: DEFAULT_CAPACITY,
+            epoch: Instant::now(),
+            buffers: HashMap::new(),
+        }
+    }
+
+    /// The amount of time in the past that values are rendered at
+    pub fn delay(&self) -> Duration {
+        self.delay
+    }
+
+    /// Change the amount of time in the past that values are rendered at
+    ///
+    /// This should be a bit more than the time between updates from the network
+    pub fn set_delay(&mut self, delay: Duration) {
+        self.delay = delay;
+    }
+
+    /// The current time on the local timeline, as used by [Interpolator::push]
+    pub fn now(&self) -> f64 {
+        self.epoch.elapsed().as_secs_f64()
+    }
+
+    /// Record a snapshot of a value as received right now
+    pub fn push(&mut self, key: K, value: T) {
+        let time = self.now();
+        self.push_at(key, time, value);
+    }
+
+    /// Record a snapshot of a value taken at a specific time
+    ///
+    /// If timestamps are given on a different timeline (e.g. server time),
+    /// use [Interpolator::sample_at] to read values back.
+    pub fn push_at(&mut self, key: K, time: f64, value: T) {
+        let capacity = self.capacity;
+        self.buffers
+            .entry(key)
+            .or_insert_with(|| SnapshotBuffer::new(capacity))
+            .push(time, value);
+    }
+
+    /// Get the value to render right now, [Interpolator::delay] in the past
+    pub fn sample(&self, key: &K) -> Option<T> {
+        self.sample_at(key, self.now() - self.delay.as_secs_f64())
+    }
+
+    /// Get the value at a specific time
+    pub fn sample_at(&self, key: &K, time: f64) -> Option<T> {
+        self.buffers.get(key).and_then(|buf| buf.sample(time))
+    }
+
+    /// Iterate over the interpolated values for every key at a specific time
+    pub fn sample_all_at(&self, time: f64) -> impl Iterator<Item = (&K, T)> {
+        self.buffers
+            .iter()
+            .filter_map(move |(key, buf)| Some((key, buf.sample(time)?)))
+    }
+
+    /// Drop snapshots that are too old to ever be rendered again
+    pub fn discard_before(&mut self, time: f64) {
+        for buf in self.buffers.values_mut() {
+            buf.discard_before(time);
+        }
+    }
+
+    /// Stop tracking a value, e.g. when an entity is despawned
+    pub fn remove(&mut self, key: &K) {
+        self.buffers.remove(key);
+    }
+}
+
+impl<T: Interpolate> Interpolator<usize, T> {
+    /// Record a snapshot of the current value of a [SyncedValue], keyed by its id
+    ///
+    /// Call this whenever the value has been updated from the network.
+    pub fn push_synced<C>(&mut self, value: &SyncedValue<T, C>)
+    where
+        T: Transferable<C>,
+    {
+        let snapshot = value.get().clone();
+        self.push(value.id(), snapshot);
+    }
+}
@@ -16,7 +16,8 @@
 //! module to create a custom server that contains your game's core logic,
 //! and create a client that resets any variables that may be visible over
 //! the network to the values that are on the server. The [`sync::SyncedValue`]
-//! type might be helpful for this.
+//! type might be helpful for this, along with [`sync::SyncedVec`] and
+//! [`sync::SyncedMap`] for collections.
 //!
 //! # Trust everyone
 //! Trusting everyone means that each client handles it's own calculations
@@ -32,7 +33,34 @@
 //! resolving situations where multiple clients cannot come to an agreement
 //! over the state of the game. [Dolphin](https://dolphin-emu.org/)'s netplay
 //! feature uses this approach.
+//!
+//! The [`lockstep`] module implements this approach, by only exchanging
+//! inputs and waiting until every player's input for a tick has arrived.
 
+pub mod admin;
+pub mod chat;
 pub mod client;
+mod clock;
+pub mod compression;
+mod connection;
+pub mod driver;
+#[cfg(feature = "encryption")]
+pub mod encryption;
+pub mod interest;
+pub mod interpolation;
+pub mod lockstep;
+mod packet;
+pub mod persistence;
+pub mod rpc;
 pub mod server;
+pub mod stats;
+#[cfg(feature = "steam")]
+pub mod steam;
 pub mod sync;
+pub mod throttle;
+pub mod transport;
+#[cfg(feature = "voice")]
+pub mod voice;
+
+pub use care_macro::care_rpc as rpc;
+pub use connection::{Reliability, MAX_MESSAGE_SIZE, MAX_USER_CHANNEL};
@@ -0,0 +1,429 @@
+//! Deterministic lockstep sessions, for the "trust noone" model
+//!
+//! In a lockstep session, peers only ever exchange their inputs. Every peer
+//! runs the entire simulation, and a tick is only simulated once the inputs
+//! of every player for that tick have arrived. This requires the simulation
+//! to be perfectly deterministic (including random numbers and floating
+//! point maths) so every peer ends up in the same state.
+//!
+//! To hide latency, local inputs are scheduled [LockstepConfig::input_delay]
+//! ticks in the future, giving them time to reach the other peers before
+//! they are needed.
+
+use std::{
+    collections::{BTreeMap, VecDeque},
+    io,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    packet::{PacketReader, PacketWriter},
+    sync::Transferable,
+    transport::{Address, Transport, MAX_PACKET_SIZE},
+};
+
+const PACKET_INPUTS: u8 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// What to do when the inputs of a remote player stop arriving
+pub enum StallPolicy {
+    /// Keep waiting for the inputs forever
+    Wait,
+    /// Disconnect players whose inputs haven't arrived after the given duration
+    ///
+    /// Disconnected players are treated as having the default input from then on. Every peer
+    /// decides this on its own, so with more than two players, peers that got different inputs
+    /// from a player before it went quiet will desync, this is only safe for two player sessions.
+    Disconnect(Duration),
+}
+
+#[derive(Debug, Clone)]
+/// Configuration for a [LockstepSession]
+pub struct LockstepConfig {
+    /// How many ticks in the future local inputs are scheduled for
+    pub input_delay: u64,
+    /// What to do when inputs stop arriving
+    pub stall_policy: StallPolicy,
+    /// How often unacknowledged inputs are sent again while waiting
+    pub resend_interval: Duration,
+    /// Maximum number of unacknowledged inputs to send in each packet
+    ///
+    /// Inputs further ahead than this of the ones still needed from a peer are ignored, so they
+    /// can't fill up memory.
+    pub max_redundancy: usize,
+}
+
+impl Default for LockstepConfig {
+    fn default() -> Self {
+        Self {
+            input_delay: 2,
+            stall_policy: StallPolicy::Wait,
+            resend_interval: Duration::from_millis(33),
+            max_redundancy: 32,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// The result of trying to advance a [LockstepSession]
+pub enum LockstepStatus<I> {
+    /// All inputs have arrived, simulate this tick using these inputs (indexed by player)
+    Advance {
+        /// The tick to simulate
+        tick: u64,
+        /// The input of every player for this tick
+        inputs: Vec<I>,
+    },
+    /// Some inputs haven't arrived yet, don't simulate anything
+    Waiting {
+        /// The players whose inputs are missing (may include the local player)
+        missing: Vec<usize>,
+        /// How long the session has been waiting for
+        stalled_for: Duration,
+    },
+    /// The stall policy has caused some players to be disconnected
+    Disconnected {
+        /// The players that have been disconnected
+        players: Vec<usize>,
+    },
+}
+
+#[derive(Debug)]
+struct Peer {
+    addr: Address,
+    connected: bool,
+    /// The first tick we don't have this peer's input for
+    next_needed: u64,
+    /// The first tick of our inputs that this peer doesn't have
+    acked: u64,
+}
+
+/// A lockstep session between a fixed set of players
+pub struct LockstepSession<I> {
+    transport: Box<dyn Transport>,
+    config: LockstepConfig,
+    local_player: usize,
+    players: Vec<Option<Peer>>,
+    tick: u64,
+    next_local_tick: u64,
+    inputs: BTreeMap<u64, Vec<Option<I>>>,
+    local_history: VecDeque<(u64, Vec<u8>)>,
+    last_send: Instant,
+    stalled_since: Option<Instant>,
+}
+
+impl<I> std::fmt::Debug for LockstepSession<I> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LockstepSession")
+            .field("config", &self.config)
+            .field("local_player", &self.local_player)
+            .field("players", &self.players)
+            .field("tick", &self.tick)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<I: Transferable + Clone + Default> LockstepSession<I> {
+    /// Start a new session, `peers` lists the player index and address of
+    /// every remote player
+    ///
+    /// Every peer must agree on the player indices and the input delay.
+    /// Player indices are sent as 16 bit numbers, so this panics for more than
+    /// 65536 players, or if the local player is listed as a peer.
+    pub fn new(
+        transport: impl Transport + 'static,
+        local_player: usize,
+        peers: &[(usize, Address)],
+        config: LockstepConfig,
+    ) -> Self {
+        let num_players = peers
+            .iter()
+            .map(|(player, _)| player + 1)
+            .max()
+            .unwrap_or(0)
+            .max(local_player + 1);
+        assert!(
+            num_players <= usize::from(u16::MAX) + 1,
+            "Lockstep sessions can't have more than 65536 players"
+        );
+        let mut players: Vec<Option<Peer>> = (0..num_players).map(|_| None).collect();
+        for &(player, addr) in peers {
+            assert!(
+                player != local_player,
+                "The local player can't also be a remote peer"
+            );
+            players[player] = Some(Peer {
+                addr,
+                connected: true,
+                next_needed: config.input_delay,
+                acked: config.input_delay,
+            });
+        }
+        // Nobody has any input for the first few ticks, so just use the default for everyone
+        let inputs = (0..config.input_delay)
+            .map(|tick| (tick, vec![Some(I::default()); num_players]))
+            .collect();
+        Self {
+            transport: Box::new(transport),
+            local_player,
+            players,
+            tick: 0,
+            next_local_tick: config.input_delay,
+            inputs,
+            local_history: VecDeque::new(),
+            last_send: Instant::now(),
+            stalled_since: None,
+            config,
+        }
+    }
+
+    /// The next tick to be simulated
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    /// The index of the local player
+    pub fn local_player(&self) -> usize {
+        self.local_player
+    }
+
+    /// The total number of players, including the local player
+    pub fn num_players(&self) -> usize {
+        self.players.len()
+    }
+
+    /// Whether a player is still part of the session
+    pub fn is_connected(&self, player: usize) -> bool {
+        player == self.local_player
+            || self
+                .players
+                .get(player)
+                .is_some_and(|p| p.as_ref().is_some_and(|p| p.connected))
+    }
+
+    /// Stop waiting for a player's inputs, they'll use the default input from now on
+    pub fn disconnect(&mut self, player: usize) {
+        if let Some(Some(peer)) = self.players.get_mut(player) {
+            peer.connected = false;
+        }
+    }
+
+    /// Submit the local player's input for the next tick, it will be used
+    /// [LockstepConfig::input_delay] ticks from now
+    pub fn add_local_input(&mut self, input: I) -> io::Result<()> {
+        let tick = self.next_local_tick;
+        self.next_local_tick += 1;
+        self.local_history.push_back((tick, input.send(&())));
+        let num_players = self.players.len();
+        self.inputs
+            .entry(tick)
+            .or_insert_with(|| vec![None; num_players])[self.local_player] = Some(input);
+        self.send_inputs()
+    }
+
+    /// Receive inputs from the network, and resend our own if needed
+    ///
+    /// Call this at least once per frame
+    pub fn poll(&mut self) -> io::Result<()> {
+        while let Some((addr, data)) = self.transport.recv_from()? {
+            self.receive_packet(addr, &data);
+        }
+        if self.last_send.elapsed() >= self.config.resend_interval {
+            self.send_inputs()?;
+        }
+        Ok(())
+    }
+
+    /// Try to advance the simulation by one tick
+    ///
+    /// Keep calling this until it stops returning [LockstepStatus::Advance]
+    /// to catch up on every tick that's ready.
+    pub fn advance(&mut self) -> LockstepStatus<I> {
+        let missing: Vec<_> = (0..self.players.len())
+            .filter(|&player| {
+                self.is_connected(player)
+                    && self
+                        .inputs
+                        .get(&self.tick)
+                        .is_none_or(|row| row[player].is_none())
+            })
+            .collect();
+        if missing.is_empty() {
+            let tick = self.tick;
+            self.tick += 1;
+            self.stalled_since = None;
+            let inputs = self
+                .inputs
+                .remove(&tick)
+                .unwrap_or_else(|| vec![None; self.players.len()])
+                .into_iter()
+                .map(Option::unwrap_or_default)
+                .collect();
+            return LockstepStatus::Advance { tick, inputs };
+        }
+
+        let stalled_for = self.stalled_since.get_or_insert_with(Instant::now).elapsed();
+        if let StallPolicy::Disconnect(timeout) = self.config.stall_policy {
+            let remote_missing: Vec<_> = missing
+                .iter()
+                .copied()
+                .filter(|&player| player != self.local_player)
+                .collect();
+            if stalled_for >= timeout && !remote_missing.is_empty() {
+                for &player in &remote_missing {
+                    self.disconnect(player);
+                }
+                self.stalled_since = None;
+                return LockstepStatus::Disconnected {
+                    players: remote_missing,
+                };
+            }
+        }
+        LockstepStatus::Waiting {
+            missing,
+            stalled_for,
+        }
+    }
+
+    fn send_inputs(&mut self) -> io::Result<()> {
+        self.last_send = Instant::now();
+        // Forget inputs every connected peer already has
+        let min_acked = self
+            .players
+            .iter()
+            .flatten()
+            .filter(|p| p.connected)
+            .map(|p| p.acked)
+            .min()
+            .unwrap_or(self.next_local_tick);
+        while self
+            .local_history
+            .front()
+            .is_some_and(|(tick, _)| *tick < min_acked)
+        {
+            self.local_history.pop_front();
+        }
+
+        for peer in self.players.iter().flatten().filter(|p| p.connected) {
+            let mut packet = PacketWriter::new(PACKET_INPUTS);
+            packet.u16(self.local_player as u16).u64(peer.next_needed);
+            let pending: Vec<_> = self
+                .local_history
+                .iter()
+                .filter(|(tick, _)| *tick >= peer.acked)
+                .take(self.config.max_redundancy)
+                .take_while({
+                    let mut size = packet.len() + 2;
+                    move |(_, data)| {
+                        size += 12 + data.len();
+                        size <= MAX_PACKET_SIZE
+                    }
+                })
+                .collect();
+            packet.u16(pending.len() as u16);
+            for (tick, data) in pending {
+                packet.u64(*tick).bytes(data);
+            }
+            self.transport.send_to(peer.addr, &packet.finish())?;
+        }
+        Ok(())
+    }
+
+    fn receive_packet(&mut self, addr: Address, data: &[u8]) {
+        let Some((PACKET_INPUTS, mut reader)) = PacketReader::new(data) else {
+            return;
+        };
+        let Some(player) = reader.u16().map(usize::from) else {
+            return;
+        };
+        let num_players = self.players.len();
+        let Some(Some(peer)) = self.players.get_mut(player) else {
+            return;
+        };
+        if peer.addr != addr || !peer.connected {
+            return;
+        }
+        if let Some(acked) = reader.u64() {
+            peer.acked = peer.acked.max(acked);
+        }
+        // The peer only ever sends this many inputs from the first one we need
+        let horizon = peer
+            .next_needed
+            .saturating_add(self.config.max_redundancy as u64);
+        let count = reader.u16().unwrap_or(0);
+        for _ in 0..count {
+            let (Some(tick), Some(data)) = (reader.u64(), reader.bytes()) else {
+                break;
+            };
+            if tick < self.tick || tick >= horizon {
+                continue;
+            }
+            let row = self
+                .inputs
+                .entry(tick)
+                .or_insert_with(|| vec![None; num_players]);
+            if row[player].is_none() {
+                row[player] = Some(I::receive(data, &()));
+            }
+        }
+        while self
+            .inputs
+            .get(&peer.next_needed)
+            .is_some_and(|row| row[player].is_some())
+        {
+            peer.next_needed += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct NullTransport;
+
+    impl Transport for NullTransport {
+        fn send_to(&self, _addr: Address, _data: &[u8]) -> io::Result<()> {
+            Ok(())
+        }
+
+        fn recv_from(&self) -> io::Result<Option<(Address, Vec<u8>)>> {
+            Ok(None)
+        }
+
+        fn local_addr(&self) -> io::Result<Address> {
+            Ok(addr())
+        }
+    }
+
+    fn addr() -> Address {
+        "127.0.0.1:4000".parse().unwrap()
+    }
+
+    fn inputs(ticks: &[u64]) -> Vec<u8> {
+        let mut packet = PacketWriter::new(PACKET_INPUTS);
+        packet.u16(1).u64(0).u16(ticks.len() as u16);
+        for &tick in ticks {
+            packet.u64(tick).bytes(&7u32.send(&()));
+        }
+        packet.finish()
+    }
+
+    #[test]
+    fn inputs_too_far_ahead_are_ignored() {
+        let config = LockstepConfig::default();
+        let horizon = config.input_delay + config.max_redundancy as u64;
+        let mut session =
+            LockstepSession::<u32>::new(NullTransport, 0, &[(1, addr())], config);
+        session.receive_packet(addr(), &inputs(&[horizon - 1, horizon, u64::MAX]));
+        assert!(session.inputs.contains_key(&(horizon - 1)));
+        assert!(!session.inputs.contains_key(&horizon));
+        assert!(!session.inputs.contains_key(&u64::MAX));
+    }
+
+    #[test]
+    #[should_panic]
+    fn the_local_player_cant_be_a_peer() {
+        LockstepSession::<u32>::new(NullTransport, 0, &[(0, addr())], Default::default());
+    }
+}
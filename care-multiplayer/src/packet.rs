@@ -0,0 +1,101 @@
+//! Helpers for reading and writing the binary packet formats used internally
+
+#[derive(Debug, Default)]
+pub(crate) struct PacketWriter {
+    data: Vec<u8>,
+}
+
+impl PacketWriter {
+    pub fn new(kind: u8) -> Self {
+        Self { data: vec![kind] }
+    }
+
+    pub fn u8(&mut self, n: u8) -> &mut Self {
+        self.data.push(n);
+        self
+    }
+
+    pub fn u16(&mut self, n: u16) -> &mut Self {
+        self.data.extend_from_slice(&n.to_le_bytes());
+        self
+    }
+
+    pub fn u32(&mut self, n: u32) -> &mut Self {
+        self.data.extend_from_slice(&n.to_le_bytes());
+        self
+    }
+
+    pub fn u64(&mut self, n: u64) -> &mut Self {
+        self.data.extend_from_slice(&n.to_le_bytes());
+        self
+    }
+
+    pub fn f64(&mut self, n: f64) -> &mut Self {
+        self.u64(n.to_bits())
+    }
+
+    /// Write a length-prefixed blob of bytes
+    pub fn bytes(&mut self, data: &[u8]) -> &mut Self {
+        self.u32(data.len() as u32);
+        self.data.extend_from_slice(data);
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn finish(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.data)
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct PacketReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> PacketReader<'a> {
+    /// Start reading a packet, returning the packet kind along with the reader
+    pub fn new(data: &'a [u8]) -> Option<(u8, Self)> {
+        let (&kind, data) = data.split_first()?;
+        Some((kind, Self { data }))
+    }
+
+    fn take<const N: usize>(&mut self) -> Option<[u8; N]> {
+        let (bytes, rest) = self.data.split_first_chunk::<N>()?;
+        self.data = rest;
+        Some(*bytes)
+    }
+
+    pub fn u8(&mut self) -> Option<u8> {
+        self.take::<1>().map(|[n]| n)
+    }
+
+    pub fn u16(&mut self) -> Option<u16> {
+        self.take().map(u16::from_le_bytes)
+    }
+
+    pub fn u32(&mut self) -> Option<u32> {
+        self.take().map(u32::from_le_bytes)
+    }
+
+    pub fn u64(&mut self) -> Option<u64> {
+        self.take().map(u64::from_le_bytes)
+    }
+
+    pub fn f64(&mut self) -> Option<f64> {
+        self.u64().map(f64::from_bits)
+    }
+
+    /// Read a length-prefixed blob of bytes
+    pub fn bytes(&mut self) -> Option<&'a [u8]> {
+        let len = self.u32()? as usize;
+        if len > self.data.len() {
+            return None;
+        }
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        Some(bytes)
+    }
+}
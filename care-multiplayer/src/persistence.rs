@@ -0,0 +1,159 @@
+//! Saving the server's world to disk, so it survives restarts
+//!
+//! A [WorldSave] holds the latest value of every synced value the server
+//! knows about (see [crate::server::Server::save_world]), the current tick,
+//! and any other data the game wants to keep, stored by name.
+//!
+//! ```ignore
+//! // Saving, e.g. every few minutes and when shutting down
+//! let mut save = server.save_world(WORLD_VERSION);
+//! save.set("seed", &seed, &());
+//! save.write("world.save")?;
+//!
+//! // Loading, before any clients connect
+//! let save = WorldSave::read("world.save", WORLD_VERSION, |save, from| {
+//!     // Upgrade saves from older versions of the game, one version at a time
+//!     if from == 1 {
+//!         save.set("difficulty", &1u8, &());
+//!     }
+//!     Ok(())
+//! })?;
+//! server.load_world(&save);
+//! for (id, data) in save.synced() {
+//!     // Give the values back to the local synced values with `receive`
+//! }
+//! ```
+
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::{self, Write},
+    path::Path,
+};
+
+use crate::{
+    packet::{PacketReader, PacketWriter},
+    sync::Transferable,
+};
+
+const MAGIC: &[u8; 4] = b"CARE";
+/// The version of the file format itself, separate from the game's version
+const FORMAT_VERSION: u8 = 1;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+/// A saved world, see the [module level documentation](self)
+pub struct WorldSave {
+    /// The version of the game that made the save
+    pub version: u32,
+    /// The server tick when the save was made
+    pub tick: u64,
+    /// Synced values by id, and whether each is a synced collection
+    pub(crate) synced: BTreeMap<usize, (Vec<u8>, bool)>,
+    data: BTreeMap<String, Vec<u8>>,
+}
+
+impl WorldSave {
+    /// Create an empty save
+    pub fn new(version: u32) -> Self {
+        Self {
+            version,
+            ..Default::default()
+        }
+    }
+
+    /// Iterate over the saved synced values, the data can be given to
+    /// [crate::sync::SyncedValue::receive] (or the `receive` of a synced collection)
+    pub fn synced(&self) -> impl Iterator<Item = (usize, &[u8])> {
+        self.synced
+            .iter()
+            .map(|(&id, (data, _))| (id, data.as_slice()))
+    }
+
+    /// Store some other data in the save
+    pub fn set<T: Transferable<C>, C>(&mut self, key: &str, value: &T, context: &C) {
+        self.data.insert(key.to_string(), value.send(context));
+    }
+
+    /// Read data stored with [WorldSave::set]
+    pub fn get<T: Transferable<C>, C>(&self, key: &str, context: &C) -> Option<T> {
+        self.data.get(key).map(|data| T::receive(data, context))
+    }
+
+    /// Remove data stored with [WorldSave::set], e.g. while migrating
+    pub fn remove(&mut self, key: &str) {
+        self.data.remove(key);
+    }
+
+    /// Write the save to a file
+    ///
+    /// The save is written to a temporary file first and then moved into
+    /// place, so crashing part way through never leaves a broken save behind.
+    pub fn write(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref();
+        let mut data = PacketWriter::new(FORMAT_VERSION);
+        data.u32(self.version)
+            .u64(self.tick)
+            .u32(self.synced.len() as u32);
+        for (&id, (value, collection)) in &self.synced {
+            data.u64(id as u64).u8(*collection as u8).bytes(value);
+        }
+        data.u32(self.data.len() as u32);
+        for (key, value) in &self.data {
+            data.bytes(key.as_bytes()).bytes(value);
+        }
+
+        let mut temp = path.as_os_str().to_owned();
+        temp.push(".tmp");
+        let mut file = File::create(&temp)?;
+        file.write_all(MAGIC)?;
+        file.write_all(&data.finish())?;
+        file.sync_all()?;
+        fs::rename(&temp, path)
+    }
+
+    /// Read a save from a file
+    ///
+    /// Saves made by older versions of the game are upgraded by calling
+    /// `migrate` with each version in turn, from the save's version up to
+    /// `version`. Saves from newer versions can't be read.
+    pub fn read(
+        path: impl AsRef<Path>,
+        version: u32,
+        mut migrate: impl FnMut(&mut WorldSave, u32) -> io::Result<()>,
+    ) -> io::Result<Self> {
+        let invalid = |message| io::Error::new(io::ErrorKind::InvalidData, message);
+        let file = fs::read(path)?;
+        let data = file
+            .strip_prefix(MAGIC)
+            .ok_or_else(|| invalid("Not a world save"))?;
+        let Some((FORMAT_VERSION, mut reader)) = PacketReader::new(data) else {
+            return Err(invalid("Unsupported world save format"));
+        };
+        let mut save =
+            Self::read_contents(&mut reader).ok_or_else(|| invalid("Corrupt world save"))?;
+        if save.version > version {
+            return Err(invalid("World save is from a newer version of the game"));
+        }
+        for from in save.version..version {
+            migrate(&mut save, from)?;
+            save.version = from + 1;
+        }
+        Ok(save)
+    }
+
+    fn read_contents(reader: &mut PacketReader) -> Option<Self> {
+        let mut save = Self::new(reader.u32()?);
+        save.tick = reader.u64()?;
+        for _ in 0..reader.u32()? {
+            let id = reader.u64()? as usize;
+            let collection = reader.u8()? != 0;
+            save.synced
+                .insert(id, (reader.bytes()?.to_vec(), collection));
+        }
+        for _ in 0..reader.u32()? {
+            let key = String::from_utf8_lossy(reader.bytes()?).into_owned();
+            save.data.insert(key, reader.bytes()?.to_vec());
+        }
+        Some(save)
+    }
+}
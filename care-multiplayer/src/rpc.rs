@@ -0,0 +1,310 @@
+//! Typed remote procedure calls
+//!
+//! Mark a function with `#[care_multiplayer::rpc]` to make it callable over
+//! the network, the arguments and return value are sent using
+//! [Transferable]. The function can optionally take an `&RpcContext` as its
+//! first parameter, to find out who called it.
+//!
+//! ```ignore
+//! #[care_multiplayer::rpc]
+//! fn give_item(ctx: &RpcContext, item: u32, count: u8) -> bool {
+//!     println!("{:?} wants {count} of {item}", ctx.caller);
+//!     true
+//! }
+//!
+//! // On the server
+//! server.register_rpc::<give_item>();
+//! // On the client
+//! let reply = client.request::<give_item>((5, 1))?;
+//! ```
+//!
+//! RPCs are reliable by default, use `#[care_multiplayer::rpc(unreliable)]`
+//! for calls where it doesn't matter if they get lost. Requests that don't get
+//! a reply within 10 seconds, e.g. because they were lost, resolve to `None`.
+
+use std::{
+    collections::HashMap,
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll, Waker},
+    time::{Duration, Instant},
+};
+
+use parking_lot::Mutex;
+
+use crate::{
+    connection::Reliability,
+    packet::{PacketReader, PacketWriter},
+    server::ClientId,
+    sync::Transferable,
+};
+
+const RPC_CALL: u8 = 0;
+const RPC_REQUEST: u8 = 1;
+const RPC_REPLY: u8 = 2;
+
+/// Requests that haven't been answered after this long are given up on
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Information about who called a remote procedure
+pub struct RpcContext {
+    /// The client that made the call, or `None` if it was called by the server
+    pub caller: Option<ClientId>,
+}
+
+/// A function that can be called over the network, usually implemented with
+/// the `#[care_multiplayer::rpc]` attribute
+pub trait Rpc: 'static {
+    /// The arguments of the function, as a tuple
+    type Args: Transferable;
+    /// The return value of the function
+    type Output: Transferable;
+    /// Name of the function, must be the same on every peer
+    const NAME: &'static str;
+    /// How calls to this function should be sent
+    const RELIABILITY: Reliability;
+
+    /// Run the function locally
+    fn call(ctx: &RpcContext, args: Self::Args) -> Self::Output;
+
+    /// The id used to identify this function over the network
+    fn id() -> u64 {
+        // FNV-1a, so ids are stable between builds
+        Self::NAME.bytes().fold(0xcbf29ce484222325, |hash, b| {
+            (hash ^ b as u64).wrapping_mul(0x100000001b3)
+        })
+    }
+}
+
+#[derive(Debug, Default)]
+struct ReplySlot {
+    data: Option<Vec<u8>>,
+    /// Set when no reply is coming
+    closed: bool,
+    waker: Option<Waker>,
+}
+
+impl ReplySlot {
+    /// Resolve the call with no reply
+    fn close(&mut self) {
+        self.closed = true;
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+#[derive(Debug)]
+/// The reply to a remote procedure call, which can either be awaited or polled with [RpcReply::try_get]
+pub struct RpcReply<T> {
+    slot: Arc<Mutex<ReplySlot>>,
+    _output: std::marker::PhantomData<fn() -> T>,
+}
+
+impl<T: Transferable> RpcReply<T> {
+    /// Get the reply if it has arrived
+    ///
+    /// Returns `Some(None)` if the connection was closed, or the request timed
+    /// out, before a reply arrived, or if the reply couldn't be read
+    pub fn try_get(&self) -> Option<Option<T>> {
+        let slot = self.slot.lock();
+        if let Some(data) = &slot.data {
+            Some(Some(T::receive(data, &())))
+        } else if slot.closed {
+            Some(None)
+        } else {
+            None
+        }
+    }
+}
+
+impl<T: Transferable> Future for RpcReply<T> {
+    /// The return value, or `None` if the connection was closed, or the
+    /// request timed out, before a reply arrived
+    type Output = Option<T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        if let Some(reply) = self.try_get() {
+            return Poll::Ready(reply);
+        }
+        self.slot.lock().waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+type Handler = Box<dyn Fn(&RpcContext, &[u8]) -> Vec<u8> + Send + Sync>;
+
+#[derive(Debug)]
+/// A request that's waiting for its reply
+struct Pending {
+    /// Who the request was sent to, `None` for the server
+    peer: Option<ClientId>,
+    slot: Arc<Mutex<ReplySlot>>,
+    sent: Instant,
+}
+
+#[derive(Default)]
+/// Registered procedures and outstanding calls, shared by clients and servers
+pub(crate) struct RpcEndpoint {
+    handlers: HashMap<u64, Handler>,
+    /// Outstanding requests, by call id
+    pending: HashMap<u32, Pending>,
+    next_call_id: u32,
+}
+
+impl std::fmt::Debug for RpcEndpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RpcEndpoint")
+            .field("handlers", &self.handlers.keys())
+            .field("pending", &self.pending)
+            .finish_non_exhaustive()
+    }
+}
+
+impl RpcEndpoint {
+    pub fn register<R: Rpc>(&mut self) {
+        self.handlers.insert(
+            R::id(),
+            Box::new(|ctx, args| R::call(ctx, R::Args::receive(args, &())).send(&())),
+        );
+    }
+
+    /// Encode a call with no reply
+    pub fn call<R: Rpc>(&mut self, args: &R::Args) -> Vec<u8> {
+        let mut message = PacketWriter::new(RPC_CALL);
+        message.u32(0).u64(R::id()).bytes(&args.send(&()));
+        message.finish()
+    }
+
+    /// Encode a call to `peer` (`None` for the server), along with the reply
+    /// that will be filled in later
+    pub fn request<R: Rpc>(
+        &mut self,
+        peer: Option<ClientId>,
+        args: &R::Args,
+    ) -> (Vec<u8>, RpcReply<R::Output>) {
+        let call_id = self.next_call_id;
+        self.next_call_id = self.next_call_id.wrapping_add(1);
+        let slot = Arc::new(Mutex::new(ReplySlot::default()));
+        self.pending.insert(
+            call_id,
+            Pending {
+                peer,
+                slot: slot.clone(),
+                sent: Instant::now(),
+            },
+        );
+        let mut message = PacketWriter::new(RPC_REQUEST);
+        message.u32(call_id).u64(R::id()).bytes(&args.send(&()));
+        (
+            message.finish(),
+            RpcReply {
+                slot,
+                _output: std::marker::PhantomData,
+            },
+        )
+    }
+
+    /// Handle an incoming RPC message, returning the reply to send back, if any
+    pub fn handle(&mut self, ctx: &RpcContext, message: &[u8]) -> Option<Vec<u8>> {
+        let (kind, mut reader) = PacketReader::new(message)?;
+        let call_id = reader.u32()?;
+        match kind {
+            RPC_CALL | RPC_REQUEST => {
+                let handler = self.handlers.get(&reader.u64()?)?;
+                let output = handler(ctx, reader.bytes()?);
+                (kind == RPC_REQUEST).then(|| {
+                    let mut reply = PacketWriter::new(RPC_REPLY);
+                    reply.u32(call_id).bytes(&output);
+                    reply.finish()
+                })
+            }
+            RPC_REPLY => {
+                if self.pending.get(&call_id)?.peer != ctx.caller {
+                    return None;
+                }
+                let pending = self.pending.remove(&call_id)?;
+                let mut slot = pending.slot.lock();
+                match reader.bytes() {
+                    Some(data) => {
+                        slot.data = Some(data.to_vec());
+                        if let Some(waker) = slot.waker.take() {
+                            waker.wake();
+                        }
+                    }
+                    // The reply can't be read, so the call is resolved with no reply
+                    None => slot.close(),
+                }
+                None
+            }
+            _ => None,
+        }
+    }
+
+    /// Resolve every outstanding call to `peer` with no reply, when the connection is closed
+    pub fn close(&mut self, peer: Option<ClientId>) {
+        self.pending.retain(|_, pending| {
+            if pending.peer != peer {
+                return true;
+            }
+            pending.slot.lock().close();
+            false
+        });
+    }
+
+    /// Resolve every call that has waited too long for a reply with no reply
+    pub fn expire(&mut self) {
+        self.pending.retain(|_, pending| {
+            if pending.sent.elapsed() < REQUEST_TIMEOUT {
+                return true;
+            }
+            pending.slot.lock().close();
+            false
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Double;
+
+    impl Rpc for Double {
+        type Args = u32;
+        type Output = u32;
+        const NAME: &'static str = "double";
+        const RELIABILITY: Reliability = Reliability::Reliable;
+
+        fn call(_ctx: &RpcContext, args: u32) -> u32 {
+            args * 2
+        }
+    }
+
+    const SERVER: RpcContext = RpcContext { caller: None };
+
+    #[test]
+    fn replies_resolve_requests() {
+        let (mut client, mut server) = (RpcEndpoint::default(), RpcEndpoint::default());
+        server.register::<Double>();
+        let (request, reply) = client.request::<Double>(None, &21);
+        let response = server.handle(&SERVER, &request).unwrap();
+        assert_eq!(reply.try_get(), None);
+        client.handle(&SERVER, &response);
+        assert_eq!(reply.try_get(), Some(Some(42)));
+    }
+
+    #[test]
+    fn malformed_replies_resolve_requests_with_no_reply() {
+        let mut client = RpcEndpoint::default();
+        let (_, reply) = client.request::<Double>(None, &21);
+        let mut response = PacketWriter::new(RPC_REPLY);
+        // The call id of the request, with the length of a reply that isn't there
+        response.u32(0).u32(4);
+        client.handle(&SERVER, &response.finish());
+        assert_eq!(reply.try_get(), Some(None));
+        assert!(client.pending.is_empty());
+    }
+}
@@ -1 +1,1128 @@
-pub struct SyncedValue {}
+//! Dedicated or player-hosted game servers
+//!
+//! A [Server] accepts connections from any number of [crate::client::Client]s,
+//! and can send messages and synced values to them.
+
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    io,
+    net::{IpAddr, ToSocketAddrs},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    admin::{self, AdminState, ADMIN_COMMAND, ADMIN_LOGIN, ADMIN_OUTPUT},
+    chat::{
+        encode_message, encode_room, read_string, ChatConfig, ChatMessage, ChatRooms, CHAT_JOIN,
+        CHAT_LEAVE, CHAT_SEND, GLOBAL_ROOM,
+    },
+    compression::{Compression, CompressionConfig},
+    connection::{
+        Connection, Reliability, CHANNEL_ADMIN, CHANNEL_CHAT, CHANNEL_OWNERSHIP, CHANNEL_RPC,
+        CHANNEL_SYNC, CHANNEL_SYNC_DELTA, CHANNEL_SYNC_REMOVE, CHANNEL_VOICE, MAX_USER_CHANNEL,
+        PACKET_ACCEPT, PACKET_CONNECT, PACKET_DISCONNECT, PACKET_PING, PACKET_PONG, PACKET_REJECT,
+        PROTOCOL_VERSION,
+    },
+    interest::{Group, InterestManager, Region},
+    packet::{PacketReader, PacketWriter},
+    persistence::WorldSave,
+    rpc::{Rpc, RpcContext, RpcEndpoint, RpcReply},
+    stats::NetStats,
+    sync::{
+        decode_sync, encode_sync, SyncCache, SyncManager, SyncQueue, OWNERSHIP_CHANGED,
+        OWNERSHIP_RELEASE, OWNERSHIP_REQUEST,
+    },
+    throttle::BandwidthConfig,
+    transport::{Address, Transport, UdpTransport},
+};
+
+/// Unique id of a client connected to a server
+pub type ClientId = u64;
+
+#[derive(Debug, Clone)]
+/// Configuration for a [Server]
+pub struct ServerConfig {
+    /// The maximum number of clients that can be connected at once
+    pub max_clients: usize,
+    /// Clients are disconnected after not hearing from them for this long
+    pub timeout: Duration,
+    /// How requests for ownership of synced values are handled
+    pub ownership: OwnershipPolicy,
+    /// How many times per second the tick callback runs, see [Server::update]
+    pub tick_rate: f64,
+    /// What happens when ticks take too long to keep up with the tick rate
+    pub catch_up: CatchUp,
+    /// How messages are compressed, for clients that support it
+    pub compression: CompressionConfig,
+    /// The version of your game's network messages, clients with a different
+    /// version are rejected
+    ///
+    /// Change this whenever messages, RPCs or synced values change, so
+    /// players running an old version of the game get a clear error instead
+    /// of garbled data.
+    pub version: u32,
+    /// Chat rooms and message limits, see [crate::chat]
+    pub chat: ChatConfig,
+    /// Send limits for each client's connection, see [crate::throttle]
+    pub bandwidth: BandwidthConfig,
+    /// The password clients need to run commands, or `None` to only allow
+    /// commands from the server itself, see [crate::admin]
+    pub admin_password: Option<String>,
+    /// The folder the `save` command writes worlds to, or `None` to turn the
+    /// command off, see [crate::admin]
+    pub save_dir: Option<PathBuf>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            max_clients: 32,
+            timeout: Duration::from_secs(10),
+            ownership: OwnershipPolicy::default(),
+            tick_rate: 30.0,
+            catch_up: CatchUp::default(),
+            compression: CompressionConfig::default(),
+            version: 0,
+            chat: ChatConfig::default(),
+            bandwidth: BandwidthConfig::default(),
+            admin_password: None,
+            save_dir: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// What the server does when it falls behind its tick rate
+pub enum CatchUp {
+    /// Run every missed tick, which can make things worse if ticks are what's slow
+    All,
+    /// Run up to this many missed ticks in a single update, skipping the rest
+    ///
+    /// Tick numbers still count skipped ticks, so they keep matching the time. At least one tick
+    /// always runs, so `Limit(0)` is the same as `Limit(1)`.
+    Limit(u32),
+    /// Run one tick per update, and let the whole game slow down instead of skipping ticks
+    Delay,
+}
+
+impl Default for CatchUp {
+    fn default() -> Self {
+        Self::Limit(5)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+/// How the server decides who gets ownership of a synced value
+///
+/// The owner of a synced value is the only client whose updates to it are
+/// relayed to other clients, so e.g. the last player to touch a physics
+/// object can be the one simulating it. Everything not owned by a client is
+/// owned by the server.
+pub enum OwnershipPolicy {
+    /// Every request is granted, taking ownership from whoever had it
+    #[default]
+    Steal,
+    /// Requests are only granted if the server currently owns the value
+    IfUnowned,
+    /// Requests are sent as [ServerEvent::OwnershipRequested], and granted
+    /// with [Server::set_owner]
+    Manual,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// Something that happened on the server
+pub enum ServerEvent {
+    /// A new client connected
+    Connected(ClientId),
+    /// A client disconnected or timed out
+    Disconnected(ClientId),
+    /// A client sent a message
+    Message {
+        /// The client that sent the message
+        client: ClientId,
+        /// The channel the message was sent on
+        channel: u8,
+        /// The contents of the message
+        data: Vec<u8>,
+    },
+    /// A client sent a new value for a synced value
+    Sync {
+        /// The client that sent the value
+        client: ClientId,
+        /// The id of the synced value
+        id: usize,
+        /// The newest server tick the client had seen when it sent the value
+        tick: u64,
+        /// The new value, or changes to a synced collection, see
+        /// [crate::sync::SyncedValue::receive] and [crate::sync::SyncedMap::receive]
+        data: Vec<u8>,
+    },
+    /// A client asked to own a synced value, only sent with [OwnershipPolicy::Manual]
+    OwnershipRequested {
+        /// The client that wants ownership
+        client: ClientId,
+        /// The id of the synced value
+        id: usize,
+    },
+    /// The owner of a synced value changed
+    OwnershipChanged {
+        /// The id of the synced value
+        id: usize,
+        /// The new owner, or `None` if it's now owned by the server
+        owner: Option<ClientId>,
+    },
+    /// A chat message was delivered, after being filtered, see [Server::set_chat_filter]
+    Chat(ChatMessage),
+    /// A client sent a voice packet, which has already been relayed to every
+    /// other client, see [crate::voice]
+    Voice {
+        /// The client that is speaking
+        client: ClientId,
+        /// The voice packet
+        data: Vec<u8>,
+    },
+    /// A logged in admin client ran a command, see [crate::admin]
+    AdminCommand {
+        /// The client that ran it
+        client: ClientId,
+        /// The whole line, including arguments
+        command: String,
+    },
+}
+
+#[derive(Debug)]
+struct ConnectedClient {
+    connection: Connection,
+    /// The synced values this client currently knows about
+    visible: HashSet<usize>,
+}
+
+/// A game server
+pub struct Server {
+    transport: Box<dyn Transport>,
+    config: ServerConfig,
+    clients: HashMap<ClientId, ConnectedClient>,
+    addrs: HashMap<Address, ClientId>,
+    next_client_id: ClientId,
+    events: VecDeque<ServerEvent>,
+    sync_queue: Arc<SyncQueue>,
+    /// The latest value of every synced value, and the tick it was sent on
+    sync_cache: HashMap<usize, (u64, SyncCache)>,
+    owners: HashMap<usize, ClientId>,
+    interest: InterestManager,
+    rpc: RpcEndpoint,
+    chat: ChatRooms,
+    admin: AdminState,
+    tick: u64,
+    next_tick: Instant,
+    /// When the current tick ran, see [Server::time]
+    tick_time: f64,
+    started: Instant,
+}
+
+impl std::fmt::Debug for Server {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Server")
+            .field("config", &self.config)
+            .field("clients", &self.clients)
+            .field("events", &self.events)
+            .field("rpc", &self.rpc)
+            .field("chat", &self.chat)
+            .field("admin", &self.admin)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Server {
+    /// Start a server listening for UDP connections on a local address
+    pub fn bind(addr: impl ToSocketAddrs) -> io::Result<Self> {
+        Self::new(UdpTransport::bind(addr)?, ServerConfig::default())
+    }
+
+    /// Start a server on any transport
+    ///
+    /// This fails if the config's tick rate isn't a positive number.
+    pub fn new(transport: impl Transport + 'static, config: ServerConfig) -> io::Result<Self> {
+        let tick_rate = config.tick_rate;
+        if !(tick_rate > 0.0 && tick_rate.is_finite()) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Invalid tick rate {tick_rate}, it has to be positive"),
+            ));
+        }
+        Ok(Self {
+            transport: Box::new(transport),
+            chat: ChatRooms::new(&config.chat),
+            config,
+            clients: HashMap::new(),
+            addrs: HashMap::new(),
+            next_client_id: 1,
+            events: VecDeque::new(),
+            sync_queue: Arc::new(SyncQueue::server()),
+            sync_cache: HashMap::new(),
+            owners: HashMap::new(),
+            interest: InterestManager::default(),
+            rpc: RpcEndpoint::default(),
+            admin: AdminState::default(),
+            tick: 0,
+            next_tick: Instant::now(),
+            tick_time: 0.0,
+            started: Instant::now(),
+        })
+    }
+
+    /// The local address the server is listening on
+    pub fn local_addr(&self) -> io::Result<Address> {
+        self.transport.local_addr()
+    }
+
+    /// A sync manager to create [crate::sync::SyncedValue]s owned by this server
+    pub fn sync_manager<C>(&self) -> Arc<dyn SyncManager<C>> {
+        self.sync_queue.clone()
+    }
+
+    /// Receive and send everything that's waiting, call this at least once per frame
+    ///
+    /// For servers with a fixed tick rate, use [Server::update] instead.
+    pub fn poll(&mut self) -> io::Result<()> {
+        self.receive_all()?;
+        self.flush_sync()?;
+        self.send_all()
+    }
+
+    /// Receive and send everything that's waiting, and call `on_tick` for
+    /// every tick that's due at the configured tick rate
+    ///
+    /// Synced values flushed during a tick are sent with that tick's number,
+    /// so clients can tell exactly when each snapshot is from.
+    pub fn update(&mut self, mut on_tick: impl FnMut(&mut Self, u64)) -> io::Result<()> {
+        self.receive_all()?;
+        let interval = self.tick_interval();
+        let now = Instant::now();
+        let mut ran = 0;
+        while self.next_tick <= now {
+            let behind = match self.config.catch_up {
+                CatchUp::All => false,
+                CatchUp::Limit(max) => ran >= max.max(1),
+                CatchUp::Delay => ran >= 1,
+            };
+            if behind {
+                if self.config.catch_up == CatchUp::Delay {
+                    self.next_tick = now + interval;
+                } else {
+                    let skipped = (now - self.next_tick).as_secs_f64() / interval.as_secs_f64();
+                    let skipped = skipped as u64 + 1;
+                    self.tick += skipped;
+                    self.next_tick += interval * skipped as u32;
+                }
+                break;
+            }
+            self.tick += 1;
+            self.next_tick += interval;
+            ran += 1;
+            self.tick_time = self.time();
+            on_tick(self, self.tick);
+            self.flush_sync()?;
+        }
+        self.send_all()
+    }
+
+    /// The number of the current tick, see [Server::update]
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    /// Seconds since the server started, clients can estimate this with
+    /// [crate::client::Client::server_time]
+    pub fn time(&self) -> f64 {
+        self.started.elapsed().as_secs_f64()
+    }
+
+    pub(crate) fn version(&self) -> u32 {
+        self.config.version
+    }
+
+    pub(crate) fn save_dir(&self) -> Option<&Path> {
+        self.config.save_dir.as_deref()
+    }
+
+    /// The time between ticks
+    pub fn tick_interval(&self) -> Duration {
+        Duration::from_secs_f64(1.0 / self.config.tick_rate)
+    }
+
+    /// Save the latest value of every synced value, and the current tick,
+    /// see [crate::persistence]
+    pub fn save_world(&self, version: u32) -> WorldSave {
+        let mut save = WorldSave::new(version);
+        save.tick = self.tick;
+        for (&id, (_, cache)) in &self.sync_cache {
+            save.synced.insert(id, cache.full());
+        }
+        save
+    }
+
+    /// Restore synced values and the tick from a save, so they're sent to
+    /// clients as if they had never been lost
+    ///
+    /// This doesn't change the local synced values, see [WorldSave::synced].
+    pub fn load_world(&mut self, save: &WorldSave) {
+        self.tick = save.tick;
+        for (&id, (data, collection)) in &save.synced {
+            self.sync_cache
+                .insert(id, (save.tick, SyncCache::new(data, *collection)));
+        }
+        self.interest.dirty = true;
+    }
+
+    fn receive_all(&mut self) -> io::Result<()> {
+        while let Some((addr, data)) = self.transport.recv_from()? {
+            self.receive_packet(addr, &data)?;
+        }
+
+        let timed_out: Vec<_> = self
+            .clients
+            .iter()
+            .filter(|(_, c)| c.connection.timed_out(self.config.timeout))
+            .map(|(&id, _)| id)
+            .collect();
+        for id in timed_out {
+            self.remove_client(id)?;
+        }
+        self.rpc.expire();
+        Ok(())
+    }
+
+    /// Update every client's connection, returning the first error after
+    /// trying all of them, so one client can't stop the others being sent to
+    fn send_all(&mut self) -> io::Result<()> {
+        let mut result = Ok(());
+        for client in self.clients.values_mut() {
+            let sent = client.connection.update(&*self.transport);
+            if result.is_ok() {
+                result = sent;
+            }
+        }
+        result
+    }
+
+    /// Get the next event that happened, if any
+    pub fn next_event(&mut self) -> Option<ServerEvent> {
+        self.events.pop_front()
+    }
+
+    /// Take every event that happened since this was last called, e.g. once per frame
+    pub fn take_events(&mut self) -> Vec<ServerEvent> {
+        self.events.drain(..).collect()
+    }
+
+    /// Iterate over the ids of every connected client
+    pub fn clients(&self) -> impl Iterator<Item = ClientId> + '_ {
+        self.clients.keys().copied()
+    }
+
+    /// The address a client is connected from
+    pub fn client_addr(&self, client: ClientId) -> Option<Address> {
+        self.clients.get(&client).map(|c| c.connection.addr)
+    }
+
+    /// Measurements of the connection to a client
+    pub fn client_stats(&self, client: ClientId) -> Option<&NetStats> {
+        self.clients.get(&client).map(|c| c.connection.stats())
+    }
+
+    /// Change the send limits for a single client, e.g. to give players on a
+    /// slow connection a smaller budget
+    pub fn set_client_bandwidth(&mut self, client: ClientId, config: BandwidthConfig) {
+        if let Some(client) = self.clients.get_mut(&client) {
+            client.connection.set_bandwidth(config);
+        }
+    }
+
+    /// Send a message to a single client, on a user channel (up to [MAX_USER_CHANNEL])
+    pub fn send(
+        &mut self,
+        client: ClientId,
+        channel: u8,
+        data: Vec<u8>,
+        reliability: Reliability,
+    ) -> io::Result<()> {
+        assert!(
+            channel <= MAX_USER_CHANNEL,
+            "Channels above {MAX_USER_CHANNEL} are reserved"
+        );
+        if let Some(client) = self.clients.get_mut(&client) {
+            client
+                .connection
+                .send(&*self.transport, channel, data, reliability)?;
+        }
+        Ok(())
+    }
+
+    /// Send a message to every connected client
+    pub fn broadcast(
+        &mut self,
+        channel: u8,
+        data: Vec<u8>,
+        reliability: Reliability,
+    ) -> io::Result<()> {
+        let clients: Vec<_> = self.clients().collect();
+        for client in clients {
+            self.send(client, channel, data.clone(), reliability)?;
+        }
+        Ok(())
+    }
+
+    /// Disconnect a client
+    pub fn disconnect(&mut self, client: ClientId) -> io::Result<()> {
+        if let Some(connected) = self.clients.get_mut(&client) {
+            connected.connection.send_raw(
+                &*self.transport,
+                &PacketWriter::new(PACKET_DISCONNECT).finish(),
+            )?;
+        }
+        self.remove_client(client)
+    }
+
+    /// Disconnect a client, and stop anyone from connecting from its IP address again
+    pub fn ban(&mut self, client: ClientId) -> io::Result<()> {
+        if let Some(addr) = self.client_addr(client) {
+            self.admin.banned.insert(addr.ip());
+        }
+        self.disconnect(client)
+    }
+
+    /// Let a banned IP address connect again, returning false if it wasn't banned
+    pub fn unban(&mut self, ip: IpAddr) -> bool {
+        self.admin.banned.remove(&ip)
+    }
+
+    /// Iterate over every banned IP address
+    pub fn banned(&self) -> impl Iterator<Item = IpAddr> + '_ {
+        self.admin.banned.iter().copied()
+    }
+
+    /// Add a command, or replace an existing one, see [crate::admin]
+    ///
+    /// `handler` is given the server and the command's arguments, and
+    /// returns the output.
+    pub fn register_command(
+        &mut self,
+        name: &str,
+        help: &str,
+        handler: impl FnMut(&mut Server, &[&str]) -> String + Send + 'static,
+    ) {
+        self.admin.register(name, help, handler);
+    }
+
+    /// Iterate over the name and help text of every command
+    pub fn commands(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.admin.commands()
+    }
+
+    /// Run a command as the server, returning its output
+    pub fn run_command(&mut self, line: &str) -> String {
+        let words: Vec<_> = line.split_whitespace().collect();
+        let Some((&name, args)) = words.split_first() else {
+            return String::new();
+        };
+        let Some(mut handler) = self.admin.take_handler(name) else {
+            return format!("Unknown command `{name}`, try `help`");
+        };
+        let mut output = handler(self, args);
+        self.admin.return_handler(name, handler);
+        output.truncate(output.trim_end().len());
+        output
+    }
+
+    /// Whether a client has logged in to run commands
+    pub fn is_admin(&self, client: ClientId) -> bool {
+        self.admin.logged_in.contains(&client)
+    }
+
+    /// The client that owns a synced value, or `None` if the server owns it
+    pub fn owner(&self, id: usize) -> Option<ClientId> {
+        self.owners.get(&id).copied()
+    }
+
+    /// Give ownership of a synced value to a client, or take it back for the server with `None`
+    pub fn set_owner(&mut self, id: usize, owner: Option<ClientId>) -> io::Result<()> {
+        if owner.is_some_and(|client| !self.clients.contains_key(&client)) {
+            return Ok(());
+        }
+        let previous = match owner {
+            Some(client) => self.owners.insert(id, client),
+            None => self.owners.remove(&id),
+        };
+        if previous == owner {
+            return Ok(());
+        }
+        self.sync_queue.set_owned(id, owner.is_some());
+        let message = encode_owner(id, owner);
+        for client in self.clients.values_mut() {
+            client.connection.send(
+                &*self.transport,
+                CHANNEL_OWNERSHIP,
+                message.clone(),
+                Reliability::Reliable,
+            )?;
+        }
+        self.events
+            .push_back(ServerEvent::OwnershipChanged { id, owner });
+        Ok(())
+    }
+
+    /// Allow clients to call a remote procedure on this server
+    pub fn register_rpc<R: Rpc>(&mut self) {
+        self.rpc.register::<R>();
+    }
+
+    /// Call a remote procedure on a client, ignoring the return value
+    pub fn call<R: Rpc>(&mut self, client: ClientId, args: R::Args) -> io::Result<()> {
+        let message = self.rpc.call::<R>(&args);
+        if let Some(client) = self.clients.get_mut(&client) {
+            client
+                .connection
+                .send(&*self.transport, CHANNEL_RPC, message, R::RELIABILITY)?;
+        }
+        Ok(())
+    }
+
+    /// Call a remote procedure on every connected client, ignoring the return values
+    pub fn call_all<R: Rpc>(&mut self, args: R::Args) -> io::Result<()> {
+        let message = self.rpc.call::<R>(&args);
+        for client in self.clients.values_mut() {
+            client.connection.send(
+                &*self.transport,
+                CHANNEL_RPC,
+                message.clone(),
+                R::RELIABILITY,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Call a remote procedure on a client, the return value can be awaited
+    pub fn request<R: Rpc>(
+        &mut self,
+        client: ClientId,
+        args: R::Args,
+    ) -> io::Result<RpcReply<R::Output>> {
+        let (message, reply) = self.rpc.request::<R>(Some(client), &args);
+        match self.clients.get_mut(&client) {
+            Some(connected) => {
+                connected.connection.send(
+                    &*self.transport,
+                    CHANNEL_RPC,
+                    message,
+                    R::RELIABILITY,
+                )?;
+            }
+            None => self.rpc.close(Some(client)),
+        }
+        Ok(reply)
+    }
+
+    /// Send a chat message from the server to everyone in a room
+    ///
+    /// This isn't passed through the chat filter.
+    pub fn send_chat(&mut self, room: &str, text: &str) -> io::Result<()> {
+        self.deliver_chat(ChatMessage {
+            room: room.to_string(),
+            sender: None,
+            text: text.to_string(),
+            time: self.time(),
+        })
+    }
+
+    /// Check every chat message sent by a client before it's delivered
+    ///
+    /// The filter returns the text to send, which can be changed (e.g. to
+    /// hide swearing), or `None` to drop the message entirely.
+    pub fn set_chat_filter(
+        &mut self,
+        filter: impl FnMut(&ChatMessage) -> Option<String> + Send + 'static,
+    ) {
+        self.chat.filter = Some(Box::new(filter));
+    }
+
+    /// Add a client to a chat room, sending it the room's recent messages
+    pub fn join_room(&mut self, client: ClientId, room: &str) -> io::Result<()> {
+        let Some(connected) = self.clients.get_mut(&client) else {
+            return Ok(());
+        };
+        if !self.chat.join(client, room) {
+            return Ok(());
+        }
+        let history = self.chat.history.get(room).map(encode_message);
+        for message in std::iter::once(encode_room(CHAT_JOIN, room)).chain(history) {
+            connected.connection.send(
+                &*self.transport,
+                CHANNEL_CHAT,
+                message,
+                Reliability::Reliable,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Remove a client from a chat room
+    pub fn leave_room(&mut self, client: ClientId, room: &str) -> io::Result<()> {
+        if !self.chat.leave(client, room) {
+            return Ok(());
+        }
+        if let Some(connected) = self.clients.get_mut(&client) {
+            connected.connection.send(
+                &*self.transport,
+                CHANNEL_CHAT,
+                encode_room(CHAT_LEAVE, room),
+                Reliability::Reliable,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Iterate over the clients in a chat room
+    pub fn room_members(&self, room: &str) -> impl Iterator<Item = ClientId> + '_ {
+        self.chat.members(room)
+    }
+
+    /// The most recent messages sent to a chat room, oldest first
+    pub fn chat_history(&self, room: &str) -> impl Iterator<Item = &ChatMessage> {
+        self.chat.history.get(room)
+    }
+
+    fn deliver_chat(&mut self, message: ChatMessage) -> io::Result<()> {
+        let data = encode_message(&message);
+        for member in self.chat.members(&message.room) {
+            if let Some(client) = self.clients.get_mut(&member) {
+                client.connection.send(
+                    &*self.transport,
+                    CHANNEL_CHAT,
+                    data.clone(),
+                    Reliability::Reliable,
+                )?;
+            }
+        }
+        self.chat.record(message.clone());
+        self.events.push_back(ServerEvent::Chat(message));
+        Ok(())
+    }
+
+    /// Set the area of the world a client is interested in
+    ///
+    /// Synced values with a position outside of this region won't be sent to the client
+    pub fn set_interest(&mut self, client: ClientId, region: Region) {
+        self.interest.set_region(client, region);
+    }
+
+    /// Subscribe a client to a group, so it receives synced values in that group
+    pub fn subscribe(&mut self, client: ClientId, group: Group) {
+        self.interest.subscribe(client, group);
+    }
+
+    /// Unsubscribe a client from a group
+    pub fn unsubscribe(&mut self, client: ClientId, group: Group) {
+        self.interest.unsubscribe(client, group);
+    }
+
+    /// Set the position of a synced value (e.g. the entity it belongs to), for
+    /// use with [Server::set_interest]
+    ///
+    /// Values without a position are sent to clients regardless of their region
+    pub fn set_position(&mut self, id: usize, position: Option<[f32; 2]>) {
+        self.interest.set_position(id, position);
+    }
+
+    /// Put a synced value in a group, so only subscribed clients receive it
+    pub fn set_group(&mut self, id: usize, group: Option<Group>) {
+        self.interest.set_group(id, group);
+    }
+
+    /// Whether a client currently receives updates for a synced value
+    pub fn is_visible(&self, client: ClientId, id: usize) -> bool {
+        self.clients
+            .get(&client)
+            .is_some_and(|c| c.visible.contains(&id))
+    }
+
+    fn remove_client(&mut self, id: ClientId) -> io::Result<()> {
+        if let Some(client) = self.clients.remove(&id) {
+            self.addrs.remove(&client.connection.addr);
+            self.interest.remove_client(id);
+            self.rpc.close(Some(id));
+            self.chat.remove_client(id);
+            self.admin.remove_client(id);
+            self.events.push_back(ServerEvent::Disconnected(id));
+            let owned: Vec<_> = self
+                .owners
+                .iter()
+                .filter(|(_, &owner)| owner == id)
+                .map(|(&synced, _)| synced)
+                .collect();
+            for synced in owned {
+                self.set_owner(synced, None)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn receive_packet(&mut self, addr: Address, data: &[u8]) -> io::Result<()> {
+        let Some((kind, mut reader)) = PacketReader::new(data) else {
+            return Ok(());
+        };
+        let Some(&id) = self.addrs.get(&addr) else {
+            if kind == PACKET_CONNECT {
+                self.accept(addr, reader)?;
+            }
+            return Ok(());
+        };
+        let client = self.clients.get_mut(&id).unwrap();
+        client.connection.record_received(data.len());
+        match kind {
+            PACKET_CONNECT => {
+                // The accept packet must have been lost
+                let mut packet = PacketWriter::new(PACKET_ACCEPT);
+                packet.u64(id).u8(client.connection.compression().id());
+                client
+                    .connection
+                    .send_raw(&*self.transport, &packet.finish())?;
+            }
+            PACKET_DISCONNECT => self.remove_client(id)?,
+            PACKET_PING => {
+                client.connection.mark_received();
+                if let Some(sent) = reader.f64() {
+                    let mut pong = PacketWriter::new(PACKET_PONG);
+                    pong.f64(sent)
+                        .f64(self.started.elapsed().as_secs_f64())
+                        .u64(self.tick)
+                        .f64(self.tick_time)
+                        .f64(1.0 / self.config.tick_rate);
+                    client
+                        .connection
+                        .send_raw(&*self.transport, &pong.finish())?;
+                }
+            }
+            _ => {
+                for (channel, data) in client.connection.receive(kind, reader) {
+                    self.handle_message(id, channel, data)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn accept(&mut self, addr: Address, mut reader: PacketReader) -> io::Result<()> {
+        let protocol = reader.u16().unwrap_or(0);
+        let version = reader.u32().unwrap_or(0);
+        let mut packet = PacketWriter::new(PACKET_REJECT);
+        if protocol != PROTOCOL_VERSION {
+            let reason = format!(
+                "Incompatible care-multiplayer protocol (client has version {protocol}, server has version {PROTOCOL_VERSION})"
+            );
+            packet.bytes(reason.as_bytes());
+            return self.transport.send_to(addr, &packet.finish());
+        }
+        if version != self.config.version {
+            let reason = format!(
+                "Incompatible game version (client has version {version}, server has version {})",
+                self.config.version
+            );
+            packet.bytes(reason.as_bytes()).u32(self.config.version);
+            return self.transport.send_to(addr, &packet.finish());
+        }
+        if self.admin.banned.contains(&addr.ip()) {
+            packet.bytes(b"Banned from this server");
+            return self.transport.send_to(addr, &packet.finish());
+        }
+        if self.clients.len() >= self.config.max_clients {
+            packet.bytes(b"Server is full");
+            return self.transport.send_to(addr, &packet.finish());
+        }
+        let id = self.next_client_id;
+        self.next_client_id += 1;
+        // Use the configured compression if the client supports it
+        let supported = reader.u8().unwrap_or(0);
+        let mut compression = self.config.compression;
+        if !compression.algorithm.in_mask(supported) {
+            compression.algorithm = Compression::None;
+        }
+        let mut connection = Connection::new(addr);
+        let mut packet = PacketWriter::new(PACKET_ACCEPT);
+        packet.u64(id).u8(compression.algorithm.id());
+        connection.send_raw(&*self.transport, &packet.finish())?;
+        connection.set_compression(compression);
+        connection.set_bandwidth(self.config.bandwidth.clone());
+        for (&synced, &owner) in &self.owners {
+            connection.send(
+                &*self.transport,
+                CHANNEL_OWNERSHIP,
+                encode_owner(synced, Some(owner)),
+                Reliability::Reliable,
+            )?;
+        }
+        self.clients.insert(
+            id,
+            ConnectedClient {
+                connection,
+                visible: HashSet::new(),
+            },
+        );
+        self.addrs.insert(addr, id);
+        // The new client needs to be sent everything it's interested in
+        self.interest.dirty = true;
+        self.events.push_back(ServerEvent::Connected(id));
+        self.join_room(id, GLOBAL_ROOM)?;
+        Ok(())
+    }
+
+    fn handle_message(&mut self, client: ClientId, channel: u8, data: Vec<u8>) -> io::Result<()> {
+        match channel {
+            0..=MAX_USER_CHANNEL => self.events.push_back(ServerEvent::Message {
+                client,
+                channel,
+                data,
+            }),
+            CHANNEL_SYNC | CHANNEL_SYNC_DELTA => {
+                if let Some((id, tick, data)) = decode_sync(&data) {
+                    if self.owners.get(&id) == Some(&client) {
+                        self.send_sync(Some(client), id, data, channel == CHANNEL_SYNC_DELTA)?;
+                    }
+                    self.events.push_back(ServerEvent::Sync {
+                        client,
+                        id,
+                        tick,
+                        data: data.to_vec(),
+                    });
+                }
+            }
+            CHANNEL_OWNERSHIP => {
+                let Some((kind, mut reader)) = PacketReader::new(&data) else {
+                    return Ok(());
+                };
+                let Some(id) = reader.u64().map(|id| id as usize) else {
+                    return Ok(());
+                };
+                match (kind, self.config.ownership) {
+                    (OWNERSHIP_REQUEST, OwnershipPolicy::Steal) => {
+                        self.set_owner(id, Some(client))?
+                    }
+                    (OWNERSHIP_REQUEST, OwnershipPolicy::IfUnowned)
+                        if !self.owners.contains_key(&id) =>
+                    {
+                        self.set_owner(id, Some(client))?
+                    }
+                    (OWNERSHIP_REQUEST, OwnershipPolicy::Manual) => self
+                        .events
+                        .push_back(ServerEvent::OwnershipRequested { client, id }),
+                    (OWNERSHIP_RELEASE, _) if self.owners.get(&id) == Some(&client) => {
+                        self.set_owner(id, None)?
+                    }
+                    _ => {}
+                }
+            }
+            CHANNEL_VOICE => {
+                let mut message = client.to_le_bytes().to_vec();
+                message.extend_from_slice(&data);
+                for (&id, other) in &mut self.clients {
+                    if id != client {
+                        other.connection.send(
+                            &*self.transport,
+                            CHANNEL_VOICE,
+                            message.clone(),
+                            Reliability::Unreliable,
+                        )?;
+                    }
+                }
+                self.events.push_back(ServerEvent::Voice { client, data });
+            }
+            CHANNEL_CHAT => {
+                let Some((kind, mut reader)) = PacketReader::new(&data) else {
+                    return Ok(());
+                };
+                let Some(room) = read_string(&mut reader) else {
+                    return Ok(());
+                };
+                match kind {
+                    CHAT_JOIN if self.config.chat.open_rooms && self.chat.can_join(&room) => {
+                        self.join_room(client, &room)?
+                    }
+                    CHAT_LEAVE if self.config.chat.open_rooms => self.leave_room(client, &room)?,
+                    CHAT_SEND if self.chat.is_member(client, &room) => {
+                        let Some(text) = read_string(&mut reader) else {
+                            return Ok(());
+                        };
+                        let mut message = ChatMessage {
+                            room,
+                            sender: Some(client),
+                            text: self.chat.truncate(text),
+                            time: self.time(),
+                        };
+                        if let Some(filter) = &mut self.chat.filter {
+                            match filter(&message) {
+                                Some(text) => message.text = text,
+                                None => return Ok(()),
+                            }
+                        }
+                        self.deliver_chat(message)?;
+                    }
+                    _ => {}
+                }
+            }
+            CHANNEL_ADMIN => {
+                let Some((kind, mut reader)) = PacketReader::new(&data) else {
+                    return Ok(());
+                };
+                let Some(text) = read_string(&mut reader) else {
+                    return Ok(());
+                };
+                let output = match kind {
+                    ADMIN_LOGIN => {
+                        // Wrong passwords are counted by address, so reconnecting doesn't
+                        // give more guesses
+                        let Some(ip) = self.client_addr(client).map(|addr| addr.ip()) else {
+                            return Ok(());
+                        };
+                        let password = self.config.admin_password.as_ref();
+                        if self.admin.is_locked_out(ip) {
+                            "Too many wrong passwords, try again later".to_string()
+                        } else if password.is_some_and(|password| admin::password_matches(password, &text)) {
+                            self.admin.login_succeeded(ip);
+                            self.admin.logged_in.insert(client);
+                            "Logged in".to_string()
+                        } else if self.admin.login_failed(ip) {
+                            return self.disconnect(client);
+                        } else {
+                            "Wrong password".to_string()
+                        }
+                    }
+                    ADMIN_COMMAND if self.admin.logged_in.contains(&client) => {
+                        self.events.push_back(ServerEvent::AdminCommand {
+                            client,
+                            command: text.clone(),
+                        });
+                        self.run_command(&text)
+                    }
+                    ADMIN_COMMAND => "Not logged in".to_string(),
+                    _ => return Ok(()),
+                };
+                if let Some(connected) = self.clients.get_mut(&client) {
+                    connected.connection.send(
+                        &*self.transport,
+                        CHANNEL_ADMIN,
+                        admin::encode(ADMIN_OUTPUT, &output),
+                        Reliability::Reliable,
+                    )?;
+                }
+            }
+            CHANNEL_RPC => {
+                let ctx = RpcContext {
+                    caller: Some(client),
+                };
+                if let Some(reply) = self.rpc.handle(&ctx, &data) {
+                    if let Some(connected) = self.clients.get_mut(&client) {
+                        connected.connection.send(
+                            &*self.transport,
+                            CHANNEL_RPC,
+                            reply,
+                            Reliability::Reliable,
+                        )?;
+                    }
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Send an update to a synced value to every client interested in it,
+    /// except its owner which already has it
+    fn send_sync(
+        &mut self,
+        owner: Option<ClientId>,
+        id: usize,
+        data: &[u8],
+        delta: bool,
+    ) -> io::Result<()> {
+        let (tick, cache) = self
+            .sync_cache
+            .entry(id)
+            .and_modify(|(tick, cache)| {
+                *tick = self.tick;
+                cache.update(data, delta);
+            })
+            .or_insert_with(|| (self.tick, SyncCache::new(data, delta)));
+        for (&client_id, client) in &mut self.clients {
+            if Some(client_id) == owner || !self.interest.is_interested(client_id, id) {
+                continue;
+            }
+            // Clients that haven't seen the value yet need all of it, not
+            // just the latest changes
+            let (data, delta) = if client.visible.insert(id) {
+                cache.full()
+            } else {
+                (data.to_vec(), delta)
+            };
+            client.connection.send(
+                &*self.transport,
+                if delta {
+                    CHANNEL_SYNC_DELTA
+                } else {
+                    CHANNEL_SYNC
+                },
+                encode_sync(id, *tick, &data),
+                Reliability::Reliable,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Send queued synced values to every client interested in them
+    fn flush_sync(&mut self) -> io::Result<()> {
+        if std::mem::take(&mut self.interest.dirty) {
+            for (&client_id, client) in &mut self.clients {
+                for (&id, (tick, cache)) in &self.sync_cache {
+                    if self.owners.get(&id) == Some(&client_id) {
+                        // The owner already has the latest value
+                        continue;
+                    }
+                    let interested = self.interest.is_interested(client_id, id);
+                    if interested && client.visible.insert(id) {
+                        let (data, delta) = cache.full();
+                        client.connection.send(
+                            &*self.transport,
+                            if delta {
+                                CHANNEL_SYNC_DELTA
+                            } else {
+                                CHANNEL_SYNC
+                            },
+                            encode_sync(id, *tick, &data),
+                            Reliability::Reliable,
+                        )?;
+                    } else if !interested && client.visible.remove(&id) {
+                        client.connection.send(
+                            &*self.transport,
+                            CHANNEL_SYNC_REMOVE,
+                            (id as u64).to_le_bytes().to_vec(),
+                            Reliability::Reliable,
+                        )?;
+                    }
+                }
+            }
+        }
+
+        for (id, data, delta) in self.sync_queue.take() {
+            self.send_sync(None, id, &data, delta)?;
+        }
+        Ok(())
+    }
+}
+
+fn encode_owner(id: usize, owner: Option<ClientId>) -> Vec<u8> {
+    let mut message = PacketWriter::new(OWNERSHIP_CHANGED);
+    // Client ids start at 1, so 0 is used for the server
+    message.u64(id as u64).u64(owner.unwrap_or(0));
+    message.finish()
+}
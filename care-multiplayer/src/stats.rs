@@ -0,0 +1,186 @@
+//! Network statistics, for tuning send rates or showing a ping indicator
+//!
+//! See [crate::client::Client::stats] and [crate::server::Server::client_stats].
+
+use std::{
+    collections::BTreeMap,
+    time::{Duration, Instant},
+};
+
+/// How often the per second rates are updated
+const WINDOW: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+/// A counter, both in total and over the last second
+pub struct Rate {
+    /// Since the connection was opened
+    pub total: u64,
+    /// Over the last second
+    pub per_second: u64,
+}
+
+impl Rate {
+    fn add(&mut self, amount: u64) {
+        self.total += amount;
+    }
+
+    fn roll(&mut self, previous: &Rate, elapsed: Duration) {
+        let amount = (self.total - previous.total) as f64;
+        self.per_second = (amount / elapsed.as_secs_f64()).round() as u64;
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+/// Traffic on a single channel
+pub struct ChannelStats {
+    /// Messages sent on this channel, not including resends
+    pub messages_sent: Rate,
+    /// Messages received on this channel
+    pub messages_received: Rate,
+    /// Bytes of message data sent on this channel
+    pub bytes_sent: Rate,
+    /// Bytes of message data received on this channel
+    pub bytes_received: Rate,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+/// Measurements of a single connection
+pub struct NetStats {
+    /// Smoothed round trip time
+    pub rtt: Duration,
+    /// How much the round trip time varies
+    pub jitter: Duration,
+    /// Estimated fraction of packets that are lost, from 0 to 1, based on
+    /// how many reliable messages had to be resent
+    pub packet_loss: f32,
+    /// Packets sent, including acks, resends and heartbeats
+    pub packets_sent: Rate,
+    /// Packets received
+    pub packets_received: Rate,
+    /// Bytes sent, including packet headers
+    pub bytes_sent: Rate,
+    /// Bytes received, including packet headers
+    pub bytes_received: Rate,
+    /// Messages sent, not including resends
+    pub messages_sent: Rate,
+    /// Messages received
+    pub messages_received: Rate,
+    /// Unreliable messages that were never sent because the connection was
+    /// over its budget, see [crate::throttle]
+    pub messages_dropped: Rate,
+    /// Traffic for each channel that has been used, including the internal
+    /// channels above [crate::MAX_USER_CHANNEL]
+    pub channels: BTreeMap<u8, ChannelStats>,
+}
+
+#[derive(Debug)]
+/// Collects statistics for a connection
+pub(crate) struct StatsTracker {
+    stats: NetStats,
+    previous: NetStats,
+    window_start: Instant,
+    window_reliable: u64,
+    window_resent: u64,
+    has_rtt: bool,
+}
+
+impl Default for StatsTracker {
+    fn default() -> Self {
+        Self {
+            stats: NetStats::default(),
+            previous: NetStats::default(),
+            window_start: Instant::now(),
+            window_reliable: 0,
+            window_resent: 0,
+            has_rtt: false,
+        }
+    }
+}
+
+impl StatsTracker {
+    pub fn stats(&self) -> &NetStats {
+        &self.stats
+    }
+
+    pub fn packet_sent(&mut self, len: usize) {
+        self.stats.packets_sent.add(1);
+        self.stats.bytes_sent.add(len as u64);
+    }
+
+    pub fn packet_received(&mut self, len: usize) {
+        self.stats.packets_received.add(1);
+        self.stats.bytes_received.add(len as u64);
+    }
+
+    pub fn message_sent(&mut self, channel: u8, len: usize, reliable: bool) {
+        self.stats.messages_sent.add(1);
+        let channel = self.stats.channels.entry(channel).or_default();
+        channel.messages_sent.add(1);
+        channel.bytes_sent.add(len as u64);
+        if reliable {
+            self.window_reliable += 1;
+        }
+    }
+
+    pub fn message_received(&mut self, channel: u8, len: usize) {
+        self.stats.messages_received.add(1);
+        let channel = self.stats.channels.entry(channel).or_default();
+        channel.messages_received.add(1);
+        channel.bytes_received.add(len as u64);
+    }
+
+    pub fn messages_dropped(&mut self, count: u64) {
+        self.stats.messages_dropped.add(count);
+    }
+
+    pub fn message_resent(&mut self) {
+        self.window_resent += 1;
+    }
+
+    /// Add a round trip time measurement, smoothed the same way as TCP (RFC 6298)
+    pub fn rtt_sample(&mut self, sample: Duration) {
+        if !self.has_rtt {
+            self.has_rtt = true;
+            self.stats.rtt = sample;
+            self.stats.jitter = sample / 2;
+        } else {
+            let diff = self.stats.rtt.abs_diff(sample);
+            self.stats.jitter = (self.stats.jitter * 3 + diff) / 4;
+            self.stats.rtt = (self.stats.rtt * 7 + sample) / 8;
+        }
+    }
+
+    /// Update the per second rates, if a second has passed
+    pub fn update(&mut self) {
+        let elapsed = self.window_start.elapsed();
+        if elapsed < WINDOW {
+            return;
+        }
+        let (stats, previous) = (&mut self.stats, &self.previous);
+        stats.packets_sent.roll(&previous.packets_sent, elapsed);
+        stats.packets_received.roll(&previous.packets_received, elapsed);
+        stats.bytes_sent.roll(&previous.bytes_sent, elapsed);
+        stats.bytes_received.roll(&previous.bytes_received, elapsed);
+        stats.messages_sent.roll(&previous.messages_sent, elapsed);
+        stats.messages_received.roll(&previous.messages_received, elapsed);
+        stats.messages_dropped.roll(&previous.messages_dropped, elapsed);
+        for (id, channel) in &mut stats.channels {
+            let previous = previous.channels.get(id).copied().unwrap_or_default();
+            channel.messages_sent.roll(&previous.messages_sent, elapsed);
+            channel.messages_received.roll(&previous.messages_received, elapsed);
+            channel.bytes_sent.roll(&previous.bytes_sent, elapsed);
+            channel.bytes_received.roll(&previous.bytes_received, elapsed);
+        }
+
+        let attempts = self.window_reliable + self.window_resent;
+        if attempts > 0 {
+            let loss = self.window_resent as f32 / attempts as f32;
+            stats.packet_loss = stats.packet_loss * 0.75 + loss * 0.25;
+        }
+
+        self.previous = self.stats.clone();
+        self.window_start = Instant::now();
+        self.window_reliable = 0;
+        self.window_resent = 0;
+    }
+}
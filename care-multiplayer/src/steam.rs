@@ -0,0 +1,166 @@
+//! A transport over Steam's networking, using the
+//! [steamworks](https://crates.io/crates/steamworks) crate
+//!
+//! Packets are sent through Valve's relay network, so players can connect
+//! to each other without port forwarding and without their IP addresses
+//! being revealed. Peers are identified by their Steam id, which Steam has
+//! already authenticated, so a server can trust that a client is who it
+//! says it is, see [SteamTransport::steam_id] and
+//! [crate::server::Server::client_addr].
+//!
+//! Since every other part of this crate identifies peers with an
+//! [Address], Steam ids are stored inside of (fake) IPv6 addresses, see
+//! [address] and [steam_id].
+//!
+//! Steam callbacks have to keep running for sessions to be accepted, so
+//! make sure `SingleClient::run_callbacks` is called every frame.
+//!
+//! ```ignore
+//! let (steam, single) = steamworks::Client::init()?;
+//!
+//! // Hosting, and letting friends join from their friends list
+//! let transport = SteamTransport::new(steam.clone());
+//! transport.allow_joining(true);
+//! let server = Server::new(transport, ServerConfig::default())?;
+//!
+//! // Joining a friend, e.g. after a `GameRichPresenceJoinRequested` callback
+//! let host = parse_connect(&join.connect).unwrap();
+//! let client = Client::new(SteamTransport::new(steam.clone()), host, ClientConfig::default())?;
+//! ```
+
+use std::{
+    collections::VecDeque,
+    io,
+    net::{Ipv6Addr, SocketAddrV6},
+};
+
+use parking_lot::Mutex;
+use steamworks::{
+    networking_types::{NetworkingIdentity, SendFlags},
+    Client as SteamClient, SteamId,
+};
+
+use crate::transport::{Address, Transport};
+
+/// Steam ids are stored in the low 64 bits of addresses in this (unique local) prefix
+const ADDRESS_PREFIX: u64 = 0xfd00_5354_4541_4d00;
+/// How many messages are received from Steam at once
+const BATCH_SIZE: usize = 64;
+/// The rich presence key Steam uses for "Join Game" in the friends list
+const CONNECT_KEY: &str = "connect";
+/// The start of the connect string, followed by the host's Steam id
+const CONNECT_PREFIX: &str = "+connect_steam ";
+
+/// The address used for a Steam user
+pub fn address(steam_id: SteamId) -> Address {
+    let ip = Ipv6Addr::from(((ADDRESS_PREFIX as u128) << 64) | steam_id.raw() as u128);
+    Address::V6(SocketAddrV6::new(ip, 0, 0, 0))
+}
+
+/// The Steam user an address belongs to, if it's from a [SteamTransport]
+pub fn steam_id(addr: Address) -> Option<SteamId> {
+    let Address::V6(addr) = addr else {
+        return None;
+    };
+    let bits = u128::from(*addr.ip());
+    ((bits >> 64) as u64 == ADDRESS_PREFIX).then(|| SteamId::from_raw(bits as u64))
+}
+
+/// Read the address of the host from a connect string, as given to
+/// `GameRichPresenceJoinRequested` or on the command line when the game is
+/// launched by joining a friend
+pub fn parse_connect(connect: &str) -> Option<Address> {
+    let id = connect.trim().strip_prefix(CONNECT_PREFIX.trim())?;
+    Some(address(SteamId::from_raw(id.trim().parse().ok()?)))
+}
+
+/// A transport that sends packets through Steam's networking
+pub struct SteamTransport {
+    steam: SteamClient,
+    channel: u32,
+    received: Mutex<VecDeque<(Address, Vec<u8>)>>,
+}
+
+impl std::fmt::Debug for SteamTransport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SteamTransport")
+            .field("steam_id", &self.steam.user().steam_id())
+            .field("channel", &self.channel)
+            .finish_non_exhaustive()
+    }
+}
+
+impl SteamTransport {
+    /// Start sending and receiving through Steam, on channel 0
+    ///
+    /// Every session other players start is accepted, the server decides
+    /// whether to actually let them in.
+    pub fn new(steam: SteamClient) -> Self {
+        Self::with_channel(steam, 0)
+    }
+
+    /// Start sending and receiving through Steam, on a specific channel, so
+    /// several transports can be used at once
+    pub fn with_channel(steam: SteamClient, channel: u32) -> Self {
+        // Starting this early saves time when the first packet is sent
+        steam.networking_utils().init_relay_network_access();
+        steam
+            .networking_messages()
+            .session_request_callback(|request| request.accept());
+        Self {
+            steam,
+            channel,
+            received: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// The Steam user an address belongs to, see [steam_id]
+    pub fn steam_id(&self, addr: Address) -> Option<SteamId> {
+        steam_id(addr)
+    }
+
+    /// Let friends join this game from their friends list, or stop them
+    pub fn allow_joining(&self, allow: bool) {
+        let connect = format!("{CONNECT_PREFIX}{}", self.steam.user().steam_id().raw());
+        self.steam
+            .friends()
+            .set_rich_presence(CONNECT_KEY, allow.then_some(connect.as_str()));
+    }
+}
+
+impl Transport for SteamTransport {
+    fn send_to(&self, addr: Address, data: &[u8]) -> io::Result<()> {
+        let steam_id = steam_id(addr).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "Not the address of a Steam user")
+        })?;
+        self.steam
+            .networking_messages()
+            .send_message_to_user(
+                NetworkingIdentity::new_steam_id(steam_id),
+                // Everything above this already handles reliability
+                SendFlags::UNRELIABLE_NO_NAGLE,
+                data,
+                self.channel,
+            )
+            .map_err(io::Error::other)
+    }
+
+    fn recv_from(&self) -> io::Result<Option<(Address, Vec<u8>)>> {
+        let mut received = self.received.lock();
+        if received.is_empty() {
+            let messages = self
+                .steam
+                .networking_messages()
+                .receive_messages_on_channel(self.channel, BATCH_SIZE);
+            received.extend(messages.iter().filter_map(|message| {
+                let steam_id = message.identity_peer().steam_id()?;
+                Some((address(steam_id), message.data().to_vec()))
+            }));
+        }
+        Ok(received.pop_front())
+    }
+
+    fn local_addr(&self) -> io::Result<Address> {
+        Ok(address(self.steam.user().steam_id()))
+    }
+}
@@ -1,16 +1,172 @@
-use std::sync::atomic::AtomicBool;
+use std::{
+    collections::HashSet,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
 
-use parking_lot::RwLock;
+use parking_lot::{Mutex, RwLock, RwLockReadGuard};
+
+mod collections;
+
+pub(crate) use collections::CollectionState;
+pub use collections::{ConflictPolicy, SyncedMap, SyncedVec};
 
 pub trait Transferable<C = ()> {
     fn send(&self, context: &C) -> Vec<u8>;
     fn receive(data: &[u8], context: &C) -> Self;
 }
 
-pub trait SyncManager<C = ()> {
-    fn queue_sync<T>(&self, data: SyncedValue<T, C>)
-    where
-        T: 'static + Transferable<C>;
+pub trait SyncManager<C = ()>: Send + Sync {
+    fn queue_sync(&self, id: usize, data: Vec<u8>);
+    /// Queue changes to a synced collection, which are applied on top of
+    /// the previous changes instead of replacing them
+    fn queue_delta(&self, id: usize, data: Vec<u8>) {
+        self.queue_sync(id, data);
+    }
+
+    /// Ask to become the owner of a synced value
+    fn request_ownership(&self, _id: usize) {}
+    /// Give up ownership of a synced value, handing it back to the server
+    fn release_ownership(&self, _id: usize) {}
+    /// Whether this peer currently owns a synced value, and so should be the one changing it
+    fn is_owner(&self, _id: usize) -> bool {
+        true
+    }
+}
+
+#[derive(Debug, Default)]
+/// Collects synced values that need to be sent, for clients and servers
+pub(crate) struct SyncQueue {
+    /// Queued updates, and whether each is a change to a synced collection
+    queued: Mutex<Vec<(usize, Vec<u8>, bool)>>,
+    /// Ownership requests (true) and releases (false) to send to the server
+    ownership_requests: Mutex<Vec<(usize, bool)>>,
+    /// On a client, the ids this client owns. On the server, the ids owned by any client
+    owned: RwLock<HashSet<usize>>,
+    is_server: bool,
+}
+
+impl SyncQueue {
+    /// Create the queue for a server, which owns everything not owned by a client
+    pub fn server() -> Self {
+        Self {
+            is_server: true,
+            ..Default::default()
+        }
+    }
+
+    /// Take every queued update, oldest first
+    pub fn take(&self) -> Vec<(usize, Vec<u8>, bool)> {
+        std::mem::take(&mut *self.queued.lock())
+    }
+
+    /// Take every queued ownership request and release, oldest first
+    pub fn take_ownership_requests(&self) -> Vec<(usize, bool)> {
+        std::mem::take(&mut *self.ownership_requests.lock())
+    }
+
+    /// Record a change of ownership, `mine` is whether the id is now owned by
+    /// this client, or on the server whether it's owned by any client
+    pub fn set_owned(&self, id: usize, mine: bool) {
+        if mine {
+            self.owned.write().insert(id);
+        } else {
+            self.owned.write().remove(&id);
+        }
+    }
+
+    /// Forget every owned value, when a client disconnects
+    pub fn clear_owned(&self) {
+        self.owned.write().clear();
+    }
+}
+
+impl<C> SyncManager<C> for SyncQueue {
+    fn queue_sync(&self, id: usize, data: Vec<u8>) {
+        self.queued.lock().push((id, data, false));
+    }
+
+    fn queue_delta(&self, id: usize, data: Vec<u8>) {
+        self.queued.lock().push((id, data, true));
+    }
+
+    fn request_ownership(&self, id: usize) {
+        if !self.is_server {
+            self.ownership_requests.lock().push((id, true));
+        }
+    }
+
+    fn release_ownership(&self, id: usize) {
+        if !self.is_server {
+            self.ownership_requests.lock().push((id, false));
+        }
+    }
+
+    fn is_owner(&self, id: usize) -> bool {
+        self.owned.read().contains(&id) != self.is_server
+    }
+}
+
+pub(crate) const OWNERSHIP_REQUEST: u8 = 0;
+pub(crate) const OWNERSHIP_RELEASE: u8 = 1;
+pub(crate) const OWNERSHIP_CHANGED: u8 = 2;
+
+/// Encode a sync update message
+pub(crate) fn encode_sync(id: usize, tick: u64, data: &[u8]) -> Vec<u8> {
+    let mut message = (id as u64).to_le_bytes().to_vec();
+    message.extend_from_slice(&tick.to_le_bytes());
+    message.extend_from_slice(data);
+    message
+}
+
+/// Decode a sync update message into the id, tick and data
+pub(crate) fn decode_sync(message: &[u8]) -> Option<(usize, u64, &[u8])> {
+    let (id, message) = message.split_first_chunk::<8>()?;
+    let (tick, data) = message.split_first_chunk::<8>()?;
+    Some((
+        u64::from_le_bytes(*id) as usize,
+        u64::from_le_bytes(*tick),
+        data,
+    ))
+}
+
+#[derive(Debug, Clone)]
+/// The latest state of a synced value or collection, kept by the server
+pub(crate) enum SyncCache {
+    Value(Vec<u8>),
+    Collection(CollectionState),
+}
+
+impl SyncCache {
+    /// Start caching an update, `delta` is whether it's a change to a collection
+    pub fn new(data: &[u8], delta: bool) -> Self {
+        let mut cache = Self::Value(Vec::new());
+        cache.update(data, delta);
+        cache
+    }
+
+    pub fn update(&mut self, data: &[u8], delta: bool) {
+        match self {
+            Self::Collection(state) if delta => state.apply(data),
+            _ if delta => {
+                let mut state = CollectionState::Vec(Vec::new());
+                state.apply(data);
+                *self = Self::Collection(state);
+            }
+            _ => *self = Self::Value(data.to_vec()),
+        }
+    }
+
+    /// Everything a peer that hasn't seen any updates needs, and whether
+    /// it's a change to a collection
+    pub fn full(&self) -> (Vec<u8>, bool) {
+        match self {
+            Self::Value(data) => (data.clone(), false),
+            Self::Collection(state) => (state.snapshot(), true),
+        }
+    }
 }
 
 pub struct SyncedValue<T, C = ()>
@@ -19,6 +175,166 @@ where
 {
     id: usize,
     dirty: AtomicBool,
-    inner: RwLock<T: 'static>,
+    inner: RwLock<T>,
     manager: Arc<dyn SyncManager<C>>,
 }
+
+impl<T, C> SyncedValue<T, C>
+where
+    T: 'static + Transferable<C>,
+{
+    /// Create a new synced value, the id must be the same on every peer
+    pub fn new(id: usize, value: T, manager: Arc<dyn SyncManager<C>>) -> Self {
+        Self {
+            id,
+            dirty: AtomicBool::new(true),
+            inner: RwLock::new(value),
+            manager,
+        }
+    }
+
+    /// The id used to identify this value over the network
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    /// Read the current (latest) value
+    pub fn get(&self) -> RwLockReadGuard<'_, T> {
+        self.inner.read()
+    }
+
+    /// Change the value locally, it will be sent on the next [SyncedValue::flush]
+    pub fn set(&self, value: T) {
+        *self.inner.write() = value;
+        self.dirty.store(true, Ordering::Release);
+    }
+
+    /// Whether the value has been changed locally since it was last flushed
+    pub fn is_dirty(&self) -> bool {
+        self.dirty.load(Ordering::Acquire)
+    }
+
+    /// Queue the value to be sent to the network, if it has changed
+    pub fn flush(&self, context: &C) {
+        if self.dirty.swap(false, Ordering::AcqRel) {
+            let data = self.inner.read().send(context);
+            self.manager.queue_sync(self.id, data);
+        }
+    }
+
+    /// Ask the server to make this peer the owner of the value, see
+    /// [crate::server::OwnershipPolicy]
+    ///
+    /// Servers already own every value that isn't owned by a client, see
+    /// [crate::server::Server::set_owner] to take one back.
+    pub fn request_ownership(&self) {
+        self.manager.request_ownership(self.id);
+    }
+
+    /// Give ownership of the value back to the server
+    pub fn release_ownership(&self) {
+        self.manager.release_ownership(self.id);
+    }
+
+    /// Whether this peer owns the value, and so should be the one changing it
+    pub fn is_owner(&self) -> bool {
+        self.manager.is_owner(self.id)
+    }
+
+    /// Overwrite the value with data received from the network
+    pub fn receive(&self, data: &[u8], context: &C) {
+        *self.inner.write() = T::receive(data, context);
+    }
+}
+
+macro_rules! impl_transferable_num {
+    ($($ty:ident),*) => {
+        $(
+            impl<C> Transferable<C> for $ty {
+                fn send(&self, _context: &C) -> Vec<u8> {
+                    self.to_le_bytes().to_vec()
+                }
+                fn receive(data: &[u8], _context: &C) -> Self {
+                    let mut bytes = [0; std::mem::size_of::<$ty>()];
+                    let len = bytes.len().min(data.len());
+                    bytes[..len].copy_from_slice(&data[..len]);
+                    $ty::from_le_bytes(bytes)
+                }
+            }
+        )*
+    };
+}
+
+impl_transferable_num!(u8, i8, u16, i16, u32, i32, u64, i64, f32, f64);
+
+impl<C> Transferable<C> for bool {
+    fn send(&self, _context: &C) -> Vec<u8> {
+        vec![*self as u8]
+    }
+    fn receive(data: &[u8], _context: &C) -> Self {
+        data.first().is_some_and(|&b| b != 0)
+    }
+}
+
+impl<C> Transferable<C> for String {
+    fn send(&self, _context: &C) -> Vec<u8> {
+        self.as_bytes().to_vec()
+    }
+    fn receive(data: &[u8], _context: &C) -> Self {
+        String::from_utf8_lossy(data).into_owned()
+    }
+}
+
+impl<C> Transferable<C> for Vec<u8> {
+    fn send(&self, _context: &C) -> Vec<u8> {
+        self.clone()
+    }
+    fn receive(data: &[u8], _context: &C) -> Self {
+        data.to_vec()
+    }
+}
+
+impl<C> Transferable<C> for () {
+    fn send(&self, _context: &C) -> Vec<u8> {
+        Vec::new()
+    }
+    fn receive(_data: &[u8], _context: &C) -> Self {}
+}
+
+// Each element is prefixed with its length, so elements can be any size
+macro_rules! impl_transferable_tuple {
+    ($($name:ident),*) => {
+        impl<C, $($name: Transferable<C>,)*> Transferable<C> for ($($name,)*) {
+            #[allow(non_snake_case)]
+            fn send(&self, context: &C) -> Vec<u8> {
+                let ($($name,)*) = self;
+                let mut data = Vec::new();
+                $(
+                    let element = $name.send(context);
+                    data.extend_from_slice(&(element.len() as u32).to_le_bytes());
+                    data.extend_from_slice(&element);
+                )*
+                data
+            }
+            #[allow(unused_assignments)]
+            fn receive(mut data: &[u8], context: &C) -> Self {
+                ($({
+                    let len = data
+                        .first_chunk::<4>()
+                        .map(|len| u32::from_le_bytes(*len) as usize)
+                        .unwrap_or(0);
+                    let element = data.get(4..4 + len).unwrap_or_default();
+                    data = data.get(4 + len..).unwrap_or_default();
+                    $name::receive(element, context)
+                },)*)
+            }
+        }
+    };
+}
+
+impl_transferable_tuple!(A);
+impl_transferable_tuple!(A, B);
+impl_transferable_tuple!(A, B, D);
+impl_transferable_tuple!(A, B, D, E);
+impl_transferable_tuple!(A, B, D, E, F);
+impl_transferable_tuple!(A, B, D, E, F, G);
@@ -0,0 +1,573 @@
+//! Synced collections, which only send the elements that changed
+//!
+//! A [SyncedVec] or [SyncedMap] is sent as a list of changes (inserts,
+//! removes and changed elements) instead of the whole collection, so large
+//! collections like player lists or inventories stay cheap to sync. The
+//! server keeps track of the whole collection, so clients that join or
+//! become interested later are sent all of it.
+//!
+//! Received updates are applied with `receive`, the same as a
+//! [super::SyncedValue].
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    hash::Hash,
+    sync::Arc,
+};
+
+use parking_lot::{MappedRwLockReadGuard, RwLock, RwLockReadGuard};
+
+use super::{SyncManager, Transferable};
+use crate::packet::{PacketReader, PacketWriter};
+
+const KIND_VEC: u8 = 0;
+const KIND_MAP: u8 = 1;
+
+const OP_CLEAR: u8 = 0;
+const OP_SET: u8 = 1;
+const OP_INSERT: u8 = 2;
+const OP_REMOVE: u8 = 3;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+/// What happens when an update arrives for an element that was also changed
+/// locally, but hasn't been flushed yet
+///
+/// Clearing a collection always overrides local changes.
+pub enum ConflictPolicy {
+    /// The received value replaces the local change, which is never sent
+    #[default]
+    Remote,
+    /// The local change is kept, and will be sent on the next flush
+    Local,
+}
+
+/// Shift dirty indices to account for an element being inserted or removed at `index`
+fn shift(dirty: &mut BTreeSet<usize>, index: usize, inserted: bool) {
+    *dirty = dirty
+        .iter()
+        .filter(|&&i| inserted || i != index)
+        .map(|&i| match (i >= index, inserted) {
+            (true, true) => i + 1,
+            (true, false) if i > index => i - 1,
+            _ => i,
+        })
+        .collect();
+}
+
+#[derive(Debug, Clone, Copy)]
+enum VecOp {
+    Clear,
+    Insert(usize),
+    Remove(usize),
+}
+
+#[derive(Debug)]
+struct VecState<T> {
+    items: Vec<T>,
+    /// Inserts and removes since the last flush, in order
+    ops: Vec<VecOp>,
+    /// Indices of elements changed since the last flush
+    dirty: BTreeSet<usize>,
+}
+
+impl<T> VecState<T> {
+    fn remove(&mut self, index: usize) -> T {
+        let value = self.items.remove(index);
+        shift(&mut self.dirty, index, false);
+        self.ops.push(VecOp::Remove(index));
+        value
+    }
+}
+
+/// A list that is synced over the network one element at a time
+///
+/// Elements are identified by their index, so a vec should only be changed
+/// by one peer at a time, see [SyncedVec::request_ownership]. If several
+/// peers insert or remove elements at once they can end up in different
+/// orders, use a [SyncedMap] instead for collections that are changed from
+/// several places.
+pub struct SyncedVec<T, C = ()>
+where
+    T: 'static + Transferable<C>,
+{
+    id: usize,
+    inner: RwLock<VecState<T>>,
+    policy: ConflictPolicy,
+    manager: Arc<dyn SyncManager<C>>,
+}
+
+impl<T, C> SyncedVec<T, C>
+where
+    T: 'static + Transferable<C>,
+{
+    /// Create a new synced vec, the id must be the same on every peer
+    pub fn new(id: usize, items: Vec<T>, manager: Arc<dyn SyncManager<C>>) -> Self {
+        let ops = std::iter::once(VecOp::Clear)
+            .chain((0..items.len()).map(VecOp::Insert))
+            .collect();
+        let dirty = (0..items.len()).collect();
+        Self {
+            id,
+            inner: RwLock::new(VecState { items, ops, dirty }),
+            policy: ConflictPolicy::default(),
+            manager,
+        }
+    }
+
+    /// Set how conflicting changes are handled
+    pub fn with_policy(mut self, policy: ConflictPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// The id used to identify this vec over the network
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    /// Read the current elements
+    pub fn get(&self) -> MappedRwLockReadGuard<'_, Vec<T>> {
+        RwLockReadGuard::map(self.inner.read(), |state| &state.items)
+    }
+
+    /// The number of elements
+    pub fn len(&self) -> usize {
+        self.inner.read().items.len()
+    }
+
+    /// Whether there are no elements
+    pub fn is_empty(&self) -> bool {
+        self.inner.read().items.is_empty()
+    }
+
+    /// Add an element to the end
+    pub fn push(&self, value: T) {
+        let mut state = self.inner.write();
+        let index = state.items.len();
+        state.items.push(value);
+        state.ops.push(VecOp::Insert(index));
+        state.dirty.insert(index);
+    }
+
+    /// Insert an element at `index`, moving everything after it along
+    pub fn insert(&self, index: usize, value: T) {
+        let mut state = self.inner.write();
+        state.items.insert(index, value);
+        shift(&mut state.dirty, index, true);
+        state.ops.push(VecOp::Insert(index));
+        state.dirty.insert(index);
+    }
+
+    /// Remove and return the element at `index`
+    pub fn remove(&self, index: usize) -> T {
+        self.inner.write().remove(index)
+    }
+
+    /// Remove and return the last element
+    pub fn pop(&self) -> Option<T> {
+        let mut state = self.inner.write();
+        let len = state.items.len();
+        (len > 0).then(|| state.remove(len - 1))
+    }
+
+    /// Replace the element at `index`
+    pub fn set(&self, index: usize, value: T) {
+        let mut state = self.inner.write();
+        state.items[index] = value;
+        state.dirty.insert(index);
+    }
+
+    /// Change the element at `index` in place
+    pub fn modify<R>(&self, index: usize, f: impl FnOnce(&mut T) -> R) -> R {
+        let mut state = self.inner.write();
+        let result = f(&mut state.items[index]);
+        state.dirty.insert(index);
+        result
+    }
+
+    /// Remove every element
+    pub fn clear(&self) {
+        let mut state = self.inner.write();
+        state.items.clear();
+        state.ops = vec![VecOp::Clear];
+        state.dirty.clear();
+    }
+
+    /// Whether the vec has been changed locally since it was last flushed
+    pub fn is_dirty(&self) -> bool {
+        let state = self.inner.read();
+        !state.ops.is_empty() || !state.dirty.is_empty()
+    }
+
+    /// Queue the changes to be sent to the network, if there are any
+    pub fn flush(&self, context: &C) {
+        let mut state = self.inner.write();
+        if state.ops.is_empty() && state.dirty.is_empty() {
+            return;
+        }
+        let mut delta = PacketWriter::new(KIND_VEC);
+        for op in std::mem::take(&mut state.ops) {
+            match op {
+                VecOp::Clear => delta.u8(OP_CLEAR),
+                VecOp::Insert(index) => delta.u8(OP_INSERT).u32(index as u32),
+                VecOp::Remove(index) => delta.u8(OP_REMOVE).u32(index as u32),
+            };
+        }
+        for index in std::mem::take(&mut state.dirty) {
+            delta
+                .u8(OP_SET)
+                .u32(index as u32)
+                .bytes(&state.items[index].send(context));
+        }
+        self.manager.queue_delta(self.id, delta.finish());
+    }
+
+    /// Ask the server to make this peer the owner of the vec
+    pub fn request_ownership(&self) {
+        self.manager.request_ownership(self.id);
+    }
+
+    /// Give ownership of the vec back to the server
+    pub fn release_ownership(&self) {
+        self.manager.release_ownership(self.id);
+    }
+
+    /// Whether this peer owns the vec, and so should be the one changing it
+    pub fn is_owner(&self) -> bool {
+        self.manager.is_owner(self.id)
+    }
+
+    /// Apply changes received from the network
+    pub fn receive(&self, data: &[u8], context: &C) {
+        let Some((KIND_VEC, mut reader)) = PacketReader::new(data) else {
+            return;
+        };
+        let mut state = self.inner.write();
+        while let Some(op) = reader.u8() {
+            match op {
+                OP_CLEAR => {
+                    state.items.clear();
+                    state.ops.clear();
+                    state.dirty.clear();
+                }
+                OP_INSERT => {
+                    let Some(index) = reader.u32().map(|i| i as usize) else {
+                        return;
+                    };
+                    if index <= state.items.len() {
+                        // The value follows in a set
+                        state.items.insert(index, T::receive(&[], context));
+                        shift(&mut state.dirty, index, true);
+                    }
+                }
+                OP_REMOVE => {
+                    let Some(index) = reader.u32().map(|i| i as usize) else {
+                        return;
+                    };
+                    if index < state.items.len() {
+                        state.items.remove(index);
+                        shift(&mut state.dirty, index, false);
+                    }
+                }
+                OP_SET => {
+                    let (Some(index), Some(value)) = (reader.u32(), reader.bytes()) else {
+                        return;
+                    };
+                    let index = index as usize;
+                    if index >= state.items.len() {
+                        continue;
+                    }
+                    if state.dirty.contains(&index) {
+                        match self.policy {
+                            ConflictPolicy::Remote => state.dirty.remove(&index),
+                            ConflictPolicy::Local => continue,
+                        };
+                    }
+                    state.items[index] = T::receive(value, context);
+                }
+                _ => return,
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+struct MapState<K, V> {
+    items: HashMap<K, V>,
+    /// Whether the map was cleared since the last flush
+    cleared: bool,
+    /// Keys inserted or changed since the last flush
+    dirty: HashSet<K>,
+    /// Keys removed since the last flush
+    removed: HashSet<K>,
+}
+
+/// A map that is synced over the network one entry at a time
+pub struct SyncedMap<K, V, C = ()>
+where
+    K: 'static + Transferable<C> + Eq + Hash + Clone,
+    V: 'static + Transferable<C>,
+{
+    id: usize,
+    inner: RwLock<MapState<K, V>>,
+    policy: ConflictPolicy,
+    manager: Arc<dyn SyncManager<C>>,
+}
+
+impl<K, V, C> SyncedMap<K, V, C>
+where
+    K: 'static + Transferable<C> + Eq + Hash + Clone,
+    V: 'static + Transferable<C>,
+{
+    /// Create a new synced map, the id must be the same on every peer
+    pub fn new(id: usize, items: HashMap<K, V>, manager: Arc<dyn SyncManager<C>>) -> Self {
+        let dirty = items.keys().cloned().collect();
+        Self {
+            id,
+            inner: RwLock::new(MapState {
+                items,
+                cleared: true,
+                dirty,
+                removed: HashSet::new(),
+            }),
+            policy: ConflictPolicy::default(),
+            manager,
+        }
+    }
+
+    /// Set how conflicting changes are handled
+    pub fn with_policy(mut self, policy: ConflictPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// The id used to identify this map over the network
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    /// Read the current entries
+    pub fn get(&self) -> MappedRwLockReadGuard<'_, HashMap<K, V>> {
+        RwLockReadGuard::map(self.inner.read(), |state| &state.items)
+    }
+
+    /// The number of entries
+    pub fn len(&self) -> usize {
+        self.inner.read().items.len()
+    }
+
+    /// Whether there are no entries
+    pub fn is_empty(&self) -> bool {
+        self.inner.read().items.is_empty()
+    }
+
+    /// Insert or replace an entry, returning the old value
+    pub fn insert(&self, key: K, value: V) -> Option<V> {
+        let mut state = self.inner.write();
+        state.removed.remove(&key);
+        state.dirty.insert(key.clone());
+        state.items.insert(key, value)
+    }
+
+    /// Remove an entry, returning its value
+    pub fn remove(&self, key: &K) -> Option<V> {
+        let mut state = self.inner.write();
+        let value = state.items.remove(key)?;
+        state.dirty.remove(key);
+        state.removed.insert(key.clone());
+        Some(value)
+    }
+
+    /// Change an entry in place, if it exists
+    pub fn modify<R>(&self, key: &K, f: impl FnOnce(&mut V) -> R) -> Option<R> {
+        let mut state = self.inner.write();
+        let result = f(state.items.get_mut(key)?);
+        state.dirty.insert(key.clone());
+        Some(result)
+    }
+
+    /// Remove every entry
+    pub fn clear(&self) {
+        let mut state = self.inner.write();
+        state.items.clear();
+        state.cleared = true;
+        state.dirty.clear();
+        state.removed.clear();
+    }
+
+    /// Whether the map has been changed locally since it was last flushed
+    pub fn is_dirty(&self) -> bool {
+        let state = self.inner.read();
+        state.cleared || !state.dirty.is_empty() || !state.removed.is_empty()
+    }
+
+    /// Queue the changes to be sent to the network, if there are any
+    pub fn flush(&self, context: &C) {
+        if !self.is_dirty() {
+            return;
+        }
+        let mut state = self.inner.write();
+        let mut delta = PacketWriter::new(KIND_MAP);
+        if std::mem::take(&mut state.cleared) {
+            delta.u8(OP_CLEAR);
+        }
+        for key in std::mem::take(&mut state.removed) {
+            delta.u8(OP_REMOVE).bytes(&key.send(context));
+        }
+        for key in std::mem::take(&mut state.dirty) {
+            delta
+                .u8(OP_SET)
+                .bytes(&key.send(context))
+                .bytes(&state.items[&key].send(context));
+        }
+        self.manager.queue_delta(self.id, delta.finish());
+    }
+
+    /// Ask the server to make this peer the owner of the map
+    pub fn request_ownership(&self) {
+        self.manager.request_ownership(self.id);
+    }
+
+    /// Give ownership of the map back to the server
+    pub fn release_ownership(&self) {
+        self.manager.release_ownership(self.id);
+    }
+
+    /// Whether this peer owns the map, and so should be the one changing it
+    pub fn is_owner(&self) -> bool {
+        self.manager.is_owner(self.id)
+    }
+
+    /// Apply changes received from the network
+    pub fn receive(&self, data: &[u8], context: &C) {
+        let Some((KIND_MAP, mut reader)) = PacketReader::new(data) else {
+            return;
+        };
+        let mut state = self.inner.write();
+        while let Some(op) = reader.u8() {
+            match op {
+                OP_CLEAR => {
+                    state.items.clear();
+                    state.dirty.clear();
+                    state.removed.clear();
+                }
+                OP_REMOVE => {
+                    let Some(key) = reader.bytes() else {
+                        return;
+                    };
+                    let key = K::receive(key, context);
+                    if state.dirty.contains(&key) && self.policy == ConflictPolicy::Local {
+                        continue;
+                    }
+                    state.dirty.remove(&key);
+                    state.items.remove(&key);
+                }
+                OP_SET => {
+                    let (Some(key), Some(value)) = (reader.bytes(), reader.bytes()) else {
+                        return;
+                    };
+                    let key = K::receive(key, context);
+                    let changed = state.dirty.contains(&key) || state.removed.contains(&key);
+                    if changed && self.policy == ConflictPolicy::Local {
+                        continue;
+                    }
+                    state.dirty.remove(&key);
+                    state.removed.remove(&key);
+                    state.items.insert(key, V::receive(value, context));
+                }
+                _ => return,
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+/// The untyped contents of a synced collection, kept by the server so
+/// the whole collection can be sent to clients that didn't see every change
+pub(crate) enum CollectionState {
+    Vec(Vec<Vec<u8>>),
+    Map(BTreeMap<Vec<u8>, Vec<u8>>),
+}
+
+impl CollectionState {
+    /// Apply a list of changes, as sent by [SyncedVec::flush] or [SyncedMap::flush]
+    pub fn apply(&mut self, delta: &[u8]) {
+        let Some((kind, mut reader)) = PacketReader::new(delta) else {
+            return;
+        };
+        match (kind, &mut *self) {
+            (KIND_VEC, Self::Vec(_)) | (KIND_MAP, Self::Map(_)) => {}
+            (KIND_VEC, _) => *self = Self::Vec(Vec::new()),
+            (KIND_MAP, _) => *self = Self::Map(BTreeMap::new()),
+            _ => return,
+        }
+        while let Some(op) = reader.u8() {
+            match (op, &mut *self) {
+                (OP_CLEAR, Self::Vec(items)) => items.clear(),
+                (OP_CLEAR, Self::Map(items)) => items.clear(),
+                (OP_INSERT, Self::Vec(items)) => match reader.u32() {
+                    Some(index) if index as usize <= items.len() => {
+                        items.insert(index as usize, Vec::new())
+                    }
+                    Some(_) => {}
+                    None => return,
+                },
+                (OP_REMOVE, Self::Vec(items)) => match reader.u32() {
+                    Some(index) if (index as usize) < items.len() => {
+                        items.remove(index as usize);
+                    }
+                    Some(_) => {}
+                    None => return,
+                },
+                (OP_REMOVE, Self::Map(items)) => {
+                    let Some(key) = reader.bytes() else {
+                        return;
+                    };
+                    items.remove(key);
+                }
+                (OP_SET, Self::Vec(items)) => {
+                    let (Some(index), Some(value)) = (reader.u32(), reader.bytes()) else {
+                        return;
+                    };
+                    if let Some(item) = items.get_mut(index as usize) {
+                        *item = value.to_vec();
+                    }
+                }
+                (OP_SET, Self::Map(items)) => {
+                    let (Some(key), Some(value)) = (reader.bytes(), reader.bytes()) else {
+                        return;
+                    };
+                    items.insert(key.to_vec(), value.to_vec());
+                }
+                _ => return,
+            }
+        }
+    }
+
+    /// A list of changes that replaces the whole collection
+    pub fn snapshot(&self) -> Vec<u8> {
+        match self {
+            Self::Vec(items) => {
+                let mut delta = PacketWriter::new(KIND_VEC);
+                delta.u8(OP_CLEAR);
+                for (index, item) in items.iter().enumerate() {
+                    delta
+                        .u8(OP_INSERT)
+                        .u32(index as u32)
+                        .u8(OP_SET)
+                        .u32(index as u32)
+                        .bytes(item);
+                }
+                delta.finish()
+            }
+            Self::Map(items) => {
+                let mut delta = PacketWriter::new(KIND_MAP);
+                delta.u8(OP_CLEAR);
+                for (key, value) in items {
+                    delta.u8(OP_SET).bytes(key).bytes(value);
+                }
+                delta.finish()
+            }
+        }
+    }
+}
@@ -0,0 +1,235 @@
+//! Limiting how much is sent, so a game can't saturate its own upload link
+//!
+//! Every connection can have a budget of bytes per second, and every channel
+//! a maximum number of messages per second, e.g. 20 per second for state
+//! snapshots and 60 per second for inputs. See
+//! [crate::server::ServerConfig::bandwidth] and
+//! [crate::client::ClientConfig::bandwidth].
+//!
+//! Only unreliable messages are ever held back or dropped. Reliable messages
+//! are always sent straight away, but they still use up the budget.
+//! Unreliable messages on a throttled connection are sent the next time it's
+//! polled, highest [ChannelLimit::priority] first, and the ones that don't
+//! fit in the budget are dropped, since newer ones will be along soon
+//! anyway. Dropped messages are counted in
+//! [crate::stats::NetStats::messages_dropped].
+//!
+//! The server's synced values are sent once per tick, so
+//! [crate::server::ServerConfig::tick_rate] is their snapshot rate.
+//!
+//! ```ignore
+//! let mut bandwidth = BandwidthConfig::default();
+//! bandwidth.max_bytes_per_second = Some(64 * 1024);
+//! bandwidth.channels.insert(STATE_CHANNEL, ChannelLimit { rate: Some(20.0), priority: 0 });
+//! bandwidth.channels.insert(INPUT_CHANNEL, ChannelLimit { rate: Some(60.0), priority: 10 });
+//! let client = Client::new(transport, server, ClientConfig { bandwidth, ..Default::default() })?;
+//! ```
+
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, HashMap},
+    time::{Duration, Instant},
+};
+
+/// How much unused budget can be saved up for later, as a time at the maximum rate
+const BURST: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Clone, Default, PartialEq)]
+/// Limits on what a single connection sends, see the [module level documentation](self)
+pub struct BandwidthConfig {
+    /// The most bytes sent per second, including packet headers, or `None` for no limit
+    pub max_bytes_per_second: Option<usize>,
+    /// Limits for specific channels, channels that aren't in here aren't
+    /// rate limited and have a priority of 0
+    pub channels: BTreeMap<u8, ChannelLimit>,
+}
+
+impl BandwidthConfig {
+    fn limit(&self, channel: u8) -> ChannelLimit {
+        self.channels.get(&channel).copied().unwrap_or_default()
+    }
+}
+
+impl ChannelLimit {
+    /// The time between messages, or `None` if the channel is blocked
+    fn interval(&self) -> Option<Duration> {
+        let rate = self.rate.unwrap_or(f64::INFINITY);
+        if rate > 0.0 {
+            Duration::try_from_secs_f64(1.0 / rate).ok()
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+/// Limits on the unreliable messages sent on a channel
+pub struct ChannelLimit {
+    /// The most messages sent per second, or `None` for no limit
+    ///
+    /// Messages sent faster than this replace the one that's waiting, so
+    /// only the newest is sent. A rate of 0 (or less) blocks the channel, so
+    /// nothing is sent on it.
+    pub rate: Option<f64>,
+    /// Messages with a higher priority are sent first when the connection
+    /// is over its budget
+    pub priority: u8,
+}
+
+#[derive(Debug)]
+/// Holds back unreliable packets until they fit in a connection's budget
+pub(crate) struct Throttle {
+    config: BandwidthConfig,
+    budget: f64,
+    last_refill: Instant,
+    /// Packets waiting to be sent, by channel
+    queued: Vec<(u8, Vec<u8>)>,
+    /// The newest packet on each rate limited channel, and when it can be
+    /// sent, or `None` if it never can
+    limited: HashMap<u8, (Option<Instant>, Option<Vec<u8>>)>,
+}
+
+impl Throttle {
+    pub fn new(config: BandwidthConfig) -> Self {
+        Self {
+            config,
+            budget: 0.0,
+            last_refill: Instant::now(),
+            queued: Vec::new(),
+            limited: HashMap::new(),
+        }
+    }
+
+    /// Whether unreliable packets on a channel have to wait for [Throttle::take]
+    pub fn is_throttled(&self, channel: u8) -> bool {
+        self.config.max_bytes_per_second.is_some() || self.config.limit(channel).rate.is_some()
+    }
+
+    /// Hold back a packet, returning true if it replaced an older one
+    pub fn queue(&mut self, channel: u8, packet: Vec<u8>) -> bool {
+        if self.config.limit(channel).rate.is_some() {
+            let now = Instant::now();
+            let (_, waiting) = self.limited.entry(channel).or_insert((Some(now), None));
+            waiting.replace(packet).is_some()
+        } else {
+            self.queued.push((channel, packet));
+            false
+        }
+    }
+
+    /// Use up some of the budget for a packet that was sent straight away
+    pub fn spend(&mut self, len: usize) {
+        self.budget -= len as f64;
+    }
+
+    /// Take the packets that can be sent now, and count how many were dropped
+    pub fn take(&mut self) -> (Vec<Vec<u8>>, u64) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.last_refill = now;
+
+        let mut queued: Vec<_> = self
+            .queued
+            .drain(..)
+            .map(|(channel, packet)| (channel, packet, false))
+            .collect();
+        for (&channel, (next, waiting)) in &mut self.limited {
+            let blocked = self.config.limit(channel).interval().is_none();
+            if !blocked && next.is_some_and(|next| now >= next) {
+                if let Some(packet) = waiting.take() {
+                    queued.push((channel, packet, true));
+                }
+            }
+        }
+        let budget = self.config.max_bytes_per_second.map(|max| {
+            let max = max as f64;
+            (self.budget + elapsed * max).min(max * BURST.as_secs_f64())
+        });
+        self.budget = budget.unwrap_or(0.0);
+        // Stable, so packets with the same priority keep their order
+        queued.sort_by_key(|&(channel, _, _)| Reverse(self.config.limit(channel).priority));
+
+        let mut packets = Vec::new();
+        let mut dropped = 0;
+        for (channel, packet, limited) in queued {
+            // Going over a little is allowed, so big packets still get sent eventually
+            if budget.is_some() && self.budget <= 0.0 {
+                if limited {
+                    // Still the newest, so it can try again next time
+                    self.limited
+                        .get_mut(&channel)
+                        .unwrap()
+                        .1
+                        .get_or_insert(packet);
+                } else {
+                    dropped += 1;
+                }
+                continue;
+            }
+            self.budget -= packet.len() as f64;
+            packets.push(packet);
+            if limited {
+                let interval = self.config.limit(channel).interval();
+                let next = &mut self.limited.get_mut(&channel).unwrap().0;
+                *next = next
+                    .zip(interval)
+                    .and_then(|(next, interval)| next.checked_add(interval))
+                    .map(|next| next.max(now));
+            }
+        }
+        if budget.is_none() {
+            self.budget = 0.0;
+        }
+        (packets, dropped)
+    }
+
+    pub fn set_config(&mut self, config: BandwidthConfig) {
+        self.config = config;
+        // Anything waiting on a channel that's no longer rate limited goes out next time
+        for (channel, (_, waiting)) in self.limited.drain() {
+            if let Some(packet) = waiting {
+                self.queued.push((channel, packet));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limited(rate: f64) -> Throttle {
+        let mut config = BandwidthConfig::default();
+        config.channels.insert(
+            1,
+            ChannelLimit {
+                rate: Some(rate),
+                priority: 0,
+            },
+        );
+        Throttle::new(config)
+    }
+
+    #[test]
+    fn rate_limited_channels_send_the_newest_message() {
+        let mut throttle = limited(10.0);
+        assert!(throttle.is_throttled(1));
+        assert!(!throttle.queue(1, vec![1]));
+        assert!(throttle.queue(1, vec![2]));
+        assert_eq!(throttle.take(), (vec![vec![2]], 0));
+        // Too soon for the next one
+        throttle.queue(1, vec![3]);
+        assert_eq!(throttle.take(), (vec![], 0));
+    }
+
+    #[test]
+    fn channels_without_a_positive_rate_are_blocked() {
+        for rate in [0.0, -1.0, f64::NAN, f64::MIN_POSITIVE] {
+            let mut throttle = limited(rate);
+            throttle.queue(1, vec![1]);
+            assert_eq!(throttle.take(), (vec![], 0), "rate {rate}");
+            assert!(throttle.queue(1, vec![2]));
+            assert_eq!(throttle.take(), (vec![], 0), "rate {rate}");
+        }
+    }
+}
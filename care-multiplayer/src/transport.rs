@@ -0,0 +1,68 @@
+//! Low level transports, which send and receive individual (unreliable) packets
+//!
+//! Everything else in this crate is built on top of the [Transport] trait, so
+//! implementing it is all that's needed to run over some other kind of network.
+
+use std::{
+    io,
+    net::{SocketAddr, ToSocketAddrs, UdpSocket},
+};
+
+/// The address of a peer on a transport
+pub type Address = SocketAddr;
+
+/// The maximum size of a single packet, chosen to avoid IP fragmentation
+pub const MAX_PACKET_SIZE: usize = 1200;
+
+/// Large enough for any UDP packet, since wrapping transports (like
+/// encryption) can make packets bigger than [MAX_PACKET_SIZE]
+const RECV_BUFFER_SIZE: usize = 65536;
+
+/// A way of sending and receiving packets, which may be lost, duplicated or
+/// arrive out of order
+pub trait Transport: Send + Sync {
+    /// Send a single packet to a peer
+    fn send_to(&self, addr: Address, data: &[u8]) -> io::Result<()>;
+    /// Receive a single packet, if one is available, without blocking
+    fn recv_from(&self) -> io::Result<Option<(Address, Vec<u8>)>>;
+    /// The address other peers can use to reach this one
+    fn local_addr(&self) -> io::Result<Address>;
+}
+
+#[derive(Debug)]
+/// A transport that sends packets directly over UDP
+pub struct UdpTransport {
+    socket: UdpSocket,
+}
+
+impl UdpTransport {
+    /// Bind a UDP socket to a local address, use port 0 to pick any free port
+    pub fn bind(addr: impl ToSocketAddrs) -> io::Result<Self> {
+        let socket = UdpSocket::bind(addr)?;
+        socket.set_nonblocking(true)?;
+        Ok(Self { socket })
+    }
+}
+
+impl Transport for UdpTransport {
+    fn send_to(&self, addr: Address, data: &[u8]) -> io::Result<()> {
+        self.socket.send_to(data, addr).map(|_| ())
+    }
+
+    fn recv_from(&self) -> io::Result<Option<(Address, Vec<u8>)>> {
+        let mut buf = [0; RECV_BUFFER_SIZE];
+        loop {
+            match self.socket.recv_from(&mut buf) {
+                Ok((len, addr)) => return Ok(Some((addr, buf[..len].to_vec()))),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(None),
+                // Windows reports ICMP "port unreachable" messages from previous sends here
+                Err(e) if e.kind() == io::ErrorKind::ConnectionReset => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    fn local_addr(&self) -> io::Result<Address> {
+        self.socket.local_addr()
+    }
+}
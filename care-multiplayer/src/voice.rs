@@ -0,0 +1,307 @@
+//! Voice chat, using the [Opus](https://opus-codec.org/) codec
+//!
+//! Microphone input is given to a [VoiceCapture], which splits it into
+//! packets to send with [crate::client::Client::send_voice]. The server
+//! relays these to every other client, where they arrive as
+//! [crate::client::ClientEvent::Voice] and are given to a [VoiceMixer]. The
+//! mixer smooths out network jitter and mixes every player together into a
+//! single stereo stream to play.
+//!
+//! All audio is 48kHz, captured audio is mono and mixed audio is interleaved stereo.
+//!
+//! ```ignore
+//! // Sending
+//! capture.push(&microphone_samples);
+//! while let Some(packet) = capture.next_packet() {
+//!     client.send_voice(packet)?;
+//! }
+//!
+//! // Receiving
+//! while let Some(event) = client.next_event() {
+//!     if let ClientEvent::Voice { speaker, data } = event {
+//!         mixer.receive(speaker, &data);
+//!     }
+//! }
+//! mixer.mix(&mut speaker_samples);
+//! ```
+
+use std::{
+    collections::{hash_map::Entry, BTreeMap, HashMap, VecDeque},
+    f32::consts::FRAC_PI_4,
+    io,
+};
+
+use audiopus::{
+    coder::{Decoder, Encoder},
+    packet::Packet,
+    Application, Bitrate, Channels, MutSignals, SampleRate,
+};
+
+use crate::server::ClientId;
+
+/// The sample rate of all voice audio
+pub const SAMPLE_RATE: u32 = 48000;
+/// The number of samples in each packet, 20ms of audio
+pub const FRAME_SIZE: usize = 960;
+/// The largest encoded frame, well over what Opus produces at voice bitrates
+const MAX_FRAME_BYTES: usize = 1000;
+/// Packets further ahead than this are assumed to be from a restarted stream
+const MAX_BUFFERED: usize = 50;
+
+#[derive(Debug, Clone)]
+/// Settings for voice capture and playback
+pub struct VoiceConfig {
+    /// Encoded bits per second
+    pub bitrate: i32,
+    /// How many packets to buffer before playing a speaker, more smooths out
+    /// jitter at the cost of latency
+    pub jitter_frames: usize,
+    /// How far away positional speakers can be heard from
+    pub max_distance: f32,
+    /// Volume of every speaker, from 0 to 1
+    pub volume: f32,
+}
+
+impl Default for VoiceConfig {
+    fn default() -> Self {
+        Self {
+            bitrate: 24000,
+            jitter_frames: 3,
+            max_distance: 500.0,
+            volume: 1.0,
+        }
+    }
+}
+
+/// Encodes microphone input into packets
+pub struct VoiceCapture {
+    encoder: Encoder,
+    buffer: Vec<f32>,
+    sequence: u32,
+}
+
+impl std::fmt::Debug for VoiceCapture {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("VoiceCapture")
+            .field("buffered", &self.buffer.len())
+            .field("sequence", &self.sequence)
+            .finish_non_exhaustive()
+    }
+}
+
+impl VoiceCapture {
+    /// Create a new capture
+    pub fn new(config: &VoiceConfig) -> io::Result<Self> {
+        let mut encoder = Encoder::new(SampleRate::Hz48000, Channels::Mono, Application::Voip)
+            .map_err(io::Error::other)?;
+        encoder
+            .set_bitrate(Bitrate::BitsPerSecond(config.bitrate))
+            .map_err(io::Error::other)?;
+        Ok(Self {
+            encoder,
+            buffer: Vec::new(),
+            sequence: 0,
+        })
+    }
+
+    /// Add mono 48kHz samples from the microphone
+    pub fn push(&mut self, samples: &[f32]) {
+        self.buffer.extend_from_slice(samples);
+    }
+
+    /// Encode the next packet, once enough samples have been pushed
+    pub fn next_packet(&mut self) -> Option<Vec<u8>> {
+        if self.buffer.len() < FRAME_SIZE {
+            return None;
+        }
+        let mut packet = vec![0; 4 + MAX_FRAME_BYTES];
+        packet[..4].copy_from_slice(&self.sequence.to_le_bytes());
+        let len = self
+            .encoder
+            .encode_float(&self.buffer[..FRAME_SIZE], &mut packet[4..])
+            .ok()?;
+        packet.truncate(4 + len);
+        self.buffer.drain(..FRAME_SIZE);
+        self.sequence = self.sequence.wrapping_add(1);
+        Some(packet)
+    }
+}
+
+struct Speaker {
+    decoder: Decoder,
+    /// Packets waiting to be decoded, by sequence number
+    buffered: BTreeMap<u32, Vec<u8>>,
+    /// The next sequence number to play, or `None` while buffering
+    next: Option<u32>,
+    decoded: VecDeque<f32>,
+    muted: bool,
+    position: Option<[f32; 2]>,
+}
+
+impl Speaker {
+    /// Decode packets until at least `samples` samples are ready, or the buffer runs dry
+    fn decode(&mut self, samples: usize, jitter_frames: usize) {
+        let mut frame = vec![0.0; FRAME_SIZE];
+        while self.decoded.len() < samples {
+            let Some(&first) = self.buffered.keys().next() else {
+                // Ran out, so buffer up again to absorb the next bit of jitter
+                self.next = None;
+                return;
+            };
+            let next = match self.next {
+                // Skip over long gaps instead of filling them in
+                Some(next) if first.wrapping_sub(next) as usize > MAX_BUFFERED => first,
+                Some(next) => next,
+                None if self.buffered.len() >= jitter_frames.max(1) => first,
+                None => return,
+            };
+            let data = self.buffered.remove(&next);
+            let packet = data.as_deref().and_then(|d| Packet::try_from(d).ok());
+            // A missing packet is decoded as `None`, which lets Opus fill in the gap
+            let len = MutSignals::try_from(&mut frame[..])
+                .ok()
+                .and_then(|output| self.decoder.decode_float(packet, output, false).ok())
+                .unwrap_or(0);
+            self.decoded.extend(&frame[..len]);
+            self.next = Some(next.wrapping_add(1));
+        }
+    }
+}
+
+/// Buffers and mixes voice from every other player
+pub struct VoiceMixer {
+    config: VoiceConfig,
+    speakers: HashMap<ClientId, Speaker>,
+    listener: [f32; 2],
+}
+
+impl std::fmt::Debug for VoiceMixer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("VoiceMixer")
+            .field("config", &self.config)
+            .field("speakers", &self.speakers.keys())
+            .field("listener", &self.listener)
+            .finish_non_exhaustive()
+    }
+}
+
+impl VoiceMixer {
+    /// Create a new mixer, with nobody speaking
+    pub fn new(config: VoiceConfig) -> Self {
+        Self {
+            config,
+            speakers: HashMap::new(),
+            listener: [0.0; 2],
+        }
+    }
+
+    fn speaker(&mut self, speaker: ClientId) -> io::Result<&mut Speaker> {
+        Ok(match self.speakers.entry(speaker) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(Speaker {
+                decoder: Decoder::new(SampleRate::Hz48000, Channels::Mono)
+                    .map_err(io::Error::other)?,
+                buffered: BTreeMap::new(),
+                next: None,
+                decoded: VecDeque::new(),
+                muted: false,
+                position: None,
+            }),
+        })
+    }
+
+    /// Add a packet received from a speaker
+    pub fn receive(&mut self, speaker: ClientId, data: &[u8]) -> io::Result<()> {
+        let Some((sequence, frame)) = data.split_first_chunk::<4>() else {
+            return Ok(());
+        };
+        let sequence = u32::from_le_bytes(*sequence);
+        let speaker = self.speaker(speaker)?;
+        if speaker.muted {
+            return Ok(());
+        }
+        if let Some(next) = speaker.next {
+            if sequence.wrapping_sub(next) >= u32::MAX / 2 {
+                // Arrived too late to be played
+                return Ok(());
+            }
+        }
+        if speaker.buffered.len() >= MAX_BUFFERED {
+            speaker.buffered.clear();
+            speaker.next = None;
+        }
+        speaker.buffered.insert(sequence, frame.to_vec());
+        Ok(())
+    }
+
+    /// Stop playing a speaker, e.g. when they disconnect
+    pub fn remove(&mut self, speaker: ClientId) {
+        self.speakers.remove(&speaker);
+    }
+
+    /// Mute or unmute a speaker
+    pub fn set_muted(&mut self, speaker: ClientId, muted: bool) -> io::Result<()> {
+        let speaker = self.speaker(speaker)?;
+        speaker.muted = muted;
+        if muted {
+            speaker.buffered.clear();
+            speaker.decoded.clear();
+            speaker.next = None;
+        }
+        Ok(())
+    }
+
+    /// Whether a speaker is muted
+    pub fn is_muted(&self, speaker: ClientId) -> bool {
+        self.speakers.get(&speaker).is_some_and(|s| s.muted)
+    }
+
+    /// Set where a speaker is in the world, or `None` to play them at full
+    /// volume in both ears
+    pub fn set_position(&mut self, speaker: ClientId, position: Option<[f32; 2]>) -> io::Result<()> {
+        self.speaker(speaker)?.position = position;
+        Ok(())
+    }
+
+    /// Set where the local player is in the world, for positional speakers
+    pub fn set_listener(&mut self, position: [f32; 2]) {
+        self.listener = position;
+    }
+
+    /// The config the mixer was created with, which can be changed at any time
+    pub fn config_mut(&mut self) -> &mut VoiceConfig {
+        &mut self.config
+    }
+
+    /// Mix every speaker into `output`, as interleaved stereo samples
+    ///
+    /// This adds to what's already in `output`, so voice can be mixed in with
+    /// other sounds.
+    pub fn mix(&mut self, output: &mut [f32]) {
+        let frames = output.len() / 2;
+        for speaker in self.speakers.values_mut() {
+            if speaker.muted {
+                continue;
+            }
+            speaker.decode(frames, self.config.jitter_frames);
+            let (left, right) = match speaker.position {
+                Some([x, y]) => {
+                    let [dx, dy] = [x - self.listener[0], y - self.listener[1]];
+                    let distance = (dx * dx + dy * dy).sqrt();
+                    let gain = (1.0 - distance / self.config.max_distance).clamp(0.0, 1.0);
+                    // Equal power panning, from -1 (left) to 1 (right)
+                    let pan = (dx / self.config.max_distance).clamp(-1.0, 1.0);
+                    let angle = (pan + 1.0) * FRAC_PI_4;
+                    (gain * angle.cos(), gain * angle.sin())
+                }
+                None => (1.0, 1.0),
+            };
+            let (left, right) = (left * self.config.volume, right * self.config.volume);
+            let len = frames.min(speaker.decoded.len());
+            for (frame, sample) in output.chunks_exact_mut(2).zip(speaker.decoded.drain(..len)) {
+                frame[0] += sample * left;
+                frame[1] += sample * right;
+            }
+        }
+    }
+}
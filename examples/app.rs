@@ -0,0 +1,63 @@
+// This example makes a game by implementing care::App instead of using care::main!, switching
+// between two scenes with space.
+
+use care::prelude::*;
+
+trait Scene {
+    fn update(&mut self, delta: f32);
+    fn draw(&self);
+}
+
+struct Spinning(f32);
+
+impl Scene for Spinning {
+    fn update(&mut self, delta: f32) {
+        self.0 += delta;
+    }
+
+    fn draw(&self) {
+        care::graphics::rectangle_rot((400, 300), (100, 100), self.0);
+    }
+}
+
+struct Still;
+
+impl Scene for Still {
+    fn update(&mut self, _delta: f32) {}
+
+    fn draw(&self) {
+        care::graphics::rectangle((350, 250), (100, 100));
+    }
+}
+
+struct Game {
+    scenes: Vec<Box<dyn Scene>>,
+    current: usize,
+}
+
+impl care::App for Game {
+    fn init() -> Self {
+        Game {
+            scenes: vec![Box::new(Spinning(0.0)), Box::new(Still)],
+            current: 0,
+        }
+    }
+
+    fn update(&mut self, delta: f32) {
+        if care::keyboard::is_pressed(Key::Space) {
+            self.current = (self.current + 1) % self.scenes.len();
+        }
+        self.scenes[self.current].update(delta);
+    }
+
+    fn draw(&mut self) {
+        self.scenes[self.current].draw();
+    }
+}
+
+fn main() {
+    care::run::<Game>(Conf {
+        title: Some("App example".to_string()),
+        ..Default::default()
+    });
+}
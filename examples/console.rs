@@ -0,0 +1,29 @@
+// This example shows off the console, press ` to open it, then try typing "help", "colour 1 0 0"
+// or "say hello".
+
+use std::sync::Mutex;
+
+// Commands can't borrow state, so anything they change has to be shared with the game
+static COLOUR: Mutex<(f32, f32, f32)> = Mutex::new((1.0, 1.0, 1.0));
+
+care::main! {
+    #[care::init]
+    fn init(_args: Vec<String>) {
+        care::console::command("say", |args| care::console::print(args.join(" ")));
+        care::console::command("colour", |args| {
+            let parts: Result<Vec<f32>, _> = args.iter().map(|arg| arg.parse()).collect();
+            match parts.as_deref() {
+                Ok(&[r, g, b]) => *COLOUR.lock().unwrap() = (r, g, b),
+                _ => care::console::print_error("Usage: colour <red> <green> <blue>"),
+            }
+        });
+        care::console::print("Welcome! Type help to see every command.");
+    }
+
+    #[care::draw]
+    fn draw() {
+        let (r, g, b) = *COLOUR.lock().unwrap();
+        care::graphics::set_colour((r, g, b, 1));
+        care::graphics::rectangle((100, 200), (200, 200));
+    }
+}
@@ -1,37 +1,49 @@
 use care::prelude::*;
 
-#[care::async_main]
-async fn main() {
-    let mut pos = Vec2::new(100, 100);
-    loop {
-        // do the update and draw stuff
-        care::graphics::rectangle(pos, (100, 100));
+care::main! {
+    #[care::async_main]
+    async fn main() {
+        // Use the same font as care::graphics::text, and a light theme
+        care::gui::add_font(
+            "default",
+            &care::graphics::default_font(),
+            &[care::gui::FontFamily::Proportional],
+        );
+        care::gui::set_theme(care::gui::Visuals::light());
 
-        if care::keyboard::is_down('d') {
-            pos.x += 1.0;
-        }
-        if care::keyboard::is_down('a') {
-            pos.x -= 1.0;
-        }
-        if care::keyboard::is_down('s') {
-            pos.y += 1.0;
-        }
-        if care::keyboard::is_down('w') {
-            pos.y -= 1.0;
-        }
+        // Care textures can be shown in egui too
+        let texture = Texture::new_fill(32, 32, (0.3, 0.5, 0.9, 1));
 
-        care::gui::gui(|ctx| {
-            care::gui::Window::new("Test").show(ctx, |ui| {
-                if ui.button("Reset").clicked() {
-                    pos.x = 100.0;
-                    pos.y = 100.0;
-                    println!("Foo!");
-                }
+        let mut pos = Vec2::new(100, 100);
+        loop {
+            // do the update and draw stuff
+            care::graphics::rectangle(pos, (100, 100));
+
+            if care::keyboard::is_down('d') {
+                pos.x += 1.0;
+            }
+            if care::keyboard::is_down('a') {
+                pos.x -= 1.0;
+            }
+            if care::keyboard::is_down('s') {
+                pos.y += 1.0;
+            }
+            if care::keyboard::is_down('w') {
+                pos.y -= 1.0;
+            }
+
+            care::gui::gui(|ctx| {
+                care::gui::Window::new("Test").show(ctx, |ui| {
+                    ui.image((care::gui::texture_id(&texture), care::gui::vec2(32.0, 32.0)));
+                    if ui.button("Reset").clicked() {
+                        pos.x = 100.0;
+                        pos.y = 100.0;
+                        println!("Foo!");
+                    }
+                });
             });
-        });
 
-        care::event::next_frame().await;
+            care::event::next_frame().await;
+        }
     }
 }
-
-care::main!();
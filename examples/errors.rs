@@ -0,0 +1,19 @@
+// This example shows that the game's functions can return errors, pressing space reads a file
+// that doesn't exist, and the error is shown instead of the game.
+
+use care::keyboard::{self, Key};
+
+care::main! {
+    #[care::update]
+    fn update(_delta: f32) -> std::io::Result<()> {
+        if keyboard::is_pressed(Key::Space) {
+            std::fs::read("this file doesn't exist")?;
+        }
+        Ok(())
+    }
+
+    #[care::draw]
+    fn draw() {
+        care::graphics::text("Press space to read a file that doesn't exist", (20, 20));
+    }
+}
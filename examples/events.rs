@@ -0,0 +1,42 @@
+// This example shows events as they happen, try typing, scrolling, dropping a file on the window,
+// resizing it or switching to another window.
+
+use care::event::{Event, EventData};
+use care::math::Vec2;
+
+care::main! {
+    #[care::state]
+    static lines: Vec<String> = Vec::new();
+
+    #[care::event]
+    fn on_event(ev: &Event) {
+        let line = match &ev.data {
+            EventData::TextEvent { text } => format!("Typed {text:?}"),
+            EventData::MouseScroll { delta } => format!("Scrolled {}, {}", delta.x, delta.y),
+            EventData::FileDropped { path } => format!("Dropped {}", path.display()),
+            EventData::MouseClick { button, pressed: true } => format!("Clicked {button}"),
+            _ => return,
+        };
+        lines.push(line);
+        if lines.len() > 20 {
+            lines.remove(0);
+        }
+    }
+
+    #[care::resize]
+    fn resize(size: Vec2) {
+        lines.push(format!("Resized to {}x{}", size.x, size.y));
+    }
+
+    #[care::focus]
+    fn focus(focused: bool) {
+        lines.push(if focused { "Focused" } else { "Unfocused" }.to_string());
+    }
+
+    #[care::draw]
+    fn draw() {
+        for (i, line) in lines.iter().enumerate() {
+            care::graphics::text(line, (10.0, 10.0 + i as f32 * 20.0));
+        }
+    }
+}
@@ -0,0 +1,35 @@
+// This example moves one box in the update function and one in the fixed update function, which
+// runs at the same rate (30 times per second here) no matter how fast the game is drawn.
+
+use care::math::Vec2;
+
+care::main! {
+    #[care::conf]
+    fn conf() -> care::Conf {
+        care::Conf {
+            fixed_update_rate: 30.0,
+            ..Default::default()
+        }
+    }
+
+    #[care::state]
+    static smooth: Vec2 = Vec2::new(0, 100);
+    #[care::state]
+    static fixed: Vec2 = Vec2::new(0, 250);
+
+    #[care::update]
+    fn update(delta: f32) {
+        smooth.x = (smooth.x + delta * 200.0) % 800.0;
+    }
+
+    #[care::fixed_update]
+    fn fixed_update(delta: f32) {
+        fixed.x = (fixed.x + delta * 200.0) % 800.0;
+    }
+
+    #[care::draw]
+    fn draw() {
+        care::graphics::rectangle(smooth, (50, 50));
+        care::graphics::rectangle(fixed, (50, 50));
+    }
+}
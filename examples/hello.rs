@@ -1,11 +1,11 @@
 // You might already notice the similarities with love 2d
 
-#[care::draw]
-fn draw() {
-    care::graphics::text("Hello, World!", (20, 20));
-}
-
 // This creates a default main function that calls the draw, update and init functions if present,
-// as well as processing events and displaying the framebuffer every frame. You can pass a
-// care::Conf, or a function returning care::Conf, to this function to configure care.
-care::main!();
+// as well as processing events and displaying the framebuffer every frame. A function marked
+// with #[care::conf] returning a care::Conf can also go inside it to configure care.
+care::main! {
+    #[care::draw]
+    fn draw() {
+        care::graphics::text("Hello, World!", (20, 20));
+    }
+}
@@ -0,0 +1,60 @@
+// This example shows off the inspector, which lets you see and tweak state variables while the
+// game is running. Press i to show or hide it.
+
+use care::{
+    gui::Inspect,
+    keyboard::Key,
+    math::{Vec2, Vec4},
+};
+
+#[derive(Inspect)]
+struct Ball {
+    pos: Vec2,
+    vel: Vec2,
+    radius: f32,
+    colour: Vec4,
+    #[inspect(skip)]
+    bounces: u32,
+}
+
+care::main! {
+    #[care::state]
+    static ball: Ball = Ball {
+        pos: Vec2::new(100, 100),
+        vel: Vec2::new(150, 120),
+        radius: 20.0,
+        colour: Vec4::new(0.9, 0.4, 0.3, 1.0),
+        bounces: 0,
+    };
+
+    #[care::state]
+    static paused: bool = false;
+
+    #[care::update]
+    fn update(delta: f32) {
+        if care::keyboard::is_pressed(Key::Char('i')) {
+            care::gui::inspector(!care::gui::is_inspector_shown());
+        }
+        if paused {
+            return;
+        }
+        ball.pos = ball.pos + ball.vel * delta;
+        let size = care::window::window_size();
+        if ball.pos.x < ball.radius || ball.pos.x > size.x - ball.radius {
+            ball.vel.x = -ball.vel.x;
+            ball.bounces += 1;
+        }
+        if ball.pos.y < ball.radius || ball.pos.y > size.y - ball.radius {
+            ball.vel.y = -ball.vel.y;
+            ball.bounces += 1;
+        }
+    }
+
+    #[care::draw]
+    fn draw() {
+        care::graphics::set_colour(ball.colour);
+        care::graphics::circle(ball.pos, ball.radius);
+        care::graphics::set_colour((1, 1, 1, 1));
+        care::graphics::text(format!("Bounces: {}", ball.bounces), (10, 10));
+    }
+}
@@ -0,0 +1,44 @@
+// This example draws a circle under every finger on the screen, and shows what's typed on the
+// on-screen keyboard, which is opened by tapping the top of the safe area.
+// See care::mobile for how to build it for Android and iOS.
+
+use care::{event::EventData, touch};
+
+care::main! {
+    #[care::state]
+    static typed: String = String::new();
+    #[care::state]
+    static keyboard_shown: bool = false;
+
+    #[care::event]
+    fn event(event: &care::event::Event) {
+        match &event.data {
+            EventData::TextEvent { text } => typed.push_str(text),
+            EventData::Suspend { suspended } => println!("Suspended: {suspended}"),
+            _ => {}
+        }
+    }
+
+    #[care::update]
+    fn update() {
+        let (pos, size) = care::mobile::safe_area();
+        for touch in touch::just_started() {
+            if touch.position.y < pos.y + 50.0 && touch.position.x < pos.x + size.x {
+                keyboard_shown = !keyboard_shown;
+                care::mobile::show_keyboard(keyboard_shown);
+            }
+        }
+    }
+
+    #[care::draw]
+    fn draw() {
+        let (pos, size) = care::mobile::safe_area();
+        care::graphics::set_colour((0.2, 0.2, 0.3, 1.0));
+        care::graphics::rectangle(pos, (size.x, 50.0));
+        care::graphics::set_colour((1.0, 1.0, 1.0, 1.0));
+        care::graphics::text(format!("Typed: {typed}"), (pos.x + 10.0, pos.y + 10.0));
+        for touch in touch::touches() {
+            care::graphics::circle(touch.position, 40);
+        }
+    }
+}
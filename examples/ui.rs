@@ -0,0 +1,29 @@
+use care::graphics::ui;
+
+care::main! {
+    #[care::async_main]
+    async fn main() {
+        let mut size = 50.0;
+        let mut round = false;
+        let mut name = String::new();
+        loop {
+            ui::label("Built in widgets", (20, 20));
+            ui::slider("Size", (20, 50), 200, &mut size, 10.0..=150.0);
+            ui::checkbox("Rounded", (20, 90), &mut round);
+            ui::text_box("Your name", (20, 120), 200, &mut name);
+            if ui::button("Reset", (20, 160)) {
+                size = 50.0;
+                round = false;
+                name.clear();
+            }
+
+            let radius = if round { size / 4.0 } else { 0.0 };
+            care::graphics::rectangle_rounded((300, 50), (size, size), 0, [radius; 4]);
+            if !name.is_empty() {
+                care::graphics::text(format!("Hello, {name}!"), (300, 60.0 + size));
+            }
+
+            care::event::next_frame().await;
+        }
+    }
+}
@@ -0,0 +1,219 @@
+use std::time::Instant;
+
+use crate::{
+    config::{self, Conf},
+    event::{self, Event, FixedTimestep},
+    math::Fl,
+};
+
+/// The window's name if the config has no title
+#[cfg(feature = "window")]
+const DEFAULT_NAME: &str = "CÄRE game";
+
+/// A game, run with [run]
+///
+/// This does the same thing as [crate::main], without macros. Every function but [App::init] can
+/// be left out, and the trait can be used as `dyn App` (e.g. for a stack of scenes).
+///
+/// ```ignore
+/// struct Game {
+///     time: f32,
+/// }
+///
+/// impl care::App for Game {
+///     fn init() -> Self {
+///         Game { time: 0.0 }
+///     }
+///
+///     fn update(&mut self, delta: f32) {
+///         self.time += delta;
+///     }
+/// }
+///
+/// fn main() {
+///     care::run::<Game>(care::Conf::default());
+/// }
+/// ```
+pub trait App {
+    /// Make the game, after the window is opened and the engine is initialized
+    fn init() -> Self
+    where
+        Self: Sized;
+
+    /// Called once per frame, with the time since the last frame in seconds
+    fn update(&mut self, _delta: Fl) {}
+
+    /// Called [Conf::fixed_update_rate] times per second no matter the frame rate, with the time
+    /// between calls in seconds
+    fn fixed_update(&mut self, _step: Fl) {}
+
+    /// Called once per frame after [App::update], to draw the game
+    fn draw(&mut self) {}
+
+    /// Called with every event, before [App::fixed_update] and [App::update]
+    fn event(&mut self, _event: &Event) {}
+
+    /// Called once when the game is about to exit, e.g. to save it
+    fn quit(&mut self) {}
+}
+
+/// Run a game with some config, opening a window for it
+///
+/// # NOTE
+/// Can only be called from the main thread, and only once.
+pub fn run<A: App + 'static>(conf: Conf) {
+    Game::new(conf)
+        .init(A::init)
+        .update(A::update)
+        .fixed_update(A::fixed_update)
+        .event(A::event)
+        .draw(A::draw)
+        .quit(A::quit)
+        .run();
+}
+
+/// A game made from closures, for when neither [crate::main] nor [App] are wanted, e.g. for
+/// quick prototypes
+///
+/// The state returned by the init closure is given to the rest of them.
+///
+/// ```no_run
+/// care::Game::new(care::Conf::default())
+///     .init(|| 0)
+///     .update(|frames, _delta| *frames += 1)
+///     .draw(|frames| care::graphics::text(format!("Frame {frames}"), (10, 10)))
+///     .run();
+/// ```
+pub struct Game<S> {
+    conf: Conf,
+    init: Box<dyn FnOnce() -> S>,
+    update: UpdateFn<S>,
+    fixed_update: UpdateFn<S>,
+    event: EventFn<S>,
+    draw: Box<dyn FnMut(&mut S)>,
+    quit: Box<dyn FnOnce(&mut S)>,
+}
+
+/// A [Game]'s update or fixed update closure, given the state and the time step
+type UpdateFn<S> = Box<dyn FnMut(&mut S, Fl)>;
+/// A [Game]'s event closure
+type EventFn<S> = Box<dyn FnMut(&mut S, &Event)>;
+
+impl Game<()> {
+    /// Make a game with some config, which does nothing until its closures are set
+    pub fn new(conf: Conf) -> Self {
+        Self {
+            conf,
+            init: Box::new(|| ()),
+            update: Box::new(|_, _| {}),
+            fixed_update: Box::new(|_, _| {}),
+            event: Box::new(|_, _| {}),
+            draw: Box::new(|_| {}),
+            quit: Box::new(|_| {}),
+        }
+    }
+
+    /// Set the closure that makes the game's state, after the window is opened and the engine is
+    /// initialized
+    ///
+    /// Closures set before this aren't given the state.
+    pub fn init<S: 'static>(self, init: impl FnOnce() -> S + 'static) -> Game<S> {
+        let Self {
+            conf,
+            init: _,
+            mut update,
+            mut fixed_update,
+            event: mut on_event,
+            mut draw,
+            quit,
+        } = self;
+        Game {
+            conf,
+            init: Box::new(init),
+            update: Box::new(move |_, delta| update(&mut (), delta)),
+            fixed_update: Box::new(move |_, step| fixed_update(&mut (), step)),
+            event: Box::new(move |_, event| on_event(&mut (), event)),
+            draw: Box::new(move |_| draw(&mut ())),
+            quit: Box::new(move |_| quit(&mut ())),
+        }
+    }
+}
+
+impl<S: 'static> Game<S> {
+    /// Set the closure called once per frame, with the time since the last frame in seconds
+    pub fn update(mut self, update: impl FnMut(&mut S, Fl) + 'static) -> Self {
+        self.update = Box::new(update);
+        self
+    }
+
+    /// Set the closure called [Conf::fixed_update_rate] times per second no matter the frame
+    /// rate, with the time between calls in seconds
+    pub fn fixed_update(mut self, fixed_update: impl FnMut(&mut S, Fl) + 'static) -> Self {
+        self.fixed_update = Box::new(fixed_update);
+        self
+    }
+
+    /// Set the closure called with every event, before the fixed update and update closures
+    pub fn event(mut self, event: impl FnMut(&mut S, &Event) + 'static) -> Self {
+        self.event = Box::new(event);
+        self
+    }
+
+    /// Set the closure called once per frame after the update closure, to draw the game
+    pub fn draw(mut self, draw: impl FnMut(&mut S) + 'static) -> Self {
+        self.draw = Box::new(draw);
+        self
+    }
+
+    /// Set the closure called once when the game is about to exit, e.g. to save it
+    pub fn quit(mut self, quit: impl FnOnce(&mut S) + 'static) -> Self {
+        self.quit = Box::new(quit);
+        self
+    }
+
+    /// Run the game, opening a window for it
+    ///
+    /// # NOTE
+    /// Can only be called from the main thread, and only once.
+    pub fn run(self) {
+        let Self {
+            conf,
+            init,
+            mut update,
+            mut fixed_update,
+            event: mut on_event,
+            mut draw,
+            quit,
+        } = self;
+        config::set(conf);
+        #[cfg(feature = "window")]
+        crate::window::open_with_settings(crate::window::WindowSettings::from_conf(
+            config::get(),
+            DEFAULT_NAME,
+        ));
+        event::main_loop_with_quit(
+            move || {
+                let fixed_timestep = FixedTimestep::new(config::get().fixed_update_rate);
+                (Instant::now(), fixed_timestep, init())
+            },
+            move |(last_time, fixed_timestep, state)| {
+                let next_time = Instant::now();
+                let delta_time = next_time.duration_since(*last_time).as_secs_f64() as Fl;
+                *last_time = next_time;
+                // The game stops once an error is reported, see crate::error
+                if crate::error::__error_screen() {
+                    return;
+                }
+                for event in event::events() {
+                    on_event(state, &event);
+                }
+                for _ in 0..fixed_timestep.advance(delta_time) {
+                    fixed_update(state, fixed_timestep.step());
+                }
+                update(state, delta_time);
+                draw(state);
+            },
+            move |(_, _, state)| quit(state),
+        );
+    }
+}
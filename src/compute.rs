@@ -1,7 +1,25 @@
-use std::sync::OnceLock;
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, OnceLock,
+    },
+    task::{Context, Poll, Waker},
+};
+
+use parking_lot::Mutex;
 
 pub use wrgpgpu::*;
 
+mod constants;
+mod pipeline;
+mod profiling;
+
+pub use constants::Constants;
+pub use pipeline::{PassDependencies, Pipeline};
+pub use profiling::{stats, ComputeStats};
+
 /// Additional available bindings for the compute shader
 pub mod bind {
     pub use wrgpgpu::bindings::buffer::*;
@@ -20,7 +38,35 @@ fn new_compute_device() -> wrgpgpu::Device {
         wrgpgpu::Device::from_wgpu(crate::graphics::GRAPHICS_STATE.device.clone(), crate::graphics::GRAPHICS_STATE.queue.clone())
     }
     #[cfg(not(feature = "graphics"))]
-    wrgpgpu::Device::auto_high_performance()
+    {
+        use pollster::FutureExt;
+
+        let instance = crate::gpu::instance();
+        let adapter = crate::gpu::adapter(&instance, None);
+        let (device, queue) = adapter
+            .request_device(
+                &wgpu::DeviceDescriptor {
+                    label: Some("Care compute device"),
+                    required_features: wgpu::Features::empty(),
+                    required_limits: adapter.limits(),
+                    memory_hints: wgpu::MemoryHints::default(),
+                },
+                None,
+            )
+            .block_on()
+            .expect("No compute device found in adapter");
+        wrgpgpu::Device::from_wgpu(std::sync::Arc::new(device), std::sync::Arc::new(queue))
+    }
+}
+
+/// Get the wgpu buffer behind a buffer binding, which wrgpgpu only exposes through [Bind::bind]
+fn wgpu_buffer<T: bytemuck::Pod, Y: bind::BufferBindType>(
+    bind: &bind::BufferBind<T, Y>,
+) -> &wgpu::Buffer {
+    match bind.bind() {
+        wgpu::BindingResource::Buffer(binding) => binding.buffer,
+        _ => unreachable!("Buffer bindings are always bound as buffers"),
+    }
 }
 
 /// Create a compute shader
@@ -66,7 +112,80 @@ pub fn dispatch<B: wrgpgpu::bindings::BindGroups>(
 ) {
     let device = COMPUTE_DEVICE.get_or_init(new_compute_device);
 
-    device.dispatch(shader, bindings, workgroups);
+    dispatch_profiled(|| device.dispatch(shader, bindings, workgroups));
+}
+
+/// Dispatch a compute shader pass, reading the number of workgroups from a buffer on the GPU
+///
+/// The buffer holds the x, y and z workgroup counts, usually written by an earlier pass (e.g. the
+/// number of surviving particles). Create one with `init_bind([1, 1, 1])` and bind it in the
+/// earlier pass to write it. The counts never leave the GPU, so this doesn't wait for the earlier
+/// passes to finish.
+pub fn dispatch_indirect<B: wrgpgpu::bindings::BindGroups>(
+    shader: &ComputeShader<B>,
+    bindings: &B,
+    indirect_buffer: &StorageBufferBind<[u32; 3]>,
+) {
+    let device = COMPUTE_DEVICE.get_or_init(new_compute_device);
+
+    // wrgpgpu buffers can't be used for indirect dispatches, so the counts are copied into one
+    // that can
+    let indirect = indirect_args_buffer(&device.device, 1);
+    let mut encoder = device
+        .device
+        .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Care compute indirect dispatch encoder"),
+        });
+    encoder.copy_buffer_to_buffer(
+        wgpu_buffer(indirect_buffer),
+        0,
+        &indirect,
+        0,
+        INDIRECT_ARGS_SIZE,
+    );
+    {
+        let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Care compute indirect dispatch pass"),
+            timestamp_writes: None,
+        });
+        set_shader(&mut pass, shader, bindings);
+        pass.dispatch_workgroups_indirect(&indirect, 0);
+    }
+    dispatch_profiled(|| {
+        device.queue.submit([encoder.finish()]);
+    });
+}
+
+/// The size of the x, y and z workgroup counts of an indirect dispatch
+const INDIRECT_ARGS_SIZE: u64 = std::mem::size_of::<[u32; 3]>() as u64;
+
+/// Create a buffer holding the workgroup counts of `count` indirect dispatches
+fn indirect_args_buffer(device: &wgpu::Device, count: usize) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Care compute indirect buffer"),
+        size: INDIRECT_ARGS_SIZE * count as u64,
+        usage: wgpu::BufferUsages::INDIRECT | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+/// Set the pipeline and bind groups of a shader on a compute pass
+fn set_shader<B: wrgpgpu::bindings::BindGroups>(
+    pass: &mut wgpu::ComputePass,
+    shader: &ComputeShader<B>,
+    bindings: &B,
+) {
+    pass.set_pipeline(&shader.pipeline);
+    for n in 0..B::LEN {
+        pass.set_bind_group(n as u32, bindings.get_bind_group_n(n), &[]);
+    }
+}
+
+/// Run a single dispatch, measuring it for [stats]
+fn dispatch_profiled(dispatch: impl FnOnce()) {
+    let profile = profiling::Dispatch::begin();
+    dispatch();
+    profile.end(1);
 }
 
 /// Check weather all compute passes are complete
@@ -76,6 +195,189 @@ pub fn is_complete() -> bool {
     device.is_complete()
 }
 
+/// Poll the GPU, which wakes the tasks waiting in [wait_complete] whose compute passes are done
+///
+/// This is called at the end of every frame, so it only has to be called when there is no frame
+/// loop, e.g. in a tool that only uses compute.
+pub fn poll() {
+    let device = COMPUTE_DEVICE.get_or_init(new_compute_device);
+
+    device.device.poll(wgpu::Maintain::Poll);
+}
+
+/// Wait until all compute passes that have been dispatched so far are complete, without blocking
+///
+/// The GPU calls back when it's done, which wakes the task, so this works with any executor. The
+/// callback runs when the GPU is polled, which happens every frame, see [poll].
+pub fn wait_complete() -> impl Future<Output = ()> + Send + 'static {
+    let device = COMPUTE_DEVICE.get_or_init(new_compute_device);
+
+    let completion = Completion::new();
+    let state = completion.state.clone();
+    device.queue.on_submitted_work_done(move || state.complete());
+    completion
+}
+
+/// Download a buffer binding once all compute passes are complete, without blocking
+///
+/// The buffer is copied to a staging buffer, which wakes the task once it's been mapped, so like
+/// [wait_complete] this works with any executor, and relies on the GPU being polled, see [poll].
+pub async fn download_async<T: bytemuck::Pod, Y: bind::BufferBindType>(
+    bind: &bind::BufferBind<T, Y>,
+) -> T {
+    bytemuck::pod_read_unaligned(&read_bytes(wgpu_buffer(bind)).await)
+}
+
+/// Download the elements of an array buffer binding once all compute passes are complete, see
+/// [download_async]
+pub async fn read_buffer_async<T: bytemuck::Pod, const N: usize, Y: bind::BufferBindType>(
+    bind: &bind::BufferBind<[T; N], Y>,
+) -> Vec<T> {
+    bytemuck::pod_collect_to_vec(&read_bytes(wgpu_buffer(bind)).await)
+}
+
+/// Copy a buffer to a staging buffer and read it back once it's been mapped
+async fn read_bytes(buffer: &wgpu::Buffer) -> Vec<u8> {
+    let device = COMPUTE_DEVICE.get_or_init(new_compute_device);
+
+    let staging = device.device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Care compute readback buffer"),
+        size: buffer.size(),
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+    let mut encoder = device
+        .device
+        .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Care compute readback encoder"),
+        });
+    encoder.copy_buffer_to_buffer(buffer, 0, &staging, 0, buffer.size());
+    device.queue.submit([encoder.finish()]);
+
+    map_read(&staging).await;
+    let data = staging.slice(..).get_mapped_range().to_vec();
+    data
+}
+
+/// Map a staging buffer for reading, waking the task once it's mapped
+async fn map_read(buffer: &wgpu::Buffer) {
+    let completion = Completion::new();
+    let state = completion.state.clone();
+    let mapped = Arc::new(AtomicBool::new(false));
+    let callback_mapped = mapped.clone();
+    buffer
+        .slice(..)
+        .map_async(wgpu::MapMode::Read, move |result| {
+            callback_mapped.store(result.is_ok(), Ordering::Release);
+            state.complete();
+        });
+    completion.await;
+    assert!(
+        mapped.load(Ordering::Acquire),
+        "Failed to read back the compute results"
+    );
+}
+
+#[cfg(feature = "graphics")]
+/// Download a texture binding once all compute passes are complete, without blocking
+///
+/// The texture is copied to a staging buffer, which wakes the task once it's been mapped, so like
+/// [wait_complete] this works with any executor, and relies on the GPU being polled, see [poll].
+pub async fn download_texture_async<T: wrgpgpu::bindings::texture::TextureBindType>(
+    binding: &wrgpgpu::TextureBind<image::RgbaImage, T>,
+) -> image::RgbaImage {
+    let texture = &binding.texture;
+    let size = texture.size();
+    // Rows of the copy have to be aligned, the padding is cut off again below
+    let row_bytes = size.width * 4;
+    let padded_row_bytes = row_bytes.next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+    let device = COMPUTE_DEVICE.get_or_init(new_compute_device);
+
+    let buffer = device.device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Care compute readback buffer"),
+        size: (padded_row_bytes * size.height) as u64,
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+    let mut encoder = device
+        .device
+        .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Care compute readback encoder"),
+        });
+    encoder.copy_texture_to_buffer(
+        texture.as_image_copy(),
+        wgpu::TexelCopyBufferInfo {
+            buffer: &buffer,
+            layout: wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(padded_row_bytes),
+                rows_per_image: Some(size.height),
+            },
+        },
+        size,
+    );
+    device.queue.submit([encoder.finish()]);
+
+    map_read(&buffer).await;
+    let data = buffer.slice(..).get_mapped_range();
+    let pixels = data
+        .chunks(padded_row_bytes as usize)
+        .flat_map(|row| &row[..row_bytes as usize])
+        .copied()
+        .collect();
+    image::RgbaImage::from_raw(size.width, size.height, pixels).expect("Read back the wrong size")
+}
+
+#[derive(Debug)]
+struct CompletionState {
+    done: AtomicBool,
+    waker: Mutex<Option<Waker>>,
+}
+
+impl CompletionState {
+    /// Mark the work as done, waking the task waiting for it
+    fn complete(&self) {
+        self.done.store(true, Ordering::Release);
+        if let Some(waker) = self.waker.lock().take() {
+            waker.wake();
+        }
+    }
+}
+
+#[derive(Debug)]
+/// A future that finishes when a GPU callback calls [CompletionState::complete]
+struct Completion {
+    state: Arc<CompletionState>,
+}
+
+impl Completion {
+    fn new() -> Self {
+        Self {
+            state: Arc::new(CompletionState {
+                done: AtomicBool::new(false),
+                waker: Mutex::new(None),
+            }),
+        }
+    }
+}
+
+impl Future for Completion {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.state.done.load(Ordering::Acquire) {
+            return Poll::Ready(());
+        }
+        *self.state.waker.lock() = Some(cx.waker().clone());
+        // The callback may have run before the waker was stored
+        if self.state.done.load(Ordering::Acquire) {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
 #[cfg(feature = "graphics")]
 /// Create a care texture, to be used in the [`crate::graphics`] module, from a compute texture binding
 ///
@@ -87,3 +389,63 @@ pub fn get_texture_from_binding<T: wrgpgpu::bindings::texture::TextureBindType>(
 ) -> crate::graphics::Texture {
     crate::graphics::Texture::new_from_wgpu(binding.texture)
 }
+
+#[cfg(feature = "graphics")]
+/// Create a compute texture binding from a care texture, the reverse of [get_texture_from_binding]
+///
+/// The binding is a copy of the texture, made on the gpu, so a compute shader can read what the
+/// texture contains (e.g. for post-processing or image analysis). Unlike [get_texture_from_binding]
+/// the storage isn't shared, so to see what the shader writes, turn the binding back into a
+/// texture with [get_texture_from_binding].
+pub fn get_binding_from_texture<T: wrgpgpu::bindings::texture::TextureBindType>(
+    texture: &crate::graphics::Texture,
+) -> wrgpgpu::TextureBind<image::RgbaImage, T>
+where
+    wrgpgpu::TextureBind<image::RgbaImage, T>: wrgpgpu::bindings::Bind<Data = image::RgbaImage>,
+{
+    use crate::graphics::GRAPHICS_STATE;
+
+    let source = &texture.0.texture;
+    let size = source.size();
+    let binding: wrgpgpu::TextureBind<image::RgbaImage, T> =
+        init_bind(image::RgbaImage::new(size.width, size.height));
+
+    // Care textures are Rgba8Unorm and compute textures Rgba8Uint, which can't be copied into each
+    // other directly, but have the same bytes, so copy through a buffer
+    let padded_row_bytes = (size.width * 4).next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+    let buffer = GRAPHICS_STATE.device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Care texture to compute binding buffer"),
+        size: (padded_row_bytes * size.height) as u64,
+        usage: wgpu::BufferUsages::COPY_SRC | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    let layout = wgpu::TexelCopyBufferLayout {
+        offset: 0,
+        bytes_per_row: Some(padded_row_bytes),
+        rows_per_image: None,
+    };
+
+    let mut encoder = GRAPHICS_STATE
+        .device
+        .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Care texture to compute binding copy"),
+        });
+    encoder.copy_texture_to_buffer(
+        source.as_image_copy(),
+        wgpu::TexelCopyBufferInfo {
+            buffer: &buffer,
+            layout,
+        },
+        size,
+    );
+    encoder.copy_buffer_to_texture(
+        wgpu::TexelCopyBufferInfo {
+            buffer: &buffer,
+            layout,
+        },
+        binding.texture.as_image_copy(),
+        size,
+    );
+    GRAPHICS_STATE.queue.submit([encoder.finish()]);
+    binding
+}
@@ -0,0 +1,58 @@
+use bytemuck::Pod;
+
+use super::{UniformBufferBind, COMPUTE_DEVICE};
+
+/// Small parameters for a compute shader (e.g. the time, frame index or mouse position), in a
+/// uniform buffer
+///
+/// Bind [Constants::bind] like any other uniform buffer binding, in a bind group of its own. The
+/// buffer is made once, and [Constants::set] writes the new value into it on the GPU queue, so the
+/// bind group stays valid, and the dispatches submitted after it see the new value. Shaders can't
+/// use push constants instead, since wrgpgpu creates them without any push constant ranges.
+///
+/// ```ignore
+/// let mut frame_index = compute::Constants::new(0u32);
+/// let bindings = (compute::bind(frame_index.bind()), compute::bind(&image));
+/// loop {
+///     frame += 1;
+///     frame_index.set(frame);
+///     compute::dispatch(&shader, &bindings, (w / 8, h / 8, 1));
+/// }
+/// ```
+pub struct Constants<P: Pod> {
+    value: P,
+    bind: UniformBufferBind<P>,
+}
+
+impl<P: Pod> Constants<P> {
+    /// Create the uniform buffer, with an initial value
+    pub fn new(value: P) -> Self {
+        Self {
+            value,
+            bind: super::init_bind(value),
+        }
+    }
+
+    /// Change the value, for the dispatches submitted after this
+    pub fn set(&mut self, value: P) {
+        if bytemuck::bytes_of(&value) == bytemuck::bytes_of(&self.value) {
+            return;
+        }
+        let device = COMPUTE_DEVICE.get_or_init(super::new_compute_device);
+
+        self.value = value;
+        device
+            .queue
+            .write_buffer(super::wgpu_buffer(&self.bind), 0, bytemuck::bytes_of(&value));
+    }
+
+    /// The current value
+    pub fn get(&self) -> P {
+        self.value
+    }
+
+    /// The uniform buffer binding to put in a bind group
+    pub fn bind(&self) -> &UniformBufferBind<P> {
+        &self.bind
+    }
+}
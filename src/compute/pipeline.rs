@@ -0,0 +1,220 @@
+use std::fmt::Debug;
+
+use wrgpgpu::{
+    bindings::{Bind, BindGroups},
+    ComputeShader,
+};
+
+use super::{profiling, StorageBufferBind, COMPUTE_DEVICE, INDIRECT_ARGS_SIZE};
+
+type SetShader<'a> = Box<dyn Fn(&mut wgpu::ComputePass) + 'a>;
+
+/// How many workgroups a pass runs
+enum Workgroups<'a> {
+    Direct(u32, u32, u32),
+    /// Read from this buffer on the GPU
+    Indirect(&'a wgpu::Buffer),
+}
+
+struct Pass<'a> {
+    set_shader: SetShader<'a>,
+    workgroups: Workgroups<'a>,
+    /// The bindings the pass reads and writes, by address
+    reads: Vec<usize>,
+    writes: Vec<usize>,
+}
+
+fn binding_id<R>(binding: &R) -> usize {
+    binding as *const R as usize
+}
+
+#[derive(Default)]
+/// Several compute passes that are recorded together, and submitted to the GPU at once
+///
+/// The GPU runs passes in the order they were added (e.g. a blur chain, or the steps of a fluid
+/// simulation), without waiting on the CPU in between. Each pass declares the bindings it reads
+/// and writes, and a pass that uses something an earlier pass writes (or writes something an
+/// earlier pass reads) starts a new compute pass on the GPU, so it always sees the finished
+/// writes. The pipeline is counted as a single submission in [super::stats].
+///
+/// ```ignore
+/// let mut pipeline = compute::Pipeline::new();
+/// pipeline
+///     .pass(&blur_x, &blur_x_binds, (w / 8, h / 8, 1))
+///     .reads(&image)
+///     .writes(&half_blurred);
+/// pipeline
+///     .pass(&blur_y, &blur_y_binds, (w / 8, h / 8, 1))
+///     .reads(&half_blurred)
+///     .writes(&blurred);
+/// pipeline.submit();
+/// ```
+pub struct Pipeline<'a> {
+    passes: Vec<Pass<'a>>,
+}
+
+impl Debug for Pipeline<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Pipeline")
+            .field("passes", &self.passes.len())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Declares which bindings a pass of a [Pipeline] reads and writes
+pub struct PassDependencies<'p, 'a> {
+    pass: &'p mut Pass<'a>,
+}
+
+impl Debug for PassDependencies<'_, '_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PassDependencies")
+            .field("reads", &self.pass.reads.len())
+            .field("writes", &self.pass.writes.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<'a> PassDependencies<'_, 'a> {
+    /// The pass reads this binding, so it has to run after the passes that write it
+    pub fn reads<R: Bind>(self, binding: &'a R) -> Self {
+        self.pass.reads.push(binding_id(binding));
+        self
+    }
+
+    /// The pass writes this binding, so the passes after it that use it have to wait for it
+    pub fn writes<R: Bind>(self, binding: &'a R) -> Self {
+        self.pass.writes.push(binding_id(binding));
+        self
+    }
+}
+
+impl<'a> Pipeline<'a> {
+    /// Create an empty pipeline
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a compute shader pass, like [super::dispatch]
+    pub fn pass<B: BindGroups>(
+        &mut self,
+        shader: &'a ComputeShader<B>,
+        bindings: &'a B,
+        workgroups: (u32, u32, u32),
+    ) -> PassDependencies<'_, 'a> {
+        let (x, y, z) = workgroups;
+        self.add(shader, bindings, Workgroups::Direct(x, y, z), Vec::new())
+    }
+
+    /// Add a compute shader pass that reads its workgroup counts from a buffer, like
+    /// [super::dispatch_indirect]
+    ///
+    /// The pass reads the buffer, so an earlier pass can write the counts, as long as it declares
+    /// that it [writes](PassDependencies::writes) them.
+    pub fn pass_indirect<B: BindGroups>(
+        &mut self,
+        shader: &'a ComputeShader<B>,
+        bindings: &'a B,
+        indirect_buffer: &'a StorageBufferBind<[u32; 3]>,
+    ) -> PassDependencies<'_, 'a> {
+        let workgroups = Workgroups::Indirect(super::wgpu_buffer(indirect_buffer));
+        self.add(shader, bindings, workgroups, vec![binding_id(indirect_buffer)])
+    }
+
+    fn add<B: BindGroups>(
+        &mut self,
+        shader: &'a ComputeShader<B>,
+        bindings: &'a B,
+        workgroups: Workgroups<'a>,
+        reads: Vec<usize>,
+    ) -> PassDependencies<'_, 'a> {
+        self.passes.push(Pass {
+            set_shader: Box::new(move |pass| super::set_shader(pass, shader, bindings)),
+            workgroups,
+            reads,
+            writes: Vec::new(),
+        });
+        PassDependencies {
+            pass: self.passes.last_mut().unwrap(),
+        }
+    }
+
+    /// Record every pass into one command encoder, and submit it to the GPU
+    pub fn submit(self) {
+        if self.passes.is_empty() {
+            return;
+        }
+        let device = COMPUTE_DEVICE.get_or_init(super::new_compute_device);
+
+        let profile = profiling::Dispatch::begin();
+        let indirect_passes = self
+            .passes
+            .iter()
+            .filter(|pass| matches!(pass.workgroups, Workgroups::Indirect(_)))
+            .count();
+        let indirect = super::indirect_args_buffer(&device.device, indirect_passes);
+        let mut encoder = device
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Care compute pipeline encoder"),
+            });
+
+        let mut start = 0;
+        let mut indirect_offset = 0;
+        while start < self.passes.len() {
+            let end = group_end(&self.passes, start);
+            let group = &self.passes[start..end];
+
+            // The counts have to be copied outside of a compute pass, and the group doesn't write
+            // them, so they can be copied before it begins
+            let mut offset = indirect_offset;
+            for pass in group {
+                if let Workgroups::Indirect(buffer) = pass.workgroups {
+                    encoder.copy_buffer_to_buffer(buffer, 0, &indirect, offset, INDIRECT_ARGS_SIZE);
+                    offset += INDIRECT_ARGS_SIZE;
+                }
+            }
+
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Care compute pipeline pass"),
+                timestamp_writes: None,
+            });
+            for pass in group {
+                (pass.set_shader)(&mut compute_pass);
+                match pass.workgroups {
+                    Workgroups::Direct(x, y, z) => compute_pass.dispatch_workgroups(x, y, z),
+                    Workgroups::Indirect(_) => {
+                        compute_pass.dispatch_workgroups_indirect(&indirect, indirect_offset);
+                        indirect_offset += INDIRECT_ARGS_SIZE;
+                    }
+                }
+            }
+            drop(compute_pass);
+
+            start = end;
+        }
+
+        device.queue.submit([encoder.finish()]);
+        profile.end(self.passes.len() as u64);
+    }
+}
+
+/// Find where the group of passes starting at `start` ends, which is at the first pass that
+/// depends on an earlier pass in the group
+fn group_end(passes: &[Pass], start: usize) -> usize {
+    let mut reads = Vec::new();
+    let mut writes = Vec::new();
+    for (i, pass) in passes.iter().enumerate().skip(start) {
+        let depends = pass.reads.iter().any(|id| writes.contains(id))
+            || pass
+                .writes
+                .iter()
+                .any(|id| writes.contains(id) || reads.contains(id));
+        if depends {
+            return i;
+        }
+        reads.extend(&pass.reads);
+        writes.extend(&pass.writes);
+    }
+    passes.len()
+}
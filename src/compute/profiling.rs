@@ -0,0 +1,232 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use parking_lot::Mutex;
+
+/// How many recent dispatch times are kept
+const HISTORY: usize = 120;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+/// Measurements of compute work, see [stats]
+pub struct ComputeStats {
+    /// Dispatches since the program started
+    pub dispatches: u64,
+    /// Submissions (single dispatches, or whole pipelines) that haven't finished on the GPU yet
+    pub queue_depth: u64,
+    /// How long recent submissions took on the GPU, oldest first
+    ///
+    /// The timestamps are written in separate submissions just before and just after each
+    /// submission, so this is the queue time between them, not the time of the dispatches alone.
+    /// It also includes any other work the GPU ran in between, like drawing.
+    ///
+    /// This is always empty if the GPU doesn't support timestamp queries, or if the graphics
+    /// feature is turned off.
+    pub gpu_times: VecDeque<Duration>,
+    /// How long recent submissions took to record and submit on the CPU, oldest first
+    pub cpu_times: VecDeque<Duration>,
+}
+
+impl ComputeStats {
+    /// The average time recent submissions took on the GPU, see [ComputeStats::gpu_times]
+    pub fn average_gpu_time(&self) -> Duration {
+        average(&self.gpu_times)
+    }
+
+    /// The average time recent submissions took to record and submit on the CPU
+    pub fn average_cpu_time(&self) -> Duration {
+        average(&self.cpu_times)
+    }
+}
+
+fn average(times: &VecDeque<Duration>) -> Duration {
+    if times.is_empty() {
+        return Duration::ZERO;
+    }
+    times.iter().sum::<Duration>() / times.len() as u32
+}
+
+fn push_time(times: &mut VecDeque<Duration>, time: Duration) {
+    if times.len() >= HISTORY {
+        times.pop_front();
+    }
+    times.push_back(time);
+}
+
+static STATS: Mutex<ComputeStats> = Mutex::new(ComputeStats {
+    dispatches: 0,
+    queue_depth: 0,
+    gpu_times: VecDeque::new(),
+    cpu_times: VecDeque::new(),
+});
+
+/// Get measurements of the compute work done so far, e.g. to tune workgroup sizes
+pub fn stats() -> ComputeStats {
+    #[cfg(feature = "graphics")]
+    {
+        crate::graphics::GRAPHICS_STATE.device.poll(wgpu::Maintain::Poll);
+        timestamps::collect();
+    }
+    #[cfg(not(feature = "graphics"))]
+    if super::is_complete() {
+        STATS.lock().queue_depth = 0;
+    }
+    STATS.lock().clone()
+}
+
+/// Measures a submission of one or more dispatches, started just before it's recorded
+pub(crate) struct Dispatch {
+    start: Instant,
+    #[cfg(feature = "graphics")]
+    timer: Option<timestamps::Timer>,
+}
+
+impl Dispatch {
+    pub(crate) fn begin() -> Self {
+        Self {
+            start: Instant::now(),
+            #[cfg(feature = "graphics")]
+            timer: timestamps::begin(),
+        }
+    }
+
+    /// Finish measuring, after submitting some number of dispatches
+    pub(crate) fn end(self, dispatches: u64) {
+        {
+            let mut stats = STATS.lock();
+            stats.dispatches += dispatches;
+            stats.queue_depth += 1;
+            push_time(&mut stats.cpu_times, self.start.elapsed());
+        }
+        #[cfg(feature = "graphics")]
+        {
+            if let Some(timer) = self.timer {
+                timestamps::end(timer);
+            }
+            crate::graphics::GRAPHICS_STATE
+                .queue
+                .on_submitted_work_done(|| STATS.lock().queue_depth -= 1);
+        }
+    }
+}
+
+#[cfg(feature = "graphics")]
+/// GPU timing with timestamp queries, written in their own submissions around the compute work
+///
+/// Single dispatches are recorded and submitted by wrgpgpu, so the timestamps can't be written
+/// inside their compute passes, and measure the queue time between the two submissions instead.
+mod timestamps {
+    use std::{
+        sync::{
+            atomic::{AtomicU8, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use parking_lot::Mutex;
+
+    use crate::graphics::GRAPHICS_STATE;
+
+    const PENDING: u8 = 0;
+    const MAPPED: u8 = 1;
+    const FAILED: u8 = 2;
+
+    /// Two timestamps, the resolved values, and a buffer to read them back with
+    pub(super) struct Timer {
+        query_set: wgpu::QuerySet,
+        resolve: wgpu::Buffer,
+        readback: wgpu::Buffer,
+        state: Arc<AtomicU8>,
+    }
+
+    /// Timers that can be reused, and timers waiting to be read back
+    static TIMERS: Mutex<(Vec<Timer>, Vec<Timer>)> = Mutex::new((Vec::new(), Vec::new()));
+
+    fn supported() -> bool {
+        GRAPHICS_STATE.device.features().contains(
+            wgpu::Features::TIMESTAMP_QUERY | wgpu::Features::TIMESTAMP_QUERY_INSIDE_ENCODERS,
+        )
+    }
+
+    fn new_timer() -> Timer {
+        let device = &GRAPHICS_STATE.device;
+        let size = 2 * std::mem::size_of::<u64>() as u64;
+        Timer {
+            query_set: device.create_query_set(&wgpu::QuerySetDescriptor {
+                label: Some("Care compute timestamps"),
+                ty: wgpu::QueryType::Timestamp,
+                count: 2,
+            }),
+            resolve: device.create_buffer(&wgpu::BufferDescriptor {
+                label: None,
+                size,
+                usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+                mapped_at_creation: false,
+            }),
+            readback: device.create_buffer(&wgpu::BufferDescriptor {
+                label: None,
+                size,
+                usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }),
+            state: Arc::new(AtomicU8::new(PENDING)),
+        }
+    }
+
+    pub(super) fn begin() -> Option<Timer> {
+        if !supported() {
+            return None;
+        }
+        collect();
+        let timer = TIMERS.lock().0.pop().unwrap_or_else(new_timer);
+        let mut encoder = GRAPHICS_STATE.device.create_command_encoder(&Default::default());
+        encoder.write_timestamp(&timer.query_set, 0);
+        GRAPHICS_STATE.queue.submit([encoder.finish()]);
+        Some(timer)
+    }
+
+    pub(super) fn end(timer: Timer) {
+        let mut encoder = GRAPHICS_STATE.device.create_command_encoder(&Default::default());
+        encoder.write_timestamp(&timer.query_set, 1);
+        encoder.resolve_query_set(&timer.query_set, 0..2, &timer.resolve, 0);
+        encoder.copy_buffer_to_buffer(&timer.resolve, 0, &timer.readback, 0, timer.resolve.size());
+        GRAPHICS_STATE.queue.submit([encoder.finish()]);
+
+        timer.state.store(PENDING, Ordering::Release);
+        let state = timer.state.clone();
+        timer.readback.slice(..).map_async(wgpu::MapMode::Read, move |result| {
+            state.store(if result.is_ok() { MAPPED } else { FAILED }, Ordering::Release);
+        });
+        TIMERS.lock().1.push(timer);
+    }
+
+    /// Read back every timer that's finished, and add its time to the stats
+    pub(super) fn collect() {
+        let period = GRAPHICS_STATE.queue.get_timestamp_period() as f64;
+        let mut timers = TIMERS.lock();
+        let (free, in_flight) = &mut *timers;
+        // Only from the front, so the times are added oldest first
+        while let Some(timer) = in_flight.first() {
+            match timer.state.load(Ordering::Acquire) {
+                PENDING => break,
+                MAPPED => {
+                    let timer = in_flight.remove(0);
+                    let times: [u64; 2] = {
+                        let data = timer.readback.slice(..).get_mapped_range();
+                        bytemuck::pod_read_unaligned(&data)
+                    };
+                    timer.readback.unmap();
+                    let ticks = times[1].saturating_sub(times[0]);
+                    let time = Duration::from_nanos((ticks as f64 * period) as u64);
+                    super::push_time(&mut super::STATS.lock().gpu_times, time);
+                    free.push(timer);
+                }
+                _ => {
+                    in_flight.remove(0);
+                }
+            }
+        }
+    }
+}
@@ -1,3 +1,301 @@
-#[derive(Debug, Clone, PartialEq, Default)]
+use std::{fmt, str::FromStr, sync::OnceLock};
+
+#[cfg(feature = "toml")]
+use std::path::Path;
+
+use crate::math::{Fl, Vec2};
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize, serde::Serialize),
+    serde(default)
+)]
 /// Global config for the game engine
-pub struct Conf {}
+///
+/// It can be loaded from a TOML file with [Conf::from_file], and some of it can be changed with
+/// environment variables, see [Conf::with_env].
+pub struct Conf {
+    /// The window's title, or `None` to use the crate's name
+    pub title: Option<String>,
+    /// The window's size, in pixels
+    pub size: Vec2,
+    /// Whether the window can be resized
+    pub resizable: bool,
+    /// Whether the window starts fullscreen
+    pub fullscreen: bool,
+    /// Whether to wait for the display before showing each frame, which stops tearing
+    pub vsync: bool,
+    /// How many samples per pixel to draw for antialiasing, 1 turns it off and 4 works on every
+    /// GPU
+    ///
+    /// Counts the GPU doesn't support fall back to 4, or 1 if even that isn't supported.
+    pub msaa: u32,
+    /// The most frames to draw per second, or `None` (or anything that isn't positive) for no limit
+    pub max_fps: Option<Fl>,
+    /// Draw everything at this resolution, scaled to fit the window with black bars around it,
+    /// instead of at the window's resolution
+    ///
+    /// The mouse position is given in the same coordinates.
+    pub virtual_resolution: Option<Vec2>,
+    /// Which GPU to use, for both graphics and compute
+    pub gpu: GpuPreference,
+    /// How many times per second the `#[care::fixed_update]` function runs, 60 by default
+    pub fixed_update_rate: Fl,
+    #[cfg(feature = "log")]
+    /// Show messages from the [log] crate up to this level in the console, see
+    /// `console::init_logger`
+    pub log_level: Option<log::LevelFilter>,
+}
+
+impl Default for Conf {
+    fn default() -> Self {
+        Self {
+            title: None,
+            size: Vec2::new(800, 600),
+            resizable: false,
+            fullscreen: false,
+            vsync: true,
+            msaa: 1,
+            max_fps: None,
+            virtual_resolution: None,
+            gpu: GpuPreference::default(),
+            fixed_update_rate: 60.0,
+            #[cfg(feature = "log")]
+            log_level: None,
+        }
+    }
+}
+
+impl Conf {
+    /// Get the part of a screen the virtual resolution is drawn to, as its position and size
+    #[cfg_attr(not(feature = "graphics"), allow(dead_code))]
+    pub(crate) fn letterbox(&self, screen_size: Vec2) -> Option<(Vec2, Vec2)> {
+        let resolution = self.virtual_resolution?;
+        let scale = (screen_size.x / resolution.x).min(screen_size.y / resolution.y);
+        let size = resolution * scale;
+        Some(((screen_size - size) / 2.0, size))
+    }
+
+    #[cfg(feature = "toml")]
+    /// Load a config from a TOML file, with anything the file leaves out set to the default
+    ///
+    /// ```toml
+    /// title = "My game"
+    /// size = { x = 1280, y = 720 }
+    /// vsync = false
+    ///
+    /// [gpu]
+    /// backend = "vulkan"
+    /// ```
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, ConfError> {
+        Self::default().merge_file(path)
+    }
+
+    #[cfg(feature = "toml")]
+    /// Change the parts of this config that are set in a TOML file, keeping the rest, e.g. to
+    /// override the config from the code with a file shipped with the game
+    pub fn merge_file(self, path: impl AsRef<Path>) -> Result<Self, ConfError> {
+        let file: toml::Table = std::fs::read_to_string(path)?.parse()?;
+        let mut conf = toml::Table::try_from(self).expect("The config can always be serialized");
+        merge_tables(&mut conf, file);
+        Ok(conf.try_into()?)
+    }
+
+    /// Change the config with environment variables, which is done automatically by [set]
+    ///
+    /// These variables are used, if they're set:
+    /// - `CARE_WIDTH` and `CARE_HEIGHT`: the window's size
+    /// - `CARE_FULLSCREEN`: whether the window is fullscreen, `true` or `false`
+    /// - `CARE_VSYNC`: whether vsync is on, `true` or `false`
+    /// - `CARE_BACKEND`: the graphics API, one of `vulkan`, `metal`, `dx12`, `gl` or
+    ///   `browser_web_gpu`
+    /// - `CARE_ADAPTER`: part of the GPU's name, e.g. `llvmpipe` for a software renderer
+    /// - `CARE_LOG`: the log level, e.g. `debug` (with the `log` feature)
+    ///
+    /// Variables with values that can't be used are printed and left out.
+    pub fn with_env(mut self) -> Self {
+        if let Some(width) = env_var("CARE_WIDTH") {
+            self.size.0.x = width;
+        }
+        if let Some(height) = env_var("CARE_HEIGHT") {
+            self.size.0.y = height;
+        }
+        if let Some(fullscreen) = env_var("CARE_FULLSCREEN") {
+            self.fullscreen = fullscreen;
+        }
+        if let Some(vsync) = env_var("CARE_VSYNC") {
+            self.vsync = vsync;
+        }
+        if let Some(backend) = env_var("CARE_BACKEND") {
+            self.gpu.backend = Some(backend);
+        }
+        if let Ok(adapter) = std::env::var("CARE_ADAPTER") {
+            self.gpu.adapter_name = Some(adapter);
+        }
+        #[cfg(feature = "log")]
+        if let Some(level) = env_var("CARE_LOG") {
+            self.log_level = Some(level);
+        }
+        self
+    }
+}
+
+/// Read and parse an environment variable, printing it if it can't be parsed
+fn env_var<T: FromStr>(name: &str) -> Option<T> {
+    let value = std::env::var(name).ok()?;
+    let parsed = value.parse().ok();
+    if parsed.is_none() {
+        eprintln!("Ignoring {name}, {value:?} isn't a valid value");
+    }
+    parsed
+}
+
+#[cfg(feature = "toml")]
+/// Put everything in one table into another, merging the tables inside them
+fn merge_tables(into: &mut toml::Table, from: toml::Table) {
+    for (key, value) in from {
+        match (into.get_mut(&key), value) {
+            (Some(toml::Value::Table(into)), toml::Value::Table(from)) => merge_tables(into, from),
+            (_, value) => {
+                into.insert(key, value);
+            }
+        }
+    }
+}
+
+#[cfg(feature = "toml")]
+#[derive(Debug)]
+/// An error loading a config file
+pub enum ConfError {
+    /// The file couldn't be read
+    Io(std::io::Error),
+    /// The file isn't valid TOML, or doesn't match the config
+    Toml(toml::de::Error),
+}
+
+#[cfg(feature = "toml")]
+impl fmt::Display for ConfError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfError::Io(e) => write!(f, "Couldn't read the config file: {e}"),
+            ConfError::Toml(e) => write!(f, "Invalid config file: {e}"),
+        }
+    }
+}
+
+#[cfg(feature = "toml")]
+impl std::error::Error for ConfError {}
+
+#[cfg(feature = "toml")]
+impl From<std::io::Error> for ConfError {
+    fn from(e: std::io::Error) -> Self {
+        ConfError::Io(e)
+    }
+}
+
+#[cfg(feature = "toml")]
+impl From<toml::de::Error> for ConfError {
+    fn from(e: toml::de::Error) -> Self {
+        ConfError::Toml(e)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize, serde::Serialize),
+    serde(default)
+)]
+/// Which GPU (adapter) to use, and how to talk to it
+pub struct GpuPreference {
+    /// The graphics API to use, or `None` to pick the best one available
+    pub backend: Option<GpuBackend>,
+    /// Whether to prefer a faster or a more power efficient GPU, when there's more than one
+    pub power: PowerPreference,
+    /// Use the first GPU with a name containing this (ignoring case), e.g. "nvidia", if there is
+    /// one, see `gpu::adapters` to list them
+    pub adapter_name: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize, serde::Serialize),
+    serde(rename_all = "snake_case")
+)]
+/// A graphics API
+pub enum GpuBackend {
+    /// Vulkan, on Windows, Linux and Android
+    Vulkan,
+    /// Metal, on macOS and iOS
+    Metal,
+    /// DirectX 12, on Windows
+    Dx12,
+    /// OpenGL (or OpenGL ES/WebGL), the most compatible but slowest option
+    Gl,
+    /// WebGPU, in a browser
+    BrowserWebGpu,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize, serde::Serialize),
+    serde(rename_all = "snake_case")
+)]
+/// Which kind of GPU to prefer on systems with more than one, e.g. laptops with both an
+/// integrated and a dedicated GPU
+pub enum PowerPreference {
+    /// Prefer the fastest GPU, usually a dedicated one
+    #[default]
+    HighPerformance,
+    /// Prefer the GPU that uses the least power, usually an integrated one
+    LowPower,
+    /// Let the system decide
+    None,
+}
+
+impl FromStr for GpuBackend {
+    type Err = UnknownBackend;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "vulkan" => Ok(GpuBackend::Vulkan),
+            "metal" => Ok(GpuBackend::Metal),
+            "dx12" => Ok(GpuBackend::Dx12),
+            "gl" => Ok(GpuBackend::Gl),
+            "browser_web_gpu" => Ok(GpuBackend::BrowserWebGpu),
+            _ => Err(UnknownBackend),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// The error from parsing a [GpuBackend] that doesn't exist
+pub struct UnknownBackend;
+
+impl fmt::Display for UnknownBackend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Unknown graphics backend")
+    }
+}
+
+impl std::error::Error for UnknownBackend {}
+
+static CONF: OnceLock<Conf> = OnceLock::new();
+
+/// Set the global config, this is normally called automatically by [crate::main]
+///
+/// This has to happen before anything is initialized, and can only be done once.
+///
+/// The environment variables listed in [Conf::with_env] are applied to it.
+pub fn set(conf: Conf) {
+    CONF.set(conf.with_env())
+        .expect("The config can only be set once, before anything is initialized");
+}
+
+/// Get the global config, which is the default config if [set] hasn't been called
+pub fn get() -> &'static Conf {
+    CONF.get_or_init(|| Conf::default().with_env())
+}
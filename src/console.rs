@@ -0,0 +1,324 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use parking_lot::Mutex;
+
+use crate::{
+    graphics,
+    keyboard::{self, Key},
+    math::{Fl, Vec2, Vec4},
+};
+
+/// How many lines of output are kept
+const MAX_LINES: usize = 500;
+/// How many commands are kept in the history
+const MAX_HISTORY: usize = 100;
+/// How much of the window's height the console covers
+const HEIGHT: Fl = 0.4;
+const PADDING: Fl = 8.0;
+const LINE_SPACING: Fl = 2.0;
+
+const TEXT_COLOUR: Vec4 = Vec4(nalgebra::Vector4::new(0.9, 0.9, 0.9, 1.0));
+const INPUT_COLOUR: Vec4 = Vec4(nalgebra::Vector4::new(0.6, 0.8, 1.0, 1.0));
+const ERROR_COLOUR: Vec4 = Vec4(nalgebra::Vector4::new(1.0, 0.4, 0.4, 1.0));
+#[cfg(feature = "log")]
+const WARNING_COLOUR: Vec4 = Vec4(nalgebra::Vector4::new(1.0, 0.8, 0.3, 1.0));
+
+type Command = Box<dyn FnMut(&[&str]) + Send>;
+
+struct ConsoleState {
+    toggle_key: Key,
+    lines: Vec<(String, Vec4)>,
+    input: String,
+    history: Vec<String>,
+    /// The entry in the history being edited, if the player went back to an earlier command
+    history_pos: Option<usize>,
+}
+
+static CONSOLE_STATE: Mutex<ConsoleState> = Mutex::new(ConsoleState {
+    toggle_key: Key::Char('`'),
+    lines: Vec::new(),
+    input: String::new(),
+    history: Vec::new(),
+    history_pos: None,
+});
+static COMMANDS: Mutex<BTreeMap<String, Option<Command>>> = Mutex::new(BTreeMap::new());
+static OPEN: AtomicBool = AtomicBool::new(false);
+
+/// Register a console command, replacing any other command with the same name
+///
+/// The command is given the words typed after its name, words can be quoted to include spaces.
+/// It can use [print] to show its results.
+///
+/// ```ignore
+/// care::console::command("give", |args| match args {
+///     [item, count] => give(item, count.parse().unwrap_or(1)),
+///     _ => care::console::print_error("Usage: give <item> <count>"),
+/// });
+/// ```
+pub fn command(name: &str, command: impl FnMut(&[&str]) + Send + 'static) {
+    COMMANDS
+        .lock()
+        .insert(name.to_string(), Some(Box::new(command)));
+}
+
+/// Remove a console command
+pub fn remove_command(name: &str) {
+    COMMANDS.lock().remove(name);
+}
+
+/// Get the names of every registered command, in alphabetical order
+pub fn commands() -> Vec<String> {
+    let mut names: Vec<_> = COMMANDS.lock().keys().cloned().collect();
+    names.extend(["clear", "help"].map(String::from));
+    names.sort();
+    names.dedup();
+    names
+}
+
+/// Split a line into words, keeping quoted parts together
+fn split_words(line: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = None::<String>;
+    let mut quoted = false;
+    for ch in line.chars() {
+        match ch {
+            '"' => {
+                quoted = !quoted;
+                word.get_or_insert_with(String::new);
+            }
+            ch if ch.is_whitespace() && !quoted => words.extend(word.take()),
+            ch => word.get_or_insert_with(String::new).push(ch),
+        }
+    }
+    words.extend(word);
+    words
+}
+
+/// Run a line as if it was typed into the console
+pub fn run(line: &str) {
+    let words = split_words(line);
+    let Some((name, args)) = words.split_first() else {
+        return;
+    };
+    let args: Vec<_> = args.iter().map(String::as_str).collect();
+    // The command is taken out while it runs, so it can register or run other commands
+    let command = COMMANDS.lock().get_mut(name).map(Option::take);
+    match command {
+        Some(Some(mut command)) => {
+            command(&args);
+            if let Some(slot @ None) = COMMANDS.lock().get_mut(name) {
+                *slot = Some(command);
+            }
+        }
+        Some(None) => print_error(format!("{name} is already running")),
+        None if name == "help" => print(format!("Commands: {}", commands().join(", "))),
+        None if name == "clear" => CONSOLE_STATE.lock().lines.clear(),
+        None => print_error(format!("Unknown command: {name}")),
+    }
+}
+
+fn push_line(text: impl Display, colour: Vec4) {
+    let mut state = CONSOLE_STATE.lock();
+    for line in text.to_string().lines() {
+        state.lines.push((line.to_string(), colour));
+    }
+    let extra = state.lines.len().saturating_sub(MAX_LINES);
+    state.lines.drain(..extra);
+}
+
+/// Show some text in the console's output
+pub fn print(text: impl Display) {
+    push_line(text, TEXT_COLOUR);
+}
+
+/// Show some text in the console's output, coloured as an error
+pub fn print_error(text: impl Display) {
+    push_line(text, ERROR_COLOUR);
+}
+
+/// Open or close the console
+///
+/// While the console is open, it takes all keyboard input, so [keyboard] functions act like
+/// nothing is pressed.
+pub fn open(open: bool) {
+    OPEN.store(open, Ordering::Relaxed);
+    keyboard::set_captured(keyboard::Capture::Console, open);
+}
+
+/// Get whether the console is open
+pub fn is_open() -> bool {
+    OPEN.load(Ordering::Relaxed)
+}
+
+/// Set the key that opens and closes the console, by default the backtick key (`)
+pub fn set_toggle_key(key: impl Into<Key>) {
+    CONSOLE_STATE.lock().toggle_key = key.into();
+}
+
+/// Complete the command name being typed, or list the commands it could be
+fn complete(state: &mut ConsoleState) {
+    if state.input.contains(char::is_whitespace) {
+        return;
+    }
+    let matches: Vec<_> = commands()
+        .into_iter()
+        .filter(|name| name.starts_with(&state.input))
+        .collect();
+    match matches.as_slice() {
+        [] => {}
+        [name] => state.input = format!("{name} "),
+        [first, rest @ ..] => {
+            // Complete as far as every match agrees
+            let common = rest.iter().fold(first.chars().count(), |len, name| {
+                first
+                    .chars()
+                    .zip(name.chars())
+                    .take(len)
+                    .take_while(|(a, b)| a == b)
+                    .count()
+            });
+            state.input = first.chars().take(common).collect();
+            let list = matches.join("  ");
+            state.lines.push((list, TEXT_COLOUR));
+        }
+    }
+}
+
+fn move_through_history(state: &mut ConsoleState, back: bool) {
+    let pos = match (state.history_pos, back) {
+        (None, true) => state.history.len().checked_sub(1),
+        (None, false) => return,
+        (Some(pos), true) => Some(pos.saturating_sub(1)),
+        (Some(pos), false) => Some(pos + 1).filter(|&pos| pos < state.history.len()),
+    };
+    state.history_pos = pos;
+    state.input = pos
+        .map(|pos| state.history[pos].clone())
+        .unwrap_or_default();
+}
+
+/// Handle the console's keyboard input, returning the line that was entered, if any
+fn handle_input() -> Option<String> {
+    let keys = keyboard::get_state().read();
+    let mut state = CONSOLE_STATE.lock();
+    if keys.pressed.contains(&state.toggle_key) {
+        open(!is_open());
+        // The toggle key's text shouldn't be typed
+        return None;
+    }
+    if !is_open() {
+        return None;
+    }
+    if keys.pressed.contains(&Key::Escape) {
+        open(false);
+        return None;
+    }
+
+    if keys.pressed.contains(&Key::Backspace) {
+        state.input.pop();
+    }
+    state.input.push_str(&keys.text);
+    if keys.pressed.contains(&Key::Char('\t')) {
+        complete(&mut state);
+    }
+    if keys.pressed.contains(&Key::Up) {
+        move_through_history(&mut state, true);
+    }
+    if keys.pressed.contains(&Key::Down) {
+        move_through_history(&mut state, false);
+    }
+    if !keys.pressed.contains(&Key::Enter) {
+        return None;
+    }
+
+    state.history_pos = None;
+    let line = std::mem::take(&mut state.input);
+    if line.trim().is_empty() {
+        return None;
+    }
+    if state.history.last() != Some(&line) {
+        state.history.push(line.clone());
+        let extra = state.history.len().saturating_sub(MAX_HISTORY);
+        state.history.drain(..extra);
+    }
+    state.lines.push((format!("> {line}"), INPUT_COLOUR));
+    Some(line)
+}
+
+/// Handle input, run commands and draw the console, just before the frame is presented
+pub(crate) fn end_frame() {
+    if let Some(line) = handle_input() {
+        run(&line);
+    }
+    if !is_open() {
+        return;
+    }
+
+    let colour = graphics::GRAPHICS_STATE.care_render.read().current_colour;
+    let size = Vec2::new(crate::window::window_size().x, crate::window::window_size().y * HEIGHT);
+    let line_height = graphics::text_height() + LINE_SPACING;
+    graphics::set_colour((0.05, 0.05, 0.08, 0.85));
+    graphics::rectangle((0, 0), size);
+
+    let state = CONSOLE_STATE.lock();
+    let input_y = size.y - PADDING - line_height;
+    let prompt = format!("> {}", state.input);
+    graphics::set_colour(INPUT_COLOUR);
+    graphics::text(&prompt, (PADDING, input_y));
+    graphics::rectangle(
+        (PADDING + graphics::text_width(&prompt) + 1.0, input_y),
+        (2, graphics::text_height()),
+    );
+
+    // The newest lines are at the bottom, just above the input
+    let mut y = input_y - line_height;
+    for (line, colour) in state.lines.iter().rev() {
+        if y < PADDING {
+            break;
+        }
+        graphics::set_colour(*colour);
+        graphics::text(line, (PADDING, y));
+        y -= line_height;
+    }
+    graphics::set_colour(colour);
+}
+
+#[cfg(feature = "log")]
+struct Logger;
+
+#[cfg(feature = "log")]
+impl log::Log for Logger {
+    fn enabled(&self, _metadata: &log::Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &log::Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let colour = match record.level() {
+            log::Level::Error => ERROR_COLOUR,
+            log::Level::Warn => WARNING_COLOUR,
+            _ => TEXT_COLOUR,
+        };
+        let text = format!("[{}] {}", record.level(), record.args());
+        eprintln!("{text}");
+        push_line(text, colour);
+    }
+
+    fn flush(&self) {}
+}
+
+#[cfg(feature = "log")]
+/// Show messages from the [log] crate in the console (and on stderr), up to a maximum level
+///
+/// This fails if another logger was already set.
+pub fn init_logger(level: log::LevelFilter) -> Result<(), log::SetLoggerError> {
+    log::set_logger(&Logger)?;
+    log::set_max_level(level);
+    Ok(())
+}
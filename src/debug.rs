@@ -0,0 +1,281 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fmt::Debug,
+    sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+    time::{Duration, Instant},
+};
+
+use parking_lot::Mutex;
+
+use crate::math::Fl;
+
+/// How many recent frame times are kept
+const HISTORY: usize = 120;
+/// The frame time at the top of the graph, unless a frame took longer
+const GRAPH_MIN_SCALE: Duration = Duration::from_millis(33);
+const GRAPH_WIDTH: Fl = 240.0;
+const GRAPH_HEIGHT: Fl = 40.0;
+
+/// Bytes used by textures that haven't been dropped yet
+pub(crate) static TEXTURE_MEMORY: AtomicU64 = AtomicU64::new(0);
+/// Tasks started with [crate::event::spawn] that haven't finished yet
+static TASKS: AtomicUsize = AtomicUsize::new(0);
+static OVERLAY: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Clone, Default, PartialEq)]
+/// Statistics about recent frames, see [stats]
+pub struct DebugStats {
+    /// How long recent frames took, oldest first
+    pub frame_times: VecDeque<Duration>,
+    /// How many draw calls the last frame took
+    pub draw_calls: usize,
+    /// How many vertices were drawn in the last frame
+    pub vertices: usize,
+    /// Roughly how many bytes of GPU memory textures are using
+    pub texture_memory: u64,
+    /// How many tasks started with [crate::event::spawn] are still running
+    pub tasks: usize,
+}
+
+impl DebugStats {
+    /// The average time recent frames took
+    pub fn average_frame_time(&self) -> Duration {
+        if self.frame_times.is_empty() {
+            return Duration::ZERO;
+        }
+        self.frame_times.iter().sum::<Duration>() / self.frame_times.len() as u32
+    }
+
+    /// The average frames per second over recent frames
+    pub fn fps(&self) -> Fl {
+        let frame_time = self.average_frame_time().as_secs_f64();
+        if frame_time > 0.0 {
+            (1.0 / frame_time) as Fl
+        } else {
+            0.0
+        }
+    }
+}
+
+struct DebugState {
+    frame_times: VecDeque<Duration>,
+    last_frame: Option<Instant>,
+    draw_calls: usize,
+    vertices: usize,
+    watches: BTreeMap<String, String>,
+}
+
+static DEBUG_STATE: Mutex<DebugState> = Mutex::new(DebugState {
+    frame_times: VecDeque::new(),
+    last_frame: None,
+    draw_calls: 0,
+    vertices: 0,
+    watches: BTreeMap::new(),
+});
+
+/// Show or hide the debug overlay, which shows the FPS, a graph of recent frame times, draw call
+/// and vertex counts, texture memory, running tasks and any values passed to [watch]
+///
+/// The overlay is drawn with egui if the `gui` feature is enabled, and with care's own graphics
+/// otherwise.
+pub fn overlay(show: bool) {
+    OVERLAY.store(show, Ordering::Relaxed);
+}
+
+/// Get whether the debug overlay is shown
+pub fn is_overlay_shown() -> bool {
+    OVERLAY.load(Ordering::Relaxed)
+}
+
+/// Show a value in the debug overlay for this frame, e.g. the position of the player
+///
+/// Call it every frame to keep the value in the overlay.
+pub fn watch(name: &str, value: impl Debug) {
+    DEBUG_STATE
+        .lock()
+        .watches
+        .insert(name.to_string(), format!("{value:?}"));
+}
+
+/// Get statistics about recent frames, the same ones shown in the overlay
+pub fn stats() -> DebugStats {
+    let state = DEBUG_STATE.lock();
+    DebugStats {
+        frame_times: state.frame_times.clone(),
+        draw_calls: state.draw_calls,
+        vertices: state.vertices,
+        texture_memory: TEXTURE_MEMORY.load(Ordering::Relaxed),
+        tasks: TASKS.load(Ordering::Relaxed),
+    }
+}
+
+/// Keeps a spawned task counted until it's dropped
+pub(crate) struct TaskGuard;
+
+impl TaskGuard {
+    pub(crate) fn new() -> Self {
+        TASKS.fetch_add(1, Ordering::Relaxed);
+        Self
+    }
+}
+
+impl Drop for TaskGuard {
+    fn drop(&mut self) {
+        TASKS.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+#[cfg_attr(not(feature = "graphics"), allow(dead_code))]
+pub(crate) fn record_draw(draw_calls: usize, vertices: usize) {
+    let mut state = DEBUG_STATE.lock();
+    state.draw_calls = draw_calls;
+    state.vertices = vertices;
+}
+
+/// Record the frame time and forget this frame's watches
+pub(crate) fn end_frame() {
+    let mut state = DEBUG_STATE.lock();
+    let now = Instant::now();
+    if let Some(last_frame) = state.last_frame.replace(now) {
+        if state.frame_times.len() >= HISTORY {
+            state.frame_times.pop_front();
+        }
+        state.frame_times.push_back(now - last_frame);
+    }
+    state.watches.clear();
+}
+
+/// The text lines shown in the overlay
+fn lines(stats: &DebugStats) -> Vec<String> {
+    let mut lines = vec![
+        format!(
+            "{:.0} FPS ({:.1}ms)",
+            stats.fps(),
+            stats.average_frame_time().as_secs_f64() * 1000.0
+        ),
+        format!("{} draw calls, {} vertices", stats.draw_calls, stats.vertices),
+        format!(
+            "Textures: {:.1} MiB",
+            stats.texture_memory as f64 / (1024.0 * 1024.0)
+        ),
+        format!("Tasks: {}", stats.tasks),
+    ];
+    #[cfg(feature = "compute")]
+    {
+        let compute = crate::compute::stats();
+        lines.push(format!(
+            "Compute: {} dispatches, {} queued",
+            compute.dispatches, compute.queue_depth
+        ));
+        lines.push(format!(
+            "Compute time: {:.2}ms GPU, {:.2}ms CPU",
+            compute.average_gpu_time().as_secs_f64() * 1000.0,
+            compute.average_cpu_time().as_secs_f64() * 1000.0
+        ));
+    }
+    lines.extend(
+        DEBUG_STATE
+            .lock()
+            .watches
+            .iter()
+            .map(|(name, value)| format!("{name}: {value}")),
+    );
+    lines
+}
+
+/// The height of every bar in the frame time graph, as a fraction of the graph's height
+fn graph_bars(stats: &DebugStats) -> impl Iterator<Item = Fl> + '_ {
+    let scale = stats
+        .frame_times
+        .iter()
+        .copied()
+        .max()
+        .unwrap_or_default()
+        .max(GRAPH_MIN_SCALE);
+    stats
+        .frame_times
+        .iter()
+        .map(move |time| (time.as_secs_f64() / scale.as_secs_f64()) as Fl)
+}
+
+/// Draw the overlay, if it's shown, just before the frame is presented
+pub(crate) fn draw_overlay() {
+    if !is_overlay_shown() {
+        return;
+    }
+    // Otherwise it's drawn by [crate::gui::gui], if the game already used egui this frame
+    #[cfg(feature = "gui")]
+    if crate::gui::EGUI_STATE.lock().full_output.is_none() {
+        crate::gui::gui(|_| {});
+    }
+    #[cfg(all(feature = "graphics", not(feature = "gui")))]
+    draw_native();
+}
+
+#[cfg(all(feature = "graphics", not(feature = "gui")))]
+fn draw_native() {
+    use crate::{graphics, math::Vec2};
+
+    const LINE_HEIGHT: Fl = 20.0;
+    const MARGIN: Fl = 10.0;
+
+    let stats = stats();
+    let lines = lines(&stats);
+    let pos = Vec2::new(MARGIN, MARGIN);
+    let height = lines.len() as Fl * LINE_HEIGHT + GRAPH_HEIGHT + MARGIN * 3.0;
+    graphics::set_colour((0, 0, 0, 0.7));
+    graphics::rectangle(pos, (GRAPH_WIDTH + MARGIN * 2.0, height));
+    graphics::set_colour((1, 1, 1, 1));
+    for (i, line) in lines.iter().enumerate() {
+        graphics::text(line, pos + Vec2::new(MARGIN, MARGIN + i as Fl * LINE_HEIGHT));
+    }
+
+    let graph_bottom = pos.y + height - MARGIN;
+    let bar_width = GRAPH_WIDTH / HISTORY as Fl;
+    graphics::set_colour((0.4, 0.9, 0.4, 1));
+    for (i, bar) in graph_bars(&stats).enumerate() {
+        let bar_height = bar * GRAPH_HEIGHT;
+        graphics::rectangle(
+            (pos.x + MARGIN + i as Fl * bar_width, graph_bottom - bar_height),
+            (bar_width, bar_height),
+        );
+    }
+}
+
+/// Draw the overlay with egui, if it's shown
+#[cfg(feature = "gui")]
+pub(crate) fn draw_egui(ctx: &egui::Context) {
+    if !is_overlay_shown() {
+        return;
+    }
+    let stats = stats();
+    // Clippy detects this as an issue because when Fl = f32, the explicit conversions are not
+    // needed, but when Fl = f64, they are neccesary.
+    #[allow(clippy::unnecessary_cast)]
+    egui::Window::new("Debug")
+        .default_pos((10.0, 10.0))
+        .resizable(false)
+        .show(ctx, |ui| {
+            for line in lines(&stats) {
+                ui.label(line);
+            }
+            let (rect, _) = ui.allocate_exact_size(
+                egui::vec2(GRAPH_WIDTH as f32, GRAPH_HEIGHT as f32),
+                egui::Sense::hover(),
+            );
+            let painter = ui.painter();
+            painter.rect_filled(rect, 0.0, egui::Color32::from_black_alpha(100));
+            let bar_width = rect.width() / HISTORY as f32;
+            for (i, bar) in graph_bars(&stats).enumerate() {
+                let left = rect.left() + i as f32 * bar_width;
+                painter.rect_filled(
+                    egui::Rect::from_min_max(
+                        egui::pos2(left, rect.bottom() - bar as f32 * rect.height()),
+                        egui::pos2(left + bar_width, rect.bottom()),
+                    ),
+                    0.0,
+                    egui::Color32::LIGHT_GREEN,
+                );
+            }
+        });
+}
@@ -0,0 +1,156 @@
+use std::{
+    fmt::{self, Debug, Display},
+    sync::Arc,
+};
+
+use parking_lot::Mutex;
+
+#[cfg(feature = "window")]
+use crate::math::{Fl, Vec2};
+
+type Handler = Arc<dyn Fn(GameError) + Send + Sync>;
+
+/// The function errors are given to, see [set_handler]
+static HANDLER: Mutex<Option<Handler>> = Mutex::new(None);
+/// The error on the error screen, see [show]
+static SHOWN: Mutex<Option<GameError>> = Mutex::new(None);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// An error returned by one of the game's functions
+pub struct GameError {
+    /// The kind of function that returned it, e.g. `"update"`
+    pub function: &'static str,
+    /// The error, formatted with [Debug] like errors returned from `main`
+    pub message: String,
+}
+
+impl Display for GameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Error in the {} function: {}",
+            self.function, self.message
+        )
+    }
+}
+
+impl std::error::Error for GameError {}
+
+/// Set the function that errors from the game's functions are given to, instead of
+/// [default_handler]
+pub fn set_handler(handler: impl Fn(GameError) + Send + Sync + 'static) {
+    *HANDLER.lock() = Some(Arc::new(handler));
+}
+
+/// Give an error to the handler set with [set_handler], this is normally called automatically
+/// when one of the game's functions returns an error
+pub fn report(error: GameError) {
+    let handler = HANDLER.lock().clone();
+    match handler {
+        Some(handler) => handler(error),
+        None => default_handler(error),
+    }
+}
+
+/// The default way errors are handled, which [log]s them and then [show]s them
+pub fn default_handler(error: GameError) {
+    log(&error);
+    show(error);
+}
+
+/// Print an error on stderr and in the console
+pub fn log(error: &GameError) {
+    eprintln!("{error}");
+    #[cfg(feature = "window")]
+    crate::console::print_error(error);
+}
+
+/// Stop running the game's functions and show an error screen instead, which exits the game when
+/// escape or enter is pressed
+///
+/// Without a window there's nowhere to show it, so the game exits straight away.
+pub fn show(error: GameError) {
+    #[cfg(feature = "window")]
+    {
+        *SHOWN.lock() = Some(error);
+    }
+    #[cfg(not(feature = "window"))]
+    {
+        let _ = error;
+        std::process::exit(1);
+    }
+}
+
+/// Get the error on the error screen, if it's shown
+pub fn shown() -> Option<GameError> {
+    SHOWN.lock().clone()
+}
+
+#[doc(hidden)]
+/// The return types the game's functions can have, used by [crate::main]
+pub trait __HandlerResult {
+    /// Get the error, formatted for a [GameError]
+    fn __into_error(self) -> Option<String>;
+}
+
+impl __HandlerResult for () {
+    fn __into_error(self) -> Option<String> {
+        None
+    }
+}
+
+impl<E: Debug> __HandlerResult for Result<(), E> {
+    fn __into_error(self) -> Option<String> {
+        self.err().map(|e| format!("{e:?}"))
+    }
+}
+
+#[doc(hidden)]
+/// Report the error a game function returned, if there is one, used by [crate::main]
+pub fn __handle(function: &'static str, result: impl __HandlerResult) {
+    if let Some(message) = result.__into_error() {
+        report(GameError { function, message });
+    }
+}
+
+#[doc(hidden)]
+/// Draw the error screen if it's shown, and get whether it is, used by [crate::main] to stop
+/// running the game's functions
+pub fn __error_screen() -> bool {
+    let Some(error) = shown() else {
+        return false;
+    };
+    #[cfg(feature = "window")]
+    draw_error_screen(&error);
+    #[cfg(not(feature = "window"))]
+    let _ = error;
+    true
+}
+
+#[cfg(feature = "window")]
+fn draw_error_screen(error: &GameError) {
+    use crate::{graphics, keyboard::Key};
+
+    const MARGIN: Fl = 20.0;
+    const LINE_SPACING: Fl = 4.0;
+
+    if crate::keyboard::is_pressed(Key::Escape) || crate::keyboard::is_pressed(Key::Enter) {
+        crate::event::exit();
+    }
+
+    let size = crate::config::get()
+        .virtual_resolution
+        .unwrap_or_else(crate::window::window_size);
+    graphics::set_colour((0.25, 0.05, 0.05, 1));
+    graphics::rectangle((0, 0), size);
+    graphics::set_colour((1, 1, 1, 1));
+    let line_height = graphics::text_height() + LINE_SPACING;
+    let title = format!("The game hit an error in its {} function:", error.function);
+    let lines = [title.as_str(), ""]
+        .into_iter()
+        .chain(error.message.lines())
+        .chain(["", "Press escape to exit"]);
+    for (i, line) in lines.enumerate() {
+        graphics::text(line, Vec2::new(MARGIN, MARGIN + i as Fl * line_height));
+    }
+}
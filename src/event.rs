@@ -1,9 +1,15 @@
-use std::{future::Future, time::Instant};
+use std::{
+    future::Future,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use parking_lot::Mutex;
 
 use crate::{
     graphics,
     keyboard::{self, Key},
-    math::Vec2,
+    math::{Fl, Vec2},
     mouse,
 };
 
@@ -46,11 +52,44 @@ pub enum EventData {
         /// Whether it's currently pressed
         pressed: bool,
     },
+    /// The mouse wheel or a touchpad scrolled
+    MouseScroll {
+        /// How far it scrolled, in lines, with positive y being up
+        delta: Vec2,
+    },
+    /// A file was dragged onto the window and dropped
+    FileDropped {
+        /// The path of the file
+        path: PathBuf,
+    },
+    /// The window was resized
+    WindowResized {
+        /// The window's new size
+        size: Vec2,
+    },
     /// The window went in or out of focus
     FocusChange {
         /// Is the window currently focused
         focused: bool,
     },
+    /// A finger touched, moved on or left the screen
+    ///
+    /// On Android and iOS the first finger also sends mouse events, as mouse button 1.
+    Touch {
+        /// Which finger it is, see [crate::touch::Touch::id]
+        id: u64,
+        /// Whether it started, moved or ended
+        phase: crate::touch::TouchPhase,
+        /// The absolute screen position for the event
+        position: Vec2,
+    },
+    /// The app was sent to the background or brought back, which mostly happens on mobile
+    ///
+    /// Nothing is drawn while it's suspended, and the game's functions aren't called.
+    Suspend {
+        /// Whether the app is currently suspended
+        suspended: bool,
+    },
 }
 
 #[derive(Debug, Clone)]
@@ -62,10 +101,66 @@ pub struct Event {
     pub data: EventData,
 }
 
+/// The events since the last frame ended
+static FRAME_EVENTS: Mutex<Vec<Event>> = Mutex::new(Vec::new());
+
+/// When the last frame ended, for [crate::Conf::max_fps]
+static LAST_FRAME_END: Mutex<Option<Instant>> = Mutex::new(None);
+
+/// Functions to run when the game exits, see [on_exit]
+static EXIT_HOOKS: Mutex<Vec<Box<dyn FnOnce() + Send>>> = Mutex::new(Vec::new());
+
+/// The most fixed steps run in one frame, so slow frames don't make the game fall further behind
+const MAX_FIXED_STEPS: Fl = 8.0;
+
+#[derive(Debug, Clone)]
+/// Runs something at a fixed rate independent of the frame rate, e.g. physics
+///
+/// Call [FixedTimestep::advance] every frame with the frame's delta time, and run the fixed step
+/// as many times as it returns.
+pub struct FixedTimestep {
+    step: Fl,
+    accumulated: Fl,
+}
+
+impl FixedTimestep {
+    /// Create a fixed timestep that runs some number of times per second
+    pub fn new(rate: Fl) -> Self {
+        Self {
+            step: 1.0 / rate,
+            accumulated: 0.0,
+        }
+    }
+
+    /// Get the time between steps, in seconds
+    pub fn step(&self) -> Fl {
+        self.step
+    }
+
+    /// Add a frame's delta time, and get how many steps should run this frame
+    pub fn advance(&mut self, delta: Fl) -> u32 {
+        self.accumulated += delta;
+        let steps = (self.accumulated / self.step).floor();
+        self.accumulated -= steps * self.step;
+        steps.min(MAX_FIXED_STEPS) as u32
+    }
+
+    /// Get how far the frame is between the last step and the next one, from 0 to 1, for
+    /// smoothly drawing things that move in fixed steps
+    pub fn alpha(&self) -> Fl {
+        self.accumulated / self.step
+    }
+}
+
 /// Initialize the care game engine, including all loaded modules
 ///
 /// This is normally called automatically
 pub fn init() {
+    #[cfg(all(feature = "log", feature = "window"))]
+    if let Some(level) = crate::config::get().log_level {
+        // The game might have set up its own logger, which is fine
+        let _ = crate::console::init_logger(level);
+    }
     graphics::init();
 }
 
@@ -73,18 +168,55 @@ pub fn init() {
 ///
 /// This is normally called automatically
 pub fn end_frame() {
+    #[cfg(feature = "window")]
+    crate::console::end_frame();
+    #[cfg(feature = "ui")]
+    graphics::ui::end_frame();
+    crate::debug::draw_overlay();
+    #[cfg(feature = "gui")]
+    crate::gui::end_frame();
     #[cfg(feature = "graphics")]
     graphics::present();
+    #[cfg(feature = "compute")]
+    crate::compute::poll();
+    crate::debug::end_frame();
     keyboard::reset();
     mouse::reset();
+    crate::touch::reset();
+    crate::navigation::reset();
+    FRAME_EVENTS.lock().clear();
+    limit_frame_rate();
+}
+
+/// Wait until it's time for the next frame, if the config sets a maximum frame rate
+fn limit_frame_rate() {
+    let max_fps = crate::config::get().max_fps;
+    let Some(max_fps) = max_fps.filter(|&max_fps| max_fps > 0.0) else {
+        return;
+    };
+    let mut last_frame_end = LAST_FRAME_END.lock();
+    if let Some(last_frame_end) = *last_frame_end {
+        let frame_time = Duration::from_secs_f64(1.0 / max_fps as f64);
+        if let Some(remaining) = frame_time.checked_sub(last_frame_end.elapsed()) {
+            std::thread::sleep(remaining);
+        }
+    }
+    *last_frame_end = Some(Instant::now());
 }
 
 /// Run the game main loop, using a specific function that gets called once per frame
-pub fn main_loop<T>(
+pub fn main_loop<T>(init_fn: impl FnOnce() -> T + 'static, loop_fn: impl FnMut(&mut T) + 'static) {
+    main_loop_with_quit(init_fn, loop_fn, |_| {});
+}
+
+/// Like [main_loop], but with a function that gets called once with the data when the main loop
+/// is about to exit, e.g. to save the game
+pub fn main_loop_with_quit<T>(
     init_fn: impl FnOnce() -> T + 'static,
     mut loop_fn: impl FnMut(&mut T) + 'static,
+    quit_fn: impl FnOnce(&mut T) + 'static,
 ) {
-    main_loop_manual(
+    run_loop(
         move || {
             init();
             init_fn()
@@ -93,6 +225,7 @@ pub fn main_loop<T>(
             loop_fn(data);
             end_frame();
         },
+        quit_fn,
     );
 }
 
@@ -100,11 +233,22 @@ pub fn main_loop<T>(
 pub fn main_loop_manual<T>(
     init_fn: impl FnOnce() -> T + 'static,
     loop_fn: impl FnMut(&mut T) + 'static,
+) {
+    run_loop(init_fn, loop_fn, |_| {});
+}
+
+/// Run the main loop on whichever backend is enabled
+fn run_loop<T>(
+    init_fn: impl FnOnce() -> T + 'static,
+    loop_fn: impl FnMut(&mut T) + 'static,
+    quit_fn: impl FnOnce(&mut T) + 'static,
 ) {
     #[cfg(feature = "window")]
-    crate::window::run(init_fn, loop_fn);
+    crate::window::run(init_fn, loop_fn, quit_fn);
     #[cfg(not(feature = "window"))]
     {
+        // Without a window there's nothing that ends the loop
+        let _ = quit_fn;
         let mut data = init_fn();
         loop {
             loop_fn(&mut data);
@@ -112,6 +256,23 @@ pub fn main_loop_manual<T>(
     }
 }
 
+/// Run a function when the game exits, after the main loop has stopped
+///
+/// This works with every kind of main loop, including async ones. The functions run in the order
+/// they were added, after the `#[care::quit]` function.
+pub fn on_exit(hook: impl FnOnce() + Send + 'static) {
+    EXIT_HOOKS.lock().push(Box::new(hook));
+}
+
+/// Run every function given to [on_exit], this is called automatically when the main loop exits
+#[cfg_attr(not(feature = "window"), allow(dead_code))]
+pub(crate) fn run_exit_hooks() {
+    let hooks: Vec<_> = EXIT_HOOKS.lock().drain(..).collect();
+    for hook in hooks {
+        hook();
+    }
+}
+
 #[cfg(all(feature = "async-custom", feature = "_async-tokio-internal"))]
 compile_error!("Only one async executor feature can be enabled at a time.");
 
@@ -161,6 +322,12 @@ pub async fn async_yield() {
 ///
 /// Panics on the "polling" executor
 pub fn spawn(task: impl Future<Output = ()> + 'static + Send) {
+    let guard = crate::debug::TaskGuard::new();
+    #[allow(unused_variables)]
+    let task = async move {
+        let _guard = guard;
+        task.await
+    };
     #[cfg(not(any(feature = "async-custom", feature = "_async-tokio-internal")))]
     panic!("The polling/null executor does not support spawning multiple tasks.");
     #[cfg(feature = "async-custom")]
@@ -178,15 +345,31 @@ pub fn exit() {
 /// Process an event, this can only send events within the game, not emulate actual mouse motion or
 /// keyboard buttons
 pub fn handle_event(ev: Event) {
+    FRAME_EVENTS.lock().push(ev.clone());
     match &ev.data {
         EventData::KeyEvent { key, pressed } => crate::keyboard::process_key_event(*key, *pressed),
         EventData::MouseMoved { position } => crate::mouse::process_mouse_moved_event(*position),
         EventData::MouseClick { button, pressed } => {
             crate::mouse::process_mouse_click_event(*button, *pressed)
         }
-        EventData::TextEvent { .. } => {}
+        EventData::TextEvent { text } => crate::keyboard::process_text_event(text),
         EventData::FocusChange { .. } => {}
         EventData::KeyRepeat { .. } => {}
+        EventData::MouseScroll { .. } => {}
+        EventData::FileDropped { .. } => {}
+        EventData::WindowResized { .. } => {}
+        EventData::Touch {
+            id,
+            phase,
+            position,
+        } => crate::touch::process_touch_event(*id, *phase, *position),
+        EventData::Suspend { .. } => {}
     }
+    #[cfg(feature = "gui")]
     crate::gui::process_event(ev);
 }
+
+/// Get every event since the last frame ended, in the order they happened
+pub fn events() -> Vec<Event> {
+    FRAME_EVENTS.lock().clone()
+}
@@ -0,0 +1,117 @@
+use pollster::FutureExt;
+
+use crate::config::{self, GpuBackend, PowerPreference};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// A GPU (or software renderer) that can be used for graphics and compute
+pub struct GpuAdapter {
+    /// The name of the GPU, which can be used for [config::GpuPreference::adapter_name]
+    pub name: String,
+    /// The graphics API it's used through, or `None` if it's one care doesn't know about
+    pub backend: Option<GpuBackend>,
+    /// What kind of GPU it is
+    pub kind: GpuKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// What kind of device a GPU is
+pub enum GpuKind {
+    /// A separate GPU, usually the fastest
+    Discrete,
+    /// A GPU built into the CPU
+    Integrated,
+    /// A GPU in a virtual machine
+    Virtual,
+    /// A software renderer running on the CPU
+    Cpu,
+    /// Something else
+    Other,
+}
+
+/// List every GPU that can be used, e.g. to let players pick one in a settings menu
+///
+/// The same GPU will be listed once for every graphics API it supports.
+pub fn adapters() -> Vec<GpuAdapter> {
+    let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor {
+        backends: wgpu::Backends::all(),
+        ..Default::default()
+    });
+    instance
+        .enumerate_adapters(wgpu::Backends::all())
+        .iter()
+        .map(|adapter| {
+            let info = adapter.get_info();
+            GpuAdapter {
+                name: info.name,
+                backend: match info.backend {
+                    wgpu::Backend::Vulkan => Some(GpuBackend::Vulkan),
+                    wgpu::Backend::Metal => Some(GpuBackend::Metal),
+                    wgpu::Backend::Dx12 => Some(GpuBackend::Dx12),
+                    wgpu::Backend::Gl => Some(GpuBackend::Gl),
+                    wgpu::Backend::BrowserWebGpu => Some(GpuBackend::BrowserWebGpu),
+                    _ => None,
+                },
+                kind: match info.device_type {
+                    wgpu::DeviceType::DiscreteGpu => GpuKind::Discrete,
+                    wgpu::DeviceType::IntegratedGpu => GpuKind::Integrated,
+                    wgpu::DeviceType::VirtualGpu => GpuKind::Virtual,
+                    wgpu::DeviceType::Cpu => GpuKind::Cpu,
+                    wgpu::DeviceType::Other => GpuKind::Other,
+                },
+            }
+        })
+        .collect()
+}
+
+/// The graphics APIs allowed by the config
+fn backends() -> wgpu::Backends {
+    match config::get().gpu.backend {
+        None => wgpu::Backends::all(),
+        Some(GpuBackend::Vulkan) => wgpu::Backends::VULKAN,
+        Some(GpuBackend::Metal) => wgpu::Backends::METAL,
+        Some(GpuBackend::Dx12) => wgpu::Backends::DX12,
+        Some(GpuBackend::Gl) => wgpu::Backends::GL,
+        Some(GpuBackend::BrowserWebGpu) => wgpu::Backends::BROWSER_WEBGPU,
+    }
+}
+
+/// Create a wgpu instance for the graphics APIs allowed by the config
+pub(crate) fn instance() -> wgpu::Instance {
+    wgpu::Instance::new(&wgpu::InstanceDescriptor {
+        backends: backends(),
+        ..Default::default()
+    })
+}
+
+/// Pick the adapter to use, following the config
+pub(crate) fn adapter(
+    instance: &wgpu::Instance,
+    surface: Option<&wgpu::Surface<'_>>,
+) -> wgpu::Adapter {
+    let preference = &config::get().gpu;
+    if let Some(name) = &preference.adapter_name {
+        let name = name.to_lowercase();
+        let adapter = instance
+            .enumerate_adapters(backends())
+            .into_iter()
+            .find(|adapter| {
+                adapter.get_info().name.to_lowercase().contains(&name)
+                    && surface.is_none_or(|surface| adapter.is_surface_supported(surface))
+            });
+        if let Some(adapter) = adapter {
+            return adapter;
+        }
+    }
+    instance
+        .request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: match preference.power {
+                PowerPreference::HighPerformance => wgpu::PowerPreference::HighPerformance,
+                PowerPreference::LowPower => wgpu::PowerPreference::LowPower,
+                PowerPreference::None => wgpu::PowerPreference::None,
+            },
+            force_fallback_adapter: false,
+            compatible_surface: surface,
+        })
+        .block_on()
+        .expect("No graphics adapter found")
+}
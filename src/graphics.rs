@@ -4,19 +4,29 @@
 mod api;
 mod font;
 mod graphics_state;
+mod headless;
 mod render_2d;
 mod texture;
+#[cfg(feature = "ui")]
+pub mod ui;
 
 #[doc(inline)]
 pub use api::*;
 #[doc(inline)]
 pub use font::Font;
 #[doc(inline)]
+pub use headless::{init_headless, is_headless, read_frame};
+#[doc(inline)]
 pub use render_2d::{LineEndStyle, LineJoinStyle};
 #[doc(inline)]
 pub use texture::Texture;
+#[cfg(feature = "gui")]
+pub(crate) use texture::TextureHandle;
 
+#[cfg(feature = "gui")]
+pub(crate) use graphics_state::present_mode;
 pub(crate) use graphics_state::GRAPHICS_STATE;
+pub(crate) use headless::headless_size;
 pub(crate) use render_2d::*;
 
 /// Useful default struct imports
@@ -4,10 +4,12 @@ use parking_lot::RwLock;
 use wgpu::{Buffer, Device, Queue};
 
 use crate::{
-    graphics::LineJoinStyle, math::{IntoFl, Vec2, Vec4}
+    graphics::LineJoinStyle, math::{Fl, IntoFl, Vec2, Vec4}
 };
 
-use super::{DrawCommand, DrawCommandData, LineEndStyle, Texture, Vertex2d, GRAPHICS_STATE};
+use super::{
+    DrawCommand, DrawCommandData, Font, LineEndStyle, Texture, Vertex2d, GRAPHICS_STATE,
+};
 
 /// Initialize the graphics library, must be called on the main thread!
 pub fn init() {
@@ -83,6 +85,36 @@ pub fn text(text: impl Display, pos: impl Into<Vec2>) {
     }
 }
 
+/// Get the font [text] uses, e.g. to use the same font in egui
+pub fn default_font() -> Font {
+    GRAPHICS_STATE.care_render.read().default_font.clone()
+}
+
+/// How wide a line of text drawn with [text] is
+pub(crate) fn text_width(text: &str) -> Fl {
+    let render = GRAPHICS_STATE.care_render.read();
+    render
+        .default_font
+        .0
+         .0
+        .layout(text, rusttype::Scale::uniform(18.0), rusttype::point(0.0, 0.0))
+        .last()
+        .map(|glyph| glyph.position().x + glyph.unpositioned().h_metrics().advance_width)
+        .unwrap_or(0.0)
+        .into_fl()
+}
+
+/// How tall a line of text drawn with [text] is
+pub(crate) fn text_height() -> Fl {
+    let render = GRAPHICS_STATE.care_render.read();
+    let metrics = render
+        .default_font
+        .0
+         .0
+        .v_metrics(rusttype::Scale::uniform(18.0));
+    (metrics.ascent - metrics.descent).into_fl()
+}
+
 #[inline(always)]
 /// Render a texture
 pub fn texture(tex: &Texture, pos: impl Into<Vec2>) {
@@ -358,6 +390,29 @@ fn upload_buffer(device: &Device, queue: &Queue, buffer_lock: &RwLock<Buffer>, d
     queue.write_buffer(&buffer, 0, data)
 }
 
+/// Get a view of the multisampled texture the 2D pipeline draws to when MSAA is on, remaking it
+/// if the screen's size changed
+fn msaa_view(screen: &wgpu::Texture, samples: u32) -> wgpu::TextureView {
+    let mut msaa_texture = GRAPHICS_STATE.msaa_texture.write();
+    if msaa_texture
+        .as_ref()
+        .is_none_or(|texture| texture.size() != screen.size())
+    {
+        *msaa_texture = Some(GRAPHICS_STATE.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("MSAA texture"),
+            size: screen.size(),
+            mip_level_count: 1,
+            sample_count: samples,
+            dimension: wgpu::TextureDimension::D2,
+            format: screen.format(),
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        }));
+    }
+    let texture = msaa_texture.as_ref().unwrap();
+    texture.create_view(&wgpu::TextureViewDescriptor::default())
+}
+
 /// Present the current frame
 pub fn present() {
     // Lets try render some stuff oh boy!
@@ -398,60 +453,36 @@ pub fn present() {
             .unwrap();
     }
 
-    let output_key = GRAPHICS_STATE.window_surfaces.keys().next().unwrap();
-    let output = GRAPHICS_STATE.window_surfaces[output_key]
-        .read()
-        .0
-        .get_current_texture();
-    let output = if let Ok(output) = output {
-        output
-    } else {
-        // Output is outdated, request a new surface...
-        let windows = crate::window::WINDOWS.read();
-        let win = windows
-            .iter()
-            .find(|w| w.id() == *output_key)
-            .cloned()
-            .unwrap();
-        let size = (win.inner_size().width, win.inner_size().height);
-        let mut output = GRAPHICS_STATE.window_surfaces[output_key].write();
-        *output = (
-            GRAPHICS_STATE
-                .instance
-                .create_surface(win)
-                .expect("Failed to create surface for window."),
-            size,
-        );
-
-        // Configure the new surface
-        let surface_caps = output.0.get_capabilities(&GRAPHICS_STATE.adapter);
-        let surface_format = surface_caps
-            .formats
-            .iter()
-            .copied()
-            .find(|f| f.is_srgb())
-            .unwrap_or(surface_caps.formats[0]);
-        let config = wgpu::SurfaceConfiguration {
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-            format: surface_format,
-            width: output.1 .0,
-            height: output.1 .1,
-            present_mode: surface_caps.present_modes[0],
-            desired_maximum_frame_latency: 10,
-            alpha_mode: surface_caps.alpha_modes[0],
-            view_formats: vec![],
-        };
-        output.0.configure(&GRAPHICS_STATE.device, &config);
-
-        output.0.get_current_texture().unwrap()
+    // Without a window, frames are drawn to an off-screen image, see init_headless
+    let output_key = GRAPHICS_STATE.window_surfaces.keys().next();
+    let output = output_key.map(|output_key| {
+        let output = GRAPHICS_STATE.window_surfaces[output_key]
+            .read()
+            .0
+            .get_current_texture();
+        if let Ok(output) = output {
+            output
+        } else {
+            // Output is outdated, request a new surface...
+            GRAPHICS_STATE.recreate_surface(*output_key);
+            GRAPHICS_STATE.window_surfaces[output_key]
+                .read()
+                .0
+                .get_current_texture()
+                .unwrap()
+        }
+    });
+    let target = match &output {
+        Some(output) => output.texture.clone(),
+        None => super::headless::headless_target(),
     };
 
-    let screen_size = output.texture.size();
+    let screen_size = target.size();
     let screen_size = Vec2::new(screen_size.width, screen_size.height);
+    let conf = crate::config::get();
+    let letterbox = conf.letterbox(screen_size);
 
-    let view = output
-        .texture
-        .create_view(&wgpu::TextureViewDescriptor::default());
+    let view = target.create_view(&wgpu::TextureViewDescriptor::default());
     let mut encoder =
         GRAPHICS_STATE
             .device
@@ -462,16 +493,19 @@ pub fn present() {
     let mut command_buffers = Vec::new();
     // Render egui
     #[cfg(feature = "gui")]
+    let mut viewport_output = Default::default();
+    #[cfg(feature = "gui")]
     let egui_data = {
         let mut egui_rend = GRAPHICS_STATE.egui.egui_renderer.lock();
-        if let Some(full_output) = crate::gui::get_full_output() {
+        if let Some(mut full_output) = crate::gui::get_full_output() {
+        viewport_output = std::mem::take(&mut full_output.viewport_output);
         let clipped_primitives = GRAPHICS_STATE
             .egui
             .egui_ctx
-            .tessellate(full_output.shapes, 1.0);
+            .tessellate(full_output.shapes, full_output.pixels_per_point);
         let egui_screen_descriptor = egui_wgpu::ScreenDescriptor {
-            size_in_pixels: [output.texture.size().width, output.texture.size().height],
-            pixels_per_point: 1.0,
+            size_in_pixels: [target.size().width, target.size().height],
+            pixels_per_point: full_output.pixels_per_point,
         };
         let mut egui_command_buffers = egui_rend.update_buffers(
             &GRAPHICS_STATE.device,
@@ -492,7 +526,14 @@ pub fn present() {
 
     // Render our stuff
     let max_textures = GRAPHICS_STATE.care_render.read().max_textures;
-    let draw_calls = GRAPHICS_STATE.care_render.write().render(screen_size);
+    let draw_calls = GRAPHICS_STATE
+        .care_render
+        .write()
+        .render(conf.virtual_resolution.unwrap_or(screen_size));
+    crate::debug::record_draw(
+        draw_calls.len(),
+        draw_calls.iter().map(|v| v.vertices.len()).sum(),
+    );
     let placeholder_tex = GRAPHICS_STATE.placeholder_texture.get().unwrap();
     let vertices: ForceAlign<Vec<Vertex2d>> = ForceAlign(
         draw_calls
@@ -520,10 +561,6 @@ pub fn present() {
         &GRAPHICS_STATE.index_buffer_2d,
         bytemuck::cast_slice(&indices.0),
     );
-    if vertices.0.is_empty() || indices.0.is_empty() {
-        GRAPHICS_STATE.care_render.write().reset();
-        return;
-    }
     let mut vstart: wgpu::BufferAddress = 0;
     let mut istart: wgpu::BufferAddress = 0;
     let draw_call_info: Vec<_> = draw_calls
@@ -568,13 +605,15 @@ pub fn present() {
         .collect();
     let vert = GRAPHICS_STATE.vertex_buffer_2d.read();
     let idx = GRAPHICS_STATE.index_buffer_2d.read();
+    let samples = GRAPHICS_STATE.msaa_samples;
+    let msaa_view = (samples > 1).then(|| msaa_view(&target, samples));
     // Render pass time
     {
         let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
             label: Some("2D Render Pass"),
             color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                view: &view,
-                resolve_target: None,
+                view: msaa_view.as_ref().unwrap_or(&view),
+                resolve_target: msaa_view.as_ref().map(|_| &view),
                 ops: wgpu::Operations {
                     load: wgpu::LoadOp::Clear(wgpu::Color {
                         r: 0.0,
@@ -589,6 +628,9 @@ pub fn present() {
             occlusion_query_set: None,
             timestamp_writes: None,
         });
+        if let Some((pos, size)) = letterbox {
+            render_pass.set_viewport(pos.x, pos.y, size.x, size.y, 0.0, 1.0);
+        }
         for (vrange, irange, bind_group, indices_count) in draw_call_info {
             render_pass.set_pipeline(&GRAPHICS_STATE.render_pipeline_2d);
             render_pass.set_bind_group(0, &bind_group, &[]);
@@ -631,7 +673,11 @@ pub fn present() {
     command_buffers.push(encoder.finish());
     GRAPHICS_STATE.queue.submit(command_buffers);
     std::thread::sleep(Duration::from_millis(2));
-    output.present();
+    if let Some(output) = output {
+        output.present();
+    }
+    #[cfg(feature = "gui")]
+    crate::gui::render_viewports(viewport_output);
 
     GRAPHICS_STATE.care_render.write().reset();
 }
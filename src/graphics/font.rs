@@ -1,10 +1,12 @@
-use std::{fmt::Debug, fs, path::Path, sync::Arc};
+use std::{borrow::Cow, fmt::Debug, path::Path, sync::Arc};
 
 use super::GRAPHICS_STATE;
 
 #[derive(Debug, Clone)]
 /// A font that can be used to display text
-pub struct Font(pub(crate) Arc<(rusttype::Font<'static>, u32)>);
+///
+/// The font file is kept too, so it can also be used by egui, see `gui::add_font`.
+pub struct Font(pub(crate) Arc<(rusttype::Font<'static>, u32, Cow<'static, [u8]>)>);
 
 fn next_font_id() -> u32 {
     let mut render = GRAPHICS_STATE.care_render.write();
@@ -16,13 +18,14 @@ fn next_font_id() -> u32 {
 impl Font {
     /// Create a new font from a font file
     pub fn new(file: impl AsRef<Path>) -> Self {
-        Font::new_from_vec(fs::read(file).unwrap())
+        Font::new_from_vec(crate::mobile::read_asset(file).unwrap())
     }
     /// Create a new font from raw data
     pub fn new_from_vec(bytes: Vec<u8>) -> Self {
         Font(Arc::new((
-            rusttype::Font::try_from_vec(bytes).unwrap(),
+            rusttype::Font::try_from_vec(bytes.clone()).unwrap(),
             next_font_id(),
+            Cow::Owned(bytes),
         )))
     }
     /// Create a new font from raw data
@@ -33,6 +36,7 @@ impl Font {
         Font(Arc::new((
             rusttype::Font::try_from_bytes(bytes).unwrap(),
             id,
+            Cow::Borrowed(bytes),
         )))
     }
 }
@@ -12,10 +12,43 @@ use winit::window::WindowId;
 
 use crate::math::{Mat3, Vec4};
 
-use super::{CareRenderState, Font, LineEndStyle, LineJoinStyle, Texture, Vertex2d};
+use super::{
+    headless::HEADLESS_FORMAT, CareRenderState, Font, LineEndStyle, LineJoinStyle, Texture,
+    Vertex2d,
+};
 
 pub type WindowSurface = RwLock<(Surface<'static>, (u32, u32))>;
 
+/// Pick how frames are shown, waiting for the display if vsync is on in the config
+pub(crate) fn present_mode(caps: &wgpu::SurfaceCapabilities) -> wgpu::PresentMode {
+    if crate::config::get().vsync {
+        // Every surface supports this
+        return wgpu::PresentMode::Fifo;
+    }
+    [wgpu::PresentMode::Immediate, wgpu::PresentMode::Mailbox]
+        .into_iter()
+        .find(|mode| caps.present_modes.contains(mode))
+        .unwrap_or(wgpu::PresentMode::Fifo)
+}
+
+/// The number of MSAA samples to draw with, which is the config's if the adapter supports it for
+/// `format`, or else 4 (or 1 if even that isn't supported), since wgpu panics on unsupported counts
+fn msaa_samples(adapter: &Adapter, format: wgpu::TextureFormat) -> u32 {
+    let requested = crate::config::get().msaa;
+    let flags = adapter.get_texture_format_features(format).flags;
+    let supported = |samples| {
+        samples == 1
+            || (flags.sample_count_supported(samples)
+                && flags.contains(wgpu::TextureFormatFeatureFlags::MULTISAMPLE_RESOLVE))
+    };
+    if supported(requested) {
+        return requested;
+    }
+    let fallback = if supported(4) { 4 } else { 1 };
+    eprintln!("MSAA with {requested} samples isn't supported, using {fallback} instead");
+    fallback
+}
+
 #[derive(Debug)]
 pub(crate) struct GraphicsState {
     pub instance: Instance,
@@ -24,6 +57,12 @@ pub(crate) struct GraphicsState {
     pub queue: Arc<Queue>,
     pub window_surfaces: HashMap<WindowId, WindowSurface>,
     pub render_pipeline_2d: RenderPipeline,
+    /// How many samples per pixel the 2D pipeline draws with, see [msaa_samples]
+    pub msaa_samples: u32,
+    /// The texture the 2D pipeline draws to before it's resolved to the screen, when MSAA is on
+    pub msaa_texture: RwLock<Option<wgpu::Texture>>,
+    /// The off-screen image frames are drawn to instead of a window, see [super::init_headless]
+    pub headless_target: RwLock<Option<wgpu::Texture>>,
     pub vertex_buffer_2d: RwLock<Buffer>,
     pub index_buffer_2d: RwLock<Buffer>,
     pub bind_group_layout_2d: wgpu::BindGroupLayout,
@@ -35,10 +74,7 @@ pub(crate) struct GraphicsState {
 
 impl GraphicsState {
     pub(crate) fn new() -> Self {
-        let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor {
-            backends: wgpu::Backends::all(),
-            ..Default::default()
-        });
+        let instance = crate::gpu::instance();
 
         #[cfg(feature = "window")]
         let window_surfaces: HashMap<_, _> = crate::window::WINDOWS
@@ -63,21 +99,18 @@ impl GraphicsState {
 
         let adapter = {
             let surface = window_surfaces.values().next().map(|surf| surf.read());
-            instance
-                .request_adapter(&wgpu::RequestAdapterOptions {
-                    power_preference: wgpu::PowerPreference::HighPerformance,
-                    force_fallback_adapter: false,
-                    compatible_surface: surface.as_ref().map(|s| &s.0),
-                })
-                .block_on()
-                .expect("No graphics adapter found")
+            crate::gpu::adapter(&instance, surface.as_ref().map(|s| &s.0))
         };
         let (device, queue) = adapter
             .request_device(
                 &wgpu::DeviceDescriptor {
                     label: Some("Care render device"),
                     required_features: wgpu::Features::default()
-                        | wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES,
+                        | wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES
+                        // Used to time compute dispatches, where supported
+                        | (adapter.features()
+                            & (wgpu::Features::TIMESTAMP_QUERY
+                                | wgpu::Features::TIMESTAMP_QUERY_INSIDE_ENCODERS)),
                     required_limits: wgpu::Limits::downlevel_defaults(),
                     memory_hints: wgpu::MemoryHints::default(),
                 },
@@ -102,7 +135,7 @@ impl GraphicsState {
                 format: surface_format,
                 width: surf.1 .0,
                 height: surf.1 .1,
-                present_mode: surface_caps.present_modes[0],
+                present_mode: present_mode(&surface_caps),
                 desired_maximum_frame_latency: 10,
                 alpha_mode: surface_caps.alpha_modes[0],
                 view_formats: vec![],
@@ -117,7 +150,7 @@ impl GraphicsState {
             current_transform: Mat3::ident(),
             current_colour: Vec4::new(1, 1, 1, 1),
             // TODO: How do render textures / canvases relate to surfaces?
-            current_surface: *window_surfaces.keys().next().unwrap(),
+            current_surface: window_surfaces.keys().next().copied(),
             commands: Vec::new(),
             max_textures: (limits.max_bindings_per_bind_group / 2)
                 .min(limits.max_sampled_textures_per_shader_stage)
@@ -133,7 +166,7 @@ impl GraphicsState {
             line_end_style: LineEndStyle::Rounded,
         };
 
-        let (render_pipeline_2d, vertex_buffer_2d, index_buffer_2d, bind_group_layouts_2d, surface_format) = {
+        let (render_pipeline_2d, vertex_buffer_2d, index_buffer_2d, bind_group_layouts_2d, surface_format, msaa_samples) = {
             let vertex_buffer = device.create_buffer(&wgpu::BufferDescriptor {
                 label: Some("2D Vertex Buffer"),
                 size: 1024,
@@ -188,7 +221,10 @@ impl GraphicsState {
                 });
             // TODO: uhhh this is sometimes BGRA on some computers I have... I probably
             // should find a function that gives me the colour space of the surface
-            let surface_format = surface_formats[&render.current_surface];
+            let surface_format = render
+                .current_surface
+                .map_or(HEADLESS_FORMAT, |surface| surface_formats[&surface]);
+            let msaa_samples = msaa_samples(&adapter, surface_format);
             let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
                 label: Some("2D Render Pipeline"),
                 layout: Some(&render_pipeline_layout),
@@ -219,7 +255,7 @@ impl GraphicsState {
                 },
                 depth_stencil: None,
                 multisample: wgpu::MultisampleState {
-                    count: 1,
+                    count: msaa_samples,
                     mask: !0,
                     alpha_to_coverage_enabled: false,
                 },
@@ -232,13 +268,20 @@ impl GraphicsState {
                 RwLock::new(index_buffer),
                 textures_bind_group_layout,
                 surface_format,
+                msaa_samples,
             )
         };
 
         #[cfg(feature = "gui")]
         let egui = crate::gui::EguiGraphics {
             egui_renderer: parking_lot::Mutex::new(egui_wgpu::Renderer::new(&device, surface_format, None, 1, false)),
-            egui_ctx: egui::Context::default(),
+            egui_ctx: {
+                // Deferred viewports get their own windows, see `gui::render_viewports`
+                let ctx = egui::Context::default();
+                ctx.set_embed_viewports(false);
+                ctx
+            },
+            surface_format,
             start_time: std::time::Instant::now(),
         };
 
@@ -249,6 +292,9 @@ impl GraphicsState {
             queue: Arc::new(queue),
             window_surfaces,
             render_pipeline_2d,
+            msaa_samples,
+            msaa_texture: RwLock::new(None),
+            headless_target: RwLock::new(None),
             vertex_buffer_2d,
             index_buffer_2d,
             bind_group_layout_2d: bind_group_layouts_2d,
@@ -259,6 +305,50 @@ impl GraphicsState {
             egui,
         }
     }
+
+    /// Make a new surface for a window, e.g. when the old one is outdated or was lost while the
+    /// app was suspended
+    pub(crate) fn recreate_surface(&self, window_id: WindowId) {
+        let Some(surface) = self.window_surfaces.get(&window_id) else {
+            return;
+        };
+        let Some(win) = crate::window::WINDOWS
+            .read()
+            .iter()
+            .find(|w| w.id() == window_id)
+            .cloned()
+        else {
+            return;
+        };
+        let size = (win.inner_size().width, win.inner_size().height);
+        let mut output = surface.write();
+        *output = (
+            self.instance
+                .create_surface(win)
+                .expect("Failed to create surface for window."),
+            size,
+        );
+
+        // Configure the new surface
+        let surface_caps = output.0.get_capabilities(&self.adapter);
+        let surface_format = surface_caps
+            .formats
+            .iter()
+            .copied()
+            .find(|f| f.is_srgb())
+            .unwrap_or(surface_caps.formats[0]);
+        let config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format: surface_format,
+            width: output.1 .0,
+            height: output.1 .1,
+            present_mode: present_mode(&surface_caps),
+            desired_maximum_frame_latency: 10,
+            alpha_mode: surface_caps.alpha_modes[0],
+            view_formats: vec![],
+        };
+        output.0.configure(&self.device, &config);
+    }
 }
 
 pub(crate) static GRAPHICS_STATE: LazyLock<GraphicsState> = LazyLock::new(GraphicsState::new);
@@ -0,0 +1,114 @@
+use image::RgbaImage;
+use parking_lot::Mutex;
+
+use crate::math::Vec2;
+
+use super::GRAPHICS_STATE;
+
+/// The format of the off-screen image, which is what the pipeline draws in when headless
+pub(crate) const HEADLESS_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+/// The size of the off-screen image in pixels, set while headless
+static HEADLESS_SIZE: Mutex<Option<(u32, u32)>> = Mutex::new(None);
+
+/// Draw to an off-screen image of some size instead of a window, which is read with [read_frame]
+///
+/// Call this instead of opening a window, e.g. in tests or on a server without a display. It can
+/// be called again later to change the size.
+pub fn init_headless(size: impl Into<Vec2>) {
+    let size = size.into();
+    *HEADLESS_SIZE.lock() = Some((size.x.round() as u32, size.y.round() as u32));
+}
+
+/// Get whether care is drawing to an off-screen image, see [init_headless]
+pub fn is_headless() -> bool {
+    HEADLESS_SIZE.lock().is_some()
+}
+
+/// Get the size of the off-screen image, if there is one
+pub(crate) fn headless_size() -> Option<Vec2> {
+    HEADLESS_SIZE
+        .lock()
+        .map(|(width, height)| Vec2::new(width, height))
+}
+
+/// Get the off-screen image frames are drawn to, remaking it if the size changed
+pub(crate) fn headless_target() -> wgpu::Texture {
+    let (width, height) = HEADLESS_SIZE
+        .lock()
+        .expect("There's no window to draw to, open one or call graphics::init_headless first");
+    let size = wgpu::Extent3d {
+        width,
+        height,
+        depth_or_array_layers: 1,
+    };
+    let mut target = GRAPHICS_STATE.headless_target.write();
+    if target.as_ref().is_none_or(|texture| texture.size() != size) {
+        *target = Some(
+            GRAPHICS_STATE
+                .device
+                .create_texture(&wgpu::TextureDescriptor {
+                    label: Some("Headless texture"),
+                    size,
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    format: HEADLESS_FORMAT,
+                    usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+                    view_formats: &[],
+                }),
+        );
+    }
+    target.clone().unwrap()
+}
+
+/// Read back the last frame drawn to the off-screen image, see [init_headless]
+///
+/// This waits for the GPU to finish drawing it, so it's slow.
+pub fn read_frame() -> RgbaImage {
+    let texture = headless_target();
+    let size = texture.size();
+    // Rows of the copy have to be aligned, the padding is cut off again below
+    let row_bytes = size.width * 4;
+    let padded_row_bytes = row_bytes.next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+    let buffer = GRAPHICS_STATE
+        .device
+        .create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Headless readback buffer"),
+            size: (padded_row_bytes * size.height) as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+    let mut encoder =
+        GRAPHICS_STATE
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Headless readback encoder"),
+            });
+    encoder.copy_texture_to_buffer(
+        texture.as_image_copy(),
+        wgpu::TexelCopyBufferInfo {
+            buffer: &buffer,
+            layout: wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(padded_row_bytes),
+                rows_per_image: Some(size.height),
+            },
+        },
+        size,
+    );
+    GRAPHICS_STATE.queue.submit([encoder.finish()]);
+
+    let slice = buffer.slice(..);
+    slice.map_async(wgpu::MapMode::Read, |result| {
+        result.expect("Failed to read back the frame")
+    });
+    GRAPHICS_STATE.device.poll(wgpu::Maintain::Wait);
+    let data = slice.get_mapped_range();
+    let pixels = data
+        .chunks(padded_row_bytes as usize)
+        .flat_map(|row| &row[..row_bytes as usize])
+        .copied()
+        .collect();
+    RgbaImage::from_raw(size.width, size.height, pixels).expect("Read back the wrong size")
+}
@@ -120,7 +120,7 @@ pub(crate) struct CareRenderState {
     pub transform_stack: Vec<Mat3>,
     pub current_transform: Mat3,
     pub current_colour: Vec4,
-    pub current_surface: WindowId,
+    pub current_surface: Option<WindowId>,
     pub commands: Vec<DrawCommand>,
     pub max_textures: usize,
     pub font_cache: FontCache<'static>,
@@ -1,4 +1,9 @@
-use std::{fmt::Debug, io::Cursor, path::Path, sync::Arc};
+use std::{
+    fmt::Debug,
+    io::Cursor,
+    path::Path,
+    sync::{atomic::Ordering, Arc},
+};
 
 use image::{DynamicImage, EncodableLayout, ImageFormat, ImageReader, RgbaImage};
 
@@ -17,9 +22,14 @@ impl PartialEq for Texture {
 }
 
 impl Texture {
-    /// Create a new texture by loading an image from the filesystem
+    /// Create a new texture by loading an image from the filesystem, or from the app's assets on
+    /// Android
     pub fn new(filename: impl AsRef<Path>) -> Self {
-        Self::new_from_image(ImageReader::open(filename).unwrap().decode().unwrap())
+        let filename = filename.as_ref();
+        Self::new_from_file_format(
+            &crate::mobile::read_asset(filename).unwrap(),
+            ImageFormat::from_path(filename).ok(),
+        )
     }
     /// Creates a new texture by loading an image from encoded image data of an optionally specified format.
     pub fn new_from_file_format(file_data: &[u8], format_hint: Option<ImageFormat>) -> Self {
@@ -54,6 +64,10 @@ impl Texture {
             height,
             depth_or_array_layers: 1,
         };
+        let usage = wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST;
+        // So the texture can be copied into a compute binding, see `compute::get_binding_from_texture`
+        #[cfg(feature = "compute")]
+        let usage = usage | wgpu::TextureUsages::COPY_SRC;
         let texture = GRAPHICS_STATE.device.create_texture(&wgpu::TextureDescriptor {
             label: None,
             size,
@@ -61,7 +75,7 @@ impl Texture {
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
             format: wgpu::TextureFormat::Rgba8Unorm,
-            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            usage,
             view_formats: &[],
         });
         GRAPHICS_STATE.queue.write_texture(
@@ -89,6 +103,7 @@ impl Texture {
             mipmap_filter: wgpu::FilterMode::Nearest,
             ..Default::default()
         });
+        crate::debug::TEXTURE_MEMORY.fetch_add(memory(&texture), Ordering::Relaxed);
         Texture(Arc::new(TextureHandle {
             size: Vec2::new(width, height),
             texture: Arc::new(texture),
@@ -107,6 +122,7 @@ impl Texture {
             mipmap_filter: wgpu::FilterMode::Nearest,
             ..Default::default()
         });
+        crate::debug::TEXTURE_MEMORY.fetch_add(memory(&texture), Ordering::Relaxed);
         Texture(Arc::new(TextureHandle {
             size: Vec2::new(texture.width(), texture.height()),
             texture,
@@ -169,3 +185,15 @@ impl TextureHandle {
         ]
     }
 }
+
+impl Drop for TextureHandle {
+    fn drop(&mut self) {
+        crate::debug::TEXTURE_MEMORY.fetch_sub(memory(&self.texture), Ordering::Relaxed);
+    }
+}
+
+/// Roughly how much memory a texture uses, for [crate::debug::stats]
+fn memory(texture: &wgpu::Texture) -> u64 {
+    let bytes_per_pixel = texture.format().block_copy_size(None).unwrap_or(4) as u64;
+    texture.width() as u64 * texture.height() as u64 * bytes_per_pixel
+}
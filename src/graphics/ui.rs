@@ -0,0 +1,429 @@
+//! A few simple immediate mode widgets, drawn with care's own graphics and driven by the
+//! [crate::mouse] and [crate::keyboard] modules, for games that don't need [egui](crate::gui)
+//!
+//! Widgets are drawn and handled by calling their function every frame, the ones that can be
+//! interacted with return whether they were clicked or changed. They're identified by their
+//! label, so every widget that's drawn in the same frame needs a different one.
+//!
+//! Widgets can also be used without a mouse, with the inputs from [crate::navigation] (e.g. the
+//! arrow keys and enter, or a gamepad). Moving selects a widget, confirming uses it like a click
+//! and left and right change the selected slider.
+//!
+//! ```ignore
+//! #[care::draw]
+//! fn draw() {
+//!     care::graphics::ui::label("Settings", (20, 20));
+//!     care::graphics::ui::slider("Volume", (20, 50), 200, &mut volume, 0.0..=1.0);
+//!     care::graphics::ui::checkbox("Fullscreen", (20, 90), &mut fullscreen);
+//!     care::graphics::ui::text_box("Name", (20, 130), 200, &mut name);
+//!     if care::graphics::ui::button("Play", (20, 170)) {
+//!         start_game();
+//!     }
+//! }
+//! ```
+//!
+//! Positions are in screen coordinates, so widgets should be drawn without a transform.
+
+use std::{
+    hash::{DefaultHasher, Hash, Hasher},
+    ops::RangeInclusive,
+    sync::LazyLock,
+};
+
+use parking_lot::Mutex;
+
+use crate::{
+    keyboard::{self, Key},
+    math::{Fl, IntoFl, Vec2, Vec4},
+    mouse,
+    navigation::{self, Nav},
+};
+
+use super::{text_height, text_width, GRAPHICS_STATE};
+
+/// The mouse button that interacts with widgets
+const BUTTON: i32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// The colours and spacing used to draw widgets, see [set_style]
+pub struct Style {
+    /// The colour of text
+    pub text: Vec4,
+    /// The colour of placeholder text in empty text boxes
+    pub placeholder: Vec4,
+    /// The background of widgets
+    pub background: Vec4,
+    /// The background of widgets under the mouse
+    pub hovered: Vec4,
+    /// The background of widgets being clicked or typed in
+    pub active: Vec4,
+    /// The colour of checkmarks, filled parts of sliders and text cursors
+    pub accent: Vec4,
+    /// The space between the edges of widgets and their text
+    pub padding: Fl,
+    /// The radius of the corners of widgets
+    pub corner_radius: Fl,
+}
+
+impl Default for Style {
+    fn default() -> Self {
+        Self {
+            text: Vec4::new(1, 1, 1, 1),
+            placeholder: Vec4::new(0.6, 0.6, 0.6, 1),
+            background: Vec4::new(0.2, 0.2, 0.2, 1),
+            hovered: Vec4::new(0.3, 0.3, 0.3, 1),
+            active: Vec4::new(0.15, 0.15, 0.15, 1),
+            accent: Vec4::new(0.3, 0.5, 0.9, 1),
+            padding: 6.0,
+            corner_radius: 4.0,
+        }
+    }
+}
+
+#[derive(Debug)]
+/// A widget that was drawn this frame
+struct Widget {
+    id: u64,
+    pos: Vec2,
+    size: Vec2,
+    /// Left and right change the widget, instead of moving to another one
+    adjustable: bool,
+}
+
+#[derive(Debug, Default)]
+struct UiState {
+    style: Style,
+    /// The widget the mouse button was pressed on, until it's released
+    active: Option<u64>,
+    /// The text box being typed in
+    focused: Option<u64>,
+    /// The widget selected with navigation inputs
+    selected: Option<u64>,
+    /// The widgets drawn this frame, in order, for moving the selection between them
+    widgets: Vec<Widget>,
+}
+
+static UI_STATE: LazyLock<Mutex<UiState>> = LazyLock::new(|| Mutex::new(UiState::default()));
+
+/// Set the colours and spacing used to draw widgets
+pub fn set_style(style: Style) {
+    UI_STATE.lock().style = style;
+}
+
+/// Get the colours and spacing used to draw widgets
+pub fn get_style() -> Style {
+    UI_STATE.lock().style
+}
+
+fn id(label: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    label.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// How the mouse or navigation inputs are interacting with a widget this frame
+struct Interaction {
+    hovered: bool,
+    active: bool,
+    clicked: bool,
+    selected: bool,
+}
+
+fn interact(id: u64, pos: Vec2, size: Vec2, adjustable: bool) -> Interaction {
+    let mouse = mouse::get_position();
+    let hovered =
+        mouse.x >= pos.x && mouse.y >= pos.y && mouse.x < pos.x + size.x && mouse.y < pos.y + size.y;
+    let mut state = UI_STATE.lock();
+    state.widgets.push(Widget {
+        id,
+        pos,
+        size,
+        adjustable,
+    });
+    if hovered && mouse::is_pressed(BUTTON) {
+        state.active = Some(id);
+    }
+    let active = state.active == Some(id);
+    let selected = state.selected == Some(id);
+    // A text box being typed in uses enter and space itself
+    let confirmed = selected && state.focused != Some(id) && navigation::is_pressed(Nav::Confirm);
+    let clicked = (active && hovered && mouse::is_released(BUTTON)) || confirmed;
+    if active && !mouse::is_down(BUTTON) {
+        state.active = None;
+    }
+    Interaction {
+        hovered,
+        active,
+        clicked,
+        selected,
+    }
+}
+
+/// How far a widget is in a direction from another one, for picking the closest one
+fn distance_in_direction(from: &Widget, to: &Widget, direction: (Fl, Fl)) -> Option<Fl> {
+    let offset = (to.pos + to.size / 2.0) - (from.pos + from.size / 2.0);
+    let along = offset.x * direction.0 + offset.y * direction.1;
+    let across = (offset.x * direction.1 - offset.y * direction.0).abs();
+    // Widgets off to the side count as further away
+    (along > 0.0).then_some(along + across * 2.0)
+}
+
+/// Move the selection with this frame's navigation inputs, before the next frame's widgets are
+/// drawn
+pub(crate) fn end_frame() {
+    let mut state = UI_STATE.lock();
+    let widgets = std::mem::take(&mut state.widgets);
+    // A text box that wasn't drawn this frame can't be typed in anymore
+    if state
+        .focused
+        .is_some_and(|id| !widgets.iter().any(|widget| widget.id == id))
+    {
+        state.focused = None;
+    }
+    // Typing in a text box uses the keys that would navigate
+    if state.focused.is_some() || widgets.is_empty() {
+        return;
+    }
+    if navigation::is_pressed(Nav::Cancel) {
+        state.selected = None;
+        return;
+    }
+    let directions = [
+        (Nav::Up, (0.0, -1.0)),
+        (Nav::Down, (0.0, 1.0)),
+        (Nav::Left, (-1.0, 0.0)),
+        (Nav::Right, (1.0, 0.0)),
+    ];
+    let current = state
+        .selected
+        .and_then(|id| widgets.iter().position(|widget| widget.id == id));
+    let next = match current {
+        // Nothing is selected yet, so start at one end
+        None if navigation::is_pressed(Nav::Previous) => widgets.len() - 1,
+        None if navigation::is_pressed(Nav::Next)
+            || directions.iter().any(|(nav, _)| navigation::is_pressed(*nav)) =>
+        {
+            0
+        }
+        None => return,
+        Some(i) if navigation::is_pressed(Nav::Next) => (i + 1) % widgets.len(),
+        Some(i) if navigation::is_pressed(Nav::Previous) => {
+            (i + widgets.len() - 1) % widgets.len()
+        }
+        Some(i) => {
+            let Some(&(_, direction)) = directions.iter().find(|(nav, direction)| {
+                navigation::is_pressed(*nav) && !(widgets[i].adjustable && direction.0 != 0.0)
+            }) else {
+                return;
+            };
+            widgets
+                .iter()
+                .enumerate()
+                .filter_map(|(j, widget)| {
+                    Some((j, distance_in_direction(&widgets[i], widget, direction)?))
+                })
+                .min_by(|(_, a), (_, b)| a.total_cmp(b))
+                .map_or(i, |(j, _)| j)
+        }
+    };
+    state.selected = Some(widgets[next].id);
+}
+
+/// Draw with the widget colours, putting the current colour back afterwards
+fn draw(draw: impl FnOnce(&Style)) {
+    let colour = GRAPHICS_STATE.care_render.read().current_colour;
+    draw(&get_style());
+    super::set_colour(colour);
+}
+
+fn background(style: &Style, interaction: &Interaction, pos: Vec2, size: Vec2) {
+    super::set_colour(if interaction.active {
+        style.active
+    } else if interaction.hovered || interaction.selected {
+        style.hovered
+    } else {
+        style.background
+    });
+    super::rectangle_rounded(pos, size, 0, [style.corner_radius; 4]);
+    if interaction.selected {
+        super::set_colour(style.accent);
+        super::rectangle_line(pos - Vec2::new(2, 2), size + Vec2::new(4, 4), 2);
+    }
+}
+
+/// Draw some text in the widget text colour
+pub fn label(text: &str, pos: impl Into<Vec2>) {
+    let pos = pos.into();
+    draw(|style| {
+        super::set_colour(style.text);
+        super::text(text, pos);
+    });
+}
+
+/// Draw a button, returning true if it was clicked
+pub fn button(label: &str, pos: impl Into<Vec2>) -> bool {
+    let pos = pos.into();
+    let style = get_style();
+    let size = Vec2::new(
+        text_width(label) + style.padding * 2.0,
+        text_height() + style.padding * 2.0,
+    );
+    let interaction = interact(id(label), pos, size, false);
+    draw(|style| {
+        background(style, &interaction, pos, size);
+        super::set_colour(style.text);
+        super::text(label, pos + Vec2::new(style.padding, style.padding));
+    });
+    interaction.clicked
+}
+
+/// Draw a checkbox with a label next to it, returning true if it was toggled
+pub fn checkbox(label: &str, pos: impl Into<Vec2>, value: &mut bool) -> bool {
+    let pos = pos.into();
+    let style = get_style();
+    let height = text_height();
+    let size = Vec2::new(height + style.padding + text_width(label), height);
+    let interaction = interact(id(label), pos, size, false);
+    if interaction.clicked {
+        *value = !*value;
+    }
+    draw(|style| {
+        background(style, &interaction, pos, Vec2::new(height, height));
+        if *value {
+            let inset = height / 4.0;
+            super::set_colour(style.accent);
+            super::rectangle_rounded(
+                pos + Vec2::new(inset, inset),
+                Vec2::new(height - inset * 2.0, height - inset * 2.0),
+                0,
+                [style.corner_radius / 2.0; 4],
+            );
+        }
+        super::set_colour(style.text);
+        super::text(label, pos + Vec2::new(height + style.padding, 0));
+    });
+    interaction.clicked
+}
+
+/// Draw a slider for picking a number in a range, returning true if it was changed
+///
+/// The label and the current value are shown on the slider.
+pub fn slider(
+    label: &str,
+    pos: impl Into<Vec2>,
+    width: impl IntoFl,
+    value: &mut Fl,
+    range: RangeInclusive<Fl>,
+) -> bool {
+    let pos = pos.into();
+    let style = get_style();
+    let size = Vec2::new(width, text_height() + style.padding * 2.0);
+    let interaction = interact(id(label), pos, size, true);
+    let (min, max) = (*range.start(), *range.end());
+    let old = *value;
+    if interaction.active && max > min {
+        let amount = ((mouse::get_position().x - pos.x) / size.x).clamp(0.0, 1.0);
+        *value = min + (max - min) * amount;
+    }
+    if interaction.selected {
+        let step = (max - min) / 20.0;
+        if navigation::is_pressed(Nav::Left) {
+            *value = (*value - step).max(min);
+        }
+        if navigation::is_pressed(Nav::Right) {
+            *value = (*value + step).min(max);
+        }
+    }
+    let filled = if max > min {
+        ((*value - min) / (max - min)).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    draw(|style| {
+        background(style, &interaction, pos, size);
+        if filled > 0.0 {
+            super::set_colour(style.accent);
+            super::rectangle_rounded(
+                pos,
+                Vec2::new(size.x * filled, size.y),
+                0,
+                [style.corner_radius; 4],
+            );
+        }
+        super::set_colour(style.text);
+        super::text(
+            format!("{label}: {value:.2}"),
+            pos + Vec2::new(style.padding, style.padding),
+        );
+    });
+    *value != old
+}
+
+/// Draw a single line text box, returning true if the text was changed
+///
+/// Clicking on the text box lets the player type in it, until they press enter or escape or
+/// click somewhere else. The label is shown when the text box is empty.
+pub fn text_box(label: &str, pos: impl Into<Vec2>, width: impl IntoFl, text: &mut String) -> bool {
+    let pos = pos.into();
+    let style = get_style();
+    let size = Vec2::new(width, text_height() + style.padding * 2.0);
+    let id = id(label);
+    let interaction = interact(id, pos, size, false);
+    let focused = {
+        let mut state = UI_STATE.lock();
+        if interaction.clicked {
+            state.focused = Some(id);
+        } else if state.focused == Some(id)
+            && ((mouse::is_pressed(BUTTON) && !interaction.hovered)
+                || keyboard::is_pressed(Key::Enter)
+                || keyboard::is_pressed(Key::Escape))
+        {
+            state.focused = None;
+        }
+        state.focused == Some(id)
+    };
+
+    let old_len = text.len();
+    let mut changed = false;
+    if focused {
+        if keyboard::is_pressed(Key::Backspace) {
+            changed |= text.pop().is_some();
+        }
+        text.push_str(&keyboard::get_text());
+        changed |= text.len() != old_len;
+    }
+
+    // Only the end of the text is shown if it's too long to fit
+    let inner_width = size.x - style.padding * 2.0;
+    let mut shown = text.as_str();
+    while text_width(shown) > inner_width {
+        let mut chars = shown.chars();
+        chars.next();
+        shown = chars.as_str();
+    }
+    let cursor = text_width(shown);
+    draw(|style| {
+        background(
+            style,
+            &Interaction {
+                active: focused,
+                ..interaction
+            },
+            pos,
+            size,
+        );
+        let text_pos = pos + Vec2::new(style.padding, style.padding);
+        if text.is_empty() && !focused {
+            super::set_colour(style.placeholder);
+            super::text(label, text_pos);
+        } else {
+            super::set_colour(style.text);
+            super::text(shown, text_pos);
+        }
+        if focused {
+            super::set_colour(style.accent);
+            super::rectangle(text_pos + Vec2::new(cursor + 1.0, 0), (2, text_height()));
+        }
+    });
+    changed
+}
@@ -1,4 +1,8 @@
-use std::sync::LazyLock;
+use std::collections::HashMap;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, LazyLock,
+};
 use std::time::Instant;
 
 use parking_lot::Mutex;
@@ -7,11 +11,22 @@ pub use egui::*;
 
 use crate::event::{Event as CareEvent, EventData as CareEventData};
 use crate::keyboard::{self, Key as CareKey};
-use crate::window::window_size;
+use crate::window::{scale_factor, window_size};
+
+mod inspect;
+mod texture;
+pub use inspect::*;
+pub use texture::*;
+/// Derive [Inspect] for a struct, showing each of its fields in a collapsible section
+///
+/// Fields marked with `#[inspect(skip)]` are left out, every other field has to implement
+/// [Inspect] too.
+pub use care_macro::Inspect;
 
 pub(crate) struct EguiGraphics {
     pub egui_ctx: egui::Context,
     pub egui_renderer: Mutex<egui_wgpu::Renderer>,
+    pub surface_format: wgpu::TextureFormat,
     pub start_time: Instant,
 }
 
@@ -41,6 +56,7 @@ impl std::fmt::Debug for EguiState {
 
 pub(crate) static EGUI_STATE: LazyLock<Mutex<EguiState>> =
     LazyLock::new(|| Mutex::new(EguiState::default()));
+static CAPTURE_INPUT: AtomicBool = AtomicBool::new(true);
 
 pub(crate) fn process_event(event: CareEvent) {
     let mut events = match event.data {
@@ -65,12 +81,14 @@ pub(crate) fn process_event(event: CareEvent) {
             })
             .collect(),
         CareEventData::MouseMoved { position } => {
-            vec![Event::PointerMoved(Pos2::new(position.x, position.y))]
+            vec![Event::PointerMoved(
+                Pos2::new(position.x, position.y) / get_scale(),
+            )]
         }
         CareEventData::MouseClick { button, pressed } => {
-            let pos = crate::mouse::get_position();
+            let pos = crate::mouse::window_position();
             vec![Event::PointerButton {
-                pos: Pos2::new(pos.x, pos.y),
+                pos: Pos2::new(pos.x, pos.y) / get_scale(),
                 button: match button {
                     1 => PointerButton::Primary,
                     2 => PointerButton::Secondary,
@@ -83,7 +101,16 @@ pub(crate) fn process_event(event: CareEvent) {
             }]
         }
         CareEventData::TextEvent { text } => vec![Event::Text(text.replace(['\x7f', '\x08'], ""))],
+        CareEventData::MouseScroll { delta } => vec![Event::MouseWheel {
+            unit: MouseWheelUnit::Line,
+            delta: vec2(delta.x, delta.y),
+            modifiers: get_modifiers(),
+        }],
         CareEventData::FocusChange { focused } => vec![Event::WindowFocused(focused)],
+        CareEventData::FileDropped { .. }
+        | CareEventData::WindowResized { .. }
+        | CareEventData::Touch { .. }
+        | CareEventData::Suspend { .. } => vec![],
     };
     EGUI_STATE.lock().egui_events.append(&mut events);
 }
@@ -93,12 +120,14 @@ pub(crate) fn get_full_output() -> Option<FullOutput> {
 }
 
 pub(crate) fn get_modifiers() -> Modifiers {
+    // Not `keyboard::is_down`, since that's hidden while egui is using the keyboard
+    let held = &keyboard::get_state().read().held;
     Modifiers {
-        alt: keyboard::is_down(keyboard::Key::Alt),
-        ctrl: keyboard::is_down(keyboard::Key::Control),
-        shift: keyboard::is_down(keyboard::Key::Shift),
-        mac_cmd: keyboard::is_down(keyboard::Key::Meta),
-        command: keyboard::is_down(keyboard::Key::Meta),
+        alt: held.contains(&keyboard::Key::Alt),
+        ctrl: held.contains(&keyboard::Key::Control),
+        shift: held.contains(&keyboard::Key::Shift),
+        mac_cmd: held.contains(&keyboard::Key::Meta),
+        command: held.contains(&keyboard::Key::Meta),
     }
 }
 
@@ -111,32 +140,545 @@ pub(crate) fn get_events() -> Vec<Event> {
 /// **IMPORTANT**: Only call this function once per frame
 ///
 /// This gives you an Egui [Context] that you can use to render widgets
-pub fn gui<'a>(call: impl FnMut(&egui::Context) + 'a) {
+///
+/// Deferred viewports ([Context::show_viewport_deferred]) are shown in their own windows, e.g.
+/// to pop out an inspector. Immediate viewports are shown inside the main window instead.
+pub fn gui<'a>(mut call: impl FnMut(&egui::Context) + 'a) {
     let window_size = window_size();
     let egui_state = &crate::graphics::GRAPHICS_STATE.egui;
     let full_output = egui_state.egui_ctx.run(
         egui::RawInput {
             viewport_id: egui::ViewportId::ROOT,
-            viewports: [(egui::ViewportId::ROOT, egui::ViewportInfo::default())]
-                .into_iter()
-                .collect(),
+            viewports: [(
+                egui::ViewportId::ROOT,
+                egui::ViewportInfo {
+                    native_pixels_per_point: Some(scale_factor() as f32),
+                    ..Default::default()
+                },
+            )]
+            .into_iter()
+            .collect(),
             screen_rect: Some(egui::Rect::from_min_max(
                 egui::Pos2::ZERO,
-                egui::Pos2::new(window_size.x, window_size.y),
+                egui::Pos2::new(window_size.x, window_size.y) / get_scale(),
             )),
             max_texture_side: None,
             time: Some(egui_state.start_time.elapsed().as_secs_f64()),
             predicted_dt: 1.0 / 60.0,
             modifiers: crate::gui::get_modifiers(),
-            events: crate::gui::get_events(),
+            events: crate::gui::get_events()
+                .into_iter()
+                .chain(navigation_events(&egui_state.egui_ctx))
+                .collect(),
             hovered_files: Vec::new(),
             dropped_files: Vec::new(),
             focused: true,
             system_theme: None,
         },
-        call,
+        |ctx| {
+            call(ctx);
+            crate::debug::draw_egui(ctx);
+            inspect::keep_inspector_open(ctx);
+        },
     );
     EGUI_STATE.lock().full_output = Some(full_output);
+    let capture = CAPTURE_INPUT.load(Ordering::Relaxed);
+    crate::mouse::set_captured(capture && wants_pointer());
+    keyboard::set_captured(keyboard::Capture::Gui, capture && wants_keyboard());
+}
+
+/// Get whether egui is using the mouse, because it's over a gui window or dragging a widget
+pub fn wants_pointer() -> bool {
+    crate::graphics::GRAPHICS_STATE
+        .egui
+        .egui_ctx
+        .wants_pointer_input()
+}
+
+/// Get whether egui is using the keyboard, because a text box is focused
+pub fn wants_keyboard() -> bool {
+    crate::graphics::GRAPHICS_STATE
+        .egui
+        .egui_ctx
+        .wants_keyboard_input()
+}
+
+/// Set whether the mouse and keyboard are hidden from the game while egui is using them (see
+/// [wants_pointer] and [wants_keyboard]), so clicking a button doesn't also click whatever is
+/// under it; this is on by default
+///
+/// While they're hidden, functions like [crate::mouse::is_pressed] and
+/// [crate::keyboard::is_pressed] act like nothing is pressed.
+pub fn set_capture_input(capture: bool) {
+    CAPTURE_INPUT.store(capture, Ordering::Relaxed);
+}
+
+/// Key events for the navigation inputs sent this frame, which egui uses to move focus
+fn navigation_events(ctx: &Context) -> Vec<Event> {
+    use crate::navigation::Nav;
+
+    let nothing_focused = ctx.memory(|memory| memory.focused().is_none());
+    crate::navigation::sent()
+        .into_iter()
+        .flat_map(|nav| {
+            let (key, shift) = match nav {
+                // Egui only moves in a direction from a focused widget, so the first one is
+                // focused instead
+                Nav::Up | Nav::Down | Nav::Left | Nav::Right if nothing_focused => {
+                    (Key::Tab, false)
+                }
+                Nav::Up => (Key::ArrowUp, false),
+                Nav::Down => (Key::ArrowDown, false),
+                Nav::Left => (Key::ArrowLeft, false),
+                Nav::Right => (Key::ArrowRight, false),
+                Nav::Next => (Key::Tab, false),
+                Nav::Previous => (Key::Tab, true),
+                Nav::Confirm => (Key::Enter, false),
+                Nav::Cancel => (Key::Escape, false),
+            };
+            let modifiers = Modifiers {
+                shift,
+                ..get_modifiers()
+            };
+            [true, false].map(|pressed| Event::Key {
+                key,
+                physical_key: None,
+                pressed,
+                repeat: false,
+                modifiers,
+            })
+        })
+        .collect()
+}
+
+/// Run a pass without any of the game's gui if it didn't call [gui] this frame, so the inspector
+/// stays open, and give the game its input back if egui isn't shown
+pub(crate) fn end_frame() {
+    if is_inspector_shown() && EGUI_STATE.lock().full_output.is_none() {
+        gui(|_| {});
+    }
+    // Egui can't be using the mouse or keyboard if it wasn't shown
+    if EGUI_STATE.lock().full_output.is_none() {
+        crate::mouse::set_captured(false);
+        keyboard::set_captured(keyboard::Capture::Gui, false);
+    }
+}
+
+/// Set the colours and look of egui's widgets, e.g. [Visuals::light], instead of changing them
+/// inside every call to [gui]
+pub fn set_theme(visuals: Visuals) {
+    let ctx = &crate::graphics::GRAPHICS_STATE.egui.egui_ctx;
+    let theme = if visuals.dark_mode {
+        Theme::Dark
+    } else {
+        Theme::Light
+    };
+    ctx.set_theme(theme);
+    ctx.set_visuals_of(theme, visuals);
+}
+
+/// Make a care [Font](crate::graphics::Font) available in egui, so games don't need a second
+/// copy of the font file
+///
+/// The font can be used with `FontFamily::Name(name.into())`, and it's also used first by the
+/// given families, e.g. `&[FontFamily::Proportional]` to use it for most of egui's text.
+/// [crate::graphics::default_font] is the font used by [crate::graphics::text].
+pub fn add_font(name: &str, font: &crate::graphics::Font, families: &[FontFamily]) {
+    let families = families
+        .iter()
+        .cloned()
+        .chain([FontFamily::Name(name.into())])
+        .map(|family| epaint::text::InsertFontFamily {
+            family,
+            priority: epaint::text::FontPriority::Highest,
+        })
+        .collect();
+    crate::graphics::GRAPHICS_STATE
+        .egui
+        .egui_ctx
+        .add_font(epaint::text::FontInsert::new(
+            name,
+            FontData {
+                font: font.0 .2.clone(),
+                index: 0,
+                tweak: Default::default(),
+            },
+            families,
+        ));
+}
+
+/// Set how much bigger egui is drawn, on top of the window's own scale factor, e.g. 1.5 for 150%
+///
+/// Egui also lets players change this with ctrl + plus and ctrl + minus.
+pub fn set_scale(scale: f32) {
+    crate::graphics::GRAPHICS_STATE
+        .egui
+        .egui_ctx
+        .set_zoom_factor(scale);
+}
+
+/// Get how much bigger egui is drawn, on top of the window's own scale factor
+pub fn get_scale() -> f32 {
+    crate::graphics::GRAPHICS_STATE.egui.egui_ctx.zoom_factor()
+}
+
+/// A deferred viewport, shown in its own window
+struct Viewport {
+    parent: ViewportId,
+    ui: Arc<DeferredViewportUiCallback>,
+    /// `None` until the window has been opened
+    window: Option<(Arc<winit::window::Window>, wgpu::Surface<'static>)>,
+    events: Vec<Event>,
+    modifiers: Modifiers,
+    pointer: Pos2,
+    focused: bool,
+    close_requested: bool,
+    /// The output of a pass that was already run this frame, see `__inspect_state`
+    output: Option<FullOutput>,
+}
+
+static VIEWPORTS: LazyLock<Mutex<HashMap<ViewportId, Viewport>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+fn window_attributes(builder: &ViewportBuilder) -> winit::window::WindowAttributes {
+    use winit::dpi::{LogicalPosition, LogicalSize};
+
+    let mut attribs = winit::window::Window::default_attributes()
+        .with_title(builder.title.clone().unwrap_or_else(|| "egui".to_string()));
+    if let Some(size) = builder.inner_size {
+        attribs = attribs.with_inner_size(LogicalSize::new(size.x, size.y));
+    }
+    if let Some(size) = builder.min_inner_size {
+        attribs = attribs.with_min_inner_size(LogicalSize::new(size.x, size.y));
+    }
+    if let Some(size) = builder.max_inner_size {
+        attribs = attribs.with_max_inner_size(LogicalSize::new(size.x, size.y));
+    }
+    if let Some(pos) = builder.position {
+        attribs = attribs.with_position(LogicalPosition::new(pos.x, pos.y));
+    }
+    if let Some(resizable) = builder.resizable {
+        attribs = attribs.with_resizable(resizable);
+    }
+    if let Some(decorations) = builder.decorations {
+        attribs = attribs.with_decorations(decorations);
+    }
+    attribs
+}
+
+fn configure_surface(surface: &wgpu::Surface, window: &winit::window::Window) {
+    let graphics = &crate::graphics::GRAPHICS_STATE;
+    let size = window.inner_size();
+    if size.width == 0 || size.height == 0 {
+        return;
+    }
+    let surface_caps = surface.get_capabilities(&graphics.adapter);
+    surface.configure(
+        &graphics.device,
+        &wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            // The egui renderer only renders to this format
+            format: graphics.egui.surface_format,
+            width: size.width,
+            height: size.height,
+            present_mode: crate::graphics::present_mode(&surface_caps),
+            desired_maximum_frame_latency: 2,
+            alpha_mode: surface_caps.alpha_modes[0],
+            view_formats: vec![],
+        },
+    );
+}
+
+fn viewport_opened(id: ViewportId, window: Arc<winit::window::Window>) {
+    let surface = crate::graphics::GRAPHICS_STATE
+        .instance
+        .create_surface(window.clone())
+        .expect("Failed to create surface for window.");
+    configure_surface(&surface, &window);
+    // If the viewport was closed while its window was opening, the window is just dropped
+    if let Some(viewport) = VIEWPORTS.lock().get_mut(&id) {
+        viewport.window = Some((window, surface));
+    }
+}
+
+/// Open, close and render the windows for deferred viewports, after the main window has been
+/// presented
+pub(crate) fn render_viewports(outputs: ViewportIdMap<ViewportOutput>) {
+    texture::free_dropped();
+    let mut viewports = VIEWPORTS.lock();
+    // Viewports that weren't shown this frame are closed, along with their windows
+    viewports.retain(|id, _| outputs.contains_key(id));
+    for (id, output) in outputs {
+        // Immediate viewports don't have a callback, and are embedded in their parent instead
+        let Some(ui) = output.viewport_ui_cb else {
+            continue;
+        };
+        if let Some(viewport) = viewports.get_mut(&id) {
+            viewport.ui = ui;
+            continue;
+        }
+        viewports.insert(
+            id,
+            Viewport {
+                parent: output.parent,
+                ui,
+                window: None,
+                events: Vec::new(),
+                modifiers: Modifiers::default(),
+                pointer: Pos2::ZERO,
+                focused: true,
+                close_requested: false,
+                output: None,
+            },
+        );
+        crate::window::open_extra(window_attributes(&output.builder), move |window| {
+            viewport_opened(id, window)
+        });
+    }
+
+    for (&id, viewport) in viewports.iter_mut() {
+        let full_output = match viewport.output.take() {
+            Some(full_output) => full_output,
+            // The inspector's pass is run by `__inspect_state`, where the state can be reached
+            None if id == inspect::inspector_id() => continue,
+            None => {
+                let Some(input) = viewport_input(id, viewport) else {
+                    continue;
+                };
+                let ui = viewport.ui.clone();
+                crate::graphics::GRAPHICS_STATE
+                    .egui
+                    .egui_ctx
+                    .run(input, |ctx| ui(ctx))
+            }
+        };
+        if let Some((window, surface)) = &viewport.window {
+            present_viewport(full_output, window, surface);
+        }
+    }
+}
+
+/// The input for a viewport's next pass, or `None` if its window isn't open or is minimized
+fn viewport_input(id: ViewportId, viewport: &mut Viewport) -> Option<RawInput> {
+    let (window, _) = viewport.window.as_ref()?;
+    let size = window.inner_size();
+    if size.width == 0 || size.height == 0 {
+        return None;
+    }
+    let pixels_per_point = window.scale_factor() as f32 * get_scale();
+    Some(RawInput {
+        viewport_id: id,
+        viewports: [(
+            id,
+            ViewportInfo {
+                parent: Some(viewport.parent),
+                events: if std::mem::take(&mut viewport.close_requested) {
+                    vec![ViewportEvent::Close]
+                } else {
+                    Vec::new()
+                },
+                native_pixels_per_point: Some(window.scale_factor() as f32),
+                focused: Some(viewport.focused),
+                ..Default::default()
+            },
+        )]
+        .into_iter()
+        .collect(),
+        screen_rect: Some(Rect::from_min_size(
+            Pos2::ZERO,
+            vec2(size.width as f32, size.height as f32) / pixels_per_point,
+        )),
+        time: Some(
+            crate::graphics::GRAPHICS_STATE
+                .egui
+                .start_time
+                .elapsed()
+                .as_secs_f64(),
+        ),
+        predicted_dt: 1.0 / 60.0,
+        events: std::mem::take(&mut viewport.events),
+        modifiers: viewport.modifiers,
+        focused: viewport.focused,
+        ..Default::default()
+    })
+}
+
+fn present_viewport(
+    full_output: FullOutput,
+    window: &winit::window::Window,
+    surface: &wgpu::Surface,
+) {
+    let graphics = &crate::graphics::GRAPHICS_STATE;
+    let mut renderer = graphics.egui.egui_renderer.lock();
+    // Textures are shared by every viewport, so they're updated even if this one isn't drawn
+    for (tex, delta) in &full_output.textures_delta.set {
+        renderer.update_texture(&graphics.device, &graphics.queue, *tex, delta);
+    }
+    let clipped_primitives = graphics
+        .egui
+        .egui_ctx
+        .tessellate(full_output.shapes, full_output.pixels_per_point);
+
+    let output = match surface.get_current_texture() {
+        Ok(output) => output,
+        Err(_) => {
+            // Probably resized, so try again next frame
+            for id in &full_output.textures_delta.free {
+                renderer.free_texture(id);
+            }
+            configure_surface(surface, window);
+            return;
+        }
+    };
+    let view = output
+        .texture
+        .create_view(&wgpu::TextureViewDescriptor::default());
+    let screen_descriptor = egui_wgpu::ScreenDescriptor {
+        size_in_pixels: [output.texture.size().width, output.texture.size().height],
+        pixels_per_point: full_output.pixels_per_point,
+    };
+    let mut encoder = graphics
+        .device
+        .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("EGUI viewport command encoder"),
+        });
+    let mut command_buffers = renderer.update_buffers(
+        &graphics.device,
+        &graphics.queue,
+        &mut encoder,
+        &clipped_primitives,
+        &screen_descriptor,
+    );
+    {
+        let render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("EGUI Viewport Render Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+        renderer.render(
+            &mut render_pass.forget_lifetime(),
+            &clipped_primitives,
+            &screen_descriptor,
+        );
+    }
+    for id in &full_output.textures_delta.free {
+        renderer.free_texture(id);
+    }
+    command_buffers.push(encoder.finish());
+    graphics.queue.submit(command_buffers);
+    output.present();
+}
+
+/// Handle an event for a viewport's window, returning false if the window isn't a viewport's
+pub(crate) fn viewport_window_event(
+    window_id: winit::window::WindowId,
+    event: &winit::event::WindowEvent,
+) -> bool {
+    use winit::event::{MouseButton, MouseScrollDelta, WindowEvent};
+
+    let mut viewports = VIEWPORTS.lock();
+    let Some(viewport) = viewports.values_mut().find(|viewport| {
+        viewport
+            .window
+            .as_ref()
+            .is_some_and(|(window, _)| window.id() == window_id)
+    }) else {
+        return false;
+    };
+    let Some((window, surface)) = &viewport.window else {
+        return false;
+    };
+    let scale_factor = window.scale_factor();
+    let modifiers = viewport.modifiers;
+    match event {
+        WindowEvent::CloseRequested => viewport.close_requested = true,
+        WindowEvent::Resized(_) | WindowEvent::ScaleFactorChanged { .. } => {
+            configure_surface(surface, window)
+        }
+        WindowEvent::Focused(focused) => {
+            viewport.focused = *focused;
+            viewport.events.push(Event::WindowFocused(*focused));
+        }
+        WindowEvent::ModifiersChanged(state) => {
+            let state = state.state();
+            viewport.modifiers = Modifiers {
+                alt: state.alt_key(),
+                ctrl: state.control_key(),
+                shift: state.shift_key(),
+                mac_cmd: cfg!(target_os = "macos") && state.super_key(),
+                command: if cfg!(target_os = "macos") {
+                    state.super_key()
+                } else {
+                    state.control_key()
+                },
+            };
+        }
+        WindowEvent::CursorMoved { position, .. } => {
+            let position = position.to_logical::<f32>(scale_factor);
+            viewport.pointer = Pos2::new(position.x, position.y) / get_scale();
+            viewport.events.push(Event::PointerMoved(viewport.pointer));
+        }
+        WindowEvent::CursorLeft { .. } => viewport.events.push(Event::PointerGone),
+        WindowEvent::MouseInput { state, button, .. } => {
+            let button = match button {
+                MouseButton::Left => PointerButton::Primary,
+                MouseButton::Right => PointerButton::Secondary,
+                MouseButton::Middle => PointerButton::Middle,
+                MouseButton::Back => PointerButton::Extra1,
+                MouseButton::Forward => PointerButton::Extra2,
+                MouseButton::Other(_) => return true,
+            };
+            viewport.events.push(Event::PointerButton {
+                pos: viewport.pointer,
+                button,
+                pressed: state.is_pressed(),
+                modifiers,
+            });
+        }
+        WindowEvent::MouseWheel { delta, .. } => {
+            let (unit, delta) = match delta {
+                MouseScrollDelta::LineDelta(x, y) => (MouseWheelUnit::Line, vec2(*x, *y)),
+                MouseScrollDelta::PixelDelta(delta) => {
+                    let delta = delta.to_logical::<f32>(scale_factor);
+                    (MouseWheelUnit::Point, vec2(delta.x, delta.y) / get_scale())
+                }
+            };
+            viewport.events.push(Event::MouseWheel {
+                unit,
+                delta,
+                modifiers,
+            });
+        }
+        WindowEvent::KeyboardInput { event, .. } => {
+            let key = crate::window::convert_key(event.logical_key.clone());
+            for &key in translate_key(key) {
+                viewport.events.push(Event::Key {
+                    key,
+                    physical_key: None,
+                    pressed: event.state.is_pressed(),
+                    repeat: event.repeat,
+                    modifiers,
+                });
+            }
+            if let (true, Some(text)) = (event.state.is_pressed(), &event.text) {
+                let text = text.replace(|c: char| c.is_control(), "");
+                if !text.is_empty() {
+                    viewport.events.push(Event::Text(text));
+                }
+            }
+        }
+        _ => {}
+    }
+    true
 }
 
 fn translate_key(key: CareKey) -> &'static [egui::Key] {
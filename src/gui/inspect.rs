@@ -0,0 +1,222 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use egui::{CollapsingHeader, DragValue, Ui, ViewportBuilder, ViewportId};
+
+use crate::math::{Vec2, Vec3, Vec4};
+
+/// Something that can be viewed and edited in the [inspector]
+///
+/// This can be derived for structs, with `#[derive(Inspect)]`, fields marked with
+/// `#[inspect(skip)]` are left out.
+pub trait Inspect {
+    /// Show the value with a label, letting it be edited
+    fn inspect(&mut self, ui: &mut Ui, label: &str);
+}
+
+static INSPECTOR_SHOWN: AtomicBool = AtomicBool::new(false);
+
+pub(super) fn inspector_id() -> ViewportId {
+    ViewportId::from_hash_of("care inspector")
+}
+
+/// Show or hide the inspector, a window for viewing and editing the game's [crate::state]
+/// variables while it's running, e.g. to tweak constants
+///
+/// Only state with a type that implements [Inspect] is shown. The inspector is updated between
+/// the [crate::draw] function and the end of the frame, so it isn't available with
+/// [crate::async_main].
+pub fn inspector(show: bool) {
+    INSPECTOR_SHOWN.store(show, Ordering::Relaxed);
+}
+
+/// Get whether the inspector is shown
+pub fn is_inspector_shown() -> bool {
+    INSPECTOR_SHOWN.load(Ordering::Relaxed)
+}
+
+/// Keep the inspector's viewport (and so its window) open, called in every root pass
+pub(super) fn keep_inspector_open(ctx: &egui::Context) {
+    if is_inspector_shown() {
+        ctx.show_viewport_deferred(
+            inspector_id(),
+            ViewportBuilder::default()
+                .with_title("Inspector")
+                .with_inner_size((320.0, 480.0)),
+            // The pass is run by `__inspect_state` instead
+            |_, _| {},
+        );
+    }
+}
+
+#[doc(hidden)]
+/// Run the inspector's pass, called by the generated main function after drawing
+pub fn __inspect_state(state: &mut [(&str, Option<&mut dyn Inspect>)]) {
+    if !is_inspector_shown() {
+        return;
+    }
+    let id = inspector_id();
+    let mut viewports = super::VIEWPORTS.lock();
+    let Some(viewport) = viewports.get_mut(&id) else {
+        return;
+    };
+    let Some(input) = super::viewport_input(id, viewport) else {
+        return;
+    };
+    let full_output = crate::graphics::GRAPHICS_STATE.egui.egui_ctx.run(input, |ctx| {
+        if ctx.input(|input| input.viewport().close_requested()) {
+            inspector(false);
+        }
+        egui::CentralPanel::default().show(ctx, |ui| {
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                for (name, value) in state.iter_mut() {
+                    if let Some(value) = value {
+                        value.inspect(ui, name);
+                    }
+                }
+            });
+        });
+    });
+    viewport.output = Some(full_output);
+}
+
+#[doc(hidden)]
+/// Picks out the state that implements [Inspect], called as
+/// `(&mut __Inspectable(Some(value))).__inspectable()`
+pub struct __Inspectable<'a, T>(pub Option<&'a mut T>);
+
+#[doc(hidden)]
+pub trait __InspectableYes<'a> {
+    fn __inspectable(&mut self) -> Option<&'a mut dyn Inspect>;
+}
+
+impl<'a, T: Inspect> __InspectableYes<'a> for __Inspectable<'a, T> {
+    fn __inspectable(&mut self) -> Option<&'a mut dyn Inspect> {
+        self.0.take().map(|value| value as &mut dyn Inspect)
+    }
+}
+
+#[doc(hidden)]
+/// Only used when the type doesn't implement [Inspect], since method resolution tries
+/// [__InspectableYes] first (it takes one less reference)
+pub trait __InspectableNo<'a> {
+    fn __inspectable(&mut self) -> Option<&'a mut dyn Inspect>;
+}
+
+impl<'a, T> __InspectableNo<'a> for &mut __Inspectable<'a, T> {
+    fn __inspectable(&mut self) -> Option<&'a mut dyn Inspect> {
+        None
+    }
+}
+
+macro_rules! impl_inspect_number {
+    ($($ty:ident),*) => {
+        $(
+            impl Inspect for $ty {
+                fn inspect(&mut self, ui: &mut Ui, label: &str) {
+                    ui.horizontal(|ui| {
+                        ui.label(label);
+                        ui.add(DragValue::new(self).speed(0.1));
+                    });
+                }
+            }
+        )*
+    };
+}
+
+impl_inspect_number!(f32, f64, u8, i8, u16, i16, u32, i32, u64, i64, usize, isize);
+
+impl Inspect for bool {
+    fn inspect(&mut self, ui: &mut Ui, label: &str) {
+        ui.checkbox(self, label);
+    }
+}
+
+impl Inspect for String {
+    fn inspect(&mut self, ui: &mut Ui, label: &str) {
+        ui.horizontal(|ui| {
+            ui.label(label);
+            ui.text_edit_singleline(self);
+        });
+    }
+}
+
+impl Inspect for Vec2 {
+    fn inspect(&mut self, ui: &mut Ui, label: &str) {
+        ui.horizontal(|ui| {
+            ui.label(label);
+            ui.add(DragValue::new(&mut self.0.x).speed(0.1).prefix("x: "));
+            ui.add(DragValue::new(&mut self.0.y).speed(0.1).prefix("y: "));
+        });
+    }
+}
+
+impl Inspect for Vec3 {
+    fn inspect(&mut self, ui: &mut Ui, label: &str) {
+        ui.horizontal(|ui| {
+            ui.label(label);
+            ui.add(DragValue::new(&mut self.0.x).speed(0.1).prefix("x: "));
+            ui.add(DragValue::new(&mut self.0.y).speed(0.1).prefix("y: "));
+            ui.add(DragValue::new(&mut self.0.z).speed(0.1).prefix("z: "));
+        });
+    }
+}
+
+impl Inspect for Vec4 {
+    /// Vec4s are usually colours, so they also get a colour picker
+    fn inspect(&mut self, ui: &mut Ui, label: &str) {
+        ui.horizontal(|ui| {
+            ui.label(label);
+            // Clippy detects this as an issue because when Fl = f32, the explicit conversions are
+            // not needed, but when Fl = f64, they are neccesary.
+            #[allow(clippy::unnecessary_cast)]
+            let mut colour = [
+                self.0.x as f32,
+                self.0.y as f32,
+                self.0.z as f32,
+                self.0.w as f32,
+            ];
+            if ui.color_edit_button_rgba_unmultiplied(&mut colour).changed() {
+                *self = Vec4::new(colour[0], colour[1], colour[2], colour[3]);
+            }
+            ui.add(DragValue::new(&mut self.0.x).speed(0.01));
+            ui.add(DragValue::new(&mut self.0.y).speed(0.01));
+            ui.add(DragValue::new(&mut self.0.z).speed(0.01));
+            ui.add(DragValue::new(&mut self.0.w).speed(0.01));
+        });
+    }
+}
+
+impl<T: Inspect> Inspect for Option<T> {
+    fn inspect(&mut self, ui: &mut Ui, label: &str) {
+        match self {
+            Some(value) => value.inspect(ui, label),
+            None => {
+                ui.label(format!("{label}: None"));
+            }
+        }
+    }
+}
+
+fn inspect_items<'a, T: Inspect + 'a>(
+    items: impl Iterator<Item = &'a mut T>,
+    ui: &mut Ui,
+    label: &str,
+) {
+    CollapsingHeader::new(label).show(ui, |ui| {
+        for (i, item) in items.enumerate() {
+            item.inspect(ui, &i.to_string());
+        }
+    });
+}
+
+impl<T: Inspect> Inspect for Vec<T> {
+    fn inspect(&mut self, ui: &mut Ui, label: &str) {
+        inspect_items(self.iter_mut(), ui, label);
+    }
+}
+
+impl<T: Inspect, const N: usize> Inspect for [T; N] {
+    fn inspect(&mut self, ui: &mut Ui, label: &str) {
+        inspect_items(self.iter_mut(), ui, label);
+    }
+}
@@ -0,0 +1,67 @@
+use std::sync::{Arc, Weak};
+
+use parking_lot::Mutex;
+
+use egui::TextureId;
+
+use crate::graphics::{Texture, TextureHandle, GRAPHICS_STATE};
+
+/// Care textures that have been registered with the egui renderer
+static TEXTURES: Mutex<Vec<(Weak<TextureHandle>, TextureId)>> = Mutex::new(Vec::new());
+
+/// Get an egui [TextureId] for a care [Texture], so it can be shown in egui, e.g. with
+/// [egui::Image]
+///
+/// The texture is registered with egui the first time, and stays registered until every copy of
+/// it has been dropped.
+pub fn texture_id(texture: &Texture) -> TextureId {
+    free_dropped();
+    let mut renderer = GRAPHICS_STATE.egui.egui_renderer.lock();
+    let mut textures = TEXTURES.lock();
+    if let Some((_, id)) = textures
+        .iter()
+        .find(|(handle, _)| handle.as_ptr() == Arc::as_ptr(&texture.0))
+    {
+        return *id;
+    }
+    let id = renderer.register_native_texture(
+        &GRAPHICS_STATE.device,
+        &texture.0.view,
+        wgpu::FilterMode::Nearest,
+    );
+    textures.push((Arc::downgrade(&texture.0), id));
+    id
+}
+
+/// Get a care [Texture] for an egui [TextureId], so it can be drawn with care's graphics
+///
+/// This works for ids from [texture_id], and for images egui manages itself (like ones loaded
+/// with [egui::Context::load_texture]) once they've been uploaded, at the end of the frame they
+/// were created in. An image egui manages stops working when egui frees it, e.g. when its
+/// [egui::TextureHandle] is dropped.
+pub fn texture_from_id(id: TextureId) -> Option<Texture> {
+    if let Some(handle) = TEXTURES
+        .lock()
+        .iter()
+        .find(|(_, registered)| *registered == id)
+        .and_then(|(handle, _)| handle.upgrade())
+    {
+        return Some(Texture(handle));
+    }
+    let renderer = GRAPHICS_STATE.egui.egui_renderer.lock();
+    let texture = renderer.texture(&id)?.texture.clone()?;
+    Some(Texture::new_from_wgpu(Arc::new(texture)))
+}
+
+/// Unregister the textures from [texture_id] that have been dropped, the renderer is locked
+/// before [TEXTURES] everywhere
+pub(super) fn free_dropped() {
+    let mut renderer = GRAPHICS_STATE.egui.egui_renderer.lock();
+    TEXTURES.lock().retain(|(handle, id)| {
+        let alive = handle.strong_count() > 0;
+        if !alive {
+            renderer.free_texture(id);
+        }
+        alive
+    });
+}
@@ -1,4 +1,10 @@
-use std::{collections::HashSet, sync::OnceLock};
+use std::{
+    collections::HashSet,
+    sync::{
+        atomic::{AtomicU8, Ordering},
+        OnceLock,
+    },
+};
 
 use parking_lot::RwLock;
 
@@ -59,6 +65,7 @@ impl From<&str> for Key {
                 "right" => Self::Right,
                 "space" => Self::Space,
                 "enter" => Self::Enter,
+                "tab" => Self::Char('\t'),
                 "escape" => Self::Escape,
                 "backspace" => Self::Backspace,
                 "delete" => Self::Delete,
@@ -83,6 +90,7 @@ pub(crate) struct KeyboardState {
     pub pressed: HashSet<Key>,
     pub released: HashSet<Key>,
     pub held: HashSet<Key>,
+    pub text: String,
 }
 
 impl KeyboardState {
@@ -91,29 +99,72 @@ impl KeyboardState {
             pressed: HashSet::new(),
             released: HashSet::new(),
             held: HashSet::new(),
+            text: String::new(),
         }
     }
 }
 
 static KEYBOARD_STATE: OnceLock<RwLock<KeyboardState>> = OnceLock::new();
+/// The [Capture]s currently using the keyboard instead of the game
+static CAPTURED: AtomicU8 = AtomicU8::new(0);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Something other than the game that can take the keyboard, see [set_captured]
+pub(crate) enum Capture {
+    #[cfg_attr(not(feature = "window"), allow(dead_code))]
+    Console = 1,
+    #[cfg_attr(not(feature = "gui"), allow(dead_code))]
+    Gui = 2,
+}
 
 pub(crate) fn get_state() -> &'static RwLock<KeyboardState> {
     KEYBOARD_STATE.get_or_init(|| RwLock::new(KeyboardState::empty()))
 }
 
+#[cfg_attr(not(any(feature = "window", feature = "gui")), allow(dead_code))]
+/// Hide the keyboard from the game while something else is using it, e.g. the console is open
+pub(crate) fn set_captured(by: Capture, captured: bool) {
+    if captured {
+        CAPTURED.fetch_or(by as u8, Ordering::Relaxed);
+    } else {
+        CAPTURED.fetch_and(!(by as u8), Ordering::Relaxed);
+    }
+}
+
+fn is_captured() -> bool {
+    CAPTURED.load(Ordering::Relaxed) != 0
+}
+
 /// Get whether a key is currently being held down
 pub fn is_down(key: impl Into<Key>) -> bool {
-    get_state().read().held.contains(&key.into())
+    !is_captured() && get_state().read().held.contains(&key.into())
 }
 
 /// Get whether a key was just pressed
 pub fn is_pressed(key: impl Into<Key>) -> bool {
-    get_state().read().pressed.contains(&key.into())
+    !is_captured() && get_state().read().pressed.contains(&key.into())
 }
 
 /// Get whether a key was just released
 pub fn is_released(key: impl Into<Key>) -> bool {
-    get_state().read().released.contains(&key.into())
+    !is_captured() && get_state().read().released.contains(&key.into())
+}
+
+/// Get the text that was just typed, e.g. for a text box
+///
+/// This follows the keyboard layout and includes shifted and composed characters, unlike
+/// [is_pressed]. Control characters (like backspace) are left out.
+pub fn get_text() -> String {
+    if is_captured() {
+        return String::new();
+    }
+    get_state().read().text.clone()
+}
+
+/// Process a text event, used internally to handle typed text
+pub fn process_text_event(text: &str) {
+    let mut state = get_state().write();
+    state.text.extend(text.chars().filter(|c| !c.is_control()));
 }
 
 /// Process a key event, used internally to handle key events
@@ -133,6 +184,7 @@ pub fn reset() {
     let mut state = get_state().write();
     state.pressed.clear();
     state.released.clear();
+    state.text.clear();
 }
 
 /// Useful structs to import
@@ -1,13 +1,24 @@
 #![warn(missing_docs)]
 #![doc = include_str!("../readme.md")]
 
+mod app;
 #[cfg(feature = "compute")]
 /// Contains functions for using GPU Compute (GPGPU)
 pub mod compute;
 /// Global care configuration parameters
 pub mod config;
+#[cfg(feature = "window")]
+/// A drop-down console for running commands and reading log output while the game is running
+pub mod console;
+/// Tools for debugging games, like an overlay with performance statistics
+pub mod debug;
+/// Handling errors returned by the game's functions
+pub mod error;
 /// Low-level event handling
 pub mod event;
+#[cfg(any(feature = "graphics", feature = "compute"))]
+/// Choosing which GPU to use
+pub mod gpu;
 #[cfg(feature = "graphics")]
 /// Contains functions for rendering graphics
 pub mod graphics;
@@ -15,10 +26,21 @@ pub mod graphics;
 pub mod keyboard;
 /// Contains functions for doing various math tasks, including working with vectors
 pub mod math;
+/// Running games on Android and iOS
+pub mod mobile;
 /// Stuff for working with a mouse
 pub mod mouse;
+/// Moving between GUI widgets without a mouse, e.g. with a gamepad
+pub mod navigation;
+/// Values the game's functions can take as parameters, looked up by their type
+pub mod resource;
 /// Useful structs to have imported
 pub mod prelude;
+#[cfg(feature = "graphics")]
+/// Checking how the game looks against reference images, without a window
+pub mod testing;
+/// Stuff for working with a touch screen
+pub mod touch;
 #[cfg(feature = "window")]
 /// Contains functions for working with window(s)
 pub mod window;
@@ -26,22 +48,40 @@ pub mod window;
 /// GUI-related functions, implemented through [egui]
 pub mod gui;
 
-/// Mark a function as the care draw function.
+/// Mark a function inside [main] as the async main function, which runs the whole game itself.
 pub use care_macro::care_async_main as async_main;
-/// Mark a function as the care draw function.
+/// Mark a function inside [main] as the function that returns the [Conf].
+pub use care_macro::care_conf as conf;
+/// Mark a function inside [main] as the care draw function.
 pub use care_macro::care_draw as draw;
-/// Mark a function as the care initialization function.
+/// Mark a function inside [main] as the care event function, which is given every event (as a
+/// `&care::event::Event`) before the update function runs.
+pub use care_macro::care_event as event;
+/// Mark a function inside [main] as the care resize function, which is given the window's new
+/// size whenever it's resized.
+pub use care_macro::care_resize as resize;
+/// Mark a function inside [main] as the care focus function, which is given whether the window is
+/// focused whenever it gains or loses focus.
+pub use care_macro::care_focus as focus;
+/// Mark a function inside [main] as the care fixed update function, which runs
+/// [Conf::fixed_update_rate] times per second, no matter the frame rate.
+pub use care_macro::care_fixed_update as fixed_update;
+/// Mark a function inside [main] as the care initialization function.
 pub use care_macro::care_init as init;
-/// Make some state for the game
+/// Mark a function inside [main] as the care quit function, which is given the game's state once
+/// when the game is about to exit, e.g. to save it.
+pub use care_macro::care_quit as quit;
+/// Make some state for the game, from a static inside [main]
 pub use care_macro::care_state as state;
-/// Mark a function as the care update function.
+/// Mark a function inside [main] as the care update function.
 pub use care_macro::care_update as update;
 
 #[doc(hidden)]
 pub use care_macro::care_main as __internal_main;
 
-/// Global care configuration struct, pass to [main] (e.g. `care::main!(Conf { .. })`),
-/// to configure the framework
+#[doc(inline)]
+pub use app::{run, App, Game};
+/// Global care configuration struct, return it from a [conf] function to configure the framework
 pub use config::Conf;
 
 #[cfg(feature = "graphics")]
@@ -58,12 +98,74 @@ pub use rusttype;
 /// Inserts a default main function that automatically initializes the framework, opens a window,
 /// and calls the functions marked by [init], [update] and [draw] at appropriate
 /// times
+///
+/// The functions and state are made into an [App], which is [run] with the config from the [conf]
+/// function.
+///
+/// The game's state and functions go inside it, so it can see all of them at once:
+///
+/// ```ignore
+/// care::main! {
+///     #[care::state]
+///     static time: f32 = 0.0;
+///
+///     #[care::update]
+///     fn update(delta: f32) {
+///         time += delta;
+///     }
+/// }
+/// ```
+///
+/// Besides the arguments care gives them (like the update function's delta time), the functions
+/// can take references to state or [resource]s, which are matched by their type:
+///
+/// ```ignore
+/// struct Player {
+///     pos: care::math::Vec2,
+/// }
+///
+/// care::main! {
+///     #[care::init]
+///     fn init() {
+///         care::resource::insert(Player { pos: care::math::Vec2::new(0, 0) });
+///     }
+///
+///     #[care::draw]
+///     fn draw(player: &Player, stats: &care::debug::DebugStats) {
+///         care::graphics::rectangle(player.pos, (50, 50));
+///         care::graphics::text(format!("{:.0} fps", stats.fps()), (10, 10));
+///     }
+/// }
+/// ```
+///
+/// Any of the functions can return a `Result<(), E>` instead of nothing, and errors are given to
+/// [error::report].
 #[macro_export]
 macro_rules! main {
-    () => {
-        $crate::main!($crate::Conf::default());
-    };
-    ($conf:expr) => {
-        $crate::__internal_main!($conf);
+    ($($items:tt)*) => {
+        $crate::__internal_main! { $($items)* }
     };
 }
+
+#[cfg(feature = "gui")]
+#[doc(hidden)]
+#[macro_export]
+/// Show the state that implements [gui::Inspect] in the inspector, used by [main]
+macro_rules! __inspect_state {
+    ($($name:ident: $value:expr),* $(,)?) => {{
+        #[allow(unused_imports)]
+        use $crate::gui::{__InspectableNo as _, __InspectableYes as _};
+        $crate::gui::__inspect_state(&mut [$((
+            stringify!($name),
+            (&mut $crate::gui::__Inspectable(Some(&mut *$value))).__inspectable(),
+        )),*]);
+    }};
+}
+
+#[cfg(not(feature = "gui"))]
+#[doc(hidden)]
+#[macro_export]
+/// Show the state that implements `gui::Inspect` in the inspector, used by [main]
+macro_rules! __inspect_state {
+    ($($tt:tt)*) => {};
+}
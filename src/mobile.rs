@@ -0,0 +1,162 @@
+//! Games made with [crate::main] run on Android and iOS without changes, they just need to be
+//! built differently
+//!
+//! # Android
+//! The game has to be built as a library that the Android app loads, so add this to its
+//! `Cargo.toml`:
+//!
+//! ```toml
+//! [lib]
+//! crate-type = ["cdylib"]
+//! path = "src/main.rs"
+//! ```
+//!
+//! [crate::main] adds an `android_main` function that Android calls instead of `main`, which is
+//! then built and packaged into an APK with [cargo-apk](https://github.com/rust-mobile/cargo-apk):
+//!
+//! ```sh
+//! cargo apk run --lib
+//! ```
+//!
+//! Files loaded with [crate::graphics::Texture::new], [crate::graphics::Font::new] and
+//! [read_asset] come from the APK's `assets` folder, set with `assets = "assets"` under
+//! `[package.metadata.android]`.
+//!
+//! # iOS
+//! The game is built like any other binary, for `aarch64-apple-ios` (or `aarch64-apple-ios-sim`
+//! for the simulator), and then put in an app bundle, e.g. with
+//! [cargo-bundle](https://github.com/burtonageo/cargo-bundle):
+//!
+//! ```sh
+//! cargo bundle --target aarch64-apple-ios
+//! ```
+//!
+//! Files are loaded relative to the working directory, which is the app bundle.
+//!
+//! # Touch and lifecycle
+//! Touches are sent as [crate::event::EventData::Touch] events, and are in [crate::touch]. The
+//! first finger on the screen also acts as the mouse, so games that only use the mouse work too.
+//!
+//! Mobile apps get suspended when they go into the background, which sends a
+//! [crate::event::EventData::Suspend] event. Nothing runs until the app is resumed again, so it's
+//! a good time to save the game.
+
+use std::{io, path::Path};
+
+#[cfg(feature = "window")]
+use crate::math::Vec2;
+
+#[cfg(all(target_os = "android", feature = "window"))]
+/// The Android app the game runs in, given to `android_main`
+pub use winit::platform::android::activity::AndroidApp;
+
+#[cfg(all(target_os = "android", feature = "window"))]
+static ANDROID_APP: parking_lot::Mutex<Option<AndroidApp>> = parking_lot::Mutex::new(None);
+
+#[cfg(all(target_os = "android", feature = "window"))]
+/// Set the Android app the game runs in, this is called automatically by the `android_main`
+/// function [crate::main] adds
+pub fn set_android_app(app: AndroidApp) {
+    *ANDROID_APP.lock() = Some(app);
+}
+
+#[cfg(all(target_os = "android", feature = "window"))]
+/// Get the Android app the game runs in, if it's been set
+pub fn android_app() -> Option<AndroidApp> {
+    ANDROID_APP.lock().clone()
+}
+
+#[cfg(feature = "window")]
+/// Get the part of the main window that isn't covered by notches, rounded corners or system bars,
+/// as its position and size
+///
+/// On desktop this is the whole window.
+pub fn safe_area() -> (Vec2, Vec2) {
+    let windows = crate::window::WINDOWS.read();
+    let Some(window) = windows.first() else {
+        return (Vec2::new(0, 0), Vec2::new(0, 0));
+    };
+    let scale = window.scale_factor();
+    #[cfg(target_os = "android")]
+    if let Some(app) = android_app() {
+        let rect = app.content_rect();
+        return (
+            Vec2::new(rect.left as f64 / scale, rect.top as f64 / scale),
+            Vec2::new(
+                (rect.right - rect.left) as f64 / scale,
+                (rect.bottom - rect.top) as f64 / scale,
+            ),
+        );
+    }
+    #[cfg(target_os = "ios")]
+    {
+        // On iOS the window's inner position and size are its safe area
+        let inner = window.inner_position().unwrap_or_default();
+        let outer = window.outer_position().unwrap_or_default();
+        let size = window.inner_size();
+        return (
+            Vec2::new(
+                (inner.x - outer.x) as f64 / scale,
+                (inner.y - outer.y) as f64 / scale,
+            ),
+            Vec2::new(size.width as f64 / scale, size.height as f64 / scale),
+        );
+    }
+    #[allow(unreachable_code)]
+    {
+        let size = window.inner_size();
+        (
+            Vec2::new(0, 0),
+            Vec2::new(size.width as f64 / scale, size.height as f64 / scale),
+        )
+    }
+}
+
+#[cfg(feature = "window")]
+/// Show or hide the on-screen keyboard, text typed on it is sent as
+/// [crate::event::EventData::TextEvent]s
+///
+/// On desktop this turns on the input method, for typing e.g. Japanese, instead.
+pub fn show_keyboard(show: bool) {
+    #[cfg(target_os = "android")]
+    if let Some(app) = android_app() {
+        if show {
+            app.show_soft_input(false);
+        } else {
+            app.hide_soft_input(false);
+        }
+        return;
+    }
+    if let Some(window) = crate::window::WINDOWS.read().first() {
+        window.set_ime_allowed(show);
+    }
+}
+
+#[cfg(feature = "window")]
+/// Get whether the app is in the background, see [crate::event::EventData::Suspend]
+pub fn is_suspended() -> bool {
+    crate::window::is_suspended()
+}
+
+/// Read a file the game comes with, from the APK's assets on Android or the filesystem everywhere
+/// else
+pub fn read_asset(path: impl AsRef<Path>) -> io::Result<Vec<u8>> {
+    let path = path.as_ref();
+    #[cfg(all(target_os = "android", feature = "window"))]
+    if let (Some(app), false) = (android_app(), path.is_absolute()) {
+        use std::io::Read;
+
+        let name = path
+            .to_str()
+            .and_then(|name| std::ffi::CString::new(name).ok())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Invalid asset path"))?;
+        let mut asset = app
+            .asset_manager()
+            .open(&name)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "Asset not found"))?;
+        let mut data = Vec::new();
+        asset.read_to_end(&mut data)?;
+        return Ok(data);
+    }
+    std::fs::read(path)
+}
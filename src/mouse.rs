@@ -1,4 +1,10 @@
-use std::{collections::HashSet, sync::OnceLock};
+use std::{
+    collections::HashSet,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        OnceLock,
+    },
+};
 
 use parking_lot::RwLock;
 
@@ -24,29 +30,61 @@ impl MouseState {
 }
 
 static MOUSE_STATE: OnceLock<RwLock<MouseState>> = OnceLock::new();
+/// Set while the mouse is being used by the gui instead of the game
+static CAPTURED: AtomicBool = AtomicBool::new(false);
 
 fn get_state() -> &'static RwLock<MouseState> {
     MOUSE_STATE.get_or_init(|| RwLock::new(MouseState::empty()))
 }
 
+#[cfg_attr(not(feature = "gui"), allow(dead_code))]
+/// Hide the mouse buttons from the game, e.g. while it's over a gui window
+pub(crate) fn set_captured(captured: bool) {
+    CAPTURED.store(captured, Ordering::Relaxed);
+}
+
+fn is_captured() -> bool {
+    CAPTURED.load(Ordering::Relaxed)
+}
+
 /// Get the current position of the mouse
+///
+/// With a virtual resolution set in the config, it's given in the virtual resolution's
+/// coordinates.
 pub fn get_position() -> Vec2 {
+    let position = window_position();
+    #[cfg(feature = "window")]
+    {
+        let conf = crate::config::get();
+        if let (Some(resolution), Some((offset, size))) = (
+            conf.virtual_resolution,
+            conf.letterbox(crate::window::window_size()),
+        ) {
+            return (position - offset) / size * resolution;
+        }
+    }
+    position
+}
+
+#[cfg_attr(not(feature = "gui"), allow(dead_code))]
+/// Get the position of the mouse in the window, ignoring the virtual resolution
+pub(crate) fn window_position() -> Vec2 {
     get_state().read().position
 }
 
 /// Get whether a mouse button is currently being held down
 pub fn is_down(button: i32) -> bool {
-    get_state().read().held.contains(&button)
+    !is_captured() && get_state().read().held.contains(&button)
 }
 
 /// Get whether a mouse button was just pressed
 pub fn is_pressed(button: i32) -> bool {
-    get_state().read().pressed.contains(&button)
+    !is_captured() && get_state().read().pressed.contains(&button)
 }
 
 /// Get whether a mouse button was just released
 pub fn is_released(button: i32) -> bool {
-    get_state().read().released.contains(&button)
+    !is_captured() && get_state().read().released.contains(&button)
 }
 
 /// Process a mouse movement event, used internally to handle mouse events
@@ -0,0 +1,78 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use parking_lot::Mutex;
+
+use crate::keyboard::{self, Key};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// An input for moving between and using GUI widgets without a mouse
+pub enum Nav {
+    /// Move to the widget above
+    Up,
+    /// Move to the widget below
+    Down,
+    /// Move to the widget to the left, or turn a slider down
+    Left,
+    /// Move to the widget to the right, or turn a slider up
+    Right,
+    /// Move to the next widget
+    Next,
+    /// Move to the previous widget
+    Previous,
+    /// Use the selected widget, like clicking it
+    Confirm,
+    /// Stop selecting a widget
+    Cancel,
+}
+
+/// Inputs sent with [send] this frame
+static SENT: Mutex<Vec<Nav>> = Mutex::new(Vec::new());
+static KEYBOARD: AtomicBool = AtomicBool::new(true);
+
+/// Send a navigation input for this frame, e.g. when a gamepad's d-pad or A button is pressed
+///
+/// Inputs have to be sent before the GUI is drawn to affect it that frame.
+pub fn send(nav: Nav) {
+    SENT.lock().push(nav);
+}
+
+/// Set whether the keyboard navigates too, on by default
+///
+/// The arrow keys move in their direction, tab and shift + tab move to the next and previous
+/// widget, enter and space confirm and escape cancels.
+pub fn set_keyboard(enabled: bool) {
+    KEYBOARD.store(enabled, Ordering::Relaxed);
+}
+
+fn key_pressed(nav: Nav) -> bool {
+    if !KEYBOARD.load(Ordering::Relaxed) {
+        return false;
+    }
+    let tab = keyboard::is_pressed(Key::Char('\t'));
+    match nav {
+        Nav::Up => keyboard::is_pressed(Key::Up),
+        Nav::Down => keyboard::is_pressed(Key::Down),
+        Nav::Left => keyboard::is_pressed(Key::Left),
+        Nav::Right => keyboard::is_pressed(Key::Right),
+        Nav::Next => tab && !keyboard::is_down(Key::Shift),
+        Nav::Previous => tab && keyboard::is_down(Key::Shift),
+        Nav::Confirm => keyboard::is_pressed(Key::Enter) || keyboard::is_pressed(Key::Space),
+        Nav::Cancel => keyboard::is_pressed(Key::Escape),
+    }
+}
+
+/// Get whether a navigation input was sent or its key was pressed this frame
+pub fn is_pressed(nav: Nav) -> bool {
+    SENT.lock().contains(&nav) || key_pressed(nav)
+}
+
+#[cfg_attr(not(feature = "gui"), allow(dead_code))]
+/// The inputs sent with [send] this frame, the keyboard ones already reach egui as key events
+pub(crate) fn sent() -> Vec<Nav> {
+    SENT.lock().clone()
+}
+
+/// Forget this frame's inputs
+pub(crate) fn reset() {
+    SENT.lock().clear();
+}
@@ -0,0 +1,96 @@
+//! Resources are values stored by their type, which the functions inside [crate::main] can take
+//! as parameters, e.g. `fn update(player: &mut Player, delta: f32)`
+//!
+//! Some of the engine's own types are resources too, which are made when they're used:
+//! [crate::debug::DebugStats] and [crate::Conf]. They're copies, so they can only be taken by `&`.
+
+use std::{
+    any::{type_name, Any, TypeId},
+    collections::BTreeMap,
+};
+
+use parking_lot::Mutex;
+
+use crate::{config::Conf, debug::DebugStats};
+
+/// Every resource, by its type
+static RESOURCES: Mutex<BTreeMap<TypeId, Box<dyn Any + Send>>> = Mutex::new(BTreeMap::new());
+
+/// Add a resource, replacing and returning the one with the same type if there was one
+pub fn insert<T: Any + Send>(value: T) -> Option<T> {
+    RESOURCES
+        .lock()
+        .insert(TypeId::of::<T>(), Box::new(value))
+        .map(|old| *old.downcast().expect("Resources are stored by their type"))
+}
+
+/// Remove a resource, and get it back
+pub fn remove<T: Any + Send>() -> Option<T> {
+    take().map(|value| *value)
+}
+
+/// Get whether there's a resource with some type
+pub fn contains<T: Any + Send>() -> bool {
+    RESOURCES.lock().contains_key(&TypeId::of::<T>())
+}
+
+/// Use a resource, if there is one with that type
+///
+/// The resource is taken out while `f` runs, so it can't be used again inside `f`.
+pub fn with<T: Any + Send, R>(f: impl FnOnce(&mut T) -> R) -> Option<R> {
+    let mut value = take::<T>()?;
+    let result = f(&mut value);
+    RESOURCES.lock().insert(TypeId::of::<T>(), value);
+    Some(result)
+}
+
+fn take<T: Any + Send>() -> Option<Box<T>> {
+    let value = RESOURCES.lock().remove(&TypeId::of::<T>())?;
+    Some(
+        value
+            .downcast()
+            .expect("Resources are stored by their type"),
+    )
+}
+
+/// Get whether a type is one of the engine's own resources, which are made when they're used
+/// instead of being stored
+fn is_provided(type_id: TypeId) -> bool {
+    type_id == TypeId::of::<DebugStats>() || type_id == TypeId::of::<Conf>()
+}
+
+/// Make one of the engine's own resources
+fn provide(type_id: TypeId) -> Option<Box<dyn Any + Send>> {
+    if type_id == TypeId::of::<DebugStats>() {
+        Some(Box::new(crate::debug::stats()))
+    } else if type_id == TypeId::of::<Conf>() {
+        Some(Box::new(crate::config::get().clone()))
+    } else {
+        None
+    }
+}
+
+#[doc(hidden)]
+/// Take a resource out for one of the game's functions, used by [crate::main]
+pub fn __take<T: Any + Send>(function: &str) -> Box<T> {
+    take::<T>()
+        .or_else(|| {
+            provide(TypeId::of::<T>())
+                .map(|value| value.downcast().expect("Provided the wrong type"))
+        })
+        .unwrap_or_else(|| {
+            panic!(
+                "The {function} function takes a `{}`, but there's no resource with that type, \
+                 add one with care::resource::insert first",
+                type_name::<T>(),
+            )
+        })
+}
+
+#[doc(hidden)]
+/// Put a resource back after one of the game's functions used it, used by [crate::main]
+pub fn __put_back<T: Any + Send>(value: Box<T>) {
+    if !is_provided(TypeId::of::<T>()) {
+        RESOURCES.lock().insert(TypeId::of::<T>(), value);
+    }
+}
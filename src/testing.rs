@@ -0,0 +1,283 @@
+//! Testing how a game looks, by drawing it without a window and comparing the result with a
+//! reference image
+//!
+//! Frames are drawn with [crate::graphics::init_headless], so this works without a display, e.g.
+//! in CI. Without a GPU, a software renderer like lavapipe or llvmpipe has to be installed.
+//!
+//! ```no_run
+//! #[test]
+//! fn draws_a_box() {
+//!     let image = care::testing::render_frames((100, 100), 1, |_frame| {
+//!         care::graphics::rectangle((25, 25), (50, 50));
+//!     });
+//!     care::testing::assert_golden("tests/golden/box.png", &image, Default::default());
+//! }
+//! ```
+//!
+//! Reference images that don't exist yet, or that should change, are saved by running the tests
+//! with `CARE_UPDATE_REFERENCES=1`.
+
+use std::{
+    fmt::{self, Display},
+    path::{Path, PathBuf},
+};
+
+use image::{Rgba, RgbaImage};
+use parking_lot::Mutex;
+
+use crate::{
+    graphics::{self, LineEndStyle, LineJoinStyle},
+    math::{Fl, Vec2},
+};
+
+/// Held while frames are drawn, since the graphics state is shared by every test
+static RENDER_LOCK: Mutex<()> = Mutex::new(());
+
+/// The environment variable that makes [assert_golden] save the reference images
+const UPDATE_VAR: &str = "CARE_UPDATE_REFERENCES";
+
+/// Draw some frames without a window, calling `draw` with the frame's number for each of them,
+/// and get the last one
+///
+/// At least one frame is always drawn.
+///
+/// Every call starts with the default colour and line style, so tests don't affect each other.
+pub fn render_frames(size: impl Into<Vec2>, frames: u32, mut draw: impl FnMut(u32)) -> RgbaImage {
+    let _lock = RENDER_LOCK.lock();
+    graphics::init_headless(size);
+    crate::event::init();
+    // A test that panicked might have left things behind
+    graphics::GRAPHICS_STATE.care_render.write().reset();
+    graphics::set_line_style(LineJoinStyle::Rounded, LineEndStyle::Rounded);
+    for frame in 0..frames.max(1) {
+        draw(frame);
+        crate::event::end_frame();
+    }
+    graphics::read_frame()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// How different an image can be from its reference and still match it
+pub struct Tolerance {
+    /// How much each colour channel of a pixel can differ, from 0 to 255, before the pixel counts
+    /// as different
+    pub channel: u8,
+    /// The fraction of pixels that can be different, from 0 to 1
+    pub pixels: Fl,
+}
+
+impl Tolerance {
+    /// Every pixel has to be exactly the same
+    pub const EXACT: Self = Self {
+        channel: 0,
+        pixels: 0.0,
+    };
+}
+
+impl Default for Tolerance {
+    /// Small enough to catch real changes, but big enough for different GPUs antialiasing edges
+    /// differently
+    fn default() -> Self {
+        Self {
+            channel: 8,
+            pixels: 0.002,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+/// Why an image doesn't match its reference
+pub enum Mismatch {
+    /// The images are different sizes
+    Size {
+        /// The image's size
+        actual: (u32, u32),
+        /// The reference's size
+        reference: (u32, u32),
+    },
+    /// Too many pixels are different
+    Pixels {
+        /// How many pixels are different
+        different: usize,
+        /// How many pixels there are
+        total: usize,
+        /// The reference, dimmed, with the different pixels in red
+        diff: RgbaImage,
+    },
+}
+
+impl Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Mismatch::Size { actual, reference } => write!(
+                f,
+                "The image is {}x{} but the reference is {}x{}",
+                actual.0, actual.1, reference.0, reference.1
+            ),
+            Mismatch::Pixels {
+                different, total, ..
+            } => write!(
+                f,
+                "{different} of {total} pixels are different ({:.2}%)",
+                *different as f64 / *total as f64 * 100.0
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Mismatch {}
+
+/// Compare an image with its reference
+pub fn compare(
+    actual: &RgbaImage,
+    reference: &RgbaImage,
+    tolerance: Tolerance,
+) -> Result<(), Mismatch> {
+    if actual.dimensions() != reference.dimensions() {
+        return Err(Mismatch::Size {
+            actual: actual.dimensions(),
+            reference: reference.dimensions(),
+        });
+    }
+    let mut diff = RgbaImage::new(reference.width(), reference.height());
+    let mut different = 0;
+    for ((actual, reference), diff) in actual
+        .pixels()
+        .zip(reference.pixels())
+        .zip(diff.pixels_mut())
+    {
+        let is_different = actual
+            .0
+            .iter()
+            .zip(reference.0)
+            .any(|(a, r)| a.abs_diff(r) > tolerance.channel);
+        *diff = if is_different {
+            different += 1;
+            Rgba([255, 0, 0, 255])
+        } else {
+            let [r, g, b, _] = reference.0;
+            Rgba([r / 4, g / 4, b / 4, 255])
+        };
+    }
+    let total = (reference.width() * reference.height()) as usize;
+    if different as Fl > tolerance.pixels * total as Fl {
+        return Err(Mismatch::Pixels {
+            different,
+            total,
+            diff,
+        });
+    }
+    Ok(())
+}
+
+/// Check that an image matches the reference image at a path, panicking if it doesn't
+///
+/// When it doesn't, the image and a diff are saved next to the reference, as `name.actual.png`
+/// and `name.diff.png`. With the `CARE_UPDATE_REFERENCES` environment variable set, the image is
+/// saved as the reference instead.
+pub fn assert_golden(reference: impl AsRef<Path>, actual: &RgbaImage, tolerance: Tolerance) {
+    let path = reference.as_ref();
+    if std::env::var(UPDATE_VAR).is_ok_and(|value| !value.is_empty() && value != "0") {
+        save(actual, path);
+        return;
+    }
+    let reference = match image::open(path) {
+        Ok(reference) => reference.to_rgba8(),
+        Err(e) => {
+            let actual_path = sibling(path, "actual");
+            save(actual, &actual_path);
+            panic!(
+                "Couldn't load the reference image {}: {e}\nThe image was saved to {}, run with \
+                 {UPDATE_VAR}=1 to save it as the reference",
+                path.display(),
+                actual_path.display(),
+            );
+        }
+    };
+    if let Err(mismatch) = compare(actual, &reference, tolerance) {
+        let actual_path = sibling(path, "actual");
+        save(actual, &actual_path);
+        if let Mismatch::Pixels { diff, .. } = &mismatch {
+            save(diff, &sibling(path, "diff"));
+        }
+        panic!(
+            "The image doesn't match {}: {mismatch}\nThe image was saved to {}",
+            path.display(),
+            actual_path.display(),
+        );
+    }
+}
+
+/// Get the path of a file next to a reference image, e.g. `box.actual.png` for `box.png`
+fn sibling(path: &Path, kind: &str) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!("{stem}.{kind}.png"))
+}
+
+fn save(image: &RgbaImage, path: &Path) {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).expect("Failed to make the reference image's folder");
+    }
+    image
+        .save(path)
+        .unwrap_or_else(|e| panic!("Failed to save {}: {e}", path.display()));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grey(value: u8) -> RgbaImage {
+        RgbaImage::from_pixel(10, 10, Rgba([value, value, value, 255]))
+    }
+
+    #[test]
+    fn identical_images_match() {
+        assert!(compare(&grey(100), &grey(100), Tolerance::EXACT).is_ok());
+    }
+
+    #[test]
+    fn small_differences_are_tolerated() {
+        let mut actual = grey(100);
+        // Every pixel is a little off, and one pixel is very different
+        for pixel in actual.pixels_mut() {
+            pixel.0[0] = 104;
+        }
+        actual.put_pixel(3, 3, Rgba([0, 0, 0, 255]));
+        let tolerance = Tolerance {
+            channel: 8,
+            pixels: 0.01,
+        };
+        assert!(compare(&actual, &grey(100), tolerance).is_ok());
+    }
+
+    #[test]
+    fn big_differences_are_not_tolerated() {
+        let mut actual = grey(100);
+        actual.put_pixel(3, 3, Rgba([0, 0, 0, 255]));
+        actual.put_pixel(4, 3, Rgba([100, 100, 109, 255]));
+        match compare(&actual, &grey(100), Tolerance::default()) {
+            Err(Mismatch::Pixels {
+                different,
+                total,
+                diff,
+            }) => {
+                assert_eq!((different, total), (2, 100));
+                assert_eq!(diff.get_pixel(3, 3), &Rgba([255, 0, 0, 255]));
+                assert_eq!(diff.get_pixel(0, 0), &Rgba([25, 25, 25, 255]));
+            }
+            result => panic!("Expected the pixels to be different, got {result:?}"),
+        }
+    }
+
+    #[test]
+    fn different_sizes_do_not_match() {
+        let actual = RgbaImage::new(10, 20);
+        match compare(&actual, &grey(0), Tolerance::default()) {
+            Err(Mismatch::Size { actual, reference }) => {
+                assert_eq!((actual, reference), ((10, 20), (10, 10)));
+            }
+            result => panic!("Expected the sizes to be different, got {result:?}"),
+        }
+    }
+}
@@ -0,0 +1,100 @@
+use parking_lot::RwLock;
+
+use crate::math::Vec2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// Where a touch is in its life, see [crate::event::EventData::Touch]
+pub enum TouchPhase {
+    /// A finger was put down
+    Started,
+    /// A finger moved
+    Moved,
+    /// A finger was lifted
+    Ended,
+    /// The system took over the touch, e.g. for a gesture, so it should be treated as if it
+    /// never happened
+    Cancelled,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// A finger on the screen
+pub struct Touch {
+    /// Stays the same while the finger is down, but can be reused after it's lifted
+    pub id: u64,
+    /// Where the finger is, in logical pixels like [crate::event::EventData::MouseMoved]
+    pub position: Vec2,
+}
+
+#[derive(Debug)]
+struct TouchState {
+    /// The fingers on the screen, in the order they were put down
+    held: Vec<Touch>,
+    started: Vec<Touch>,
+    ended: Vec<Touch>,
+}
+
+static TOUCH_STATE: RwLock<TouchState> = RwLock::new(TouchState {
+    held: Vec::new(),
+    started: Vec::new(),
+    ended: Vec::new(),
+});
+
+/// Get every finger that's on the screen, in the order they were put down
+pub fn touches() -> Vec<Touch> {
+    TOUCH_STATE.read().held.clone()
+}
+
+/// Get where a finger is, if it's on the screen
+pub fn get_position(id: u64) -> Option<Vec2> {
+    TOUCH_STATE
+        .read()
+        .held
+        .iter()
+        .find(|touch| touch.id == id)
+        .map(|touch| touch.position)
+}
+
+/// Get the first finger that's still on the screen, which is the one that acts as the mouse on
+/// mobile
+pub fn primary() -> Option<Touch> {
+    TOUCH_STATE.read().held.first().copied()
+}
+
+/// Get the fingers that were just put down
+pub fn just_started() -> Vec<Touch> {
+    TOUCH_STATE.read().started.clone()
+}
+
+/// Get the fingers that were just lifted (or cancelled), where they were last
+pub fn just_ended() -> Vec<Touch> {
+    TOUCH_STATE.read().ended.clone()
+}
+
+/// Process a touch event
+pub fn process_touch_event(id: u64, phase: TouchPhase, position: Vec2) {
+    let mut state = TOUCH_STATE.write();
+    let touch = Touch { id, position };
+    match phase {
+        TouchPhase::Started => {
+            state.held.retain(|touch| touch.id != id);
+            state.held.push(touch);
+            state.started.push(touch);
+        }
+        // Moved in place, so the order the fingers were put down in stays the same
+        TouchPhase::Moved => match state.held.iter_mut().find(|touch| touch.id == id) {
+            Some(held) => held.position = position,
+            None => state.held.push(touch),
+        },
+        TouchPhase::Ended | TouchPhase::Cancelled => {
+            state.held.retain(|touch| touch.id != id);
+            state.ended.push(touch);
+        }
+    }
+}
+
+/// Reset the touches that started and ended, at the end of a frame
+pub fn reset() {
+    let mut state = TOUCH_STATE.write();
+    state.started.clear();
+    state.ended.clear();
+}
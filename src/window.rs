@@ -10,13 +10,18 @@ use parking_lot::{Mutex, RwLock};
 use winit::{
     application::ApplicationHandler,
     dpi::{LogicalPosition, LogicalSize},
-    event::{KeyEvent, WindowEvent},
-    event_loop::{ControlFlow, EventLoop},
+    event::{Ime, KeyEvent, MouseScrollDelta, WindowEvent},
+    event_loop::{ActiveEventLoop, ControlFlow, EventLoop},
     keyboard::{Key as WKey, NamedKey, SmolStr},
-    window::{Window, WindowAttributes},
+    window::{Fullscreen, Window, WindowAttributes},
 };
 
-use crate::{math::Vec2, prelude::Key};
+use crate::{
+    math::{Fl, Vec2},
+    prelude::Key,
+    touch::TouchPhase,
+    Conf,
+};
 
 static HAS_INITIALIZED: AtomicBool = AtomicBool::new(false);
 static INIT_COMPLETE: AtomicBool = AtomicBool::new(false);
@@ -24,8 +29,16 @@ thread_local! {
     static EVENT_LOOP: RwLock<Option<EventLoop<()>>> = const { RwLock::new(None) };
 }
 static EXIT_REQUEST: AtomicBool = AtomicBool::new(false);
+/// Set while the app is in the background, it starts suspended until the event loop resumes it
+static SUSPENDED: AtomicBool = AtomicBool::new(true);
+/// How many pixels a touchpad has to scroll to count as one line
+const PIXELS_PER_LINE: f64 = 20.0;
+
+/// Given a window that was opened with [open_extra]
+pub(crate) type OnOpened = Box<dyn FnOnce(Arc<Window>) + Send>;
 
-pub(crate) static CREATE_WINDOWS: Mutex<Vec<WindowAttributes>> = Mutex::new(Vec::new());
+pub(crate) static CREATE_WINDOWS: Mutex<Vec<(WindowAttributes, Option<OnOpened>)>> =
+    Mutex::new(Vec::new());
 pub(crate) static WINDOWS: RwLock<Vec<Arc<Window>>> = RwLock::new(Vec::new());
 
 fn init() {
@@ -42,7 +55,18 @@ fn init() {
         EVENT_LOOP.with(|el_cell| {
             let mut el = el_cell.write();
             if el.is_none() {
+                #[cfg(not(target_os = "android"))]
                 let tmp = EventLoop::new().unwrap();
+                #[cfg(target_os = "android")]
+                let tmp = {
+                    use winit::platform::android::EventLoopBuilderExtAndroid;
+                    EventLoop::builder()
+                        .with_android_app(crate::mobile::android_app().expect(
+                            "The Android app must be set with care::mobile::set_android_app first",
+                        ))
+                        .build()
+                        .unwrap()
+                };
                 tmp.set_control_flow(ControlFlow::Poll);
                 *el = Some(tmp);
             }
@@ -53,14 +77,16 @@ fn init() {
 /// Settings specifying how to open a window, see [open_with_settings].
 #[derive(Debug)]
 pub struct WindowSettings<'a> {
-    // Title/name of the window
-    name: &'a str,
+    /// Title/name of the window
+    pub name: &'a str,
     /// Size, in pixels
-    size: Option<Vec2>,
-    // Whether the window is resizable or not
-    resizable: bool,
-    // Position, in pixels
-    pos: Option<Vec2>,
+    pub size: Option<Vec2>,
+    /// Whether the window is resizable or not
+    pub resizable: bool,
+    /// Whether the window is fullscreen
+    pub fullscreen: bool,
+    /// Position, in pixels
+    pub pos: Option<Vec2>,
 }
 
 impl Default for WindowSettings<'_> {
@@ -69,6 +95,20 @@ impl Default for WindowSettings<'_> {
             name: "CÄRE game",
             size: Some((800, 600).into()),
             resizable: false,
+            fullscreen: false,
+            pos: None,
+        }
+    }
+}
+
+impl<'a> WindowSettings<'a> {
+    /// Settings for the main window from the config, named `default_name` if it has no title
+    pub fn from_conf(conf: &'a Conf, default_name: &'a str) -> Self {
+        Self {
+            name: conf.title.as_deref().unwrap_or(default_name),
+            size: Some(conf.size),
+            resizable: conf.resizable,
+            fullscreen: conf.fullscreen,
             pos: None,
         }
     }
@@ -96,7 +136,24 @@ pub fn open_with_settings(settings: WindowSettings) {
     if let Some(pos) = settings.pos {
         attribs = attribs.with_position(LogicalPosition::new(pos.0.x, pos.0.y));
     }
-    CREATE_WINDOWS.lock().push(attribs);
+    if settings.fullscreen {
+        attribs = attribs.with_fullscreen(Some(Fullscreen::Borderless(None)));
+    }
+    CREATE_WINDOWS.lock().push((attribs, None));
+}
+
+/// Open a window that isn't one of the game's windows, e.g. for an egui viewport
+///
+/// It's given to `on_opened` once it's open, instead of being added to [WINDOWS], and its
+/// events are never handled as game events.
+#[cfg_attr(not(feature = "gui"), allow(dead_code))]
+pub(crate) fn open_extra(
+    attribs: WindowAttributes,
+    on_opened: impl FnOnce(Arc<Window>) + Send + 'static,
+) {
+    CREATE_WINDOWS
+        .lock()
+        .push((attribs, Some(Box::new(on_opened))));
 }
 
 /// WIP Function to set the main window size
@@ -111,17 +168,45 @@ pub fn set_window_size(size: impl Into<Vec2>) {
 ///
 /// Currently the implementation reads from the list of windows, so the result should probably be
 /// cached per-frame, but in the future this function may be cached for speed
+///
+/// Without a window, it's the size of the off-screen image, see [crate::graphics::init_headless].
 pub fn window_size() -> Vec2 {
     let windows = WINDOWS.read();
     if let Some(window) = windows.first() {
         let size: LogicalSize<f32> = window.inner_size().to_logical(window.scale_factor());
         Vec2::new(size.width, size.height)
     } else {
-        Vec2::new(0.0, 0.0)
+        crate::graphics::headless_size().unwrap_or(Vec2::new(0.0, 0.0))
     }
 }
 
-fn convert_key(key: winit::keyboard::Key<SmolStr>) -> Key {
+/// Get the scale factor of the main window, the number of pixels per logical pixel
+///
+/// This is usually 1, but can be bigger on high DPI displays.
+pub fn scale_factor() -> Fl {
+    let windows = WINDOWS.read();
+    windows
+        .first()
+        .map(|window| window.scale_factor() as Fl)
+        .unwrap_or(1.0)
+}
+
+/// Get whether the app is in the background, see [crate::event::EventData::Suspend]
+pub(crate) fn is_suspended() -> bool {
+    SUSPENDED.load(Ordering::Relaxed)
+}
+
+/// Get the scale factor of a window, see [scale_factor]
+fn window_scale_factor(window_id: winit::window::WindowId) -> f64 {
+    WINDOWS
+        .read()
+        .iter()
+        .find(|w| w.id() == window_id)
+        .map(|w| w.scale_factor())
+        .unwrap_or(1.0)
+}
+
+pub(crate) fn convert_key(key: winit::keyboard::Key<SmolStr>) -> Key {
     match key {
         WKey::Named(NamedKey::ArrowUp) => Key::Up,
         WKey::Named(NamedKey::ArrowDown) => Key::Down,
@@ -129,6 +214,7 @@ fn convert_key(key: winit::keyboard::Key<SmolStr>) -> Key {
         WKey::Named(NamedKey::ArrowRight) => Key::Right,
         WKey::Named(NamedKey::Space) => Key::Space,
         WKey::Named(NamedKey::Enter) => Key::Enter,
+        WKey::Named(NamedKey::Tab) => Key::Char('\t'),
         WKey::Named(NamedKey::Escape) => Key::Escape,
         WKey::Named(NamedKey::Backspace) => Key::Backspace,
         WKey::Named(NamedKey::Delete) => Key::Delete,
@@ -146,22 +232,54 @@ enum AppData<T, F: FnOnce() -> T> {
     Data(T),
 }
 
-struct AppHandler<T, F: FnMut(&mut T), I: FnOnce() -> T> {
+struct AppHandler<T, F: FnMut(&mut T), I: FnOnce() -> T, Q: FnOnce(&mut T)> {
     data: AppData<T, I>,
     loop_fn: F,
+    quit_fn: Option<Q>,
 }
 
-impl<T, F: FnMut(&mut T), I: FnOnce() -> T> ApplicationHandler for AppHandler<T, F, I> {
-    fn resumed(&mut self, _event_loop: &winit::event_loop::ActiveEventLoop) {}
+impl<T, F: FnMut(&mut T), I: FnOnce() -> T, Q: FnOnce(&mut T)> ApplicationHandler
+    for AppHandler<T, F, I, Q>
+{
+    fn resumed(&mut self, event_loop: &ActiveEventLoop) {
+        SUSPENDED.store(false, Ordering::Relaxed);
+        // Windows can't be opened before the first resume on some platforms, e.g. Android
+        create_windows(event_loop);
+        if let AppData::Data(_) = self.data {
+            // The surfaces are lost while the app is suspended on Android
+            let graphics = &crate::graphics::GRAPHICS_STATE;
+            for window_id in graphics.window_surfaces.keys() {
+                graphics.recreate_surface(*window_id);
+            }
+            crate::event::handle_event(crate::event::Event {
+                timestamp: Instant::now(),
+                data: crate::event::EventData::Suspend { suspended: false },
+            });
+        }
+    }
+
+    fn suspended(&mut self, _event_loop: &ActiveEventLoop) {
+        SUSPENDED.store(true, Ordering::Relaxed);
+        crate::event::handle_event(crate::event::Event {
+            timestamp: Instant::now(),
+            data: crate::event::EventData::Suspend { suspended: true },
+        });
+    }
+
+    fn exiting(&mut self, _event_loop: &ActiveEventLoop) {
+        // The game never started if the loop exits before the first frame
+        if let (AppData::Data(data), Some(quit_fn)) = (&mut self.data, self.quit_fn.take()) {
+            quit_fn(data);
+        }
+        crate::event::run_exit_hooks();
+    }
 
-    fn about_to_wait(&mut self, event_loop: &winit::event_loop::ActiveEventLoop) {
-        for attribs in CREATE_WINDOWS.lock().drain(..) {
-            WINDOWS.write().push(Arc::new(
-                event_loop
-                    .create_window(attribs)
-                    .expect("Failed to open window"),
-            ));
+    fn about_to_wait(&mut self, event_loop: &ActiveEventLoop) {
+        // Nothing can be drawn while the app is in the background
+        if is_suspended() {
+            return;
         }
+        create_windows(event_loop);
         if let AppData::Init(init) = &mut self.data {
             self.data = AppData::Data((init.take().unwrap())());
         };
@@ -173,7 +291,7 @@ impl<T, F: FnMut(&mut T), I: FnOnce() -> T> ApplicationHandler for AppHandler<T,
 
     fn window_event(
         &mut self,
-        event_loop: &winit::event_loop::ActiveEventLoop,
+        event_loop: &ActiveEventLoop,
         window_id: winit::window::WindowId,
         ev: WindowEvent,
     ) {
@@ -181,6 +299,10 @@ impl<T, F: FnMut(&mut T), I: FnOnce() -> T> ApplicationHandler for AppHandler<T,
             event_loop.exit();
             return;
         }
+        #[cfg(feature = "gui")]
+        if crate::gui::viewport_window_event(window_id, &ev) {
+            return;
+        }
         match ev {
             WindowEvent::CloseRequested => {
                 event_loop.exit();
@@ -221,14 +343,8 @@ impl<T, F: FnMut(&mut T), I: FnOnce() -> T> ApplicationHandler for AppHandler<T,
                 }
             }
             WindowEvent::CursorMoved { position, .. } => {
-                let position: LogicalPosition<f64> = position.to_logical(
-                    WINDOWS
-                        .read()
-                        .iter()
-                        .find(|w| w.id() == window_id)
-                        .map(|w| w.scale_factor())
-                        .unwrap_or(1.0),
-                );
+                let position: LogicalPosition<f64> =
+                    position.to_logical(window_scale_factor(window_id));
                 crate::event::handle_event(crate::event::Event {
                     timestamp: Instant::now(),
                     data: crate::event::EventData::MouseMoved {
@@ -252,19 +368,121 @@ impl<T, F: FnMut(&mut T), I: FnOnce() -> T> ApplicationHandler for AppHandler<T,
                     },
                 });
             }
+            WindowEvent::MouseWheel { delta, .. } => {
+                let delta = match delta {
+                    MouseScrollDelta::LineDelta(x, y) => Vec2::new(x, y),
+                    MouseScrollDelta::PixelDelta(delta) => {
+                        Vec2::new(delta.x / PIXELS_PER_LINE, delta.y / PIXELS_PER_LINE)
+                    }
+                };
+                crate::event::handle_event(crate::event::Event {
+                    timestamp: Instant::now(),
+                    data: crate::event::EventData::MouseScroll { delta },
+                });
+            }
+            WindowEvent::Resized(size) => {
+                let size: LogicalSize<f32> = size.to_logical(window_scale_factor(window_id));
+                crate::event::handle_event(crate::event::Event {
+                    timestamp: Instant::now(),
+                    data: crate::event::EventData::WindowResized {
+                        size: Vec2::new(size.width, size.height),
+                    },
+                });
+            }
+            WindowEvent::DroppedFile(path) => crate::event::handle_event(crate::event::Event {
+                timestamp: Instant::now(),
+                data: crate::event::EventData::FileDropped { path },
+            }),
             WindowEvent::Focused(focused) => crate::event::handle_event(crate::event::Event {
                 timestamp: Instant::now(),
                 data: crate::event::EventData::FocusChange { focused },
             }),
+            WindowEvent::Touch(touch) => {
+                let position: LogicalPosition<f64> =
+                    touch.location.to_logical(window_scale_factor(window_id));
+                let position = Vec2::new(position.x, position.y);
+                let phase = match touch.phase {
+                    winit::event::TouchPhase::Started => TouchPhase::Started,
+                    winit::event::TouchPhase::Moved => TouchPhase::Moved,
+                    winit::event::TouchPhase::Ended => TouchPhase::Ended,
+                    winit::event::TouchPhase::Cancelled => TouchPhase::Cancelled,
+                };
+                #[cfg(any(target_os = "android", target_os = "ios"))]
+                let primary = crate::touch::primary().map(|touch| touch.id);
+                crate::event::handle_event(crate::event::Event {
+                    timestamp: Instant::now(),
+                    data: crate::event::EventData::Touch {
+                        id: touch.id,
+                        phase,
+                        position,
+                    },
+                });
+                // Phones have no mouse, so the first finger acts as one
+                #[cfg(any(target_os = "android", target_os = "ios"))]
+                emulate_mouse(touch.id, phase, position, primary);
+            }
+            WindowEvent::Ime(Ime::Commit(text)) => {
+                crate::event::handle_event(crate::event::Event {
+                    timestamp: Instant::now(),
+                    data: crate::event::EventData::TextEvent { text },
+                })
+            }
             _ => {}
         }
     }
 }
 
+/// Open the windows that are waiting to be opened
+fn create_windows(event_loop: &ActiveEventLoop) {
+    let create_windows: Vec<_> = CREATE_WINDOWS.lock().drain(..).collect();
+    for (attribs, on_opened) in create_windows {
+        let window = Arc::new(
+            event_loop
+                .create_window(attribs)
+                .expect("Failed to open window"),
+        );
+        match on_opened {
+            Some(on_opened) => on_opened(window),
+            None => WINDOWS.write().push(window),
+        }
+    }
+}
+
+/// Send mouse events for a touch, if it's the first finger on the screen
+///
+/// `primary` is the first finger before the touch was processed.
+#[cfg(any(target_os = "android", target_os = "ios"))]
+fn emulate_mouse(id: u64, phase: TouchPhase, position: Vec2, primary: Option<u64>) {
+    let is_primary = match phase {
+        TouchPhase::Started => primary.is_none(),
+        _ => primary == Some(id),
+    };
+    if !is_primary {
+        return;
+    }
+    crate::event::handle_event(crate::event::Event {
+        timestamp: Instant::now(),
+        data: crate::event::EventData::MouseMoved { position },
+    });
+    if phase != TouchPhase::Moved {
+        crate::event::handle_event(crate::event::Event {
+            timestamp: Instant::now(),
+            data: crate::event::EventData::MouseClick {
+                button: 1,
+                pressed: phase == TouchPhase::Started,
+            },
+        });
+    }
+}
+
 /// Window implementation of the event loop running function
 ///
 /// Also initializes the window system
-pub(crate) fn run<T>(init_fn: impl FnOnce() -> T, loop_fn: impl FnMut(&mut T)) {
+pub(crate) fn run<T>(
+    init_fn: impl FnOnce() -> T,
+    loop_fn: impl FnMut(&mut T),
+    quit_fn: impl FnOnce(&mut T),
+) {
     init();
     EVENT_LOOP.with(move |el_call| {
         let el = el_call
@@ -274,6 +492,7 @@ pub(crate) fn run<T>(init_fn: impl FnOnce() -> T, loop_fn: impl FnMut(&mut T)) {
         el.run_app(&mut AppHandler {
             data: AppData::Init(Some(init_fn)),
             loop_fn,
+            quit_fn: Some(quit_fn),
         })
         .unwrap();
     });
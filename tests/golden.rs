@@ -0,0 +1,16 @@
+//! Drawing without a window and comparing the frames with the reference images in `golden`,
+//! which needs a GPU or a software renderer, so these only run with `cargo test -- --ignored`
+
+#![cfg(feature = "graphics")]
+
+use care::testing::{assert_golden, render_frames, Tolerance};
+
+#[test]
+#[ignore = "needs a GPU or a software renderer"]
+fn draws_a_rectangle() {
+    let image = render_frames((100, 100), 1, |_frame| {
+        care::graphics::set_colour((1.0, 0.5, 0.0, 1.0));
+        care::graphics::rectangle((25, 25), (50, 50));
+    });
+    assert_golden("tests/golden/rectangle.png", &image, Tolerance::default());
+}